@@ -12,6 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::overflow::OverflowPolicy;
+use bip_utils::wakeup::WaitStrategy;
+use handler_schema::{Argument, ArgumentType, HandlerSchema, Role};
+use socket_utils::buffered_socket_writer::InFlightPolicy;
+use socket_utils::link::LinkType;
 use structopt::StructOpt;
 #[derive(StructOpt)]
 pub struct OptIngress {
@@ -46,6 +51,26 @@ pub struct OptIngress {
     #[structopt(long = "bip_buffer_element_count", default_value = "10")]
     pub bip_buffer_element_count: usize,
 
+    ///How threads wait for data or space in the bip buffer: spin, park or adaptive.
+    #[structopt(long = "wait_strategy", default_value = "adaptive")]
+    pub wait_strategy: WaitStrategy,
+
+    ///The kind of link to the adjacent handlers in the chain: socket or shared_memory.
+    #[structopt(long = "link", default_value = "socket")]
+    pub link: LinkType,
+
+    ///Policy used when the bip buffer is full, can be block, drop_newest or drop_oldest.
+    #[structopt(long = "overflow_policy", default_value = "block")]
+    pub overflow_policy: OverflowPolicy,
+
+    ///The maximum time in milliseconds the drop_oldest policy waits for space in the bip buffer.
+    #[structopt(long = "overflow_wait_ms", default_value = "100")]
+    pub overflow_wait_ms: u64,
+
+    ///What happens to the element being sent when the next handler is lost: keep or discard.
+    #[structopt(long = "in_flight_policy", default_value = "keep")]
+    pub in_flight_policy: InFlightPolicy,
+
     ///maximum size of a message
     #[structopt(long = "max_message_size", default_value = "1050000")]
     pub max_message_size: usize,
@@ -74,3 +99,75 @@ pub struct OptIngress {
     #[structopt(long = "handler_name", default_value = "ph_kafka_ingress")]
     pub handler_name: String,
 }
+
+///The arguments of filter, printed with `--describe`.
+pub const FILTER_SCHEMA: HandlerSchema = HandlerSchema {
+    role: Role::Filter,
+    arguments: &[
+        Argument::new("socket_path_in", ArgumentType::String)
+            .default("/tmp/handler_to_transport")
+            .help("Location of the socket"),
+        Argument::new("socket_path_out", ArgumentType::String)
+            .default("/tmp/handler_to_transport")
+            .help("Location of the socket"),
+        Argument::new("word_to_filter", ArgumentType::String)
+            .default("secret")
+            .help("StatsD server host"),
+        Argument::new("stats_server_address", ArgumentType::String)
+            .default("127.0.0.1")
+            .help("StatsD server host"),
+        Argument::new("stats_server_port", ArgumentType::Integer)
+            .default("8125")
+            .help("StatsD server port"),
+        Argument::new("bip_buffer_element_count", ArgumentType::Integer)
+            .default("10")
+            .help("The maximum amount of elements the bip buffer can store. The size of a single element is 1Mb"),
+        Argument::new("wait_strategy", ArgumentType::String)
+            .default("adaptive")
+            .help("How threads wait for data or space in the bip buffer: spin, park or adaptive"),
+        Argument::new("link", ArgumentType::String)
+            .default("socket")
+            .help("The kind of link to the adjacent handlers in the chain: socket or shared_memory"),
+        Argument::new("overflow_policy", ArgumentType::String)
+            .default("block")
+            .help("Policy used when the bip buffer is full, can be block, drop_newest or drop_oldest"),
+        Argument::new("overflow_wait_ms", ArgumentType::Integer)
+            .default("100")
+            .help("The maximum time in milliseconds the drop_oldest policy waits for space in the bip buffer"),
+        Argument::new("in_flight_policy", ArgumentType::String)
+            .default("keep")
+            .help("What happens to the element being sent when the next handler is lost: keep or discard"),
+        Argument::new("max_message_size", ArgumentType::Integer)
+            .default("1050000")
+            .help("maximum size of a message"),
+        Argument::new("from_host_sys_log", ArgumentType::String)
+            .default("0.0.0.0")
+            .help("From syslog server host"),
+        Argument::new("from_port_sys_log", ArgumentType::Integer)
+            .default("8127")
+            .help("From syslog server port"),
+        Argument::new("to_host_sys_log", ArgumentType::String)
+            .default("127.0.0.1")
+            .help("To syslog udp host"),
+        Argument::new("to_port_sys_log", ArgumentType::Integer)
+            .default("8082")
+            .help("To syslog udp port"),
+        Argument::new("log_level", ArgumentType::String)
+            .default("Warn")
+            .help("Log level for logging"),
+        Argument::new("handler_name", ArgumentType::String)
+            .default("ph_kafka_ingress")
+            .help("Name of the handler"),
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use handler_schema::check_schema;
+
+    #[test]
+    fn schema_test() {
+        assert_eq!(check_schema(OptIngress::clap(), &FILTER_SCHEMA), Ok(()));
+    }
+}
@@ -12,18 +12,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bip_utils::read_from_bip_buffer;
+use bip_utils::bip_buffer_len_for;
+use bip_utils::bip_buffer_with_len;
+use bip_utils::overflow::BufferMonitor;
+use bip_utils::read_fitting_from_bip_buffer;
+use bip_utils::wakeup::set_wait_strategy;
 use filter::errors::*;
 use filter::*;
+use handler_schema::describe_if_requested;
 use logging::*;
-use socket_utils::buffered_socket_reader::BufferedSocketReader;
-use socket_utils::buffered_socket_writer::BufferedSocketWriter;
-use spsc_bip_buffer::bip_buffer_with_len;
+use socket_utils::link::{LinkReader, LinkWriter};
 use statistics_handler::*;
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use structopt::StructOpt;
 
 fn main() {
+    describe_if_requested(&arguments::FILTER_SCHEMA);
     std::panic::set_hook(Box::new(|panic_info| {
         if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
             log::error!("{}", s);
@@ -42,7 +48,7 @@ fn main() {
 }
 
 /// This filter checks for the first bytes of the incoming data. If it matches the configured "word_to_filter" then it drops the data.
-/// If the data is corrupted and cannot be read as KafkaMessage then the data will always drop.
+/// If the data is corrupted and cannot be read as an Envelope then the data will always drop.
 fn filter() -> Result<()> {
     let opt = arguments::OptIngress::from_args();
     set_syslog(
@@ -55,6 +61,7 @@ fn filter() -> Result<()> {
     )
     .chain_err(|| "Error initializing syslog")?;
     log::info!("start {}", &opt.handler_name);
+    set_wait_strategy(opt.wait_strategy);
 
     //Start stats thread
     let stats_addr = format!("{}:{}", opt.host_stats_server, opt.port_stats_server);
@@ -69,16 +76,32 @@ fn filter() -> Result<()> {
     let stats_data2 = stats.get_data_clone();
     let stats_data3 = stats.get_data_clone();
 
-    //Create bipbuffers with the a size of 1Mb times the incoming bip_bupffer_element_count in argument
-    let (bip_writer_first, mut bip_reader_first) =
-        bip_buffer_with_len(opt.bip_buffer_element_count * BUFFER_SIZE_BYTES);
-    let (mut bip_writer_second, mut bip_reader_second) =
-        bip_buffer_with_len(opt.bip_buffer_element_count * BUFFER_SIZE_BYTES);
+    //Create bipbuffers that hold the incoming bip_buffer_element_count of 1Mb elements
+    let bip_buffer_len = bip_buffer_len_for(opt.bip_buffer_element_count, BUFFER_SIZE_BYTES);
+    let (bip_writer_first, mut bip_reader_first) = bip_buffer_with_len(bip_buffer_len);
+    let (mut bip_writer_second, mut bip_reader_second) = bip_buffer_with_len(bip_buffer_len);
 
-    let mut socket_reader = BufferedSocketReader::new(&opt.socket_path_in, bip_writer_first)
-        .chain_err(|| "Error while creating socket reader")?;
-    let mut socket_writer = BufferedSocketWriter::start_listening(&opt.socket_path_out)
+    //The monitor applies the overflow policy when the filtering thread writes to the second bipbuffer.
+    let monitor = Arc::new(BufferMonitor::new(
+        opt.overflow_policy,
+        Duration::from_millis(opt.overflow_wait_ms),
+        bip_buffer_len,
+        stats.data.register_buffer("filtering_to_socket"),
+    ));
+
+    let mut socket_reader = LinkReader::new(
+        opt.link,
+        &opt.socket_path_in,
+        bip_writer_first,
+        bip_buffer_len,
+    )
+    .chain_err(|| "Error while creating socket reader")?;
+    let mut socket_writer = LinkWriter::start(opt.link, &opt.socket_path_out, bip_buffer_len)
         .chain_err(|| "Error creating socket writer")?;
+    socket_writer.set_buffer_monitor(monitor.clone());
+    socket_writer.set_in_flight_policy(opt.in_flight_policy);
+    socket_writer.set_stats_data(stats.get_data_clone());
+    socket_reader.set_stats_data(stats.get_data_clone());
 
     //3 threads:
     //- get_data_from_socket_send_to_bip_buffer
@@ -95,11 +118,15 @@ fn filter() -> Result<()> {
         .spawn(move || {
             let mut buffer = [0; BUFFER_SIZE_BYTES];
             loop {
-                let element_length = read_from_bip_buffer(&mut bip_reader_first, &mut buffer);
+                let element_length =
+                    read_fitting_from_bip_buffer(&mut bip_reader_first, &mut buffer, &stats_data)
+                        .chain_err(|| "Error in filtering thread")
+                        .chain_unwrap();
                 filtering(
                     &buffer,
                     element_length,
                     &mut bip_writer_second,
+                    &monitor,
                     &word_to_filter,
                     &stats_data,
                 );
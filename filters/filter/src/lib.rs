@@ -12,9 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bip_utils::write_to_bip_buffer;
-use ph_kafka::KafkaMessage;
-use spsc_bip_buffer::BipBufferWriter;
+use bip_utils::overflow::BufferMonitor;
+use bip_utils::BipBufferWriter;
+use envelope::Envelope;
+use socket_utils::frame::{frame_payload, MAX_FRAME_PREFIX_LEN};
 use std::sync::Arc;
 
 use std::str;
@@ -24,20 +25,31 @@ pub mod arguments;
 pub mod errors;
 
 ///The maximum size in bytes of a single bipbuffer message.
-//a bit more allocated then needed. 1_048_576(1 Mb) is needed + BIP_BUFFER_LEN_FIELD_LEN
-pub const BUFFER_SIZE_BYTES: usize = 1_050_000;
+//a bit more allocated then needed. 1_048_576(1 Mb) is needed + BIP_BUFFER_LEN_FIELD_LEN + the frame prefix
+pub const BUFFER_SIZE_BYTES: usize = 1_050_000 + MAX_FRAME_PREFIX_LEN;
 
-///Check for the first bytes of a kafka message. If it matches the word_to_filter then it drops the data. Else it is written to the bipbuffer.
+///Check for the first bytes of the payload of an envelope. If it matches the word_to_filter then it drops the data. Else it is written to the bipbuffer.
+///The element is a frame, the envelope is its payload. The envelope can come from a chain of any protocol. A forwarded frame is written unchanged so the metadata is kept.
 pub fn filtering(
     buffer: &[u8; BUFFER_SIZE_BYTES],
     element_length: usize,
     mut bip_writer_second: &mut BipBufferWriter,
+    monitor: &BufferMonitor,
     word_to_filter: &str,
     stats_data: &Arc<statistics_handler::StatsAllHandlers>,
 ) {
-    match KafkaMessage::deserialize_packet(&buffer[..element_length]) {
-        Ok(kafka_message) => {
-            let message = kafka_message.payload;
+    let payload = match frame_payload(&buffer[..element_length]) {
+        Ok(payload) => payload,
+        Err(e) => {
+            log::warn!("Cannot read the frame: {}", e);
+            stats_data.dropped_packets.add(1);
+            stats_data.dropped_bytes.add(element_length as u64);
+            return;
+        }
+    };
+    match Envelope::deserialize_packet(payload) {
+        Ok(envelope) => {
+            let message = envelope.payload;
             if message.len() >= word_to_filter.len() {
                 match str::from_utf8(&message[0..word_to_filter.len()]) {
                     Ok(first_x_bytes_tex) => {
@@ -46,24 +58,50 @@ pub fn filtering(
                             stats_data.dropped_packets.add(1);
                             stats_data.dropped_bytes.add(element_length as u64);
                         } else {
-                            write_to_bip_buffer(&mut bip_writer_second, &buffer[..element_length]);
+                            forward(
+                                &mut bip_writer_second,
+                                monitor,
+                                &buffer[..element_length],
+                                stats_data,
+                            );
                         }
                     }
                     //The the first x bytes of the incoming data is not utf8 then it cannot be checked and will be sent to the bipbuffer
-                    Err(_) => {
-                        write_to_bip_buffer(&mut bip_writer_second, &buffer[..element_length])
-                    }
+                    Err(_) => forward(
+                        &mut bip_writer_second,
+                        monitor,
+                        &buffer[..element_length],
+                        stats_data,
+                    ),
                 }
             //The data is smaller then the word_to_filter it will never match so it will be sent to the bipbuffer
             } else {
-                write_to_bip_buffer(&mut bip_writer_second, &buffer[..element_length])
+                forward(
+                    &mut bip_writer_second,
+                    monitor,
+                    &buffer[..element_length],
+                    stats_data,
+                )
             }
         }
-        //The data cannot be converted to a kafka message. It is problably corrupted data so it will be dropped.
+        //The data cannot be converted to an envelope. It is problably corrupted data so it will be dropped.
         Err(e) => {
-            log::warn!("Cannot read the kafka message: {}", e);
+            log::warn!("Cannot read the envelope: {}", e);
             stats_data.dropped_packets.add(1);
             stats_data.dropped_bytes.add(element_length as u64);
         }
     };
 }
+
+///Writes an element that passed the filter to the bipbuffer. Elements dropped by the overflow policy are counted.
+fn forward(
+    bip_writer_second: &mut BipBufferWriter,
+    monitor: &BufferMonitor,
+    element: &[u8],
+    stats_data: &Arc<statistics_handler::StatsAllHandlers>,
+) {
+    if !monitor.write(bip_writer_second, element) {
+        stats_data.dropped_packets.add(1);
+        stats_data.dropped_bytes.add(element.len() as u64);
+    }
+}
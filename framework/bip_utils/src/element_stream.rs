@@ -0,0 +1,219 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::ErrorKind::{ElementTooLarge, Shutdown};
+use crate::errors::*;
+use crate::wakeup::is_shut_down;
+use crate::{max_element_bytes, BipBufferReader, BipBufferWriter, DISCARDED_ELEMENT_FLAG};
+use framework_constants::*;
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+///Reads the elements of a bip_buffer as a Stream, for handlers that run on an async runtime.
+///The Stream ends when the bip_buffers of this process are shut down.
+pub struct ElementStream {
+    reader: BipBufferReader,
+}
+
+impl ElementStream {
+    ///Creates a new instance of ElementStream.
+    /// # Arguments
+    /// * `reader` - The BipBufferReader used to read the elements from the bip_buffer.
+    pub fn new(reader: BipBufferReader) -> ElementStream {
+        ElementStream { reader }
+    }
+
+    ///Reads the next element when it is complete in the bip_buffer.
+    fn next_element(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let valid = self.reader.valid();
+            if valid.len() < BIP_BUFFER_LEN_FIELD_LEN {
+                return None;
+            }
+            let mut length_bytes = [0; BIP_BUFFER_LEN_FIELD_LEN];
+            length_bytes.copy_from_slice(&valid[..BIP_BUFFER_LEN_FIELD_LEN]);
+            let length_field = usize::from_le_bytes(length_bytes);
+            let element_bytes = (length_field & !DISCARDED_ELEMENT_FLAG) + BIP_BUFFER_LEN_FIELD_LEN;
+            if valid.len() < element_bytes {
+                return None;
+            }
+            //elements the writer abandoned are skipped, see `try_write_in_place`.
+            if length_field & DISCARDED_ELEMENT_FLAG != 0 {
+                self.reader.consume(element_bytes);
+                continue;
+            }
+            let element = valid[BIP_BUFFER_LEN_FIELD_LEN..element_bytes].to_vec();
+            self.reader.consume(element_bytes);
+            return Some(element);
+        }
+    }
+}
+
+impl Stream for ElementStream {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        let stream = self.get_mut();
+        if let Some(element) = stream.next_element() {
+            return Poll::Ready(Some(element));
+        }
+        stream.reader.wakeup().register_data_waker(cx.waker());
+        //the element may have been sent before the waker was registered.
+        match stream.next_element() {
+            Some(element) => Poll::Ready(Some(element)),
+            None if is_shut_down() => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+///Writes elements to a bip_buffer as a Sink, for handlers that run on an async runtime.
+///The Sink holds at most one element, it is ready again when that element is written to the bip_buffer.
+pub struct ElementSink {
+    writer: BipBufferWriter,
+    capacity: usize,
+    pending: Option<Vec<u8>>,
+}
+
+impl ElementSink {
+    ///Creates a new instance of ElementSink.
+    /// # Arguments
+    /// * `writer` - The BipBufferWriter used to write the elements to the bip_buffer.
+    /// * `capacity` - The size of the bip_buffer in bytes, elements larger than `max_element_bytes` are refused.
+    pub fn new(writer: BipBufferWriter, capacity: usize) -> ElementSink {
+        ElementSink {
+            writer,
+            capacity,
+            pending: None,
+        }
+    }
+
+    ///Writes the pending element to the bip_buffer when there is space for it.
+    fn write_pending(&mut self) -> bool {
+        let element = match &self.pending {
+            Some(element) => element,
+            None => return true,
+        };
+        let element_bytes = element.len() + BIP_BUFFER_LEN_FIELD_LEN;
+        match self.writer.reserve(element_bytes) {
+            Some(mut reservation) => {
+                reservation[..BIP_BUFFER_LEN_FIELD_LEN]
+                    .copy_from_slice(&element.len().to_le_bytes());
+                reservation[BIP_BUFFER_LEN_FIELD_LEN..element_bytes].copy_from_slice(element);
+                reservation.send();
+            }
+            None => return false,
+        }
+        self.writer.wakeup().notify_data();
+        self.pending = None;
+        true
+    }
+}
+
+impl Sink<Vec<u8>> for ElementSink {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+
+    ///Refuses an element that can never fit in the bip_buffer with an ElementTooLarge error.
+    fn start_send(self: Pin<&mut Self>, element: Vec<u8>) -> Result<()> {
+        let sink = self.get_mut();
+        let element_bytes = element.len() + BIP_BUFFER_LEN_FIELD_LEN;
+        if element_bytes > max_element_bytes(sink.capacity) {
+            return Err(ElementTooLarge(element_bytes, sink.capacity).into());
+        }
+        sink.pending = Some(element);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let sink = self.get_mut();
+        if sink.write_pending() {
+            return Poll::Ready(Ok(()));
+        }
+        sink.writer.wakeup().register_space_waker(cx.waker());
+        //the space may have been freed before the waker was registered.
+        if sink.write_pending() {
+            Poll::Ready(Ok(()))
+        } else if is_shut_down() {
+            Poll::Ready(Err(Shutdown.into()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bip_buffer_with_len;
+    use crate::element_stream::*;
+    use std::future::{poll_fn, Future};
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::thread::Thread;
+
+    ///Wakes up the thread blocked on a future.
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    ///Runs a future to completion on the current thread.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    ///Is used to test that elements sent to a full ElementSink wait for the ElementStream to make space.
+    fn sink_to_stream_backpressure_test() {
+        let capacity = 100;
+        let (writer, reader) = bip_buffer_with_len(capacity);
+        let mut sink = ElementSink::new(writer, capacity);
+        let mut stream = ElementStream::new(reader);
+
+        let sender = std::thread::spawn(move || {
+            for i in 0..50u8 {
+                block_on(poll_fn(|cx| Pin::new(&mut sink).poll_ready(cx))).unwrap();
+                Pin::new(&mut sink).start_send(vec![i; 30]).unwrap();
+            }
+            block_on(poll_fn(|cx| Pin::new(&mut sink).poll_flush(cx))).unwrap();
+            //an element that never fits is refused
+            assert!(Pin::new(&mut sink).start_send(vec![0; 100]).is_err());
+        });
+        for i in 0..50u8 {
+            let element = block_on(poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)));
+            assert_eq!(element, Some(vec![i; 30]));
+        }
+        sender.join().unwrap();
+    }
+}
@@ -0,0 +1,37 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![allow(clippy::large_enum_variant)]
+use error_chain::*;
+
+error_chain! {
+    types {
+        Error, ErrorKind, ResultExt, Result;
+    }
+
+    errors {
+        ElementTooLarge(element_bytes: usize, capacity: usize) {
+            description("Element too large for bip_buffer")
+            display("Element of {} bytes is larger than the {} bytes that always fit in bip_buffer of {} bytes", element_bytes, capacity / 2, capacity)
+        }
+        BufferTooSmall(element_length: usize, buffer_length: usize) {
+            description("Buffer too small for element")
+            display("Element of {} bytes does not fit in buffer of {} bytes", element_length, buffer_length)
+        }
+        Shutdown {
+            description("bip_buffer shut down")
+            display("bip_buffers have been shut down")
+        }
+    }
+}
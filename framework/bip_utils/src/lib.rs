@@ -12,21 +12,288 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::errors::ErrorKind::{BufferTooSmall, ElementTooLarge, Shutdown};
+use crate::errors::*;
+use crate::wakeup::{is_shut_down, Wakeup};
 use framework_constants::*;
-use spsc_bip_buffer::BipBufferReader;
-use spsc_bip_buffer::BipBufferWriter;
+use statistics_handler::StatsAllHandlers;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+///Error chain for the checked reads and writes of a bip_buffer.
+pub mod errors;
+
+///This module contains the Stream and Sink of elements in a bip_buffer, for handlers on an async runtime.
+pub mod element_stream;
+
+///This module contains the OverflowPolicy and the BufferMonitor used to apply it.
+pub mod overflow;
+
+///This module contains the WaitStrategy and the wakeups between the writer and reader of a bip_buffer.
+pub mod wakeup;
+
+///The sending end of a bip_buffer.
+///It derefs to the BipBufferWriter of spsc_bip_buffer and shares the Wakeup of its bip_buffer with the BipBufferReader.
+pub struct BipBufferWriter {
+    writer: spsc_bip_buffer::BipBufferWriter,
+    wakeup: Arc<Wakeup>,
+}
+
+///The receiving end of a bip_buffer.
+///It derefs to the BipBufferReader of spsc_bip_buffer and shares the Wakeup of its bip_buffer with the BipBufferWriter.
+pub struct BipBufferReader {
+    reader: spsc_bip_buffer::BipBufferReader,
+    wakeup: Arc<Wakeup>,
+}
+
+///Creates a new bip_buffer with its own Wakeup.
+/// # Arguments
+/// * `len` - The size of the bip_buffer in bytes.
+/// # Returns
+/// * `(BipBufferWriter, BipBufferReader)` - The sending and the receiving end of the bip_buffer.
+pub fn bip_buffer_with_len(len: usize) -> (BipBufferWriter, BipBufferReader) {
+    let (writer, reader) = spsc_bip_buffer::bip_buffer_with_len(len);
+    let wakeup = Wakeup::new();
+    (
+        BipBufferWriter {
+            writer,
+            wakeup: wakeup.clone(),
+        },
+        BipBufferReader { reader, wakeup },
+    )
+}
+
+impl BipBufferWriter {
+    ///Returns the Wakeup of this bip_buffer.
+    ///`notify_data` should be called after sending a reservation taken with `reserve`.
+    pub fn wakeup(&self) -> &Arc<Wakeup> {
+        &self.wakeup
+    }
+}
+
+impl Deref for BipBufferWriter {
+    type Target = spsc_bip_buffer::BipBufferWriter;
+
+    fn deref(&self) -> &spsc_bip_buffer::BipBufferWriter {
+        &self.writer
+    }
+}
+
+impl DerefMut for BipBufferWriter {
+    fn deref_mut(&mut self) -> &mut spsc_bip_buffer::BipBufferWriter {
+        &mut self.writer
+    }
+}
+
+impl BipBufferReader {
+    ///Returns the Wakeup of this bip_buffer.
+    pub fn wakeup(&self) -> &Arc<Wakeup> {
+        &self.wakeup
+    }
+
+    ///Consumes the first `len` bytes in `valid` and wakes up the writer when it is waiting for space.
+    /// # Arguments
+    /// * `len` - The amount of bytes to consume.
+    /// # Returns
+    /// * `bool` - False when there were less than `len` bytes to consume.
+    pub fn consume(&mut self, len: usize) -> bool {
+        let consumed = self.reader.consume(len);
+        self.wakeup.notify_space();
+        consumed
+    }
+
+    ///Returns the spsc_bip_buffer reader and the Wakeup, to wait on the Wakeup until data is valid.
+    fn parts(&mut self) -> (&mut spsc_bip_buffer::BipBufferReader, &Wakeup) {
+        (&mut self.reader, &self.wakeup)
+    }
+}
+
+impl Deref for BipBufferReader {
+    type Target = spsc_bip_buffer::BipBufferReader;
+
+    fn deref(&self) -> &spsc_bip_buffer::BipBufferReader {
+        &self.reader
+    }
+}
+
+impl DerefMut for BipBufferReader {
+    fn deref_mut(&mut self) -> &mut spsc_bip_buffer::BipBufferReader {
+        &mut self.reader
+    }
+}
+
+///Set in the length field of an element that the writer abandoned, the reader skips the bytes that follow it.
+///Only `try_write_in_place` writes such elements, `get_element_length` and `try_read_from_bip_buffer` skip them.
+pub const DISCARDED_ELEMENT_FLAG: usize = 1 << (usize::BITS - 1);
+
+///Returns the size in bytes of the largest element, including its length field, that always fits in a bip_buffer.
+///A reservation in a bip_buffer is contiguous, so depending on where the reader and the writer are
+///an element larger than half of the bip_buffer may never fit, even when the bip_buffer is empty.
+/// # Arguments
+/// * `capacity` - The size of the bip_buffer in bytes.
+pub fn max_element_bytes(capacity: usize) -> usize {
+    capacity / 2
+}
+
+///Returns the size in bytes of a bip_buffer for `element_count` elements of `element_size` bytes.
+///The bip_buffer is twice as large, so an element of `element_size` bytes always fits, see `max_element_bytes`.
+/// # Arguments
+/// * `element_count` - The amount of elements that can be buffered.
+/// * `element_size` - The size of the largest element in bytes, including its length field.
+pub fn bip_buffer_len_for(element_count: usize, element_size: usize) -> usize {
+    2 * element_count * element_size
+}
 
 ///This function is used to write to the bip_buffer using the supplied writer.
+///The element is dropped when the bip_buffers are shut down while waiting for space.
 /// # Arguments
 /// * `writer` - The bipBufferWriter used to write to the bip_buffer.
 /// * `buffer` - The buffer that should be written to the bip_buffer.
 pub fn write_to_bip_buffer(writer: &mut BipBufferWriter, buffer: &[u8]) {
-    let mut reservation = writer.spin_reserve(buffer.len() + BIP_BUFFER_LEN_FIELD_LEN);
-    let byte_len = buffer.len().to_le_bytes();
-    reservation[0..BIP_BUFFER_LEN_FIELD_LEN].copy_from_slice(&byte_len);
-    reservation[BIP_BUFFER_LEN_FIELD_LEN..buffer.len() + BIP_BUFFER_LEN_FIELD_LEN]
-        .copy_from_slice(&buffer);
-    reservation.send();
+    let _ = write_with(writer, buffer.len(), None, |element| {
+        element.copy_from_slice(buffer)
+    });
+}
+
+///Writes `buffer` as a single element to the bip_buffer, like `write_to_bip_buffer`.
+///Instead of waiting forever, an error is returned when the element can never fit in the bip_buffer
+///or when the bip_buffers are shut down while waiting for space.
+/// # Arguments
+/// * `writer` - The bipBufferWriter used to write to the bip_buffer.
+/// * `buffer` - The buffer that should be written to the bip_buffer.
+/// * `capacity` - The size of the bip_buffer in bytes.
+pub fn try_write_to_bip_buffer(
+    writer: &mut BipBufferWriter,
+    buffer: &[u8],
+    capacity: usize,
+) -> Result<()> {
+    try_write_with(writer, buffer.len(), capacity, None, |element| {
+        element.copy_from_slice(buffer)
+    })
+    .map(|_| ())
+}
+
+///Writes an element of `element_length` bytes to the bip_buffer, waiting at most `timeout` for space.
+///`fill` writes the element into its reservation, the length field is written before it.
+///The element is only reserved once it fits, because a reservation is sent to the reader when it is dropped.
+/// # Arguments
+/// * `writer` - The bipBufferWriter used to write to the bip_buffer.
+/// * `element_length` - The length of the element in bytes, without the length field.
+/// * `capacity` - The size of the bip_buffer in bytes.
+/// * `timeout` - The maximum time to wait for space, None waits until there is space.
+/// * `fill` - Writes the element into the reserved slice of `element_length` bytes.
+/// # Returns
+/// * `Result<bool>` - False when the element did not fit within the timeout. An ElementTooLarge error when
+///   the element is larger than `max_element_bytes`, a Shutdown error when the bip_buffers were shut down while waiting.
+pub fn try_write_with<F: FnOnce(&mut [u8])>(
+    writer: &mut BipBufferWriter,
+    element_length: usize,
+    capacity: usize,
+    timeout: Option<Duration>,
+    fill: F,
+) -> Result<bool> {
+    let element_bytes = element_length + BIP_BUFFER_LEN_FIELD_LEN;
+    if element_bytes > max_element_bytes(capacity) {
+        return Err(ElementTooLarge(element_bytes, capacity).into());
+    }
+    write_with(writer, element_length, timeout, fill)
+}
+
+///Writes an element of at most `max_element_length` bytes straight into its reservation in the bip_buffer,
+///waiting at most `timeout` for space. This saves a copy when the element is received in parts.
+///`fill` writes the element at the start of the slice and returns its length, or None to abandon the element.
+///A reservation can not be shortened or taken back once it is taken, so the part of the reservation that is not used
+///is sent as an element with the DISCARDED_ELEMENT_FLAG, which the reader skips.
+///The reservation holds room for the length field of that element, `max_element_length + 2 * BIP_BUFFER_LEN_FIELD_LEN` bytes.
+/// # Arguments
+/// * `writer` - The bipBufferWriter used to write to the bip_buffer.
+/// * `max_element_length` - The largest length of the element in bytes, without the length field.
+/// * `capacity` - The size of the bip_buffer in bytes.
+/// * `timeout` - The maximum time to wait for space, None waits until there is space.
+/// * `fill` - Writes the element into the reserved slice of `max_element_length` bytes.
+/// # Returns
+/// * `Result<bool>` - False when there was no space within the timeout and `fill` was not called, errors like `try_write_with`.
+pub fn try_write_in_place<F: FnOnce(&mut [u8]) -> Option<usize>>(
+    writer: &mut BipBufferWriter,
+    max_element_length: usize,
+    capacity: usize,
+    timeout: Option<Duration>,
+    fill: F,
+) -> Result<bool> {
+    let reserved_bytes = max_element_length + 2 * BIP_BUFFER_LEN_FIELD_LEN;
+    if reserved_bytes > max_element_bytes(capacity) {
+        return Err(ElementTooLarge(reserved_bytes, capacity).into());
+    }
+    reserve_with(writer, reserved_bytes, timeout, |reservation| {
+        let element_end = BIP_BUFFER_LEN_FIELD_LEN + max_element_length;
+        let length_field = match fill(&mut reservation[BIP_BUFFER_LEN_FIELD_LEN..element_end])
+            .filter(|element_length| *element_length <= max_element_length)
+        {
+            Some(element_length) => {
+                let unused_bytes = max_element_length - element_length;
+                let unused_start = BIP_BUFFER_LEN_FIELD_LEN + element_length;
+                reservation[unused_start..unused_start + BIP_BUFFER_LEN_FIELD_LEN]
+                    .copy_from_slice(&(unused_bytes | DISCARDED_ELEMENT_FLAG).to_le_bytes());
+                element_length
+            }
+            None => (reserved_bytes - BIP_BUFFER_LEN_FIELD_LEN) | DISCARDED_ELEMENT_FLAG,
+        };
+        reservation[..BIP_BUFFER_LEN_FIELD_LEN].copy_from_slice(&length_field.to_le_bytes());
+    })
+}
+
+///Writes an element with `fill` like `try_write_with`, without checking whether it can ever fit.
+fn write_with<F: FnOnce(&mut [u8])>(
+    writer: &mut BipBufferWriter,
+    element_length: usize,
+    timeout: Option<Duration>,
+    fill: F,
+) -> Result<bool> {
+    let element_bytes = element_length + BIP_BUFFER_LEN_FIELD_LEN;
+    reserve_with(writer, element_bytes, timeout, |reservation| {
+        reservation[..BIP_BUFFER_LEN_FIELD_LEN].copy_from_slice(&element_length.to_le_bytes());
+        fill(&mut reservation[BIP_BUFFER_LEN_FIELD_LEN..]);
+    })
+}
+
+///Reserves `reserved_bytes` in the bip_buffer, waiting at most `timeout` for space.
+///`fill` writes the length fields and the elements into the whole reservation before it is sent.
+fn reserve_with<F: FnOnce(&mut [u8])>(
+    writer: &mut BipBufferWriter,
+    reserved_bytes: usize,
+    timeout: Option<Duration>,
+    fill: F,
+) -> Result<bool> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let BipBufferWriter { writer, wakeup } = writer;
+    loop {
+        //the epoch is read before reserving, so space that is freed after a failed reservation is not missed.
+        let space_epoch = wakeup.space_epoch();
+        if let Some(mut reservation) = writer.reserve(reserved_bytes) {
+            fill(&mut reservation);
+            reservation.send();
+            wakeup.notify_data();
+            return Ok(true);
+        }
+        if is_shut_down() {
+            return Err(Shutdown.into());
+        }
+        let timeout = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(false);
+                }
+                Some(deadline - now)
+            }
+            None => None,
+        };
+        wakeup.wait_for_space_until(
+            || is_shut_down() || wakeup.space_epoch() != space_epoch,
+            timeout,
+        );
+    }
 }
 
 ///This function is used to read from the bip_buffer using the supplied reader.
@@ -47,47 +314,147 @@ pub fn read_from_bip_buffer(reader: &mut BipBufferReader, buffer: &mut [u8]) ->
     element_length
 }
 
+///Reads an element from the bip_buffer, like `read_from_bip_buffer`.
+///An element that is larger than `buffer` is consumed from the bip_buffer and returned as a BufferTooSmall error,
+///so the reader can count it and continue with the next element.
+/// # Arguments
+/// * `reader` - The bipBufferReader used to read from the bip_buffer.
+/// * `buffer` - The buffer to be filled with data from the bip_buffer.
+/// # Returns
+/// * `usize` - The amount of bytes read from the bip_buffer.
+pub fn try_read_from_bip_buffer(reader: &mut BipBufferReader, buffer: &mut [u8]) -> Result<usize> {
+    let element_length = loop {
+        try_wait_for_data(reader, BIP_BUFFER_LEN_FIELD_LEN)?;
+        let length_field = consume_length_field(reader);
+        if length_field & DISCARDED_ELEMENT_FLAG == 0 {
+            break length_field;
+        }
+        let discarded_bytes = length_field & !DISCARDED_ELEMENT_FLAG;
+        try_wait_for_data(reader, discarded_bytes)?;
+        reader.consume(discarded_bytes);
+    };
+    try_wait_for_data(reader, element_length)?;
+    if element_length > buffer.len() {
+        reader.consume(element_length);
+        return Err(BufferTooSmall(element_length, buffer.len()).into());
+    }
+    buffer[..element_length].copy_from_slice(&reader.valid()[..element_length]);
+    reader.consume(element_length);
+    Ok(element_length)
+}
+
+///Reads the next element that fits in `buffer` from the bip_buffer, like `try_read_from_bip_buffer`.
+///Elements that do not fit are dropped and counted in the statistics.
+/// # Arguments
+/// * `reader` - The bipBufferReader used to read from the bip_buffer.
+/// * `buffer` - The buffer to be filled with data from the bip_buffer.
+/// * `stats_data` - The statistics the dropped elements are counted in.
+/// # Returns
+/// * `usize` - The amount of bytes read from the bip_buffer.
+pub fn read_fitting_from_bip_buffer(
+    reader: &mut BipBufferReader,
+    buffer: &mut [u8],
+    stats_data: &StatsAllHandlers,
+) -> Result<usize> {
+    loop {
+        match try_read_from_bip_buffer(reader, buffer) {
+            Err(Error(BufferTooSmall(element_length, buffer_length), _)) => {
+                log::warn!(
+                    "Dropped element of {} bytes, it does not fit in a buffer of {} bytes",
+                    element_length,
+                    buffer_length
+                );
+                stats_data.oversized_packets.add(1);
+                stats_data.dropped_packets.add(1);
+                stats_data.dropped_bytes.add(element_length as u64);
+            }
+            result => return result,
+        }
+    }
+}
+
 ///Function used to get the element length field from the bip_buffer.
 ///The element length is consumed from the buffer by calling this function.
+///Elements the writer abandoned are consumed and skipped, see `try_write_in_place`.
 /// # Arguments
 /// * `reader` - The bipBufferReader used to read the element length.
 /// # Returns
 /// * `usize` - The length of the read element in bytes.
 pub fn get_element_length(reader: &mut BipBufferReader) -> usize {
-    wait_for_data(reader, BIP_BUFFER_LEN_FIELD_LEN);
-    let length_buffer = &mut reader.valid()[..BIP_BUFFER_LEN_FIELD_LEN];
+    loop {
+        wait_for_data(reader, BIP_BUFFER_LEN_FIELD_LEN);
+        let length_field = consume_length_field(reader);
+        if length_field & DISCARDED_ELEMENT_FLAG == 0 {
+            return length_field;
+        }
+        let discarded_bytes = length_field & !DISCARDED_ELEMENT_FLAG;
+        wait_for_data(reader, discarded_bytes);
+        reader.consume(discarded_bytes);
+    }
+}
+
+///Reads the length field at the start of the valid data and consumes it, the field must be available.
+fn consume_length_field(reader: &mut BipBufferReader) -> usize {
     let mut length_bytes_fixed = [0; BIP_BUFFER_LEN_FIELD_LEN];
-    length_bytes_fixed.copy_from_slice(&length_buffer[0..BIP_BUFFER_LEN_FIELD_LEN]);
-    let element_length = usize::from_le_bytes(length_bytes_fixed);
+    length_bytes_fixed.copy_from_slice(&reader.valid()[..BIP_BUFFER_LEN_FIELD_LEN]);
     reader.consume(BIP_BUFFER_LEN_FIELD_LEN);
-    element_length
+    usize::from_le_bytes(length_bytes_fixed)
 }
 
 ///Wait for the given amount of bytes to be available for reading in the bip_buffer.
+///The thread waits according to the WaitStrategy set with `wakeup::set_wait_strategy`.
 /// # Arguments
 /// * `reader` - The bipBufferReader used to read from the bip_buffer.
 /// * `bytes` - The amount of available bytes to wait for
 pub fn wait_for_data(reader: &mut BipBufferReader, bytes: usize) {
-    let mut spin_count = 0;
-    while reader.valid().len() < (bytes) {
-        if spin_count < 100_000 {
-            std::sync::atomic::spin_loop_hint();
-            spin_count += 1;
-        } else {
-            std::thread::sleep(std::time::Duration::from_millis(100));
-            spin_count = 0;
-        }
+    let (reader, wakeup) = reader.parts();
+    wakeup.wait_for_data_until(|| reader.valid().len() >= bytes, None);
+}
+
+///Wait at most `timeout` for the given amount of bytes to be available for reading in the bip_buffer.
+/// # Arguments
+/// * `reader` - The bipBufferReader used to read from the bip_buffer.
+/// * `bytes` - The amount of available bytes to wait for
+/// * `timeout` - The maximum time to wait.
+/// # Returns
+/// * `bool` - True when the bytes are available, false when the timeout passed.
+pub fn wait_for_data_timeout(
+    reader: &mut BipBufferReader,
+    bytes: usize,
+    timeout: Duration,
+) -> bool {
+    let (reader, wakeup) = reader.parts();
+    wakeup.wait_for_data_until(|| reader.valid().len() >= bytes, Some(timeout))
+}
+
+///Wait for the given amount of bytes to be available for reading in the bip_buffer, like `wait_for_data`.
+/// # Arguments
+/// * `reader` - The bipBufferReader used to read from the bip_buffer.
+/// * `bytes` - The amount of available bytes to wait for
+/// # Returns
+/// * `Result<()>` - A Shutdown error when the bip_buffers were shut down while waiting.
+pub fn try_wait_for_data(reader: &mut BipBufferReader, bytes: usize) -> Result<()> {
+    let (reader, wakeup) = reader.parts();
+    wakeup.wait_for_data_until(|| is_shut_down() || reader.valid().len() >= bytes, None);
+    if reader.valid().len() >= bytes {
+        Ok(())
+    } else {
+        Err(Shutdown.into())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::errors::ErrorKind;
     use crate::get_element_length;
+    use crate::try_read_from_bip_buffer;
+    use crate::try_write_in_place;
+    use crate::try_write_to_bip_buffer;
     use crate::wait_for_data;
     use crate::write_to_bip_buffer;
+    use crate::{bip_buffer_len_for, max_element_bytes};
+    use crate::{bip_buffer_with_len, BipBufferReader};
     use framework_constants::*;
-    use spsc_bip_buffer::bip_buffer_with_len;
-    use spsc_bip_buffer::BipBufferReader;
     #[test]
     ///Is used to test reading and writing of multiple buffers to a bip_buffer.
     fn write_read_bip_buffer() {
@@ -99,6 +466,109 @@ mod tests {
         }
     }
 
+    #[test]
+    ///Is used to test that an element just under the capacity is refused, it may never be reserved in one piece.
+    fn element_just_under_capacity_bip_buffer() {
+        let (mut writer, mut reader) = bip_buffer_with_len(100);
+        match try_write_to_bip_buffer(&mut writer, &[1; 80], 100) {
+            Err(e) => match e.kind() {
+                ErrorKind::ElementTooLarge(88, 100) => (),
+                kind => panic!("Unexpected error {}", kind),
+            },
+            Ok(()) => panic!("An element of 88 bytes was accepted by a bip_buffer of 100 bytes"),
+        }
+
+        //the largest element that is accepted always fits, wherever the reader and the writer are.
+        let mut read_buffer = [0; 42];
+        for offset in 1..10 {
+            try_write_to_bip_buffer(&mut writer, &vec![3; offset], 100)
+                .expect("Can't write element");
+            try_read_from_bip_buffer(&mut reader, &mut read_buffer).expect("Can't read element");
+            try_write_to_bip_buffer(&mut writer, &[4; 42], 100).expect("Can't write element");
+            try_read_from_bip_buffer(&mut reader, &mut read_buffer).expect("Can't read element");
+            assert_eq!(read_buffer, [4; 42]);
+        }
+    }
+
+    #[test]
+    ///Is used to test that a bip_buffer sized for a single element can always hold that element.
+    fn bip_buffer_len_for_single_element() {
+        let bip_buffer_len = bip_buffer_len_for(1, 100);
+        assert_eq!(max_element_bytes(bip_buffer_len), 100);
+        let (mut writer, mut reader) = bip_buffer_with_len(bip_buffer_len);
+        let mut read_buffer = [0; 92];
+        for _ in 0..3 {
+            try_write_to_bip_buffer(&mut writer, &[5; 92], bip_buffer_len)
+                .expect("Can't write element");
+            try_read_from_bip_buffer(&mut reader, &mut read_buffer).expect("Can't read element");
+        }
+    }
+
+    #[test]
+    ///Is used to test that oversized elements are refused instead of waiting forever.
+    fn oversized_element_bip_buffer() {
+        let (mut writer, mut reader) = bip_buffer_with_len(100);
+        match try_write_to_bip_buffer(&mut writer, &[1; 200], 100) {
+            Err(e) => match e.kind() {
+                ErrorKind::ElementTooLarge(208, 100) => (),
+                kind => panic!("Unexpected error {}", kind),
+            },
+            Ok(()) => panic!("An element of 200 bytes was written to a bip_buffer of 100 bytes"),
+        }
+
+        //an element that does not fit in the read buffer is skipped.
+        try_write_to_bip_buffer(&mut writer, &[1; 40], 100).expect("Can't write element");
+        try_write_to_bip_buffer(&mut writer, &[2; 10], 100).expect("Can't write element");
+        let mut read_buffer = [0; 20];
+        match try_read_from_bip_buffer(&mut reader, &mut read_buffer) {
+            Err(e) => match e.kind() {
+                ErrorKind::BufferTooSmall(40, 20) => (),
+                kind => panic!("Unexpected error {}", kind),
+            },
+            Ok(_) => panic!("An element of 40 bytes was read into a buffer of 20 bytes"),
+        }
+        assert_eq!(
+            try_read_from_bip_buffer(&mut reader, &mut read_buffer).expect("Can't read element"),
+            10
+        );
+        assert_eq!(&read_buffer[..10], &[2; 10]);
+    }
+
+    #[test]
+    ///Is used to test that the unused part of an element written in place and abandoned elements are skipped.
+    fn write_in_place_bip_buffer() {
+        let (mut writer, mut reader) = bip_buffer_with_len(200);
+        let written = try_write_in_place(&mut writer, 50, 200, None, |element| {
+            element[..10].copy_from_slice(&[1; 10]);
+            Some(10)
+        });
+        assert!(written.expect("Can't write element"));
+        let abandoned = try_write_in_place(&mut writer, 50, 200, None, |element| {
+            element[..10].copy_from_slice(&[2; 10]);
+            None
+        });
+        assert!(abandoned.expect("Can't write element"));
+        try_write_to_bip_buffer(&mut writer, &[3; 5], 200).expect("Can't write element");
+
+        let mut read_buffer = [0; 50];
+        assert_eq!(
+            try_read_from_bip_buffer(&mut reader, &mut read_buffer).expect("Can't read element"),
+            10
+        );
+        assert_eq!(&read_buffer[..10], &[1; 10]);
+        assert_on_byte_array(&mut reader, &[3; 5]);
+        assert_eq!(reader.valid().len(), 0);
+
+        //the reservation, with room for the length field of the unused part, must always fit.
+        match try_write_in_place(&mut writer, 90, 200, None, |_| None) {
+            Err(e) => match e.kind() {
+                ErrorKind::ElementTooLarge(106, 200) => (),
+                kind => panic!("Unexpected error {}", kind),
+            },
+            Ok(_) => panic!("A reservation of 106 bytes was accepted by a bip_buffer of 200 bytes"),
+        }
+    }
+
     ///asserts if the given buffer equals the buffer read from the bip_buffer.
     fn assert_on_byte_array(receiver_reader: &mut BipBufferReader, send_buffer: &[u8]) {
         let element_length = get_element_length(receiver_reader);
@@ -0,0 +1,396 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::Error;
+use crate::errors::ErrorKind::ElementTooLarge;
+use crate::get_element_length;
+use crate::max_element_bytes;
+use crate::try_write_in_place;
+use crate::try_write_with;
+use crate::wait_for_data;
+use crate::BipBufferReader;
+use crate::BipBufferWriter;
+use framework_constants::BIP_BUFFER_LEN_FIELD_LEN;
+use statistics_handler::BufferStats;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+///The policy used when an element does not fit in the bip_buffer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OverflowPolicy {
+    ///Wait until the reader has made space for the element.
+    Block,
+    ///Drop the element that does not fit.
+    DropNewest,
+    ///Ask the reader to discard the oldest elements until the element fits.
+    ///When the reader has not made space within the wait time of the BufferMonitor, the element is dropped.
+    DropOldest,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<OverflowPolicy, String> {
+        match policy {
+            "block" => Ok(OverflowPolicy::Block),
+            "drop_newest" => Ok(OverflowPolicy::DropNewest),
+            "drop_oldest" => Ok(OverflowPolicy::DropOldest),
+            _ => Err(format!(
+                "Unknown overflow policy {}, expected block, drop_newest or drop_oldest",
+                policy
+            )),
+        }
+    }
+}
+
+///The BufferMonitor is shared by the writer and the reader of a single bip_buffer.
+///The writer uses it to apply the OverflowPolicy, the reader uses it to discard the oldest elements when asked.
+///Both sides keep track of the bytes in use, which is published as the fill level of the buffer.
+pub struct BufferMonitor {
+    policy: OverflowPolicy,
+    max_wait: Duration,
+    capacity: usize,
+    bytes_in_use: AtomicUsize,
+    discard_requests: AtomicUsize,
+    stats: Arc<BufferStats>,
+}
+
+impl BufferMonitor {
+    ///Creates a new BufferMonitor.
+    /// # Arguments
+    /// * `policy` - The OverflowPolicy used when an element does not fit in the bip_buffer.
+    /// * `max_wait` - The maximum time the DropOldest policy waits for the reader to make space.
+    /// * `capacity` - The size of the monitored bip_buffer in bytes.
+    /// * `stats` - The statistics of the monitored bip_buffer.
+    pub fn new(
+        policy: OverflowPolicy,
+        max_wait: Duration,
+        capacity: usize,
+        stats: Arc<BufferStats>,
+    ) -> BufferMonitor {
+        BufferMonitor {
+            policy,
+            max_wait,
+            capacity,
+            bytes_in_use: AtomicUsize::new(0),
+            discard_requests: AtomicUsize::new(0),
+            stats,
+        }
+    }
+
+    ///Writes `buffer` as a single element to the bip_buffer, applying the OverflowPolicy.
+    ///An element larger than `max_element_bytes` may never fit and is always dropped.
+    /// # Arguments
+    /// * `writer` - The BipBufferWriter used to write to the bip_buffer.
+    /// * `buffer` - The buffer that should be written to the bip_buffer.
+    /// # Returns
+    /// * `bool` - True when the element was written, false when it was dropped.
+    pub fn write(&self, writer: &mut BipBufferWriter, buffer: &[u8]) -> bool {
+        let fill = |element: &mut [u8]| element.copy_from_slice(buffer);
+        let written = self.apply_policy(writer, |writer, timeout| {
+            try_write_with(writer, buffer.len(), self.capacity, timeout, fill)
+        });
+        if written {
+            self.written(buffer.len());
+        } else {
+            self.dropped(buffer.len());
+        }
+        written
+    }
+
+    ///Writes an element of at most `max_element_length` bytes straight into its reservation, applying the OverflowPolicy.
+    ///`fill` writes the element and returns its length, or None to abandon it, see `try_write_in_place`.
+    ///An element that does not fit is not counted as dropped, its length is only known to the caller.
+    /// # Arguments
+    /// * `writer` - The BipBufferWriter used to write to the bip_buffer.
+    /// * `max_element_length` - The largest length of the element in bytes.
+    /// * `fill` - Writes the element into the reserved slice of `max_element_length` bytes.
+    /// # Returns
+    /// * `bool` - True when `fill` was given the reservation, false when there was no space for the element.
+    pub fn write_in_place<F: FnOnce(&mut [u8]) -> Option<usize>>(
+        &self,
+        writer: &mut BipBufferWriter,
+        max_element_length: usize,
+        fill: F,
+    ) -> bool {
+        let mut fill = Some(fill);
+        let mut element_length = None;
+        let reserved = self.apply_policy(writer, |writer, timeout| {
+            try_write_in_place(
+                writer,
+                max_element_length,
+                self.capacity,
+                timeout,
+                |element| {
+                    element_length = fill.take().and_then(|fill| fill(element));
+                    element_length
+                },
+            )
+        });
+        if let Some(element_length) = element_length {
+            self.written(element_length);
+        }
+        reserved
+    }
+
+    ///Calls `try_write` with the time the OverflowPolicy waits for space, or until the DropOldest policy gives up.
+    ///`try_write` returns false when the element did not fit within the given time.
+    fn apply_policy<
+        F: FnMut(&mut BipBufferWriter, Option<Duration>) -> crate::errors::Result<bool>,
+    >(
+        &self,
+        writer: &mut BipBufferWriter,
+        mut try_write: F,
+    ) -> bool {
+        let written = match self.policy {
+            //a blocked writer gives up when the bip_buffers are shut down.
+            OverflowPolicy::Block => try_write(writer, None),
+            OverflowPolicy::DropNewest => try_write(writer, Some(Duration::from_millis(0))),
+            OverflowPolicy::DropOldest => self.write_dropping_oldest(writer, |writer| {
+                try_write(writer, Some(Duration::from_millis(0)))
+            }),
+        };
+        match written {
+            Ok(written) => written,
+            Err(e) => {
+                if let Error(ElementTooLarge(element_bytes, capacity), _) = e {
+                    log::error!(
+                        "Element of {} bytes is larger than the {} bytes that always fit in bip_buffer {} of {} bytes",
+                        element_bytes,
+                        max_element_bytes(capacity),
+                        self.stats.name,
+                        capacity
+                    );
+                }
+                false
+            }
+        }
+    }
+
+    ///Reads the length of the next element from the bip_buffer, like `get_element_length`.
+    ///Elements the writer asked to discard are consumed and counted as dropped.
+    /// # Arguments
+    /// * `reader` - The bipBufferReader used to read the element length.
+    /// # Returns
+    /// * `usize` - The length of the read element in bytes.
+    pub fn get_element_length(&self, reader: &mut BipBufferReader) -> usize {
+        loop {
+            let element_length = get_element_length(reader);
+            if self
+                .discard_requests
+                .compare_exchange(1, 0, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+            {
+                return element_length;
+            }
+            wait_for_data(reader, element_length);
+            reader.consume(element_length);
+            self.consumed(element_length);
+            self.dropped(element_length);
+            log::warn!(
+                "Discarded oldest element from bip_buffer {}",
+                self.stats.name
+            );
+        }
+    }
+
+    ///Reads an element from the bip_buffer, like `read_from_bip_buffer`.
+    /// # Arguments
+    /// * `reader` - The bipBufferReader used to read from the bip_buffer.
+    /// * `buffer` - The buffer to be filled with data from the bip_buffer.
+    /// # Returns
+    /// * `usize` - The amount of bytes read from the bip_buffer.
+    pub fn read(&self, reader: &mut BipBufferReader, buffer: &mut [u8]) -> usize {
+        let element_length = self.get_element_length(reader);
+        wait_for_data(reader, element_length);
+        buffer[..element_length].copy_from_slice(&reader.valid()[..element_length]);
+        reader.consume(element_length);
+        self.consumed(element_length);
+        element_length
+    }
+
+    ///Returns the length of the largest element that is written to the monitored bip_buffer, see `max_element_bytes`.
+    pub fn max_element_length(&self) -> usize {
+        max_element_bytes(self.capacity).saturating_sub(BIP_BUFFER_LEN_FIELD_LEN)
+    }
+
+    ///Marks an element of `element_length` bytes as written to the bip_buffer.
+    pub fn written(&self, element_length: usize) {
+        let element_bytes = element_length + BIP_BUFFER_LEN_FIELD_LEN;
+        let bytes_in_use = self.bytes_in_use.fetch_add(element_bytes, Ordering::SeqCst);
+        self.update_fill_level(bytes_in_use + element_bytes);
+    }
+
+    ///Marks an element of `element_length` bytes as consumed from the bip_buffer.
+    ///The count of bytes in use does not wrap when an element was not counted as written.
+    pub fn consumed(&self, element_length: usize) {
+        let element_bytes = element_length + BIP_BUFFER_LEN_FIELD_LEN;
+        let bytes_in_use = self
+            .bytes_in_use
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bytes_in_use| {
+                Some(bytes_in_use.saturating_sub(element_bytes))
+            })
+            .unwrap_or_else(|bytes_in_use| bytes_in_use);
+        self.update_fill_level(bytes_in_use.saturating_sub(element_bytes));
+    }
+
+    ///Counts an element of `element_length` bytes as dropped in the statistics of the bip_buffer.
+    pub fn dropped(&self, element_length: usize) {
+        self.stats.dropped_bytes.add(element_length as u64);
+        self.stats.dropped_packets.add(1);
+    }
+
+    ///Writes an element with `try_write` when it fits within `max_wait`.
+    ///While waiting, the reader is asked to discard the oldest element each time it handled the previous request.
+    fn write_dropping_oldest<F: FnMut(&mut BipBufferWriter) -> crate::errors::Result<bool>>(
+        &self,
+        writer: &mut BipBufferWriter,
+        mut try_write: F,
+    ) -> crate::errors::Result<bool> {
+        let start = Instant::now();
+        let written = loop {
+            let space_epoch = writer.wakeup().space_epoch();
+            match try_write(writer) {
+                Ok(false) => (),
+                written => break written,
+            }
+            if start.elapsed() >= self.max_wait {
+                log::warn!(
+                    "No space made in bip_buffer {} within {:?}",
+                    self.stats.name,
+                    self.max_wait
+                );
+                break Ok(false);
+            }
+            let _ =
+                self.discard_requests
+                    .compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst);
+            //the reader consumes an element when it discarded it or read it.
+            writer.wakeup().wait_for_space_until(
+                || writer.wakeup().space_epoch() != space_epoch,
+                Some(self.max_wait.saturating_sub(start.elapsed())),
+            );
+        };
+        self.discard_requests.store(0, Ordering::SeqCst);
+        written
+    }
+
+    fn update_fill_level(&self, bytes_in_use: usize) {
+        self.stats
+            .fill_level
+            .set((bytes_in_use * 100 / self.capacity) as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bip_buffer_with_len;
+    use crate::overflow::*;
+
+    #[test]
+    fn overflow_policy_from_str_test() {
+        assert_eq!("block".parse(), Ok(OverflowPolicy::Block));
+        assert_eq!("drop_newest".parse(), Ok(OverflowPolicy::DropNewest));
+        assert_eq!("drop_oldest".parse(), Ok(OverflowPolicy::DropOldest));
+        assert!("drop_all".parse::<OverflowPolicy>().is_err());
+    }
+
+    #[test]
+    fn drop_newest_test() {
+        let (mut writer, mut reader) = bip_buffer_with_len(100);
+        let stats = Arc::new(BufferStats::default());
+        let monitor = BufferMonitor::new(
+            OverflowPolicy::DropNewest,
+            Duration::from_millis(0),
+            100,
+            stats.clone(),
+        );
+        let buffer = [1; 40];
+        //two elements of 40 bytes + length field fit in the bip_buffer, the third is dropped.
+        assert!(monitor.write(&mut writer, &buffer));
+        assert!(monitor.write(&mut writer, &buffer));
+        assert!(!monitor.write(&mut writer, &buffer));
+        assert_eq!(stats.dropped_packets.load(), 1);
+        assert_eq!(stats.dropped_bytes.load(), 40);
+
+        //reading the elements frees their space again.
+        let mut read_buffer = [0; 40];
+        assert_eq!(monitor.read(&mut reader, &mut read_buffer), 40);
+        assert_eq!(&read_buffer[..], &buffer[..]);
+        assert_eq!(monitor.read(&mut reader, &mut read_buffer), 40);
+        assert!(monitor.write(&mut writer, &buffer));
+    }
+
+    #[test]
+    fn write_in_place_test() {
+        let (mut writer, mut reader) = bip_buffer_with_len(100);
+        let stats = Arc::new(BufferStats::default());
+        let monitor = BufferMonitor::new(
+            OverflowPolicy::DropNewest,
+            Duration::from_millis(0),
+            100,
+            stats.clone(),
+        );
+        assert!(monitor.write_in_place(&mut writer, 30, |element| {
+            element[..20].copy_from_slice(&[1; 20]);
+            Some(20)
+        }));
+        //an abandoned element is skipped by the reader.
+        assert!(monitor.write_in_place(&mut writer, 10, |_| None));
+        //the reservation of the next element does not fit anymore, fill is not called.
+        assert!(!monitor.write_in_place(&mut writer, 30, |_| panic!("No space was reserved")));
+        assert_eq!(stats.dropped_packets.load(), 0);
+
+        let mut read_buffer = [0; 30];
+        assert_eq!(monitor.read(&mut reader, &mut read_buffer), 20);
+        assert_eq!(&read_buffer[..20], &[1; 20][..]);
+        assert!(monitor.write(&mut writer, &[2; 10]));
+        assert_eq!(monitor.read(&mut reader, &mut read_buffer), 10);
+        assert_eq!(reader.valid().len(), 0);
+    }
+
+    #[test]
+    fn oversized_element_test() {
+        let (mut writer, _) = bip_buffer_with_len(100);
+        let stats = Arc::new(BufferStats::default());
+        let monitor = BufferMonitor::new(
+            OverflowPolicy::Block,
+            Duration::from_millis(0),
+            100,
+            stats.clone(),
+        );
+        assert!(!monitor.write(&mut writer, &[0; 200]));
+        //an element just under the capacity may never fit either.
+        assert!(!monitor.write(&mut writer, &[0; 90]));
+        assert_eq!(stats.dropped_packets.load(), 2);
+        assert_eq!(monitor.max_element_length(), 42);
+        assert!(monitor.write(&mut writer, &[0; 42]));
+    }
+
+    #[test]
+    fn consumed_without_written_test() {
+        let monitor = BufferMonitor::new(
+            OverflowPolicy::Block,
+            Duration::from_millis(0),
+            100,
+            Arc::new(BufferStats::default()),
+        );
+        monitor.consumed(40);
+        assert_eq!(monitor.bytes_in_use.load(Ordering::SeqCst), 0);
+        monitor.written(42);
+        assert_eq!(monitor.bytes_in_use.load(Ordering::SeqCst), 50);
+    }
+}
@@ -0,0 +1,343 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lazy_static::lazy_static;
+use std::cell::Cell;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::task::Waker;
+use std::time::{Duration, Instant};
+
+///The least amount of spins the adaptive strategy does before parking.
+const MIN_SPIN_COUNT: u32 = 64;
+///The most amount of spins the adaptive strategy does before parking.
+const MAX_SPIN_COUNT: u32 = 100_000;
+///A parked thread checks its condition again after this time, even when it was not woken up.
+const PARK_TIMEOUT: Duration = Duration::from_millis(100);
+
+///The strategy used by a thread waiting for data or space in a bip_buffer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WaitStrategy {
+    ///Keep spinning until the condition is met, this costs a core per waiting thread.
+    Spin,
+    ///Park the thread right away until it is woken up by the other side of the bip_buffer.
+    Park,
+    ///Spin for a while before parking. The amount of spins grows when spinning was enough
+    ///and shrinks when the thread had to be parked anyway.
+    Adaptive,
+}
+
+impl FromStr for WaitStrategy {
+    type Err = String;
+
+    fn from_str(strategy: &str) -> Result<WaitStrategy, String> {
+        match strategy {
+            "spin" => Ok(WaitStrategy::Spin),
+            "park" => Ok(WaitStrategy::Park),
+            "adaptive" => Ok(WaitStrategy::Adaptive),
+            _ => Err(format!(
+                "Unknown wait strategy {}, expected spin, park or adaptive",
+                strategy
+            )),
+        }
+    }
+}
+
+///An event threads can park on and tasks can register a Waker with until another thread notifies it.
+///Notifying is an atomic increment of the epoch and a single atomic load when no thread is parked and no Waker is registered.
+struct Event {
+    epoch: AtomicUsize,
+    waiters: AtomicUsize,
+    lock: Mutex<()>,
+    condvar: Condvar,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Event {
+    fn new() -> Event {
+        Event {
+            epoch: AtomicUsize::new(0),
+            waiters: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            condvar: Condvar::new(),
+            wakers: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn notify(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            //taking the lock makes sure a waiter is either parked or has not checked its condition yet.
+            let _guard = self.lock.lock().expect("Error locking wakeup mutex");
+            self.condvar.notify_all();
+            let wakers: Vec<Waker> = self
+                .wakers
+                .lock()
+                .expect("Error locking wakeup mutex")
+                .drain(..)
+                .collect();
+            self.waiters.fetch_sub(wakers.len(), Ordering::SeqCst);
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+
+    ///Registers a Waker that is woken up once by the next notification.
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().expect("Error locking wakeup mutex");
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+            self.waiters.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    ///Parks until `ready` returns true or `deadline` has passed.
+    fn park_until<F: FnMut() -> bool>(&self, ready: &mut F, deadline: Option<Instant>) -> bool {
+        let mut guard = self.lock.lock().expect("Error locking wakeup mutex");
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let result = loop {
+            if ready() {
+                break true;
+            }
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break false;
+                    }
+                    PARK_TIMEOUT.min(deadline - now)
+                }
+                None => PARK_TIMEOUT,
+            };
+            guard = self
+                .condvar
+                .wait_timeout(guard, timeout)
+                .expect("Error waiting on wakeup condvar")
+                .0;
+        };
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+}
+
+///The events between the writer and the reader of a single bip_buffer.
+///Both ends of a bip_buffer share one Wakeup, so a notification only wakes up the threads waiting on that bip_buffer.
+pub struct Wakeup {
+    data: Event,
+    space: Event,
+}
+
+lazy_static! {
+    ///Every Wakeup of this process, so `shutdown` can wake up the threads waiting on any bip_buffer.
+    static ref WAKEUPS: Mutex<Vec<Weak<Wakeup>>> = Mutex::new(Vec::new());
+}
+
+static WAIT_STRATEGY: AtomicUsize = AtomicUsize::new(WaitStrategy::Adaptive as usize);
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static SPIN_COUNT: Cell<u32> = const { Cell::new(MIN_SPIN_COUNT) };
+}
+
+///Sets the WaitStrategy used by all threads of this process.
+/// # Arguments
+/// * `strategy` - The WaitStrategy used when waiting for data or space in a bip_buffer.
+pub fn set_wait_strategy(strategy: WaitStrategy) {
+    WAIT_STRATEGY.store(strategy as usize, Ordering::SeqCst);
+}
+
+///Returns the WaitStrategy used by all threads of this process.
+pub fn wait_strategy() -> WaitStrategy {
+    match WAIT_STRATEGY.load(Ordering::SeqCst) {
+        x if x == WaitStrategy::Spin as usize => WaitStrategy::Spin,
+        x if x == WaitStrategy::Park as usize => WaitStrategy::Park,
+        _ => WaitStrategy::Adaptive,
+    }
+}
+
+///Shuts down the bip_buffers of this process.
+///Threads waiting in the checked reads and writes of `bip_utils` wake up and return a Shutdown error.
+pub fn shutdown() {
+    SHUT_DOWN.store(true, Ordering::SeqCst);
+    let wakeups = WAKEUPS.lock().expect("Error locking wakeup mutex");
+    for wakeup in wakeups.iter().filter_map(Weak::upgrade) {
+        wakeup.notify_data();
+        wakeup.notify_space();
+    }
+}
+
+///Returns true when the bip_buffers of this process are shut down.
+pub fn is_shut_down() -> bool {
+    SHUT_DOWN.load(Ordering::SeqCst)
+}
+
+impl Wakeup {
+    ///Creates the Wakeup shared by the writer and the reader of a new bip_buffer.
+    pub fn new() -> Arc<Wakeup> {
+        let wakeup = Arc::new(Wakeup {
+            data: Event::new(),
+            space: Event::new(),
+        });
+        let mut wakeups = WAKEUPS.lock().expect("Error locking wakeup mutex");
+        wakeups.retain(|wakeup| wakeup.strong_count() > 0);
+        wakeups.push(Arc::downgrade(&wakeup));
+        wakeup
+    }
+
+    ///Wakes up the threads waiting for data. Should be called after an element is sent to the bip_buffer.
+    pub fn notify_data(&self) {
+        self.data.notify();
+    }
+
+    ///Wakes up the threads waiting for space. Should be called after data is consumed from the bip_buffer.
+    pub fn notify_space(&self) {
+        self.space.notify();
+    }
+
+    ///Returns the amount of times `notify_space` was called.
+    ///A writer that did not find space reads it before reserving, and waits for it to change.
+    pub fn space_epoch(&self) -> usize {
+        self.space.epoch.load(Ordering::SeqCst)
+    }
+
+    ///Registers a Waker that is woken up by the next `notify_data`.
+    ///The task must check for data again after registering, the data may have arrived before the Waker was registered.
+    /// # Arguments
+    /// * `waker` - The Waker of the task waiting for data.
+    pub fn register_data_waker(&self, waker: &Waker) {
+        self.data.register(waker);
+    }
+
+    ///Registers a Waker that is woken up by the next `notify_space`.
+    ///The task must check for space again after registering, the space may have been freed before the Waker was registered.
+    /// # Arguments
+    /// * `waker` - The Waker of the task waiting for space.
+    pub fn register_space_waker(&self, waker: &Waker) {
+        self.space.register(waker);
+    }
+
+    ///Waits until `ready` returns true for data in the bip_buffer.
+    /// # Arguments
+    /// * `ready` - Checks whether the data is available.
+    /// * `timeout` - The maximum time to wait, None waits forever.
+    /// # Returns
+    /// * `bool` - True when the data is available, false when the timeout passed.
+    pub fn wait_for_data_until<F: FnMut() -> bool>(
+        &self,
+        ready: F,
+        timeout: Option<Duration>,
+    ) -> bool {
+        wait_until(&self.data, ready, timeout)
+    }
+
+    ///Waits until `ready` returns true for space in the bip_buffer.
+    /// # Arguments
+    /// * `ready` - Checks whether the space is available.
+    /// * `timeout` - The maximum time to wait, None waits forever.
+    /// # Returns
+    /// * `bool` - True when the space is available, false when the timeout passed.
+    pub fn wait_for_space_until<F: FnMut() -> bool>(
+        &self,
+        ready: F,
+        timeout: Option<Duration>,
+    ) -> bool {
+        wait_until(&self.space, ready, timeout)
+    }
+}
+
+fn wait_until<F: FnMut() -> bool>(event: &Event, mut ready: F, timeout: Option<Duration>) -> bool {
+    if ready() {
+        return true;
+    }
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let strategy = wait_strategy();
+    let spin_count = match strategy {
+        WaitStrategy::Spin => u32::MAX,
+        WaitStrategy::Park => 0,
+        WaitStrategy::Adaptive => SPIN_COUNT.with(|count| count.get()),
+    };
+    let mut spins: u32 = 0;
+    while spins < spin_count || strategy == WaitStrategy::Spin {
+        if ready() {
+            if strategy == WaitStrategy::Adaptive {
+                SPIN_COUNT.with(|count| count.set((spin_count * 2).min(MAX_SPIN_COUNT)));
+            }
+            return true;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return false;
+        }
+        std::hint::spin_loop();
+        spins = spins.wrapping_add(1);
+    }
+    if strategy == WaitStrategy::Adaptive {
+        SPIN_COUNT.with(|count| count.set((spin_count / 2).max(MIN_SPIN_COUNT)));
+    }
+    event.park_until(&mut ready, deadline)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wakeup::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    #[test]
+    fn wait_strategy_from_str_test() {
+        assert_eq!("spin".parse(), Ok(WaitStrategy::Spin));
+        assert_eq!("park".parse(), Ok(WaitStrategy::Park));
+        assert_eq!("adaptive".parse(), Ok(WaitStrategy::Adaptive));
+        assert!("sleep".parse::<WaitStrategy>().is_err());
+    }
+
+    #[test]
+    fn wait_timeout_test() {
+        let wakeup = Wakeup::new();
+        let start = Instant::now();
+        assert!(!wakeup.wait_for_data_until(|| false, Some(Duration::from_millis(20))));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(wakeup.wait_for_data_until(|| true, Some(Duration::from_millis(0))));
+    }
+
+    #[test]
+    fn notify_wakes_parked_thread_test() {
+        let wakeup = Wakeup::new();
+        let ready = Arc::new(AtomicBool::new(false));
+        let (waiter_wakeup, waiter_ready) = (wakeup.clone(), ready.clone());
+        let waiter = std::thread::spawn(move || {
+            let start = Instant::now();
+            assert!(
+                waiter_wakeup.wait_for_space_until(|| waiter_ready.load(Ordering::SeqCst), None)
+            );
+            start.elapsed()
+        });
+        std::thread::sleep(Duration::from_millis(10));
+        ready.store(true, Ordering::SeqCst);
+        wakeup.notify_space();
+        //the waiter is woken up by the notification, well before the park timeout.
+        assert!(waiter.join().unwrap() < PARK_TIMEOUT);
+    }
+
+    #[test]
+    fn notify_only_wakes_own_bip_buffer_test() {
+        let (wakeup, other_wakeup) = (Wakeup::new(), Wakeup::new());
+        let space_epoch = wakeup.space_epoch();
+        other_wakeup.notify_space();
+        assert_eq!(wakeup.space_epoch(), space_epoch);
+        wakeup.notify_space();
+        assert_eq!(wakeup.space_epoch(), space_epoch + 1);
+    }
+}
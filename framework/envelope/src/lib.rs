@@ -0,0 +1,133 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The envelope is the message that is sent between the handlers of a chain.
+//! An ingress protocol handler puts the message it receives in an envelope, an egress protocol handler maps the envelope back to its own protocol.
+//! Filters only look at the envelope, so a filter works in every chain regardless of the protocol.
+
+use crate::errors::*;
+use bincode::{deserialize, serialize_into, serialized_size};
+use serde::{Deserialize, Serialize};
+
+/// Error handling for the envelope with chain_error
+pub mod errors;
+
+///The content type in the frame metadata of a frame that carries an envelope.
+pub const CONTENT_TYPE: &str = "envelope";
+
+///The protocol the message was received with by the ingress protocol handler.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Protocol {
+    Kafka,
+    Udp,
+    ///A protocol without its own variant, for example the protocol of a custom handler.
+    Other(String),
+}
+
+/// A struct for a message of any protocol
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Envelope {
+    /// The protocol the message was received with
+    pub protocol: Protocol,
+    /// The topic or subject of the message, if the protocol has one
+    pub topic: Option<String>,
+    /// The key of the message, if the protocol has one
+    pub key: Option<Vec<u8>>,
+    /// Protocol specific headers, as name and value
+    pub headers: Vec<(String, Vec<u8>)>,
+    /// The timestamp set by the source of the message in microseconds since the unix epoch, if the protocol has one
+    pub source_timestamp: Option<u64>,
+    /// The time the ingress protocol handler received the message in microseconds since the unix epoch
+    pub received_timestamp: u64,
+    /// The message itself
+    pub payload: Vec<u8>,
+}
+
+impl Envelope {
+    ///Creates an envelope without topic, key, headers and timestamps.
+    /// # Arguments
+    /// * `protocol` - The protocol the message was received with.
+    /// * `payload` - The message itself.
+    pub fn new(protocol: Protocol, payload: &[u8]) -> Envelope {
+        Envelope {
+            protocol,
+            topic: None,
+            key: None,
+            headers: Vec::new(),
+            source_timestamp: None,
+            received_timestamp: 0,
+            payload: payload.to_vec(),
+        }
+    }
+
+    ///Returns the value of the first header with the given name.
+    /// # Arguments
+    /// * `name` - The name of the header.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| &value[..])
+    }
+
+    ///Serializes the envelope into `buf`.
+    /// # Arguments
+    /// * `buf` - The buffer to write to, it must be large enough for the envelope.
+    /// # Returns
+    /// * `usize` - The amount of bytes written.
+    pub fn serialize_packet(&self, buf: &mut [u8]) -> Result<usize> {
+        serialize_into(&mut buf[..], self).chain_err(|| "Failed serializing Envelope")?;
+        Ok(serialized_size(self).chain_err(|| "Failed serializing Envelope")? as usize)
+    }
+
+    ///Deserializes an envelope from `buf`.
+    /// # Arguments
+    /// * `buf` - The serialized envelope.
+    pub fn deserialize_packet(buf: &[u8]) -> Result<Envelope> {
+        deserialize(buf).chain_err(|| "Failed deserializing Envelope")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn serialize_deserialize_test() {
+        let mut envelope = Envelope::new(Protocol::Kafka, &[1, 2, 3]);
+        envelope.topic = Some("topic".to_string());
+        envelope.key = Some(vec![4]);
+        envelope
+            .headers
+            .push(("kafka.offset".to_string(), b"12".to_vec()));
+        envelope.received_timestamp = 42;
+
+        let mut buf = [0; 1024];
+        let length = envelope
+            .serialize_packet(&mut buf)
+            .expect("Can't serialize");
+        let received = Envelope::deserialize_packet(&buf[..length]).expect("Can't deserialize");
+        assert_eq!(received, envelope);
+        assert_eq!(received.header("kafka.offset"), Some(&b"12"[..]));
+        assert_eq!(received.header("other"), None);
+    }
+
+    #[test]
+    fn buffer_too_small_test() {
+        let envelope = Envelope::new(Protocol::Udp, &[0; 100]);
+        let mut buf = [0; 10];
+        assert!(envelope.serialize_packet(&mut buf).is_err());
+        assert!(Envelope::deserialize_packet(&buf).is_err());
+    }
+}
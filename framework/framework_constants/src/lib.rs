@@ -12,7 +12,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-
 ///The maximum size of the packet buffer.
 ///The field size sets a theoretical limit of 65,535 bytes (8 byte header + 65,527 bytes of data) for a UDP datagram.\
 ///However the actual limit for the data length, which is imposed by the underlying IPv4 protocol, is 65,507 bytes (65,535 − 8 byte UDP header − 20 byte IP header).
@@ -36,6 +35,25 @@ pub const BIP_BUFFER_LEN_FIELD_LEN: usize = std::mem::size_of::<usize>();
 //a bit more allocated then needed. 1_048_576(1 Mb) is needed + BIP_BUFFER_LEN_FIELD_LEN
 pub const MAX_BIP_BUFFER_MESSAGE_SIZE: usize = 1_050_000;
 
+///The size in bytes of the ingress timestamp carried by a Timestamp message.
+pub const TIMESTAMP_SIZE_BYTES: usize = std::mem::size_of::<u64>();
+
+///The size in bytes of the sender totals carried by a SenderStats message.
+//packets + elements + bytes, all u64.
+pub const SENDER_STATS_SIZE_BYTES: usize = 3 * std::mem::size_of::<u64>();
+
+///The size in bytes of the interleave header in front of the data of an interleaved message.
+//element id u32 + group index u16 + group size u16.
+pub const INTERLEAVE_HEADER_SIZE_BYTES: usize = 8;
+
+///The maximum size in bytes of the data of a single interleaved message.
+pub const MAX_INTERLEAVED_DATA_SIZE_BYTES: usize =
+    MAX_PAYLOAD_SIZE_BYTES - INTERLEAVE_HEADER_SIZE_BYTES;
+
+///The maximum amount of elements that can be interleaved.
+//every element of a group is buffered by the receiver, this limits the memory used to about 32Mb.
+pub const MAX_INTERLEAVE_DEPTH: usize = 32;
+
 ///The messagetype used to determine the type of packet that was sent.
 #[repr(u8)]
 #[derive(Debug, Copy, Clone)]
@@ -45,6 +63,10 @@ pub enum MessageType {
     DataFirst = 3u8,
     Data = 4u8,
     Shutdown = 5u8,
+    Timestamp = 6u8,
+    SenderStats = 7u8,
+    InterleavedFirst = 8u8,
+    Interleaved = 9u8,
 }
 
 impl MessageType {
@@ -66,6 +88,16 @@ impl MessageType {
             byte if byte == MessageType::as_u8(MessageType::DataFirst) => MessageType::DataFirst,
             byte if byte == MessageType::as_u8(MessageType::Data) => MessageType::Data,
             byte if byte == MessageType::as_u8(MessageType::Shutdown) => MessageType::Shutdown,
+            byte if byte == MessageType::as_u8(MessageType::Timestamp) => MessageType::Timestamp,
+            byte if byte == MessageType::as_u8(MessageType::SenderStats) => {
+                MessageType::SenderStats
+            }
+            byte if byte == MessageType::as_u8(MessageType::InterleavedFirst) => {
+                MessageType::InterleavedFirst
+            }
+            byte if byte == MessageType::as_u8(MessageType::Interleaved) => {
+                MessageType::Interleaved
+            }
             _ => MessageType::DataFirst,
         }
     }
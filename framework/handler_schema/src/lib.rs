@@ -0,0 +1,344 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use structopt::clap::App;
+
+///The argument that makes a handler print its schema instead of running.
+pub const DESCRIBE_ARGUMENT: &str = "--describe";
+
+///The place of a handler in a chain.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Role {
+    ///A protocol handler in the ingress network, it sends to the transport handler.
+    Ingress,
+    ///A protocol handler in the egress network, it receives from the transport handler.
+    Egress,
+    ///A filter between the protocol handler and the transport handler.
+    Filter,
+    ///A transport handler that sends or receives over the diode.
+    Transport,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Ingress => write!(f, "ingress"),
+            Role::Egress => write!(f, "egress"),
+            Role::Filter => write!(f, "filter"),
+            Role::Transport => write!(f, "transport"),
+        }
+    }
+}
+
+///The type of the value of an argument.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ArgumentType {
+    Integer,
+    Number,
+    Boolean,
+    String,
+    ///An argument without a value.
+    Flag,
+}
+
+impl fmt::Display for ArgumentType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgumentType::Integer => write!(f, "integer"),
+            ArgumentType::Number => write!(f, "number"),
+            ArgumentType::Boolean => write!(f, "boolean"),
+            ArgumentType::String => write!(f, "string"),
+            ArgumentType::Flag => write!(f, "flag"),
+        }
+    }
+}
+
+///An argument a handler accepts.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Argument {
+    ///The long name of the argument, without `--`.
+    pub name: &'static str,
+    pub argument_type: ArgumentType,
+    pub default: Option<&'static str>,
+    pub required: bool,
+    pub help: Option<&'static str>,
+}
+
+impl Argument {
+    ///Creates an argument that is not required, without a default and help.
+    pub const fn new(name: &'static str, argument_type: ArgumentType) -> Argument {
+        Argument {
+            name,
+            argument_type,
+            default: None,
+            required: false,
+            help: None,
+        }
+    }
+
+    ///Sets the value the handler uses when the argument is not given.
+    pub const fn default(self, default: &'static str) -> Argument {
+        Argument {
+            default: Some(default),
+            ..self
+        }
+    }
+
+    ///Makes the handler refuse to start without the argument.
+    pub const fn required(self) -> Argument {
+        Argument {
+            required: true,
+            ..self
+        }
+    }
+
+    ///Sets the description of the argument.
+    pub const fn help(self, help: &'static str) -> Argument {
+        Argument {
+            help: Some(help),
+            ..self
+        }
+    }
+}
+
+///The place of a handler in a chain and the arguments it accepts.
+///Every handler declares it next to its structopt arguments, `check_schema` tests that they agree.
+#[derive(Debug)]
+pub struct HandlerSchema {
+    pub role: Role,
+    pub arguments: &'static [Argument],
+}
+
+///Prints the schema of the arguments of the handler and exits when `--describe` is its first argument.
+///Call it before the arguments are parsed, so required arguments do not have to be given.
+/// # Arguments
+/// * `schema` - The schema of the arguments of the handler.
+pub fn describe_if_requested(schema: &HandlerSchema) {
+    if std::env::args().nth(1).as_deref() == Some(DESCRIBE_ARGUMENT) {
+        print!("{}", describe(schema));
+        std::process::exit(0);
+    }
+}
+
+///Returns the schema of the arguments of a handler as TOML.
+///Every argument has a `type`, `integer`, `number`, `boolean`, `string` or `flag`, whether it is `required`,
+///and when it has them its `default` and `help`.
+/// # Arguments
+/// * `schema` - The schema of the arguments of the handler.
+pub fn describe(schema: &HandlerSchema) -> String {
+    let mut toml = format!("role = \"{}\"\n", schema.role);
+    for argument in schema.arguments {
+        toml.push_str(&format!(
+            "\n[arguments.{}]\ntype = \"{}\"\n",
+            argument.name, argument.argument_type
+        ));
+        if let Some(default) = argument.default {
+            toml.push_str(&format!("default = {}\n", toml_string(default)));
+        }
+        toml.push_str(&format!("required = {}\n", argument.required));
+        if let Some(help) = argument.help {
+            toml.push_str(&format!("help = {}\n", toml_string(help)));
+        }
+    }
+    toml
+}
+
+///Checks that a schema declares the arguments the clap App of a handler accepts.
+///The names are read from the help of the App. Every argument is parsed with a value of its type
+///and every argument that is not a string is parsed with a value of another type.
+/// # Arguments
+/// * `app` - The clap App of the arguments of the handler, `StructOpt::clap()`.
+/// * `schema` - The schema of the arguments of the handler.
+/// # Returns
+/// The first difference between the schema and the App.
+pub fn check_schema(mut app: App, schema: &HandlerSchema) -> Result<(), String> {
+    let mut help = Vec::new();
+    app.write_long_help(&mut help)
+        .map_err(|e| format!("Cannot write the help: {}", e))?;
+    let help = String::from_utf8_lossy(&help);
+    let mut names: Vec<&str> = help
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter_map(|word| word.strip_prefix("--"))
+        .filter(|name| !name.is_empty() && *name != "help" && *name != "version")
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    let mut declared: Vec<&str> = schema.arguments.iter().map(|x| x.name).collect();
+    declared.sort_unstable();
+    if names != declared {
+        return Err(format!(
+            "The handler accepts {:?}, the schema declares {:?}",
+            names, declared
+        ));
+    }
+
+    //parses the arguments with the required arguments that are not in them
+    let mut parse = |extra: &[&str]| {
+        let mut arguments = vec!["handler".to_string()];
+        for argument in schema.arguments.iter().filter(|x| x.required) {
+            let name = format!("--{}", argument.name);
+            if extra.first() != Some(&name.as_str()) {
+                arguments.push(name);
+                arguments.push(sample_value(argument).to_string());
+            }
+        }
+        arguments.extend(extra.iter().map(|x| x.to_string()));
+        app.get_matches_from_safe_borrow(arguments).is_ok()
+    };
+    if !parse(&[]) {
+        return Err("The handler needs an argument the schema does not require".to_string());
+    }
+    for argument in schema.arguments {
+        let name = format!("--{}", argument.name);
+        let accepted = match argument.argument_type {
+            ArgumentType::Flag => parse(&[&name]),
+            _ => parse(&[&name, sample_value(argument)]),
+        };
+        let rejected = match argument.argument_type {
+            ArgumentType::Integer => !parse(&[&name, "1.5"]),
+            ArgumentType::Number | ArgumentType::Boolean => !parse(&[&name, "x"]),
+            ArgumentType::Flag => !parse(&[&name, "true"]),
+            ArgumentType::String => true,
+        };
+        if !accepted || !rejected {
+            return Err(format!(
+                "{} is not an argument of type {} with the declared default",
+                name, argument.argument_type
+            ));
+        }
+    }
+    Ok(())
+}
+
+///A value an argument accepts, its default or else a value of its type.
+fn sample_value(argument: &Argument) -> &'static str {
+    match (argument.default, argument.argument_type) {
+        (Some(default), _) => default,
+        (None, ArgumentType::Integer) => "1",
+        (None, ArgumentType::Number) => "1.5",
+        (None, ArgumentType::Boolean) => "true",
+        (None, _) => "x",
+    }
+}
+
+///Quotes a string for TOML, escaping the backslashes, double quotes and control characters.
+fn toml_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    #[derive(StructOpt)]
+    #[allow(dead_code)]
+    struct Opt {
+        ///Port to listen on.
+        #[structopt(long = "listening_port", default_value = "1235")]
+        listening_port: u16,
+
+        ///The name of the "topic".
+        #[structopt(long = "topic_name")]
+        topic_name: String,
+
+        #[structopt(long = "rate", default_value = "0.5")]
+        rate: f64,
+
+        #[structopt(long = "timestamp", default_value = "false", parse(try_from_str))]
+        timestamp: bool,
+
+        #[structopt(long = "tap_address")]
+        tap_address: Option<String>,
+
+        #[structopt(long = "verbose")]
+        verbose: bool,
+    }
+
+    const SCHEMA: HandlerSchema = HandlerSchema {
+        role: Role::Ingress,
+        arguments: &[
+            Argument::new("listening_port", ArgumentType::Integer)
+                .default("1235")
+                .help("Port to listen on"),
+            Argument::new("topic_name", ArgumentType::String)
+                .required()
+                .help("The name of the \"topic\""),
+            Argument::new("rate", ArgumentType::Number).default("0.5"),
+            Argument::new("timestamp", ArgumentType::Boolean).default("false"),
+            Argument::new("tap_address", ArgumentType::String),
+            Argument::new("verbose", ArgumentType::Flag),
+        ],
+    };
+
+    #[test]
+    fn describe_test() {
+        assert_eq!(
+            describe(&SCHEMA),
+            "role = \"ingress\"\n\
+             \n[arguments.listening_port]\ntype = \"integer\"\ndefault = \"1235\"\nrequired = false\nhelp = \"Port to listen on\"\n\
+             \n[arguments.topic_name]\ntype = \"string\"\nrequired = true\nhelp = \"The name of the \\\"topic\\\"\"\n\
+             \n[arguments.rate]\ntype = \"number\"\ndefault = \"0.5\"\nrequired = false\n\
+             \n[arguments.timestamp]\ntype = \"boolean\"\ndefault = \"false\"\nrequired = false\n\
+             \n[arguments.tap_address]\ntype = \"string\"\nrequired = false\n\
+             \n[arguments.verbose]\ntype = \"flag\"\nrequired = false\n"
+        );
+    }
+
+    #[test]
+    fn check_schema_test() {
+        assert_eq!(check_schema(Opt::clap(), &SCHEMA), Ok(()));
+
+        let missing = HandlerSchema {
+            role: Role::Ingress,
+            arguments: &SCHEMA.arguments[1..],
+        };
+        assert!(check_schema(Opt::clap(), &missing).is_err());
+
+        let mut arguments = SCHEMA.arguments.to_vec();
+        //an integer declared as a number or a string, a required argument that is not declared so
+        //and a boolean declared as a flag
+        for (index, wrong) in &[
+            (0, Argument::new("listening_port", ArgumentType::Number)),
+            (0, Argument::new("listening_port", ArgumentType::String)),
+            (1, Argument::new("topic_name", ArgumentType::String)),
+            (3, Argument::new("timestamp", ArgumentType::Flag)),
+        ] {
+            let correct = std::mem::replace(&mut arguments[*index], *wrong);
+            let schema = HandlerSchema {
+                role: Role::Ingress,
+                arguments: Box::leak(arguments.clone().into_boxed_slice()),
+            };
+            assert!(
+                check_schema(Opt::clap(), &schema).is_err(),
+                "{:?} was accepted",
+                wrong
+            );
+            arguments[*index] = correct;
+        }
+    }
+}
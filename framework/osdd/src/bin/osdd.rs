@@ -12,14 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use error_chain::ChainedError;
 use logging::set_syslog;
-use osdd::docker_runner::*;
+use osdd::container_monitor::*;
+use osdd::errors::ErrorKind::ConfigurationError;
 use osdd::errors::Result;
 use osdd::errors::*;
+use osdd::export::*;
 use osdd::read_toml::*;
+use osdd::reconcile;
+use osdd::reload::*;
 use osdd::udp_multiplexer_stats::*;
+use osdd::validate::*;
 use osdd::*;
 use std::panic;
+use std::path::Path;
 use std::thread;
 use std::time;
 use structopt::StructOpt;
@@ -28,8 +35,42 @@ const HANDLER_NAME_STRING: &str = "OSSD";
 
 #[derive(StructOpt)]
 struct Opt {
-    #[structopt(short, long = "config_file", default_value = "/home/osdd/Config.toml")]
-    config_file: String,
+    /// The configuration file, of osdd itself and of every command
+    #[structopt(
+        short,
+        long = "config",
+        alias = "config_file",
+        global = true,
+        default_value = "/home/osdd/Config.toml"
+    )]
+    config: String,
+    #[structopt(subcommand)]
+    command: Option<OsddCommand>,
+}
+
+#[derive(StructOpt)]
+enum OsddCommand {
+    /// Checks the configuration file and reports every problem with its location
+    Validate,
+    /// Prints the commands osdd would run for the configuration file, without running them
+    DryRun,
+    /// Prints the state and restarts of the container of every handler in the configuration file
+    Status,
+    /// Starts the handlers of the configuration file that are not running with their current configuration,
+    /// and removes the containers of handlers that are not in it anymore
+    Up,
+    /// Stops and removes the containers of the instance and network of the configuration file, and their sockets
+    Down,
+    /// Restarts the containers of a chain, or of all chains when no chain is given
+    Restart { chain: Option<String> },
+    /// Exports the deployment of the configuration file as "compose", "systemd" units or a "dot" diagram
+    Export {
+        #[structopt(long = "format")]
+        format: ExportFormat,
+        /// The directory the files are written to, they are printed when it is not given
+        #[structopt(long = "output")]
+        output: Option<String>,
+    },
 }
 
 fn main() {
@@ -53,23 +94,49 @@ fn main() {
         std::process::exit(1);
     }));
 
-    osdd().chain_unwrap();
+    let opt = Opt::from_args();
+    let config = opt.config;
+    match opt.command {
+        Some(OsddCommand::Validate) => std::process::exit(validate(&config)),
+        Some(OsddCommand::DryRun) => return dry_run(&config).chain_unwrap(),
+        Some(OsddCommand::Status) => return status(&config).chain_unwrap(),
+        Some(OsddCommand::Up) => return up(&config).chain_unwrap(),
+        Some(OsddCommand::Down) => return down(&config).chain_unwrap(),
+        Some(OsddCommand::Restart { chain }) => {
+            return restart(&config, chain.as_deref()).chain_unwrap()
+        }
+        Some(OsddCommand::Export { format, output }) => {
+            return export_deployment(&config, format, output.as_deref()).chain_unwrap()
+        }
+        None => (),
+    }
+
+    let mut running = osdd(&config).chain_unwrap();
+    reload_on_sighup().chain_unwrap();
+    let mut watcher = ConfigWatcher::new(&config);
     loop {
-        thread::sleep(time::Duration::from_millis(5000));
+        thread::sleep(time::Duration::from_millis(2000));
+        //both are checked, so a SIGHUP is not handled again after a change of the file
+        let (changed, requested) = (watcher.changed(), take_reload_request());
+        if changed || requested {
+            if let Err(e) = running.reload(&config) {
+                log::error!("{}", e.display_chain());
+            }
+        }
     }
 }
 
 /// This function starts the open source data diode.
 /// It loads the configuration from a TOML file.
 /// It starts a UDP multiplexer for statitics.
-/// It create and execute commands to run Docker containers.
-fn osdd() -> Result<()> {
-    let opt = Opt::from_args();
-
+/// It brings the Docker containers in line with the configuration, or starts child processes with the native runtime.
+/// The Docker containers are monitored and their status is sent to the UDP multiplexer.
+/// Returns the running handlers, so they can be changed when the configuration is reloaded.
+fn osdd(config_file: &str) -> Result<RunningDeployment> {
     eprintln!("start {}", HANDLER_NAME_STRING);
 
     //Read handlers, settings_options and chains from the config file
-    let toml_config = read_toml(&opt.config_file)?;
+    let toml_config = load_config(config_file)?;
 
     let chain_handler_name = format!(
         "osdd.{}.{}",
@@ -87,21 +154,19 @@ fn osdd() -> Result<()> {
     )
     .chain_err(|| "Error initializing syslog")?;
 
-    //creating /sockets path
-    match std::fs::create_dir(format!("{}/sockets", &toml_config.settings.path)) {
+    //creating the socket directory, it is mounted in the containers
+    let socket_dir = toml_config.settings.host_socket_dir();
+    match std::fs::create_dir(&socket_dir) {
         Ok(_) => {
-            log::trace!("created /sockets path");
+            log::trace!("created {}", socket_dir.display());
         }
         Err(ref e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-            log::trace!("/sockets already exist");
+            log::trace!("{} already exist", socket_dir.display());
         }
         Err(e) => {
             return Err(Error::with_chain(
                 e,
-                format!(
-                    "Error while creating {}/sockets path",
-                    &toml_config.settings.path
-                ),
+                format!("Error while creating {}", socket_dir.display()),
             ))
         }
     }
@@ -111,22 +176,186 @@ fn osdd() -> Result<()> {
         .stats_multiplexer_listening_port
         .parse::<u16>()?;
 
-    //create commands to run processes
-    let commands = create_commands_all_handlers(
+    //start udp multiplexer in other thread
+    run(
+        stats_multiplexer_listening_port_u16,
+        toml_config.settings.stats_servers.clone(),
+    )?;
+
+    //Starting dockers that are not running yet, or child processes that are restarted when they crash
+    RunningDeployment::start(toml_config)
+}
+
+/// Checks the configuration file and reads it.
+/// The problems are printed because logging is not initialized yet.
+fn load_config(config_file: &str) -> Result<TomlConfig> {
+    let diagnostics = validate_file(config_file)?;
+    for diagnostic in &diagnostics {
+        eprintln!("{}: {}", config_file, diagnostic);
+    }
+    if has_errors(&diagnostics) {
+        return Err(ConfigurationError(format!("{} has errors", config_file)).into());
+    }
+    read_toml(config_file)
+}
+
+/// Creates the deployments of all handlers in the configuration for a runtime
+fn deployments(toml_config: TomlConfig, runtime: Runtime) -> Result<Vec<HandlerDeployment>> {
+    let stats_multiplexer_listening_port_u16 = toml_config
+        .settings
+        .stats_multiplexer_listening_port
+        .parse::<u16>()?;
+    create_deployments_all_handlers(
         toml_config.chains,
         toml_config.handlers,
         stats_multiplexer_listening_port_u16,
         &toml_config.settings,
-    )?;
+        runtime,
+    )
+}
 
-    //start udp multiplexer in other thread
-    run(
-        stats_multiplexer_listening_port_u16,
-        toml_config.settings.stats_servers,
-    )?;
+/// Prints the commands osdd would run for the configuration file, with the directory they run in.
+/// The sockets between the handlers are printed before the commands of every chain.
+fn dry_run(config_file: &str) -> Result<()> {
+    let toml_config = load_config(config_file)?;
+    let runtime = toml_config.settings.runtime()?;
+    let deployments = deployments(toml_config, runtime)?;
+    for (index, deployment) in deployments.iter().enumerate() {
+        if index == 0 || deployments[index - 1].chain != deployment.chain {
+            println!("# chain {}", deployment.chain);
+            for from in deployments.iter().filter(|x| x.chain == deployment.chain) {
+                for (to, socket) in &from.outgoing_sockets {
+                    println!("#   {} -> {}: {}", from.handler, to, socket);
+                }
+            }
+        }
+        println!("# {}", deployment.name);
+        if let (Runtime::Native, Some((uid, gid))) = (runtime, deployment.user) {
+            println!("# runs as {}:{}", uid, gid);
+        }
+        println!("{}", shell_line(&deployment.command().command));
+    }
+    Ok(())
+}
 
-    //Starting dockers and monitoring
-    handle_processes(commands)?;
+/// Prints the state and restarts of the container of every handler in the configuration file.
+fn status(config_file: &str) -> Result<()> {
+    let toml_config = load_docker_config(config_file, "status")?;
+    let names = deployments(toml_config, Runtime::Docker)?
+        .into_iter()
+        .map(|deployment| deployment.name)
+        .collect();
+    let statuses = HandlerStatuses::new(names);
+    for e in statuses.inspect_all() {
+        eprintln!("{}", e.display_chain());
+    }
+    print!("{}", statuses.summary());
+    Ok(())
+}
 
+/// Reads the configuration file for a command that manages the containers of the docker runtime
+fn load_docker_config(config_file: &str, command: &str) -> Result<TomlConfig> {
+    let toml_config = load_config(config_file)?;
+    if toml_config.settings.runtime()? != Runtime::Docker {
+        return Err(ConfigurationError(format!(
+            "{} is only available for the docker runtime",
+            command
+        ))
+        .into());
+    }
+    Ok(toml_config)
+}
+
+/// Brings the containers in line with the configuration file and prints what changed.
+fn up(config_file: &str) -> Result<()> {
+    let toml_config = load_docker_config(config_file, "up")?;
+    let socket_dir = toml_config.settings.host_socket_dir();
+    let name_prefix = toml_config.settings.name_prefix();
+    let deployments = deployments(toml_config, Runtime::Docker)?;
+    print_changes(reconcile::up(&deployments, &name_prefix, &socket_dir)?);
+    Ok(())
+}
+
+/// Removes the containers of the instance and network of the configuration file, and their sockets.
+fn down(config_file: &str) -> Result<()> {
+    let toml_config = load_docker_config(config_file, "down")?;
+    print_changes(reconcile::down(
+        &toml_config.settings.name_prefix(),
+        &toml_config.settings.host_socket_dir(),
+    )?);
     Ok(())
 }
+
+/// Restarts the containers of a chain in the configuration file, or of all chains.
+fn restart(config_file: &str, chain: Option<&str>) -> Result<()> {
+    let toml_config = load_docker_config(config_file, "restart")?;
+    let socket_dir = toml_config.settings.host_socket_dir();
+    let name_prefix = toml_config.settings.name_prefix();
+    let deployments = deployments(toml_config, Runtime::Docker)?;
+    let to_restart: Vec<&HandlerDeployment> = deployments
+        .iter()
+        .filter(|d| chain.is_none() || chain == Some(d.chain.as_str()))
+        .collect();
+    if to_restart.is_empty() {
+        return Err(ConfigurationError(format!(
+            "There is no chain {} in {}",
+            chain.unwrap_or_default(),
+            config_file
+        ))
+        .into());
+    }
+    print_changes(reconcile::restart(&to_restart, &name_prefix, &socket_dir)?);
+    Ok(())
+}
+
+/// Prints the changes made to the containers, or that nothing changed
+fn print_changes(changes: Vec<String>) {
+    if changes.is_empty() {
+        println!("Nothing changed");
+    }
+    for change in changes {
+        println!("{}", change);
+    }
+}
+
+/// Exports the deployment of the configuration file to the output directory, or prints it.
+fn export_deployment(config_file: &str, format: ExportFormat, output: Option<&str>) -> Result<()> {
+    let toml_config = load_config(config_file)?;
+    //the services of a compose file always run in containers
+    let runtime = match format {
+        ExportFormat::Compose => Runtime::Docker,
+        _ => toml_config.settings.runtime()?,
+    };
+    let files = export(&deployments(toml_config, runtime)?, format);
+    let print_names = files.len() > 1;
+    for (name, content) in files {
+        match output {
+            Some(dir) => std::fs::write(Path::new(dir).join(&name), content)
+                .chain_err(|| format!("Error writing {} to {}", name, dir))?,
+            None if print_names => println!("# {}\n{}", name, content),
+            None => print!("{}", content),
+        }
+    }
+    Ok(())
+}
+
+/// Checks the configuration file and prints every problem found in it.
+/// Returns the exit code of `osdd validate`, 1 when the configuration has errors.
+fn validate(config_file: &str) -> i32 {
+    let diagnostics = match validate_file(config_file) {
+        Ok(diagnostics) => diagnostics,
+        Err(e) => {
+            eprintln!("{}", e.display_chain());
+            return 1;
+        }
+    };
+    for diagnostic in &diagnostics {
+        eprintln!("{}: {}", config_file, diagnostic);
+    }
+    if has_errors(&diagnostics) {
+        1
+    } else {
+        println!("{} is valid", config_file);
+        0
+    }
+}
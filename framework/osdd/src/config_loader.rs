@@ -0,0 +1,940 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::ErrorKind::ConfigurationError;
+use crate::errors::*;
+use fnv::FnvHasher;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use toml::value::Table;
+use toml::Value;
+
+/// The tables handlers are defined in, in the order of a chain
+pub const HANDLER_TABLES: [&str; 3] = ["protocolhandler", "filterhandler", "transporthandler"];
+/// The value of a port of a transport handler that is assigned from `transport_port_range`
+const AUTO_PORT: &str = "auto";
+
+/// The file and line a table or key is defined on, the file is none for the configuration file itself
+pub type Origin = (Option<String>, usize);
+
+/// The configuration with all includes, templates, environment variables and automatic ports resolved
+pub struct LoadedConfig {
+    pub value: Value,
+    /// Where every table and key is defined, by their TOML path
+    pub origins: HashMap<String, Origin>,
+}
+
+/// Reads the configuration file and resolves it:
+/// * the files and `*.toml` files of the directories in `include` are merged into it
+/// * handlers with a `template` get the settings of the `[handler_template.<name>]`,
+///   chains with a `template` get handlers made from the `[chain_template.<name>]`
+/// * `${NAME}` and `${NAME:-default}` in a string are replaced by the environment variable `NAME`
+/// * ports of transport handlers that are `"auto"` are assigned from `settings.transport_port_range`
+///
+/// # Arguments
+/// * `config_file` - The path of the configuration file
+///
+/// # Returns
+/// The resolved configuration, or a ConfigurationError that names the problem and where it is
+pub fn load_config_file(config_file: &str) -> Result<LoadedConfig> {
+    let config_path = Path::new(config_file);
+    let mut loader = Loader {
+        config_dir: config_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf(),
+        origins: HashMap::new(),
+        including: Vec::new(),
+    };
+    let mut table = loader.read_file(config_path, None)?;
+    expand_templates(&mut table, &mut loader.origins)?;
+    let mut value = Value::Table(table);
+    interpolate(&mut value, "")?;
+    if let Value::Table(table) = &mut value {
+        assign_transport_ports(table)?;
+    }
+    Ok(LoadedConfig {
+        value,
+        origins: loader.origins,
+    })
+}
+
+/// Returns true when the handler argument is a port, `port`, `<name>_port` or `port_<name>`
+pub fn is_port_key(key: &str) -> bool {
+    key == "port" || key.ends_with("_port") || key.starts_with("port_")
+}
+
+/// A ConfigurationError at a TOML path
+fn error_at(path: &str, message: String) -> Error {
+    ConfigurationError(format!("{}: {}", path, message)).into()
+}
+
+struct Loader {
+    config_dir: PathBuf,
+    origins: HashMap<String, Origin>,
+    ///The files that are being read, to find an include of a file in itself
+    including: Vec<PathBuf>,
+}
+
+impl Loader {
+    /// Reads a file and the files it includes
+    fn read_file(&mut self, path: &Path, label: Option<String>) -> Result<Table> {
+        let canonical = path.canonicalize().map_err(|e| {
+            Error::from(ConfigurationError(format!(
+                "Cannot read {}: {}",
+                path.display(),
+                e
+            )))
+        })?;
+        if self.including.contains(&canonical) {
+            return Err(ConfigurationError(format!("{} includes itself", path.display())).into());
+        }
+        let toml_string = fs::read_to_string(path).map_err(|e| {
+            Error::from(ConfigurationError(format!(
+                "Cannot read {}: {}",
+                path.display(),
+                e
+            )))
+        })?;
+        for (key, line) in find_lines(&toml_string) {
+            self.origins.entry(key).or_insert((label.clone(), line));
+        }
+        let mut table = match toml::from_str::<Value>(&toml_string) {
+            Ok(Value::Table(table)) => table,
+            Ok(_) => Table::new(),
+            Err(e) => {
+                return Err(
+                    ConfigurationError(format!("Cannot parse {}: {}", path.display(), e)).into(),
+                )
+            }
+        };
+
+        let includes = match table.remove("include") {
+            None => Vec::new(),
+            Some(Value::String(include)) => vec![include],
+            Some(Value::Array(includes)) => {
+                let mut paths = Vec::new();
+                for include in includes {
+                    match include {
+                        Value::String(include) => paths.push(include),
+                        other => {
+                            return Err(error_at("include", format!("{} is not a path", other)))
+                        }
+                    }
+                }
+                paths
+            }
+            Some(other) => {
+                return Err(error_at(
+                    "include",
+                    format!("{} is not a path or an array of paths", other),
+                ))
+            }
+        };
+        self.including.push(canonical);
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for include in includes {
+            for file in toml_files(&base.join(&include))? {
+                let label = file
+                    .strip_prefix(&self.config_dir)
+                    .unwrap_or(&file)
+                    .display()
+                    .to_string();
+                let included = self.read_file(&file, Some(label.clone()))?;
+                merge(&mut table, included, "", &label)?;
+            }
+        }
+        self.including.pop();
+        Ok(table)
+    }
+}
+
+/// Returns the file, or the `*.toml` files in the directory sorted by name
+fn toml_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    let entries = fs::read_dir(path).map_err(|e| {
+        Error::from(ConfigurationError(format!(
+            "Cannot read {}: {}",
+            path.display(),
+            e
+        )))
+    })?;
+    for entry in entries {
+        let file = entry
+            .chain_err(|| format!("Error reading {}", path.display()))?
+            .path();
+        if file.extension() == Some(std::ffi::OsStr::new("toml")) && !file.is_dir() {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Merges an included table into the table, a key that is defined in both is an error
+fn merge(into: &mut Table, from: Table, path: &str, label: &str) -> Result<()> {
+    for (key, value) in from {
+        let key_path = if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        match (into.get_mut(&key), value) {
+            (None, value) => {
+                into.insert(key, value);
+            }
+            (Some(Value::Table(existing)), Value::Table(value)) => {
+                merge(existing, value, &key_path, label)?
+            }
+            (Some(_), _) => {
+                return Err(error_at(
+                    &key_path,
+                    format!("is defined more than once, again in {}", label),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes a table of templates from the configuration
+fn take_templates(table: &mut Table, name: &str) -> Result<Table> {
+    match table.remove(name) {
+        None => Ok(Table::new()),
+        Some(Value::Table(templates)) => Ok(templates),
+        Some(_) => Err(error_at(name, format!("expected [{}.<name>] tables", name))),
+    }
+}
+
+/// Returns the settings of a handler template, merged with the settings that override them
+fn from_handler_template(
+    templates: &Table,
+    template: &Value,
+    overrides: &Table,
+    path: &str,
+) -> Result<Table> {
+    let name = template
+        .as_str()
+        .ok_or_else(|| error_at(path, format!("{} is not the name of a template", template)))?;
+    let mut settings = match templates.get(name) {
+        Some(Value::Table(settings)) => settings.clone(),
+        Some(_) => {
+            return Err(error_at(
+                path,
+                format!(
+                    "handler_template.{} is not a table of handler settings",
+                    name
+                ),
+            ))
+        }
+        None => {
+            return Err(error_at(
+                path,
+                format!(
+                    "template {} is not defined, expected a [handler_template.{}] table",
+                    name, name
+                ),
+            ))
+        }
+    };
+    if settings.contains_key("template") {
+        return Err(error_at(
+            &format!("handler_template.{}.template", name),
+            "a template cannot use another template".to_string(),
+        ));
+    }
+    for (key, value) in overrides {
+        settings.insert(key.to_string(), value.clone());
+    }
+    Ok(settings)
+}
+
+/// Replaces `${chain}` in all strings of the value with the name of the chain
+fn substitute_chain(value: &mut Value, chain: &str) {
+    match value {
+        Value::String(x) => *x = x.replace("${chain}", chain),
+        Value::Array(elements) => elements
+            .iter_mut()
+            .for_each(|element| substitute_chain(element, chain)),
+        Value::Table(table) => table
+            .iter_mut()
+            .for_each(|(_, value)| substitute_chain(value, chain)),
+        _ => (),
+    }
+}
+
+/// Resolves the handlers and chains that use a template
+fn expand_templates(table: &mut Table, origins: &mut HashMap<String, Origin>) -> Result<()> {
+    let handler_templates = take_templates(table, "handler_template")?;
+    let chain_templates = take_templates(table, "chain_template")?;
+
+    for kind in HANDLER_TABLES.iter() {
+        if let Some(Value::Table(handlers)) = table.get_mut(*kind) {
+            for (name, handler) in handlers.iter_mut() {
+                if let Value::Table(settings) = handler {
+                    if let Some(template) = settings.remove("template") {
+                        let path = format!("{}.{}.template", kind, name);
+                        *settings =
+                            from_handler_template(&handler_templates, &template, settings, &path)?;
+                    }
+                }
+            }
+        }
+    }
+
+    let chain_names: Vec<String> = match table.get("chain") {
+        Some(Value::Table(chains)) => chains
+            .iter()
+            .filter(|(_, chain)| chain.get("template").is_some())
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    for chain_name in chain_names {
+        let chain_path = format!("chain.{}", chain_name);
+        let mut chain = match table
+            .get("chain")
+            .and_then(|chains| chains.get(&chain_name))
+        {
+            Some(Value::Table(chain)) => chain.clone(),
+            _ => continue,
+        };
+        let template_value = chain.remove("template").unwrap_or(Value::Boolean(false));
+        let template_name = template_value.as_str().ok_or_else(|| {
+            error_at(
+                &format!("{}.template", chain_path),
+                format!("{} is not the name of a template", template_value),
+            )
+        })?;
+        let template = match chain_templates.get(template_name) {
+            Some(Value::Table(template)) => template,
+            _ => {
+                return Err(error_at(
+                    &format!("{}.template", chain_path),
+                    format!(
+                        "template {} is not defined, expected a [chain_template.{}] table",
+                        template_name, template_name
+                    ),
+                ))
+            }
+        };
+        let template_path = format!("chain_template.{}", template_name);
+
+        //the handlers of the chain, made from the handler templates unless the chain names a handler
+        let mut generated: Vec<(&str, String, Table)> = Vec::new();
+        for (role, kind, suffix) in &[
+            ("protocol_handler", "protocolhandler", "ph"),
+            ("transport_handler", "transporthandler", "transport"),
+        ] {
+            let overrides = match chain.get(*role) {
+                Some(Value::String(_)) => continue,
+                Some(Value::Table(overrides)) => overrides.clone(),
+                Some(other) => {
+                    return Err(error_at(
+                        &format!("{}.{}", chain_path, role),
+                        format!("{} is not a handler name or a table of settings", other),
+                    ))
+                }
+                None => Table::new(),
+            };
+            let template_handler = template.get(*role).ok_or_else(|| {
+                error_at(
+                    &template_path,
+                    format!("the {} of the template is missing", role),
+                )
+            })?;
+            let settings = from_handler_template(
+                &handler_templates,
+                template_handler,
+                &overrides,
+                &format!("{}.{}", template_path, role),
+            )?;
+            let name = format!("{}_{}", chain_name, suffix);
+            chain.insert(role.to_string(), Value::String(name.clone()));
+            generated.push((kind, name, settings));
+        }
+        if chain.get("filter_handlers").is_none() {
+            let filters = match template.get("filter_handlers") {
+                None => Vec::new(),
+                Some(Value::Array(filters)) => filters.clone(),
+                Some(other) => {
+                    return Err(error_at(
+                        &format!("{}.filter_handlers", template_path),
+                        format!("{} is not an array of template names", other),
+                    ))
+                }
+            };
+            let mut names = Vec::new();
+            for (index, filter) in filters.iter().enumerate() {
+                let settings = from_handler_template(
+                    &handler_templates,
+                    filter,
+                    &Table::new(),
+                    &format!("{}.filter_handlers[{}]", template_path, index),
+                )?;
+                let name = format!("{}_filter{}", chain_name, index + 1);
+                names.push(Value::String(name.clone()));
+                generated.push(("filterhandler", name, settings));
+            }
+            chain.insert("filter_handlers".to_string(), Value::Array(names));
+        }
+        if chain.get("link").is_none() {
+            if let Some(link) = template.get("link") {
+                chain.insert("link".to_string(), link.clone());
+            }
+        }
+
+        for (kind, name, settings) in generated {
+            let mut settings = Value::Table(settings);
+            substitute_chain(&mut settings, &chain_name);
+            let handlers = table
+                .entry(kind.to_string())
+                .or_insert_with(|| Value::Table(Table::new()));
+            let handlers = handlers
+                .as_table_mut()
+                .ok_or_else(|| error_at(kind, format!("expected [{}.<name>] tables", kind)))?;
+            if handlers.contains_key(&name) {
+                return Err(error_at(
+                    &chain_path,
+                    format!(
+                        "the template makes handler {}, but {}.{} is already defined",
+                        name, kind, name
+                    ),
+                ));
+            }
+            handlers.insert(name.to_string(), settings);
+            //a made handler is reported at its chain
+            if let Some(origin) = origins.get(&chain_path).cloned() {
+                origins.insert(format!("{}.{}", kind, name), origin);
+            }
+        }
+        if let Some(Value::Table(chains)) = table.get_mut("chain") {
+            chains.insert(chain_name, Value::Table(chain));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the environment variables in all strings of the value
+fn interpolate(value: &mut Value, path: &str) -> Result<()> {
+    match value {
+        Value::String(x) => {
+            *x = interpolate_string(x).map_err(|message| error_at(path, message))?;
+        }
+        Value::Array(elements) => {
+            for element in elements.iter_mut() {
+                interpolate(element, path)?;
+            }
+        }
+        Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                if path.is_empty() {
+                    interpolate(value, key)?;
+                } else {
+                    interpolate(value, &format!("{}.{}", path, key))?;
+                }
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Replaces `${NAME}` with the environment variable `NAME`, or with `default` for `${NAME:-default}`
+/// when it is not set. `$$` is a `$`.
+fn interpolate_string(string: &str) -> std::result::Result<String, String> {
+    let mut result = String::new();
+    let mut rest = string;
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            result.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("{} has a ${{ without a }}", string))?;
+            let expression = &after[..end];
+            let (name, default) = match expression.find(":-") {
+                Some(index) => (&expression[..index], Some(&expression[index + 2..])),
+                None => (expression, None),
+            };
+            match (std::env::var(name), default) {
+                (Ok(value), _) => result.push_str(&value),
+                (Err(_), Some(default)) => result.push_str(default),
+                (Err(_), None) if name == "chain" => {
+                    return Err(
+                        "${chain} is only replaced in the handlers a chain template makes, \
+                         use the name of the chain here"
+                            .to_string(),
+                    )
+                }
+                (Err(_), None) => {
+                    return Err(format!(
+                        "environment variable {} is not set, use ${{{}:-default}} for a default",
+                        name, name
+                    ))
+                }
+            }
+            rest = &after[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Parses `settings.transport_port_range`, `<first>-<last>`
+fn port_range(table: &Table) -> Result<Option<(u16, u16)>> {
+    let path = "settings.transport_port_range";
+    let range = match table
+        .get("settings")
+        .and_then(|x| x.get("transport_port_range"))
+    {
+        None => return Ok(None),
+        Some(Value::String(range)) => range,
+        Some(other) => return Err(error_at(path, format!("{} is not a range", other))),
+    };
+    let ports: Vec<Option<u16>> = range.split('-').map(|x| x.trim().parse().ok()).collect();
+    match ports.as_slice() {
+        [Some(first), Some(last)] if first <= last && *first > 0 => Ok(Some((*first, *last))),
+        _ => Err(error_at(
+            path,
+            format!("{} is not a range of ports, expected <first>-<last>", range),
+        )),
+    }
+}
+
+/// Returns the ports that are set in the settings and handlers with their path, an auto port is never one of them
+fn explicit_ports(table: &Table) -> HashMap<u32, String> {
+    let mut ports = HashMap::new();
+    let mut add = |path: String, value: &Value| {
+        let port = match value {
+            Value::Integer(port) => u16::try_from(*port).ok(),
+            Value::String(port) => port.parse::<u16>().ok(),
+            _ => None,
+        };
+        if let Some(port) = port {
+            ports.entry(u32::from(port)).or_insert(path);
+        }
+    };
+    if let Some(port) = table
+        .get("settings")
+        .and_then(|x| x.get("stats_multiplexer_listening_port"))
+    {
+        add(
+            "settings.stats_multiplexer_listening_port".to_string(),
+            port,
+        );
+    }
+    for kind in HANDLER_TABLES.iter() {
+        if let Some(Value::Table(handlers)) = table.get(*kind) {
+            for (name, handler) in handlers
+                .iter()
+                .filter_map(|(name, handler)| handler.as_table().map(|handler| (name, handler)))
+            {
+                handler
+                    .iter()
+                    .filter(|(key, _)| is_port_key(key))
+                    .for_each(|(key, value)| add(format!("{}.{}.{}", kind, name, key), value));
+            }
+        }
+    }
+    ports
+}
+
+/// Gives the transport handlers with an `"auto"` port a port of `settings.transport_port_range`.
+/// The port is derived from the name of the chain, so it stays the same when other chains are added or removed,
+/// and both sides of the diode give a chain the same port. The second transport handler of a chain with edges
+/// uses `<chain>.2` as name, and so on. A port that an earlier chain got is skipped for the next port of the range,
+/// the chains are handled in the order of their names. All auto ports of one handler get the same port.
+/// A port that is set in the config file is an error instead of skipped, the ports that are set differ
+/// between both sides of the diode, so skipping them could give a chain another port on each side.
+fn assign_transport_ports(table: &mut Table) -> Result<()> {
+    let range = port_range(table)?;
+    let transport_handlers = match table.get("transporthandler") {
+        Some(Value::Table(handlers)) => handlers.clone(),
+        _ => Table::new(),
+    };
+    //the name the port is derived from, the chain and the transport handler
+    let mut keys: Vec<(String, String, String)> = Vec::new();
+    match table.get("chain") {
+        Some(Value::Table(chain_tables)) => {
+            for (name, chain) in chain_tables {
+                //the transport handlers of a DAG are the handlers its edges end at, in the order of the edges
+                let edges = chain.get("edges").and_then(Value::as_array);
+                let mut handlers: Vec<&str> = Vec::new();
+                for handler in edges
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|edge| edge.get(1))
+                    .chain(chain.get("transport_handler"))
+                    .filter_map(Value::as_str)
+                    .filter(|handler| transport_handlers.contains_key(*handler))
+                {
+                    if !handlers.contains(&handler) {
+                        handlers.push(handler);
+                    }
+                }
+                for (index, handler) in handlers.into_iter().enumerate() {
+                    let key = match index {
+                        0 => name.to_string(),
+                        _ => format!("{}.{}", name, index + 1),
+                    };
+                    keys.push((key, name.to_string(), handler.to_string()));
+                }
+            }
+        }
+        _ => return Ok(()),
+    }
+    keys.sort();
+    let explicit = explicit_ports(table);
+    let mut used = HashSet::new();
+    for (key, chain, handler_name) in keys {
+        let handler = match table
+            .get_mut("transporthandler")
+            .and_then(|handlers| handlers.get_mut(&handler_name))
+        {
+            Some(Value::Table(handler)) => handler,
+            _ => continue,
+        };
+        //a handler in several chains already has its port from the first of them
+        let auto_keys: Vec<String> = handler
+            .iter()
+            .filter(|(key, value)| is_port_key(key) && value.as_str() == Some(AUTO_PORT))
+            .map(|(key, _)| key.to_string())
+            .collect();
+        if auto_keys.is_empty() {
+            continue;
+        }
+        let path = format!("transporthandler.{}.{}", handler_name, auto_keys[0]);
+        let (first, last) = match range {
+            Some((first, last)) => (u32::from(first), u32::from(last)),
+            None => {
+                return Err(error_at(
+                    &path,
+                    "is auto, but settings.transport_port_range is not set".to_string(),
+                ))
+            }
+        };
+        let size = last - first + 1;
+        let mut hasher = FnvHasher::default();
+        hasher.write(key.as_bytes());
+        let start = (hasher.finish() % u64::from(size)) as u32;
+        let port = (0..size)
+            .map(|offset| first + (start + offset) % size)
+            .find(|port| !used.contains(port))
+            .ok_or_else(|| {
+                error_at(
+                    &path,
+                    format!(
+                        "there is no port left in settings.transport_port_range for chain {}",
+                        chain
+                    ),
+                )
+            })?;
+        if let Some(other) = explicit.get(&port) {
+            return Err(error_at(
+                &path,
+                format!(
+                    "port {} of chain {} is already set in {}, change the port or settings.transport_port_range",
+                    port, chain, other
+                ),
+            ));
+        }
+        used.insert(port);
+        for key in auto_keys {
+            handler.insert(key, Value::Integer(i64::from(port)));
+        }
+    }
+    Ok(())
+}
+
+/// Finds the line of every table and key in a configuration file, by their TOML path
+fn find_lines(toml_string: &str) -> HashMap<String, usize> {
+    let mut lines = HashMap::new();
+    let mut table = String::new();
+    for (index, line) in toml_string.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            table = line
+                .trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_string();
+            //the enclosing tables start at their first sub table
+            for (dot, _) in table.match_indices('.') {
+                lines.entry(table[..dot].to_string()).or_insert(index + 1);
+            }
+            lines.entry(table.clone()).or_insert(index + 1);
+        } else if let Some(equals) = line.find('=') {
+            if line.starts_with('#') {
+                continue;
+            }
+            let key = line[..equals].trim().trim_matches('"');
+            lines
+                .entry(format!("{}.{}", table, key))
+                .or_insert(index + 1);
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config_loader::*;
+    use tempdir::TempDir;
+
+    const SETTINGS: &str = "[settings]\ntransport_port_range = \"7000-7999\"\n";
+
+    fn load(dir: &TempDir, files: &[(&str, &str)]) -> Result<Value> {
+        for (name, content) in files {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).expect("Can't create dir");
+            fs::write(path, content).expect("Can't write file");
+        }
+        let config_file = dir.path().join(files[0].0);
+        load_config_file(&config_file.to_string_lossy()).map(|config| config.value)
+    }
+
+    fn auto_chain(name: &str) -> String {
+        format!(
+            "[transporthandler.{}_transport]\nport = \"auto\"\n\
+             [chain.{}]\ntransport_handler = \"{}_transport\"\n",
+            name, name, name
+        )
+    }
+
+    fn port(config: &Value, handler: &str) -> Option<i64> {
+        config["transporthandler"][handler]["port"].as_integer()
+    }
+
+    #[test]
+    fn include_test() {
+        let dir = TempDir::new("include_test").expect("Can't create dir");
+        let config = load(
+            &dir,
+            &[
+                (
+                    "Config.toml",
+                    "include = \"handlers\"\n[settings]\npath = \"/home/osdd\"\n",
+                ),
+                (
+                    "handlers/a.toml",
+                    "[protocolhandler.a]\ntype = \"ph_kafka\"\n",
+                ),
+                (
+                    "handlers/b.toml",
+                    "[protocolhandler.b]\ntype = \"ph_udp\"\n",
+                ),
+                ("handlers/ignored.txt", "not toml"),
+            ],
+        )
+        .expect("Can't load config");
+        assert_eq!(config["settings"]["path"].as_str(), Some("/home/osdd"));
+        assert_eq!(
+            config["protocolhandler"]["a"]["type"].as_str(),
+            Some("ph_kafka")
+        );
+        assert_eq!(
+            config["protocolhandler"]["b"]["type"].as_str(),
+            Some("ph_udp")
+        );
+        assert!(config.get("include").is_none());
+
+        let dir = TempDir::new("include_twice_test").expect("Can't create dir");
+        let error = load(
+            &dir,
+            &[
+                (
+                    "Config.toml",
+                    "include = \"a.toml\"\n[protocolhandler.a]\ntype = \"ph_udp\"\n",
+                ),
+                ("a.toml", "[protocolhandler.a]\ntype = \"ph_kafka\"\n"),
+            ],
+        )
+        .expect_err("A key defined twice is an error");
+        assert!(error
+            .to_string()
+            .contains("protocolhandler.a.type: is defined more than once, again in a.toml"));
+
+        let dir = TempDir::new("include_itself_test").expect("Can't create dir");
+        let error = load(&dir, &[("Config.toml", "include = \"Config.toml\"\n")])
+            .expect_err("A file that includes itself is an error");
+        assert!(error.to_string().contains("includes itself"));
+    }
+
+    #[test]
+    fn template_test() {
+        let dir = TempDir::new("template_test").expect("Can't create dir");
+        let config = load(
+            &dir,
+            &[(
+                "Config.toml",
+                "[handler_template.kafka]\ntype = \"ph_kafka\"\ntopic_name = \"${chain}\"\n\
+                 [handler_template.udp]\ntype = \"transport_udp_send\"\nport = \"1234\"\n\
+                 [chain_template.kafka_topic]\nprotocol_handler = \"kafka\"\ntransport_handler = \"udp\"\n\
+                 [chain.orders]\ntemplate = \"kafka_topic\"\n\
+                 [chain.payments]\ntemplate = \"kafka_topic\"\nprotocol_handler = { topic_name = \"payments.v2\" }\n",
+            )],
+        )
+        .expect("Can't load config");
+        let handlers = &config["protocolhandler"];
+        assert_eq!(handlers["orders_ph"]["topic_name"].as_str(), Some("orders"));
+        assert_eq!(
+            handlers["payments_ph"]["topic_name"].as_str(),
+            Some("payments.v2")
+        );
+        assert_eq!(
+            config["transporthandler"]["orders_transport"]["port"].as_str(),
+            Some("1234")
+        );
+        assert_eq!(
+            config["chain"]["orders"]["protocol_handler"].as_str(),
+            Some("orders_ph")
+        );
+        assert!(config.get("handler_template").is_none());
+
+        //outside a template ${chain} is not an environment variable
+        let dir = TempDir::new("chain_outside_template_test").expect("Can't create dir");
+        let error = load(
+            &dir,
+            &[(
+                "Config.toml",
+                "[protocolhandler.a]\ntopic_name = \"${chain}\"\n",
+            )],
+        )
+        .expect_err("${chain} outside a template is an error");
+        let message = error.to_string();
+        assert!(message.contains("protocolhandler.a.topic_name: ${chain} is only replaced"));
+    }
+
+    #[test]
+    fn interpolate_test() {
+        std::env::set_var("OSDD_INTERPOLATE_TEST", "kafka:9092");
+        std::env::remove_var("OSDD_INTERPOLATE_TEST_UNSET");
+        assert_eq!(
+            interpolate_string("tcp://${OSDD_INTERPOLATE_TEST}/x"),
+            Ok("tcp://kafka:9092/x".to_string())
+        );
+        assert_eq!(
+            interpolate_string("${OSDD_INTERPOLATE_TEST_UNSET:-localhost}"),
+            Ok("localhost".to_string())
+        );
+        assert_eq!(
+            interpolate_string("$$HOME and $5"),
+            Ok("$HOME and $5".to_string())
+        );
+        assert!(interpolate_string("${OSDD_INTERPOLATE_TEST_UNSET}").is_err());
+        assert!(interpolate_string("${OSDD_INTERPOLATE_TEST").is_err());
+    }
+
+    #[test]
+    fn auto_port_test() {
+        let dir = TempDir::new("auto_port_test").expect("Can't create dir");
+        let config = load(
+            &dir,
+            &[(
+                "Config.toml",
+                &(SETTINGS.to_string() + &auto_chain("orders")),
+            )],
+        )
+        .expect("Can't load config");
+        let orders_port = port(&config, "orders_transport").expect("No port assigned");
+        assert!((7000..=7999).contains(&orders_port));
+
+        //the port of a chain stays the same when other chains are added
+        let dir = TempDir::new("auto_port_stable_test").expect("Can't create dir");
+        let chains = ["alerts", "invoices", "orders", "payments", "zones"]
+            .iter()
+            .map(|name| auto_chain(name))
+            .collect::<String>();
+        let config = load(&dir, &[("Config.toml", &(SETTINGS.to_string() + &chains))])
+            .expect("Can't load config");
+        assert_eq!(port(&config, "orders_transport"), Some(orders_port));
+        let mut ports: Vec<i64> = ["alerts", "invoices", "orders", "payments", "zones"]
+            .iter()
+            .filter_map(|name| port(&config, &format!("{}_transport", name)))
+            .collect();
+        ports.sort();
+        ports.dedup();
+        assert_eq!(ports.len(), 5);
+    }
+
+    #[test]
+    fn auto_port_collision_test() {
+        //a port that is set in the config file is not skipped, it can differ on the other side of the diode
+        let dir = TempDir::new("auto_port_collision_test").expect("Can't create dir");
+        let error = load(
+            &dir,
+            &[(
+                "Config.toml",
+                &("[settings]\ntransport_port_range = \"7000-7000\"\n\
+                   [protocolhandler.fixed]\nopen_udp_port = \"7000\"\n"
+                    .to_string()
+                    + &auto_chain("orders")),
+            )],
+        )
+        .expect_err("A port that is set is an error");
+        assert!(error.to_string().contains(
+            "port 7000 of chain orders is already set in protocolhandler.fixed.open_udp_port"
+        ));
+
+        //a port an earlier chain got is skipped
+        let dir = TempDir::new("auto_port_skip_test").expect("Can't create dir");
+        let config = load(
+            &dir,
+            &[(
+                "Config.toml",
+                &("[settings]\ntransport_port_range = \"7000-7001\"\n".to_string()
+                    + &auto_chain("orders")
+                    + &auto_chain("payments")),
+            )],
+        )
+        .expect("Can't load config");
+        let mut ports: Vec<Option<i64>> = vec![
+            port(&config, "orders_transport"),
+            port(&config, "payments_transport"),
+        ];
+        ports.sort();
+        assert_eq!(ports, vec![Some(7000), Some(7001)]);
+
+        let dir = TempDir::new("auto_port_full_test").expect("Can't create dir");
+        let error = load(
+            &dir,
+            &[(
+                "Config.toml",
+                &("[settings]\ntransport_port_range = \"7000-7001\"\n".to_string()
+                    + &auto_chain("alerts")
+                    + &auto_chain("orders")
+                    + &auto_chain("payments")),
+            )],
+        )
+        .expect_err("A full range is an error");
+        assert!(error
+            .to_string()
+            .contains("there is no port left in settings.transport_port_range for chain payments"));
+    }
+}
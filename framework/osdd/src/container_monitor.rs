@@ -0,0 +1,393 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::*;
+use error_chain::ChainedError;
+use statistics_handler::errors::ErrorKind::CustomField;
+use statistics_handler::{statistics_inner_thread, StatisticData};
+use statsd::client::Pipeline;
+use std::fmt;
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The time between two inspections of the containers
+const MONITOR_INTERVAL: Duration = Duration::from_secs(5);
+/// The window in which the restarts of a container are counted to see if it is crash looping
+const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(300);
+/// A container that restarted this many times within the window is crash looping
+const CRASH_LOOP_RESTARTS: usize = 3;
+
+/// The state of a container according to docker
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContainerState {
+    Created,
+    Running,
+    Restarting,
+    Paused,
+    Exited(i32),
+    Dead,
+    /// There is no container with the name, starting it failed or it was removed
+    Missing,
+    /// A state this version of osdd does not know
+    Other(String),
+}
+
+impl fmt::Display for ContainerState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContainerState::Created => write!(f, "created"),
+            ContainerState::Running => write!(f, "running"),
+            ContainerState::Restarting => write!(f, "restarting"),
+            ContainerState::Paused => write!(f, "paused"),
+            ContainerState::Exited(code) => write!(f, "exited ({})", code),
+            ContainerState::Dead => write!(f, "dead"),
+            ContainerState::Missing => write!(f, "missing"),
+            ContainerState::Other(state) => write!(f, "{}", state),
+        }
+    }
+}
+
+/// The state of a container and the times docker restarted it
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContainerStatus {
+    pub state: ContainerState,
+    pub restart_count: u32,
+}
+
+/// Asks docker for the state of the container with the given name.
+///
+/// # Arguments
+/// * `name` - The name of the container, `osdd.<instance>.<network>.<chain>.<type>.<handler>`
+///
+/// # Returns
+/// The status of the container, with the state `Missing` when there is no such container
+pub fn inspect_container(name: &str) -> Result<ContainerStatus> {
+    let output = Command::new("docker")
+        .args([
+            "inspect",
+            "--type",
+            "container",
+            "--format",
+            "{{.State.Status}} {{.State.ExitCode}} {{.RestartCount}}",
+            name,
+        ])
+        .output()
+        .chain_err(|| format!("Error inspecting container {}", name))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No such") {
+            return Ok(ContainerStatus {
+                state: ContainerState::Missing,
+                restart_count: 0,
+            });
+        }
+        return Err(format!("Error inspecting container {}: {}", name, stderr.trim()).into());
+    }
+    parse_inspect_output(&String::from_utf8_lossy(&output.stdout))
+        .chain_err(|| format!("Error inspecting container {}", name))
+}
+
+/// Parses the status, exit code and restart count printed by `docker inspect`
+fn parse_inspect_output(output: &str) -> Result<ContainerStatus> {
+    let fields: Vec<&str> = output.split_whitespace().collect();
+    if fields.len() != 3 {
+        return Err(format!("Unexpected output of docker inspect: '{}'", output.trim()).into());
+    }
+    let state = match fields[0] {
+        "created" => ContainerState::Created,
+        "running" => ContainerState::Running,
+        "restarting" => ContainerState::Restarting,
+        "paused" => ContainerState::Paused,
+        "exited" => ContainerState::Exited(fields[1].parse()?),
+        "dead" => ContainerState::Dead,
+        other => ContainerState::Other(other.to_string()),
+    };
+    Ok(ContainerStatus {
+        state,
+        restart_count: fields[2].parse()?,
+    })
+}
+
+/// What osdd knows about the container of a handler
+struct MonitoredContainer {
+    name: String,
+    status: Option<ContainerStatus>,
+    restarts: Vec<Instant>,
+    crash_looping: bool,
+}
+
+impl MonitoredContainer {
+    /// Creates a container nothing is known about yet
+    fn new(name: String) -> MonitoredContainer {
+        MonitoredContainer {
+            name,
+            status: None,
+            restarts: Vec::new(),
+            crash_looping: false,
+        }
+    }
+
+    /// Stores the new status of the container and logs what changed since the previous one
+    fn update(&mut self, status: ContainerStatus) {
+        let previous = self.status.replace(status.clone());
+        let previous_restart_count = previous.as_ref().map_or(0, |p| p.restart_count);
+        if previous.as_ref().map(|p| &p.state) != Some(&status.state) {
+            match &status.state {
+                ContainerState::Running | ContainerState::Created => {
+                    log::info!("Container {} is {}", self.name, status.state)
+                }
+                ContainerState::Missing => log::error!(
+                    "Container {} does not exist, it was not started or it was removed",
+                    self.name
+                ),
+                state => log::error!("Container {} is {}", self.name, state),
+            }
+        }
+
+        let now = Instant::now();
+        if status.restart_count > previous_restart_count && previous.is_some() {
+            log::warn!(
+                "Container {} was restarted, {} restarts in total",
+                self.name,
+                status.restart_count
+            );
+            for _ in previous_restart_count..status.restart_count {
+                self.restarts.push(now);
+            }
+        }
+        self.restarts
+            .retain(|restart| now.duration_since(*restart) < CRASH_LOOP_WINDOW);
+        let crash_looping = self.restarts.len() >= CRASH_LOOP_RESTARTS;
+        if crash_looping && !self.crash_looping {
+            log::error!(
+                "Container {} is crash looping, it restarted {} times in {:?}",
+                self.name,
+                self.restarts.len(),
+                CRASH_LOOP_WINDOW
+            );
+        } else if !crash_looping && self.crash_looping {
+            log::info!("Container {} stopped crash looping", self.name);
+        }
+        self.crash_looping = crash_looping;
+    }
+}
+
+/// The status of the containers of all handlers, published as statsd gauges.
+/// For every handler `<name>.up` is 1 when its container runs and 0 otherwise,
+/// `<name>.restarts` is the number of times docker restarted it.
+pub struct HandlerStatuses {
+    containers: Mutex<Vec<MonitoredContainer>>,
+}
+
+impl HandlerStatuses {
+    /// Creates the statuses of the containers with the given names, nothing is known about them yet
+    pub fn new(names: Vec<String>) -> HandlerStatuses {
+        HandlerStatuses {
+            containers: Mutex::new(names.into_iter().map(MonitoredContainer::new).collect()),
+        }
+    }
+
+    /// Changes the containers that are monitored, what is known about the containers that stay is kept
+    pub fn set_names(&self, names: Vec<String>) {
+        if let Ok(mut containers) = self.containers.lock() {
+            let mut previous: Vec<MonitoredContainer> = containers.drain(..).collect();
+            for name in names {
+                match previous.iter().position(|c| c.name == name) {
+                    Some(index) => containers.push(previous.remove(index)),
+                    None => containers.push(MonitoredContainer::new(name)),
+                }
+            }
+        }
+    }
+
+    /// Inspects all containers once and logs the changes.
+    /// Returns the errors of the containers that could not be inspected.
+    pub fn inspect_all(&self) -> Vec<Error> {
+        let names: Vec<String> = match self.containers.lock() {
+            Ok(containers) => containers.iter().map(|c| c.name.to_string()).collect(),
+            Err(_) => return Vec::new(),
+        };
+        let mut errors = Vec::new();
+        for name in names {
+            match inspect_container(&name) {
+                Ok(status) => {
+                    if let Ok(mut containers) = self.containers.lock() {
+                        if let Some(container) = containers.iter_mut().find(|c| c.name == name) {
+                            container.update(status);
+                        }
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        errors
+    }
+
+    /// Returns a line for every container with its state and restarts
+    pub fn summary(&self) -> String {
+        let containers = match self.containers.lock() {
+            Ok(containers) => containers,
+            Err(_) => return String::new(),
+        };
+        let width = containers.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        let mut summary = String::new();
+        for container in containers.iter() {
+            let (state, restarts) = match &container.status {
+                Some(status) => (status.state.to_string(), status.restart_count),
+                None => ("unknown".to_string(), 0),
+            };
+            summary.push_str(&format!(
+                "{:width$}  {:12}  {} restarts{}\n",
+                container.name,
+                state,
+                restarts,
+                if container.crash_looping {
+                    "  crash looping"
+                } else {
+                    ""
+                },
+                width = width
+            ));
+        }
+        summary
+    }
+}
+
+impl StatisticData for HandlerStatuses {
+    fn fill_pipeline(&self, pipeline: &mut Pipeline) {
+        if let Ok(containers) = self.containers.lock() {
+            for container in containers.iter() {
+                if let Some(status) = &container.status {
+                    let up = match status.state {
+                        ContainerState::Running => 1.0,
+                        _ => 0.0,
+                    };
+                    pipeline.gauge(&format!("{}.up", container.name), up);
+                    pipeline.gauge(
+                        &format!("{}.restarts", container.name),
+                        status.restart_count as f64,
+                    );
+                }
+            }
+        }
+    }
+
+    fn set_custom_gauge(&self, _number: u64) -> statistics_handler::errors::Result<()> {
+        Err(CustomField("The handler statuses have no custom gauge".to_string()).into())
+    }
+
+    fn add_custom_counter(&self, _number: u64) -> statistics_handler::errors::Result<()> {
+        Err(CustomField("The handler statuses have no custom counter".to_string()).into())
+    }
+}
+
+/// Monitors the containers of the statuses in another thread.
+/// Their state is inspected every few seconds, changes are logged
+/// and the statuses are sent to the stats multiplexer of osdd.
+///
+/// # Arguments
+/// * `statuses` - The statuses of the containers of all handlers
+/// * `stats_port` - The port of the stats multiplexer
+pub fn monitor_containers(
+    statuses: Arc<HandlerStatuses>,
+    stats_port: u16,
+) -> std::io::Result<JoinHandle<()>> {
+    let stats_data = statuses.clone();
+    thread::Builder::new()
+        .name("container_stats".into())
+        .spawn(move || {
+            if let Err(e) = statistics_inner_thread(
+                Arc::new(AtomicBool::new(true)),
+                format!("127.0.0.1:{}", stats_port),
+                String::new(),
+                stats_data,
+            ) {
+                log::error!("{}", e.display_chain());
+            }
+        })?;
+    thread::Builder::new()
+        .name("container_monitor".into())
+        .spawn(move || loop {
+            for e in statuses.inspect_all() {
+                log::warn!("{}", e.display_chain());
+            }
+            thread::sleep(MONITOR_INTERVAL);
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::container_monitor::*;
+
+    fn status(state: ContainerState, restart_count: u32) -> ContainerStatus {
+        ContainerStatus {
+            state,
+            restart_count,
+        }
+    }
+
+    #[test]
+    fn parse_inspect_output_test() {
+        assert_eq!(
+            parse_inspect_output("running 0 2\n").unwrap(),
+            status(ContainerState::Running, 2)
+        );
+        assert_eq!(
+            parse_inspect_output("exited 137 0\n").unwrap(),
+            status(ContainerState::Exited(137), 0)
+        );
+        assert_eq!(
+            parse_inspect_output("removing 0 0").unwrap(),
+            status(ContainerState::Other("removing".to_string()), 0)
+        );
+        assert_eq!(
+            parse_inspect_output("running 0").unwrap_err().to_string(),
+            "Unexpected output of docker inspect: 'running 0'"
+        );
+        assert!(parse_inspect_output("running 0 many").is_err());
+    }
+
+    #[test]
+    fn crash_loop_test() {
+        let mut container = MonitoredContainer::new("osdd.1.ingress.orders.ph.kafka".to_string());
+        //the restarts before osdd started monitoring the container are not counted
+        container.update(status(ContainerState::Running, 5));
+        assert!(container.restarts.is_empty());
+        container.update(status(ContainerState::Restarting, 7));
+        assert_eq!(container.restarts.len(), 2);
+        assert!(!container.crash_looping);
+        container.update(status(ContainerState::Running, 8));
+        assert!(container.crash_looping);
+    }
+
+    #[test]
+    fn statuses_test() {
+        let statuses = HandlerStatuses::new(vec!["osdd.1.a".to_string(), "osdd.1.b".to_string()]);
+        statuses.containers.lock().unwrap()[1].update(status(ContainerState::Exited(1), 3));
+        assert_eq!(
+            statuses.summary(),
+            "osdd.1.a  unknown       0 restarts\nosdd.1.b  exited (1)    3 restarts\n"
+        );
+        //the status of a container that stays is kept, a new container is unknown
+        statuses.set_names(vec!["osdd.1.b".to_string(), "osdd.1.c".to_string()]);
+        assert_eq!(
+            statuses.summary(),
+            "osdd.1.b  exited (1)    3 restarts\nosdd.1.c  unknown       0 restarts\n"
+        );
+    }
+}
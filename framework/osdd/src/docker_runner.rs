@@ -16,14 +16,24 @@ use crate::errors::ErrorKind::ErrorStartingProcess;
 use crate::errors::*;
 use crate::*;
 
-/// Starts processes from the given commands
+/// Starts processes from the given commands.
+/// A command that fails, for example a `docker run` of a container name that is already in use,
+/// is logged with its output and does not stop the other handlers from starting.
 pub fn handle_processes(commands: Vec<CommandWithName>) -> Result<()> {
     //start al handlers
-    for mut command_with_name in commands {
-        command_with_name
-            .command
-            .spawn()
-            .chain_err(|| ErrorStartingProcess(command_with_name.command))?;
+    for command_with_name in commands {
+        let CommandWithName { mut command, name } = command_with_name;
+        let output = command
+            .output()
+            .chain_err(|| ErrorStartingProcess(command))?;
+        if !output.status.success() {
+            log::error!(
+                "Error starting handler {}, {}: {}",
+                name,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
     }
     Ok(())
 }
@@ -48,5 +48,9 @@ error_chain! {
             description("Configuration error")
             display("Configuration error: '{}'", t)
         }
+        DockerUnavailable(t: String) {
+            description("Docker is not available")
+            display("Docker is not available: '{}'", t)
+        }
     }
 }
@@ -0,0 +1,368 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::*;
+use std::str::FromStr;
+
+/// The path of docker used in systemd units
+const DOCKER_PATH: &str = "/usr/bin/docker";
+
+/// The formats a deployment can be exported to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExportFormat {
+    /// A docker-compose file with a service for every handler
+    Compose,
+    /// A systemd unit file for every handler
+    Systemd,
+    /// A Graphviz DOT diagram of the chains
+    Dot,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> std::result::Result<ExportFormat, String> {
+        match format {
+            "compose" => Ok(ExportFormat::Compose),
+            "systemd" => Ok(ExportFormat::Systemd),
+            "dot" => Ok(ExportFormat::Dot),
+            _ => Err(format!(
+                "Unknown export format {}, expected compose, systemd or dot",
+                format
+            )),
+        }
+    }
+}
+
+/// Exports the deployments to files in the given format
+/// # Returns
+/// * `Vec<(String, String)>` - The name and the content of every file.
+pub fn export(deployments: &[HandlerDeployment], format: ExportFormat) -> Vec<(String, String)> {
+    match format {
+        ExportFormat::Compose => vec![("docker-compose.yml".to_string(), to_compose(deployments))],
+        ExportFormat::Systemd => deployments
+            .iter()
+            .map(|x| (format!("{}.service", x.name), to_systemd_unit(x)))
+            .collect(),
+        ExportFormat::Dot => vec![("osdd.dot".to_string(), to_dot(deployments))],
+    }
+}
+
+/// Returns the command as a line for a shell, including its working directory
+pub fn shell_line(command: &Command) -> String {
+    let mut line = String::new();
+    if let Some(dir) = command.get_current_dir() {
+        line.push_str(&format!("cd {} && ", shell_quote(&dir.to_string_lossy())));
+    }
+    line.push_str(&shell_quote(&command.get_program().to_string_lossy()));
+    for argument in command.get_args() {
+        line.push(' ');
+        line.push_str(&shell_quote(&argument.to_string_lossy()));
+    }
+    line
+}
+
+/// Quotes a word for a shell when it contains other characters than letters, digits and `_./:=,@%+-`
+fn shell_quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_./:=,@%+-".contains(c))
+    {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Escapes the backslashes and double quotes of a string for YAML and DOT
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Quotes a string for YAML and DOT, which both accept double quoted strings with backslash escapes
+fn double_quote(value: &str) -> String {
+    format!("\"{}\"", escape(value))
+}
+
+/// Returns a docker-compose file with a service for every handler, as osdd runs it with the docker runtime
+fn to_compose(deployments: &[HandlerDeployment]) -> String {
+    let list = |values: Vec<String>| {
+        let quoted: Vec<String> = values.iter().map(|x| double_quote(x)).collect();
+        format!("[{}]", quoted.join(", "))
+    };
+    let mut compose = String::from("version: \"3\"\nservices:\n");
+    for deployment in deployments {
+        compose.push_str(&format!("  {}:\n", double_quote(&deployment.name)));
+        compose.push_str(&format!(
+            "    image: {}\n",
+            double_quote(&deployment.executable)
+        ));
+        compose.push_str(&format!(
+            "    container_name: {}\n",
+            double_quote(&deployment.name)
+        ));
+        compose.push_str(&format!(
+            "    entrypoint: {}\n",
+            list(vec![format!("./{}", deployment.executable)])
+        ));
+        compose.push_str(&format!(
+            "    command: {}\n",
+            list(deployment.arguments.clone())
+        ));
+        if deployment.host_network {
+            compose.push_str("    network_mode: \"host\"\n");
+            compose.push_str("    cap_add: [\"SYS_NICE\"]\n");
+        }
+        if !deployment.published_ports.is_empty() {
+            compose.push_str(&format!(
+                "    ports: {}\n",
+                list(deployment.published_ports.clone())
+            ));
+        }
+        if let Some((uid, gid)) = deployment.user {
+            compose.push_str(&format!("    user: \"{}:{}\"\n", uid, gid));
+        }
+        compose.push_str(&format!(
+            "    volumes: {}\n",
+            list(vec![format!(
+                "{}:{}",
+                deployment.host_socket_dir().display(),
+                PATH_PREFIX_UNIX_SOCKETS_IN_DOCKER
+            )])
+        ));
+        compose.push_str("    restart: \"always\"\n");
+    }
+    compose
+}
+
+/// Quotes a word for the command line of a systemd unit
+fn systemd_quote(word: &str) -> String {
+    let word = word.replace('%', "%%");
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| !c.is_whitespace() && c != '"' && c != '\'' && c != '\\' && c != ';')
+    {
+        word
+    } else {
+        double_quote(&word)
+    }
+}
+
+/// Returns a systemd unit that runs the handler in the foreground and restarts it when it stops
+fn to_systemd_unit(deployment: &HandlerDeployment) -> String {
+    let quote_all = |words: Vec<String>| {
+        let quoted: Vec<String> = words.iter().map(|x| systemd_quote(x)).collect();
+        quoted.join(" ")
+    };
+    let mut unit = format!("[Unit]\nDescription=OSDD handler {}\n", deployment.name);
+    let mut service = format!("[Service]\nWorkingDirectory={}\n", deployment.working_dir);
+    match deployment.runtime {
+        Runtime::Docker => {
+            unit.push_str("After=docker.service\nRequires=docker.service\n");
+            service.push_str(&format!(
+                "ExecStartPre=-{} rm -f {}\n",
+                DOCKER_PATH, deployment.name
+            ));
+            let mut command = vec![DOCKER_PATH.to_string()];
+            command.extend(deployment.docker_arguments(false));
+            service.push_str(&format!("ExecStart={}\n", quote_all(command)));
+            service.push_str(&format!(
+                "ExecStop={} stop {}\n",
+                DOCKER_PATH, deployment.name
+            ));
+        }
+        Runtime::Native => {
+            unit.push_str("After=network.target\n");
+            let mut command = vec![deployment.native_program().to_string_lossy().to_string()];
+            command.extend(deployment.arguments.iter().cloned());
+            service.push_str(&format!("ExecStart={}\n", quote_all(command)));
+            if let Some((uid, gid)) = deployment.user {
+                service.push_str(&format!("User={}\nGroup={}\n", uid, gid));
+            }
+        }
+    }
+    service.push_str("Restart=always\nRestartSec=1\n");
+    format!(
+        "{}\n{}\n[Install]\nWantedBy=multi-user.target\n",
+        unit, service
+    )
+}
+
+/// Returns a Graphviz DOT diagram with a cluster for every chain and its handlers from left to right
+fn to_dot(deployments: &[HandlerDeployment]) -> String {
+    let mut dot = String::from("digraph osdd {\n    rankdir=LR;\n    node [shape=box];\n");
+    let mut chain_start = 0;
+    while chain_start < deployments.len() {
+        let chain = &deployments[chain_start].chain;
+        let chain_len = deployments[chain_start..]
+            .iter()
+            .take_while(|x| &x.chain == chain)
+            .count();
+        let handlers = &deployments[chain_start..chain_start + chain_len];
+        dot.push_str(&format!(
+            "    subgraph {} {{\n        label={};\n",
+            double_quote(&format!("cluster_{}", chain)),
+            double_quote(chain)
+        ));
+        for handler in handlers {
+            dot.push_str(&format!(
+                "        {} [label=\"{}\\n{}\"];\n",
+                double_quote(&handler.name),
+                escape(&handler.handler),
+                escape(&handler.executable)
+            ));
+        }
+        for handler in handlers {
+            for (next, socket) in &handler.outgoing_sockets {
+                if let Some(next) = handlers.iter().find(|x| &x.handler == next) {
+                    dot.push_str(&format!(
+                        "        {} -> {} [label={}];\n",
+                        double_quote(&handler.name),
+                        double_quote(&next.name),
+                        double_quote(&link_label(handler, socket))
+                    ));
+                }
+            }
+        }
+        dot.push_str("    }\n");
+        chain_start += chain_len;
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Returns the kind of link from the handler to a next handler, or the address for a tcp or tls link
+fn link_label(deployment: &HandlerDeployment, socket: &str) -> String {
+    if socket.starts_with("tcp://") || socket.starts_with("tls://") {
+        socket.split('?').next().unwrap_or(socket).to_string()
+    } else {
+        deployment
+            .link
+            .clone()
+            .unwrap_or_else(|| "socket".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::*;
+
+    fn chain() -> Vec<HandlerDeployment> {
+        let protocol_handler = crate::tests::deployment("/tmp/osdd.1.ingress.orders_kafka_udp");
+        let mut transport_handler = crate::tests::deployment("");
+        transport_handler.name = "osdd.1.ingress.orders.transport.udp".to_string();
+        transport_handler.handler = "transport".to_string();
+        transport_handler.executable = "transport_udp_send".to_string();
+        transport_handler.arguments = vec!["--socket_path".to_string(), "/tmp/x y".to_string()];
+        transport_handler.host_network = true;
+        transport_handler.user = None;
+        transport_handler.outgoing_sockets = Vec::new();
+        vec![protocol_handler, transport_handler]
+    }
+
+    #[test]
+    fn export_format_test() {
+        assert_eq!("systemd".parse(), Ok(ExportFormat::Systemd));
+        assert!("yaml".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn compose_test() {
+        let files = export(&chain(), ExportFormat::Compose);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, "docker-compose.yml");
+        assert_eq!(
+            files[0].1,
+            "version: \"3\"\nservices:\n\
+             \x20 \"osdd.1.ingress.orders.ph.kafka\":\n\
+             \x20   image: \"ph_kafka\"\n\
+             \x20   container_name: \"osdd.1.ingress.orders.ph.kafka\"\n\
+             \x20   entrypoint: [\"./ph_kafka\"]\n\
+             \x20   command: [\"--topic_name\", \"orders\"]\n\
+             \x20   user: \"1000:1000\"\n\
+             \x20   volumes: [\"/home/osdd/sockets:/tmp/\"]\n\
+             \x20   restart: \"always\"\n\
+             \x20 \"osdd.1.ingress.orders.transport.udp\":\n\
+             \x20   image: \"transport_udp_send\"\n\
+             \x20   container_name: \"osdd.1.ingress.orders.transport.udp\"\n\
+             \x20   entrypoint: [\"./transport_udp_send\"]\n\
+             \x20   command: [\"--socket_path\", \"/tmp/x y\"]\n\
+             \x20   network_mode: \"host\"\n\
+             \x20   cap_add: [\"SYS_NICE\"]\n\
+             \x20   volumes: [\"/home/osdd/sockets:/tmp/\"]\n\
+             \x20   restart: \"always\"\n"
+        );
+    }
+
+    #[test]
+    fn systemd_test() {
+        let mut deployments = chain();
+        deployments[0].runtime = Runtime::Native;
+        let files = export(&deployments, ExportFormat::Systemd);
+        assert_eq!(files[0].0, "osdd.1.ingress.orders.ph.kafka.service");
+        assert_eq!(
+            files[0].1,
+            "[Unit]\nDescription=OSDD handler osdd.1.ingress.orders.ph.kafka\nAfter=network.target\n\n\
+             [Service]\nWorkingDirectory=/home/osdd\n\
+             ExecStart=/home/osdd/ph_kafka --topic_name orders\nUser=1000\nGroup=1000\n\
+             Restart=always\nRestartSec=1\n\n[Install]\nWantedBy=multi-user.target\n"
+        );
+        assert_eq!(files[1].0, "osdd.1.ingress.orders.transport.udp.service");
+        let unit = &files[1].1;
+        assert!(unit.contains("After=docker.service\nRequires=docker.service\n"));
+        assert!(unit
+            .contains("ExecStartPre=-/usr/bin/docker rm -f osdd.1.ingress.orders.transport.udp\n"));
+        //the container runs in the foreground and the argument with a space is quoted
+        assert!(unit.contains(
+            "ExecStart=/usr/bin/docker run --rm --network host --cap-add=sys_nice \
+             --name osdd.1.ingress.orders.transport.udp \
+             --mount type=bind,source=/home/osdd/sockets,target=/tmp/ \
+             --entrypoint ./transport_udp_send transport_udp_send --socket_path \"/tmp/x y\"\n"
+        ));
+        assert!(
+            unit.contains("ExecStop=/usr/bin/docker stop osdd.1.ingress.orders.transport.udp\n")
+        );
+    }
+
+    #[test]
+    fn dot_test() {
+        let files = export(&chain(), ExportFormat::Dot);
+        assert_eq!(
+            files,
+            vec![(
+                "osdd.dot".to_string(),
+                "digraph osdd {\n    rankdir=LR;\n    node [shape=box];\n\
+                 \x20   subgraph \"cluster_orders\" {\n        label=\"orders\";\n\
+                 \x20       \"osdd.1.ingress.orders.ph.kafka\" [label=\"kafka\\nph_kafka\"];\n\
+                 \x20       \"osdd.1.ingress.orders.transport.udp\" [label=\"transport\\ntransport_udp_send\"];\n\
+                 \x20       \"osdd.1.ingress.orders.ph.kafka\" -> \"osdd.1.ingress.orders.transport.udp\" \
+                 [label=\"socket\"];\n    }\n}\n"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn shell_line_test() {
+        let mut command = Command::new("/home/osdd/ph_kafka");
+        command.args(["--topic_name", "it's", "--empty", ""]);
+        command.current_dir("/home/my osdd");
+        assert_eq!(
+            shell_line(&command),
+            "cd '/home/my osdd' && /home/osdd/ph_kafka --topic_name 'it'\\''s' --empty ''"
+        );
+    }
+}
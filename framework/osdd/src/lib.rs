@@ -12,23 +12,46 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+/// Reads the configuration file with its includes, templates, environment variables and automatic ports
+pub mod config_loader;
+/// Inspects the containers of the handlers and publishes their status
+pub mod container_monitor;
 /// Starts processes from the given commands
 pub mod docker_runner;
 /// Error chain for OSDD
 pub mod errors;
+/// Exports the deployment of the chains to docker-compose, systemd units or a DOT diagram
+pub mod export;
+/// Starts processes from the given commands as children of osdd and restarts them when they crash
+pub mod process_supervisor;
 /// Read configuration out of the toml file
 pub mod read_toml;
+/// Brings the containers on the proxy in line with the configuration
+pub mod reconcile;
+/// Reloads the configuration while osdd runs, on SIGHUP or when the file changes
+pub mod reload;
+/// Reads the arguments a handler accepts from the handler itself
+pub mod schema;
 /// UDP multiplexer for statitics
 pub mod udp_multiplexer_stats;
+/// Checks the configuration file and reports every problem with its location
+pub mod validate;
 use crate::errors::ErrorKind::ConfigurationError;
 use crate::errors::*;
-use serde::Deserialize;
+use fnv::FnvHasher;
+use serde::{Deserialize, Deserializer};
 use std::fs;
+use std::hash::Hasher;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use toml::Value;
 
-const PATH_PREFIX_UNIX_SOCKETS_ON_PROXY: &str = "/home/osdd/sockets/";
+///The directory in the working directory of osdd the unix domain sockets are created in
+const SOCKETS_DIR: &str = "sockets";
 const PATH_PREFIX_UNIX_SOCKETS_IN_DOCKER: &str = "/tmp/";
+/// The label of a container with the hash of the configuration it was started with
+pub const CONFIG_LABEL: &str = "osdd.config";
 
 /// Set from socket port to `0` for syslog. (0 is auto assiging to a port)
 pub const PORT_FROM_UDP_SYSLOG: u16 = 0;
@@ -39,8 +62,29 @@ pub const FROM_HOST_UDP_SYSLOG: &str = "0.0.0.0";
 /// However the actual limit for the data length, which is imposed by the underlying IPv4 protocol, is 65,507 bytes (65,535 − 8 byte UDP header − 20 byte IP header).
 pub const MAX_BUFFER_SIZE_BYTES: usize = 65507;
 
+/// Reads a setting that can be written as a string or as an integer
+fn string_or_integer<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(x) => Ok(x),
+        Value::Integer(x) => Ok(x.to_string()),
+        value => Err(serde::de::Error::custom(format!(
+            "{} is not a string or integer",
+            value
+        ))),
+    }
+}
+
+/// Reads an optional setting that can be written as a string or as an integer
+fn optional_string_or_integer<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<String>, D::Error> {
+    string_or_integer(deserializer).map(Some)
+}
+
 /// OSDD Settings
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, PartialEq)]
 pub struct Settings {
     /// Sets the working directory for the docker containers.
     pub path: String,
@@ -49,26 +93,151 @@ pub struct Settings {
     /// Tells where the logging of syslog need to be sent.
     pub syslog_host: String,
     /// Tells where the logging of syslog need to be sent.
+    #[serde(deserialize_with = "string_or_integer")]
     pub syslog_port: String,
     /// The amount of logging produced, can be "Error", "Warn" "Info", or "Debug"
     pub log_level: String,
     /// Identifier for this instance of the software
+    #[serde(deserialize_with = "string_or_integer")]
     pub instance: String,
     /// The side of the data diode, can be ingress or egress
     pub network: String,
     /// The port the stats multiplexer is listening on
+    #[serde(deserialize_with = "string_or_integer")]
     pub stats_multiplexer_listening_port: String,
+    /// How the handlers are run, "docker" (default) or "native" to run them as child processes of osdd
+    pub runtime: Option<String>,
+    /// The amount of crashes in a row after which the native runtime stops restarting a handler
+    #[serde(default, deserialize_with = "optional_string_or_integer")]
+    pub max_restarts: Option<String>,
+    /// The ports `<first>-<last>` that transport handler ports that are "auto" are assigned from
+    pub transport_port_range: Option<String>,
 }
 
-/// A chain consists of exactly one transport handler and exactly one protocol handler. A chain can also contain one or more filters. Filters are placed between the protocol handler and the transport handler.
+/// How osdd runs the handlers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Runtime {
+    /// Every handler runs in a Docker container
+    Docker,
+    /// Every handler runs as a child process of osdd, started from the handler binaries in `path`
+    Native,
+}
+
+impl Settings {
+    /// Returns the configured runtime, Docker when it is not set
+    pub fn runtime(&self) -> Result<Runtime> {
+        match self.runtime.as_deref() {
+            None | Some("docker") => Ok(Runtime::Docker),
+            Some("native") => Ok(Runtime::Native),
+            Some(runtime) => Err(ConfigurationError(format!(
+                "Unknown runtime {}, expected docker or native",
+                runtime
+            ))
+            .into()),
+        }
+    }
+
+    /// Returns the configured maximum amount of crashes in a row, `DEFAULT_MAX_RESTARTS` when it is not set
+    pub fn max_restarts(&self) -> Result<u32> {
+        match &self.max_restarts {
+            Some(max_restarts) => max_restarts.parse().chain_err(|| {
+                ConfigurationError(format!("Cannot parse max_restarts {}", max_restarts))
+            }),
+            None => Ok(process_supervisor::DEFAULT_MAX_RESTARTS),
+        }
+    }
+
+    /// Returns the directory on the proxy the unix domain sockets are created in
+    pub fn host_socket_dir(&self) -> PathBuf {
+        host_socket_dir(&self.path)
+    }
+
+    /// Returns the start of the names of all containers and processes of this instance and network,
+    /// `osdd.<instance>.<network>.`
+    pub fn name_prefix(&self) -> String {
+        format!("osdd.{}.{}.", self.instance, self.network)
+    }
+
+    /// Returns the directory the unix domain sockets are created in, as seen by the handlers
+    fn socket_dir(&self, runtime: Runtime) -> String {
+        match runtime {
+            Runtime::Docker => PATH_PREFIX_UNIX_SOCKETS_IN_DOCKER.to_string(),
+            Runtime::Native => format!("{}/", self.host_socket_dir().display()),
+        }
+    }
+}
+
+/// Returns the directory on the proxy the unix domain sockets are created in,
+/// it is mounted in the containers and used by the native handlers
+///
+/// # Arguments
+/// * `path` - The working directory of osdd
+pub fn host_socket_dir(path: &str) -> PathBuf {
+    Path::new(path).join(SOCKETS_DIR)
+}
+
+/// A chain connects protocol handlers, filters and transport handlers. A linear chain consists of exactly one protocol handler,
+/// the filters and exactly one transport handler after each other. The edges of a chain can also form a DAG, so a protocol handler
+/// feeds several filter branches or transport handlers, or several protocol handlers merge into one transport handler.
 pub struct Chain {
     pub name: String,
-    ///The name of the protocol handler must match the name given in the handler
-    pub protocol_handler: String,
-    ///The name of the filter handler must match the name given in the handler
-    pub filter_handlers: Vec<String>,
-    ///The name of the transport handler must match the name given in the handler
-    pub transport_handler: String,
+    ///The pairs of handlers that are linked, from the protocol handler side to the transport handler side.
+    ///The names of the handlers must match the names given in the handlers.
+    pub edges: Vec<Edge>,
+    ///The kind of link between the handlers of the chain, "socket" or "shared_memory".
+    ///When not set the handlers use their default, a unix domain socket.
+    pub link: Option<String>,
+}
+
+/// A link between two handlers of a chain, `[from, to]` or `[from, to, lag_policy]` in the configuration file
+#[derive(Debug, Deserialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    ///What happens when the handler that receives over the edge falls behind the other handlers
+    ///the sending handler fans out to, "block", "drop" or "disconnect". When not set it drops.
+    #[serde(default)]
+    pub lag_policy: Option<String>,
+}
+
+impl Chain {
+    /// Creates a chain of a protocol handler, filters and a transport handler after each other
+    pub fn linear(
+        name: &str,
+        protocol_handler: String,
+        filter_handlers: Vec<String>,
+        transport_handler: String,
+        link: Option<String>,
+    ) -> Chain {
+        let mut handlers = vec![protocol_handler];
+        handlers.extend(filter_handlers);
+        handlers.push(transport_handler);
+        Chain {
+            name: name.to_string(),
+            edges: handlers
+                .windows(2)
+                .map(|pair| Edge {
+                    from: pair[0].to_string(),
+                    to: pair[1].to_string(),
+                    lag_policy: None,
+                })
+                .collect(),
+            link,
+        }
+    }
+
+    /// The names of the handlers in the chain, in the order they first appear in its edges
+    pub fn handlers(&self) -> Vec<&str> {
+        let mut handlers: Vec<&str> = Vec::new();
+        for edge in &self.edges {
+            for handler in &[&edge.from, &edge.to] {
+                if !handlers.contains(&handler.as_str()) {
+                    handlers.push(handler);
+                }
+            }
+        }
+        handlers
+    }
 }
 
 ///A handler read from the TOML file
@@ -78,8 +247,16 @@ pub struct Handler {
     executable: String,
     arguments: Vec<(String, String)>,
     handler_type: HandlerType,
-    incoming_socket: Option<String>,
-    outgoing_socket: Option<String>,
+    ///The addresses of the previous handlers in the chain
+    incoming_sockets: Vec<String>,
+    ///The next handlers in the chain and the address of the socket to each of them
+    outgoing_sockets: Vec<(String, String)>,
+    ///The address the handler connects to instead of the unix domain socket of the previous handler.
+    incoming_address: Option<String>,
+    ///The address the handler listens on instead of a unix domain socket for the next handler.
+    outgoing_address: Option<String>,
+    ///The uid and gid the handler runs as. The neighbours of the handler only accept a peer with this identity.
+    user: Option<(u32, u32)>,
     udp_port_option: Option<u16>,
 }
 
@@ -97,28 +274,224 @@ pub struct CommandWithName {
     pub name: String,
 }
 
-impl Handler {
-    /// Create Docker command from a handler
-    fn create_command(
-        &self,
-        chain_name: &str,
-        stats_port: u16,
-        settings: &Settings,
-    ) -> Result<CommandWithName> {
-        let mut command = Command::new("docker");
-        command.args(&["run"]);
-        command.args(&["-d"]);
+///A handler of a chain with everything needed to run it
+#[derive(Debug)]
+pub struct HandlerDeployment {
+    ///The name of the container or process, `osdd.<instance>.<network>.<chain>.<type>.<handler>`
+    pub name: String,
+    ///The chain the handler is part of
+    pub chain: String,
+    ///The name of the handler in the config file
+    pub handler: String,
+    ///The executable of the handler, also the name of its docker image
+    pub executable: String,
+    ///The arguments the executable is started with
+    pub arguments: Vec<String>,
+    ///The runtime the deployment is created for
+    pub runtime: Runtime,
+    ///The working directory of osdd, the handler binaries are in it with the native runtime
+    pub working_dir: String,
+    ///The container runs in the host network with the sys_nice capability
+    pub host_network: bool,
+    ///The ports published on the container, `port:port` or `port:port/udp`
+    pub published_ports: Vec<String>,
+    ///The uid and gid the handler runs as
+    pub user: Option<(u32, u32)>,
+    ///The kind of link to the next handler in the chain, when it is set for the chain
+    pub link: Option<String>,
+    ///The next handlers in the chain and the address of the socket to each of them
+    pub outgoing_sockets: Vec<(String, String)>,
+}
 
-        if self.handler_type == HandlerType::TransportHandler {
-            command.args(&["--network", "host"]);
-            command.args(&["--cap-add=sys_nice"]);
+impl HandlerDeployment {
+    /// The arguments of `docker` that run the handler in a container.
+    /// A detached container is restarted by docker, otherwise it runs in the foreground for a service manager.
+    pub fn docker_arguments(&self, detached: bool) -> Vec<String> {
+        let mut arguments: Vec<String> = vec!["run".to_string()];
+        if detached {
+            arguments.push("-d".to_string());
+        } else {
+            arguments.push("--rm".to_string());
         }
 
-        //if "open_udp_port" is given to the handler then publish on the same port
-        if let Some(port) = self.udp_port_option {
-            command.args(&[format!("--publish={}:{}/udp", port, port)]);
+        if self.host_network {
+            arguments.extend(vec![
+                "--network".to_string(),
+                "host".to_string(),
+                "--cap-add=sys_nice".to_string(),
+            ]);
+        }
+
+        //published udp and tcp ports
+        for port in &self.published_ports {
+            arguments.push(format!("--publish={}", port));
+        }
+
+        arguments.extend(vec!["--name".to_string(), self.name.to_string()]);
+
+        //osdd up recreates a container that was started with another configuration
+        if detached {
+            arguments.push(format!("--label={}={}", CONFIG_LABEL, self.config_hash()));
+        }
+
+        //run as the configured user, the neighbours of the handler check this identity
+        if let Some((uid, gid)) = self.user {
+            arguments.extend(vec!["--user".to_string(), format!("{}:{}", uid, gid)]);
+        }
+
+        //mount sockets path
+        arguments.extend(vec![
+            "--mount".to_string(),
+            format!(
+                "type=bind,source={},target={}",
+                self.host_socket_dir().display(),
+                PATH_PREFIX_UNIX_SOCKETS_IN_DOCKER
+            ),
+        ]);
+
+        //Restarts the container when it stops
+        if detached {
+            arguments.extend(vec!["--restart".to_string(), "always".to_string()]);
+        }
+
+        arguments.extend(vec![
+            "--entrypoint".to_string(),
+            format!("./{}", &self.executable),
+            self.executable.to_string(),
+        ]);
+        arguments.extend(self.arguments.iter().cloned());
+        arguments
+    }
+
+    /// A hash of everything the handler is started with, it changes when the configuration of the handler changes.
+    /// The hash is stored in the label of a container, so it is the same for every build of osdd.
+    pub fn config_hash(&self) -> String {
+        let runtime = match self.runtime {
+            Runtime::Docker => "docker",
+            Runtime::Native => "native",
+        };
+        let user = self.user.map(|(uid, gid)| format!("{}:{}", uid, gid));
+        let mut fields = vec![
+            self.name.as_str(),
+            &self.chain,
+            &self.handler,
+            &self.executable,
+            runtime,
+            &self.working_dir,
+            if self.host_network { "host" } else { "bridge" },
+            user.as_deref().unwrap_or(""),
+            self.link.as_deref().unwrap_or(""),
+        ];
+        let counts = [
+            self.arguments.len().to_string(),
+            self.published_ports.len().to_string(),
+            self.outgoing_sockets.len().to_string(),
+        ];
+        fields.extend(counts.iter().map(String::as_str));
+        fields.extend(self.arguments.iter().map(String::as_str));
+        fields.extend(self.published_ports.iter().map(String::as_str));
+        for (next, socket) in &self.outgoing_sockets {
+            fields.push(next);
+            fields.push(socket);
+        }
+
+        //every field is preceded by its length, so moving bytes between fields changes the hash
+        let mut hasher = FnvHasher::default();
+        for field in fields {
+            hasher.write(&(field.len() as u64).to_le_bytes());
+            hasher.write(field.as_bytes());
+        }
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Returns the directory on the proxy the unix domain sockets are created in
+    pub fn host_socket_dir(&self) -> PathBuf {
+        host_socket_dir(&self.working_dir)
+    }
+
+    /// The names of the files in the socket directory the handler creates for the next handlers in the chain,
+    /// for the links that are a unix domain socket or shared memory
+    pub fn socket_file_names(&self) -> Vec<String> {
+        self.outgoing_sockets
+            .iter()
+            .filter_map(|(_, socket)| {
+                if socket.starts_with("tcp://") || socket.starts_with("tls://") {
+                    return None;
+                }
+                let path = socket.strip_prefix("unix://").unwrap_or(socket);
+                let path = path.split('?').next()?;
+                Path::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .collect()
+    }
+
+    /// The path of the handler binary with the native runtime
+    pub fn native_program(&self) -> PathBuf {
+        Path::new(&self.working_dir).join(&self.executable)
+    }
+
+    /// Create the command that runs the handler with the runtime of the deployment
+    pub fn command(&self) -> CommandWithName {
+        let mut command = match self.runtime {
+            Runtime::Docker => {
+                let mut command = Command::new("docker");
+                command.args(self.docker_arguments(true));
+                command
+            }
+            Runtime::Native => {
+                let mut command = Command::new(self.native_program());
+                command.args(&self.arguments);
+                //run as the configured user, the neighbours of the handler check this identity
+                if let Some((uid, gid)) = self.user {
+                    command.uid(uid);
+                    command.gid(gid);
+                }
+                command
+            }
+        };
+        command.current_dir(&self.working_dir);
+        CommandWithName {
+            command,
+            name: self.name.to_string(),
         }
+    }
+}
 
+impl Handler {
+    /// The addresses of the previous handlers, separated by commas when the handler merges several of them
+    fn incoming_socket(&self) -> Option<String> {
+        if self.incoming_sockets.is_empty() {
+            None
+        } else {
+            Some(self.incoming_sockets.join(","))
+        }
+    }
+
+    /// The addresses of the next handlers, separated by commas when the handler sends to several of them
+    fn outgoing_socket(&self) -> Option<String> {
+        if self.outgoing_sockets.is_empty() {
+            None
+        } else {
+            let sockets: Vec<&str> = self
+                .outgoing_sockets
+                .iter()
+                .map(|(_, socket)| socket.as_str())
+                .collect();
+            Some(sockets.join(","))
+        }
+    }
+
+    /// Create the deployment of a handler in a chain with the given runtime
+    fn create_deployment(
+        &self,
+        chain_name: &str,
+        link: Option<&str>,
+        stats_port: u16,
+        settings: &Settings,
+        runtime: Runtime,
+    ) -> Result<HandlerDeployment> {
         //short name is needed for the correct naming format
         let handler_type_short_name = match self.handler_type {
             HandlerType::TransportHandler => "transport",
@@ -131,96 +504,135 @@ impl Handler {
             &settings.instance, &settings.network, chain_name, handler_type_short_name, &self.name
         );
 
-        command.args(&["--name", &chain_handler_name]);
-
-        //mount sockets path
-        command.args(&[
-            "--mount",
-            &format!(
-                "type=bind,source={},target={}",
-                PATH_PREFIX_UNIX_SOCKETS_ON_PROXY, PATH_PREFIX_UNIX_SOCKETS_IN_DOCKER
-            ),
-        ]);
-
-        //Removes the Container after stopping
-        command.args(&["--restart", "always"]);
-
-        command.args(&["--entrypoint", &format!("./{}", &self.executable)]);
-
-        command.args(&[&self.executable]);
         //Load all arguments
+        let mut arguments = Vec::new();
         for argument in &self.arguments {
-            let dash_dash_argument = format!("--{}", argument.0);
-            command.args(&[dash_dash_argument, argument.1.to_string()]);
+            arguments.push(format!("--{}", argument.0));
+            arguments.push(argument.1.to_string());
         }
 
         //Arguments for sockets
         match self.handler_type {
             HandlerType::ProtocolHandler | HandlerType::TransportHandler => {
-                command_socket_path_transport_protocol(&self, &mut command)?
+                command_socket_path_transport_protocol(&self, &mut arguments)?
             }
-            HandlerType::FilterHandler => command_socket_paths_filter(&self, &mut command)?,
+            HandlerType::FilterHandler => command_socket_paths_filter(&self, &mut arguments)?,
         };
+        if let Some(link) = link {
+            arguments.extend(vec!["--link".to_string(), link.to_string()]);
+        }
 
-        //Set standard arguments
-        if self.handler_type == HandlerType::ProtocolHandler
-            || self.handler_type == HandlerType::FilterHandler
+        //Set standard arguments, a docker container reaches the stats multiplexer through the docker bridge
+        let stats_server_address = if runtime == Runtime::Docker
+            && (self.handler_type == HandlerType::ProtocolHandler
+                || self.handler_type == HandlerType::FilterHandler)
         {
-            command.args(&["--stats_server_address", "172.17.0.1"]);
+            "172.17.0.1"
         } else {
-            command.args(&["--stats_server_address", "127.0.0.1"]);
+            "127.0.0.1"
+        };
+        for (name, value) in &[
+            ("--stats_server_address", stats_server_address.to_string()),
+            ("--stats_server_port", stats_port.to_string()),
+            ("--from_host_sys_log", FROM_HOST_UDP_SYSLOG.to_string()),
+            ("--from_port_sys_log", PORT_FROM_UDP_SYSLOG.to_string()),
+            ("--to_host_sys_log", settings.syslog_host.to_string()),
+            ("--to_port_sys_log", settings.syslog_port.to_string()),
+            ("--handler_name", chain_handler_name.to_string()),
+        ] {
+            arguments.push(name.to_string());
+            arguments.push(value.to_string());
         }
-        command.args(&["--stats_server_port", &stats_port.to_string()]);
-        command.args(&["--from_host_sys_log", FROM_HOST_UDP_SYSLOG]);
-        command.args(&["--from_port_sys_log", &PORT_FROM_UDP_SYSLOG.to_string()]);
-        command.args(&["--to_host_sys_log", &settings.syslog_host]);
-        command.args(&["--to_port_sys_log", &settings.syslog_port]);
-        command.args(&["--handler_name", &chain_handler_name]);
 
-        command.current_dir(settings.path.to_string());
+        let mut published_ports = Vec::new();
+        //if "open_udp_port" is given to the handler then publish on the same port
+        if let Some(port) = self.udp_port_option {
+            published_ports.push(format!("{}:{}/udp", port, port));
+        }
+        //a handler that listens on a tcp or tls address for the next handler publishes that port
+        if let Some(port) = self.outgoing_address.as_deref().and_then(tcp_port) {
+            if self.handler_type != HandlerType::TransportHandler {
+                published_ports.push(format!("{}:{}", port, port));
+            }
+        }
 
-        Ok(CommandWithName {
-            command,
+        Ok(HandlerDeployment {
             name: chain_handler_name,
+            chain: chain_name.to_string(),
+            handler: self.name.to_string(),
+            executable: self.executable.to_string(),
+            arguments,
+            runtime,
+            working_dir: settings.path.to_string(),
+            host_network: self.handler_type == HandlerType::TransportHandler,
+            published_ports,
+            user: self.user,
+            link: link.map(str::to_string),
+            outgoing_sockets: self.outgoing_sockets.clone(),
         })
     }
 }
 
-/// Creates docker commands of the given handlers
+/// Creates the commands of the given handlers for the configured runtime
 pub fn create_commands_all_handlers(
     chains: Vec<Chain>,
-    mut handlers_config: Vec<Handler>,
+    handlers_config: Vec<Handler>,
     stats_multiplexer_listening_port_u16: u16,
     settings: &Settings,
 ) -> Result<Vec<CommandWithName>> {
-    let mut commands: Vec<CommandWithName> = Vec::new();
-    for mut chain in chains {
-        //Create a vector of the chain
-        let mut handlers_to_create: Vec<String> = Vec::new();
-        handlers_to_create.push(chain.protocol_handler);
-        handlers_to_create.append(&mut chain.filter_handlers);
-        handlers_to_create.push(chain.transport_handler);
-
-        //loop all pairs of the chain
-        //Set outgoing socket for the first and incoming socket fot the second
-        for item in handlers_to_create.windows(2) {
-            let process_to_start1 = &item[0];
-            let process_to_start2 = &item[1];
+    Ok(create_deployments_all_handlers(
+        chains,
+        handlers_config,
+        stats_multiplexer_listening_port_u16,
+        settings,
+        settings.runtime()?,
+    )?
+    .iter()
+    .map(HandlerDeployment::command)
+    .collect())
+}
+
+/// Creates the deployments of the given handlers for a runtime, in the order of the chains
+pub fn create_deployments_all_handlers(
+    chains: Vec<Chain>,
+    mut handlers_config: Vec<Handler>,
+    stats_multiplexer_listening_port_u16: u16,
+    settings: &Settings,
+    runtime: Runtime,
+) -> Result<Vec<HandlerDeployment>> {
+    let socket_dir = settings.socket_dir(runtime);
+    let mut deployments: Vec<HandlerDeployment> = Vec::new();
+    for chain in chains {
+        //the sockets of a handler that is also used in an earlier chain are assigned again
+        for handler in handlers_config
+            .iter_mut()
+            .filter(|x| chain.handlers().contains(&x.name.as_str()))
+        {
+            handler.incoming_sockets.clear();
+            handler.outgoing_sockets.clear();
+        }
+
+        //Set an outgoing socket for the first and an incoming socket for the second handler of every edge
+        for edge in &chain.edges {
             assign_sockets(
                 &mut handlers_config,
-                process_to_start1,
-                process_to_start2,
+                edge,
+                settings.network == "egress",
                 &chain.name,
+                &socket_dir,
+                &settings.name_prefix(),
             )?;
         }
 
-        //Create commands to run dockers with all settings get and set before
-        for handler_to_create in handlers_to_create {
+        //Create deployments with all settings get and set before
+        for handler_to_create in chain.handlers() {
             match handlers_config.iter().find(|x| x.name == handler_to_create) {
-                Some(handler_config) => commands.push(handler_config.create_command(
+                Some(handler_config) => deployments.push(handler_config.create_deployment(
                     &chain.name,
+                    chain.link.as_deref(),
                     stats_multiplexer_listening_port_u16,
                     &settings,
+                    runtime,
                 )?),
                 None => {
                     return Err(ConfigurationError(format!(
@@ -232,21 +644,74 @@ pub fn create_commands_all_handlers(
             }
         }
     }
-    Ok(commands)
+    Ok(deployments)
 }
 
+/// Sets the outgoing socket of the first and the incoming socket of the second handler of an edge.
+/// The lag policy of the edge is given to the handler that sends over it, the first handler
+/// in the ingress network and the second handler in the egress network.
 fn assign_sockets(
     handlers_config: &mut Vec<Handler>,
-    process1: &str,
-    process2: &str,
+    edge: &Edge,
+    egress: bool,
     chain_name: &str,
+    socket_dir: &str,
+    name_prefix: &str,
 ) -> Result<()> {
+    let (process1, process2) = (edge.from.as_str(), edge.to.as_str());
+    //the name prefix keeps the sockets of other instances and networks in the same directory apart
+    let path = format!(
+        "{}{}{}_{}_{}",
+        socket_dir, name_prefix, chain_name, process1, process2
+    );
+    //a unix domain socket or ring file only accepts the configured user of the handler on the other side.
+    let user_of = |process: &str| {
+        handlers_config
+            .iter()
+            .find(|x| x.name == process)
+            .and_then(|x| x.user)
+    };
+    let (user1, user2) = (user_of(process1), user_of(process2));
+    let unix_address = |peer: Option<(u32, u32)>, mode: &str| match peer {
+        Some((uid, gid)) => {
+            format!("unix://{}?{}peer_uid={}&peer_gid={}", path, mode, uid, gid)
+        }
+        _ => path.clone(),
+    };
+
+    let with_lag_policy = |socket: String, sends: bool| match &edge.lag_policy {
+        Some(lag_policy) if sends => {
+            let separator = if socket.contains('?') { '&' } else { '?' };
+            format!("{}{}lag_policy={}", socket, separator, lag_policy)
+        }
+        _ => socket,
+    };
+
+    //a handler can only listen on or connect to its own address for a single other handler
+    let single_address = |handler: &Handler, address: &Option<String>, sockets: usize| match address
+    {
+        Some(address) if sockets > 0 => Err(ConfigurationError(format!(
+            "Handler {} uses {} and cannot be linked to several handlers in chain {}",
+            handler.name, address, chain_name
+        ))),
+        _ => Ok(()),
+    };
+
     match handlers_config.iter_mut().find(|x| x.name == process1) {
         Some(handler) => {
-            handler.outgoing_socket = Some(format!(
-                "{}{}_{}_{}",
-                PATH_PREFIX_UNIX_SOCKETS_IN_DOCKER, chain_name, process1, process2
-            ))
+            single_address(
+                handler,
+                &handler.outgoing_address,
+                handler.outgoing_sockets.len(),
+            )?;
+            let socket = match &handler.outgoing_address {
+                Some(address) => address.to_string(),
+                None => unix_address(user2, "mode=660&"),
+            };
+            let socket = with_lag_policy(socket, !egress);
+            handler
+                .outgoing_sockets
+                .push((process2.to_string(), socket));
         }
         None => {
             return Err(ConfigurationError(format!(
@@ -259,10 +724,17 @@ fn assign_sockets(
 
     match handlers_config.iter_mut().find(|x| x.name == process2) {
         Some(handler) => {
-            handler.incoming_socket = Some(format!(
-                "{}{}_{}_{}",
-                PATH_PREFIX_UNIX_SOCKETS_IN_DOCKER, chain_name, process1, process2
-            ))
+            single_address(
+                handler,
+                &handler.incoming_address,
+                handler.incoming_sockets.len(),
+            )?;
+            let socket = match &handler.incoming_address {
+                Some(address) => address.to_string(),
+                None => unix_address(user1, ""),
+            };
+            let socket = with_lag_policy(socket, egress);
+            handler.incoming_sockets.push(socket);
         }
         None => {
             return Err(ConfigurationError(format!(
@@ -276,33 +748,193 @@ fn assign_sockets(
     Ok(())
 }
 
-fn command_socket_path_transport_protocol(handler: &Handler, command: &mut Command) -> Result<()> {
-    let socket_path = match &handler.incoming_socket {
+fn command_socket_path_transport_protocol(
+    handler: &Handler,
+    arguments: &mut Vec<String>,
+) -> Result<()> {
+    let socket_path = match handler.incoming_socket() {
         Some(x) => x,
-        None => &handler.outgoing_socket.as_ref().chain_err(|| {
+        None => handler.outgoing_socket().chain_err(|| {
             ConfigurationError(format!(
                 "Cannot bind {} to other handler in chain",
                 handler.name
             ))
         })?,
     };
-    command.args(&["--socket_path", &socket_path]);
+    arguments.extend(vec!["--socket_path".to_string(), socket_path]);
     Ok(())
 }
-fn command_socket_paths_filter(handler: &Handler, command: &mut Command) -> Result<()> {
-    let incoming_socket = handler.incoming_socket.as_ref().chain_err(|| {
+fn command_socket_paths_filter(handler: &Handler, arguments: &mut Vec<String>) -> Result<()> {
+    let incoming_socket = handler.incoming_socket().chain_err(|| {
         ConfigurationError(format!(
             "Cannot bind {} to other handler in chain",
             handler.name
         ))
     })?;
-    command.args(&["--socket_path_in", &incoming_socket]);
-    let outgoing_socket = handler.outgoing_socket.as_ref().chain_err(|| {
+    arguments.extend(vec!["--socket_path_in".to_string(), incoming_socket]);
+    let outgoing_socket = handler.outgoing_socket().chain_err(|| {
         ConfigurationError(format!(
             "Cannot bind {} to other handler in chain",
             handler.name
         ))
     })?;
-    command.args(&["--socket_path_out", &outgoing_socket]);
+    arguments.extend(vec!["--socket_path_out".to_string(), outgoing_socket]);
     Ok(())
 }
+
+///Returns the port of a `tcp://host:port` or `tls://host:port?..` address.
+fn tcp_port(address: &str) -> Option<u16> {
+    let host_port = if let Some(host_port) = address.strip_prefix("tcp://") {
+        host_port
+    } else {
+        address.strip_prefix("tls://")?
+    };
+    let host_port = host_port.split('?').next()?;
+    host_port.rsplit(':').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reconcile::remove_stale_sockets;
+    use crate::*;
+    use tempdir::TempDir;
+
+    /// A deployment of the protocol handler of chain orders, for the tests of all modules
+    pub(crate) fn deployment(outgoing_socket: &str) -> HandlerDeployment {
+        HandlerDeployment {
+            name: "osdd.1.ingress.orders.ph.kafka".to_string(),
+            chain: "orders".to_string(),
+            handler: "kafka".to_string(),
+            executable: "ph_kafka".to_string(),
+            arguments: vec!["--topic_name".to_string(), "orders".to_string()],
+            runtime: Runtime::Docker,
+            working_dir: "/home/osdd".to_string(),
+            host_network: false,
+            published_ports: Vec::new(),
+            user: Some((1000, 1000)),
+            link: None,
+            outgoing_sockets: vec![("transport".to_string(), outgoing_socket.to_string())],
+        }
+    }
+
+    #[test]
+    fn config_hash_test() {
+        let mut deployment = deployment("/tmp/osdd.1.ingress.orders_kafka_transport");
+        //the hash is stored in the label of a container, so it must not change between builds
+        assert_eq!(deployment.config_hash(), "d2fcea6d7e8a8cd5");
+        deployment.arguments = vec!["--topic_name".to_string(), "orders2".to_string()];
+        assert_ne!(deployment.config_hash(), "d2fcea6d7e8a8cd5");
+    }
+
+    #[test]
+    fn docker_mounts_host_socket_dir_test() {
+        let arguments = deployment("/tmp/osdd.1.ingress.orders_kafka_transport")
+            .docker_arguments(true)
+            .join(" ");
+        assert!(arguments.contains("--mount type=bind,source=/home/osdd/sockets,target=/tmp/"));
+    }
+
+    /// A handler without settings, linked to other handlers by `assign_sockets`
+    fn handler(name: &str, user: Option<(u32, u32)>) -> Handler {
+        Handler {
+            name: name.to_string(),
+            executable: name.to_string(),
+            arguments: Vec::new(),
+            handler_type: HandlerType::FilterHandler,
+            incoming_sockets: Vec::new(),
+            outgoing_sockets: Vec::new(),
+            incoming_address: None,
+            outgoing_address: None,
+            user,
+            udp_port_option: None,
+        }
+    }
+
+    #[test]
+    fn edge_lag_policy_test() {
+        let edges = vec![
+            Edge {
+                from: "kafka".to_string(),
+                to: "udp1".to_string(),
+                lag_policy: Some("block".to_string()),
+            },
+            Edge {
+                from: "kafka".to_string(),
+                to: "udp2".to_string(),
+                lag_policy: None,
+            },
+        ];
+        for egress in &[false, true] {
+            let mut handlers = vec![
+                handler("kafka", None),
+                handler("udp1", Some((1000, 1000))),
+                handler("udp2", None),
+            ];
+            for edge in &edges {
+                assign_sockets(&mut handlers, edge, *egress, "orders", "/tmp/", "osdd.1.")
+                    .expect("Can't assign sockets");
+            }
+            //the handler that sends over the edge gets the lag policy, the other one does not need it
+            let (sending, receiving) = (
+                "unix:///tmp/osdd.1.orders_kafka_udp1?mode=660&peer_uid=1000&peer_gid=1000",
+                "/tmp/osdd.1.orders_kafka_udp1",
+            );
+            let (outgoing, incoming) = if *egress {
+                (
+                    sending.to_string(),
+                    format!("{}?lag_policy=block", receiving),
+                )
+            } else {
+                (
+                    format!("{}&lag_policy=block", sending),
+                    receiving.to_string(),
+                )
+            };
+            assert_eq!(
+                handlers[0].outgoing_socket(),
+                Some(format!("{},/tmp/osdd.1.orders_kafka_udp2", outgoing))
+            );
+            assert_eq!(handlers[1].incoming_sockets, vec![incoming]);
+            assert_eq!(
+                handlers[2].incoming_sockets,
+                vec!["/tmp/osdd.1.orders_kafka_udp2"]
+            );
+        }
+    }
+
+    #[test]
+    fn remove_stale_sockets_test() {
+        let socket_dir = TempDir::new("remove_stale_sockets_test").expect("Can't create dir");
+        for file_name in &[
+            "osdd.1.ingress.orders_kafka_transport",
+            "osdd.1.ingress.removed_kafka_transport",
+            "osdd.2.ingress.orders_kafka_transport",
+            "osdd.1.egress.orders_kafka_transport",
+        ] {
+            fs::write(socket_dir.path().join(file_name), "").expect("Can't create file");
+        }
+        let deployments = vec![deployment(
+            "unix:///tmp/osdd.1.ingress.orders_kafka_transport?mode=660&",
+        )];
+        let changes = remove_stale_sockets(&deployments, "osdd.1.ingress.", socket_dir.path())
+            .expect("Can't remove sockets");
+        assert_eq!(
+            changes,
+            vec!["Removed stale socket osdd.1.ingress.removed_kafka_transport"]
+        );
+        //the sockets of other instances and networks are kept
+        let mut file_names: Vec<String> = fs::read_dir(socket_dir.path())
+            .expect("Can't read dir")
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        file_names.sort();
+        assert_eq!(
+            file_names,
+            vec![
+                "osdd.1.egress.orders_kafka_transport",
+                "osdd.1.ingress.orders_kafka_transport",
+                "osdd.2.ingress.orders_kafka_transport"
+            ]
+        );
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::*;
+use crate::*;
+use error_chain::ChainedError;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The amount of crashes in a row after which a handler is not restarted anymore, when `max_restarts` is not set
+pub const DEFAULT_MAX_RESTARTS: u32 = 5;
+/// The time before the first restart of a crashed handler, it doubles with every crash in a row
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// The longest time before a crashed handler is restarted
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A handler that ran this long before it exited is not crashing in a loop, its crashes in a row start over
+const STABLE_RUN: Duration = Duration::from_secs(60);
+/// The time between two checks whether a child exited or has to be stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A child of osdd that is supervised in its own thread
+struct SupervisedChild {
+    name: String,
+    config_hash: String,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// Runs the handlers as children of osdd and supervises them.
+/// The stdout and stderr of every child are logged with the name of its handler.
+/// A child that exits is restarted after a backoff that doubles with every crash in a row,
+/// after `max_restarts` crashes in a row it is not restarted anymore.
+pub struct Supervisor {
+    max_restarts: u32,
+    children: Vec<SupervisedChild>,
+}
+
+impl Supervisor {
+    /// Creates a supervisor without children
+    pub fn new(max_restarts: u32) -> Supervisor {
+        Supervisor {
+            max_restarts,
+            children: Vec::new(),
+        }
+    }
+
+    /// Starts and stops children until exactly the given deployments run.
+    /// A child that runs with the current configuration of its handler is left alone,
+    /// a child of a handler whose configuration changed is stopped and started again.
+    ///
+    /// # Returns
+    /// A line for every child that was started or stopped
+    pub fn apply(&mut self, deployments: &[HandlerDeployment]) -> Result<Vec<String>> {
+        let mut changes = Vec::new();
+        let (keep, stop): (Vec<SupervisedChild>, Vec<SupervisedChild>) =
+            self.children.drain(..).partition(|child| {
+                deployments
+                    .iter()
+                    .any(|d| d.name == child.name && d.config_hash() == child.config_hash)
+            });
+        self.children = keep;
+        for child in stop {
+            child.stop.store(true, Ordering::SeqCst);
+            if child.thread.join().is_err() {
+                log::error!("The supervisor of handler {} panicked", child.name);
+            }
+            changes.push(format!("Stopped {}", child.name));
+        }
+
+        for deployment in deployments {
+            if self.children.iter().any(|c| c.name == deployment.name) {
+                continue;
+            }
+            let mut command_with_name = deployment.command();
+            let child = spawn_child(&mut command_with_name)?;
+            let stop = Arc::new(AtomicBool::new(false));
+            let stop_thread = stop.clone();
+            let max_restarts = self.max_restarts;
+            let thread = thread::Builder::new()
+                .name(command_with_name.name.to_string())
+                .spawn(move || supervise(command_with_name, child, max_restarts, &stop_thread))
+                .chain_err(|| "Error starting supervisor thread")?;
+            self.children.push(SupervisedChild {
+                name: deployment.name.to_string(),
+                config_hash: deployment.config_hash(),
+                stop,
+                thread,
+            });
+            changes.push(format!("Started {}", deployment.name));
+        }
+        Ok(changes)
+    }
+}
+
+/// Starts the command with its stdout and stderr captured.
+fn spawn_child(command_with_name: &mut CommandWithName) -> Result<Child> {
+    let mut child = command_with_name
+        .command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .chain_err(|| format!("Error starting handler {}", command_with_name.name))?;
+    if let Some(stdout) = child.stdout.take() {
+        log_output(stdout, &command_with_name.name, log::Level::Info);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        log_output(stderr, &command_with_name.name, log::Level::Warn);
+    }
+    log::info!(
+        "Started handler {} with pid {}",
+        command_with_name.name,
+        child.id()
+    );
+    Ok(child)
+}
+
+/// Logs every line of the output of a child until it is closed.
+fn log_output<R: Read + Send + 'static>(output: R, name: &str, level: log::Level) {
+    let name = name.to_string();
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines() {
+            match line {
+                Ok(line) => log::log!(level, "{}: {}", name, line),
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+/// Sleeps for the given time, or until the child has to be stopped.
+/// Returns if the child has to be stopped.
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        if stop.load(Ordering::SeqCst) {
+            return true;
+        }
+        thread::sleep(POLL_INTERVAL.min(duration - start.elapsed()));
+    }
+    stop.load(Ordering::SeqCst)
+}
+
+/// Waits for the child to exit, or kills it when it has to be stopped.
+/// Returns false when the child was stopped.
+fn wait_unless_stopped(child: &mut Child, name: &str, stop: &AtomicBool) -> bool {
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            let _ = child.kill();
+            let _ = child.wait();
+            log::info!("Stopped handler {}", name);
+            return false;
+        }
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                log::error!("Handler {} exited with {}", name, status);
+                return true;
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                log::error!("Error waiting for handler {}: {}", name, e);
+                //make sure the old child is gone before another one is started
+                let _ = child.kill();
+                let _ = child.wait();
+                return true;
+            }
+        }
+    }
+}
+
+/// Waits for the child to exit and restarts it with a backoff, until it crashed `max_restarts` times in a row
+/// or it has to be stopped.
+fn supervise(
+    mut command_with_name: CommandWithName,
+    mut child: Child,
+    max_restarts: u32,
+    stop: &AtomicBool,
+) {
+    let mut crashes = 0;
+    let mut backoff = INITIAL_BACKOFF;
+    let mut started = Instant::now();
+    loop {
+        if !wait_unless_stopped(&mut child, &command_with_name.name, stop) {
+            return;
+        }
+        if started.elapsed() >= STABLE_RUN {
+            crashes = 0;
+            backoff = INITIAL_BACKOFF;
+        }
+        child = loop {
+            crashes += 1;
+            if crashes > max_restarts {
+                log::error!(
+                    "Handler {} crashed {} times in a row, it is not restarted anymore",
+                    command_with_name.name,
+                    max_restarts
+                );
+                return;
+            }
+            log::warn!(
+                "Restarting handler {} in {:?}, restart {} of {}",
+                command_with_name.name,
+                backoff,
+                crashes,
+                max_restarts
+            );
+            if sleep_unless_stopped(backoff, stop) {
+                return;
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            match spawn_child(&mut command_with_name) {
+                Ok(child) => break child,
+                Err(e) => log::error!("{}", e.display_chain()),
+            }
+        };
+        started = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::process_supervisor::*;
+
+    fn sleeping_deployment(seconds: &str) -> HandlerDeployment {
+        let mut deployment = crate::tests::deployment("/tmp/osdd.1.ingress.orders_kafka_transport");
+        deployment.runtime = Runtime::Native;
+        deployment.working_dir = "/bin".to_string();
+        deployment.executable = "sleep".to_string();
+        deployment.arguments = vec![seconds.to_string()];
+        deployment.user = None;
+        deployment
+    }
+
+    #[test]
+    fn apply_test() {
+        let mut supervisor = Supervisor::new(DEFAULT_MAX_RESTARTS);
+        let started = supervisor
+            .apply(&[sleeping_deployment("60")])
+            .expect("Can't start child");
+        assert_eq!(started, vec!["Started osdd.1.ingress.orders.ph.kafka"]);
+
+        //a child with the same configuration keeps running
+        let unchanged = supervisor
+            .apply(&[sleeping_deployment("60")])
+            .expect("Can't apply deployments");
+        assert!(unchanged.is_empty());
+
+        //a child with another configuration is started again
+        let changed = supervisor
+            .apply(&[sleeping_deployment("61")])
+            .expect("Can't apply deployments");
+        assert_eq!(
+            changed,
+            vec![
+                "Stopped osdd.1.ingress.orders.ph.kafka",
+                "Started osdd.1.ingress.orders.ph.kafka"
+            ]
+        );
+
+        let stopped = supervisor.apply(&[]).expect("Can't stop child");
+        assert_eq!(stopped, vec!["Stopped osdd.1.ingress.orders.ph.kafka"]);
+    }
+
+    #[test]
+    fn sleep_unless_stopped_test() {
+        let stop = AtomicBool::new(false);
+        assert!(!sleep_unless_stopped(Duration::from_millis(10), &stop));
+        stop.store(true, Ordering::SeqCst);
+        let start = Instant::now();
+        assert!(sleep_unless_stopped(Duration::from_secs(60), &stop));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
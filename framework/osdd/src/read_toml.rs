@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::config_loader::load_config_file;
 use crate::errors::ErrorKind::*;
 use crate::errors::*;
 use crate::*;
@@ -25,21 +26,21 @@ pub struct TomlConfig {
 
 #[derive(Debug, Deserialize)]
 struct ChainToml {
-    pub protocol_handler: String,
-    pub filter_handlers: Vec<String>,
-    pub transport_handler: String,
+    pub protocol_handler: Option<String>,
+    pub filter_handlers: Option<Vec<String>>,
+    pub transport_handler: Option<String>,
+    pub edges: Option<Vec<Edge>>,
+    pub link: Option<String>,
 }
 
 /// Convert TOML file to settings, chains and handlers.
+/// Its includes, templates, environment variables and automatic ports are resolved first.
 /// println errors because logging is not initialized yet.
 pub fn read_toml(config_file: &str) -> Result<TomlConfig> {
     let mut handlers: Vec<Handler> = Vec::new();
     let mut chains: Vec<Chain> = Vec::new();
     let mut settings_option: Option<Settings> = None;
-    let toml_string = fs::read_to_string(config_file)
-        .chain_err(|| ConfigurationError("Config nog found".to_string()))?;
-    let toml_value: Value = toml::from_str(&toml_string)
-        .chain_err(|| ConfigurationError("Cannot convert config to toml".to_string()))?;
+    let toml_value = load_config_file(config_file)?.value;
 
     if let Some(tables) = toml_value.as_table() {
         for table in tables {
@@ -58,11 +59,45 @@ pub fn read_toml(config_file: &str) -> Result<TomlConfig> {
                                         chain_toml.0
                                     ))
                                 })?;
-                            let chain_with_name = Chain {
-                                name: chain_toml.0.to_string(),
-                                protocol_handler: chain_struct.protocol_handler,
-                                filter_handlers: chain_struct.filter_handlers,
-                                transport_handler: chain_struct.transport_handler,
+                            if let Some(link) = &chain_struct.link {
+                                if link != "socket" && link != "shared_memory" {
+                                    return Err(ConfigurationError(format!(
+                                        "Chain {} has unknown link {}, expected socket or shared_memory",
+                                        chain_toml.0, link
+                                    ))
+                                    .into());
+                                }
+                            }
+                            let chain_with_name = match chain_struct {
+                                ChainToml {
+                                    protocol_handler: None,
+                                    filter_handlers: None,
+                                    transport_handler: None,
+                                    edges: Some(edges),
+                                    link,
+                                } => Chain {
+                                    name: chain_toml.0.to_string(),
+                                    edges,
+                                    link,
+                                },
+                                ChainToml {
+                                    protocol_handler: Some(protocol_handler),
+                                    filter_handlers: Some(filter_handlers),
+                                    transport_handler: Some(transport_handler),
+                                    edges: None,
+                                    link,
+                                } => Chain::linear(
+                                    chain_toml.0,
+                                    protocol_handler,
+                                    filter_handlers,
+                                    transport_handler,
+                                    link,
+                                ),
+                                _ => return Err(ConfigurationError(format!(
+                                    "Chain {} needs either protocol_handler, filter_handlers and transport_handler, or edges",
+                                    chain_toml.0
+                                ))
+                                .into()),
                             };
 
                             chains.push(chain_with_name);
@@ -90,7 +125,8 @@ pub fn read_toml(config_file: &str) -> Result<TomlConfig> {
                         }
                     }
                 }
-                _ => log::warn!("in config {} is unknown!", table.0),
+                //unknown tables are reported by validate
+                _ => (),
             }
         }
     };
@@ -106,48 +142,139 @@ pub fn read_toml(config_file: &str) -> Result<TomlConfig> {
     }
 }
 
+/// Converts the value of a handler argument in the TOML file to the values it is given to the handler with.
+/// Strings, integers, floats, booleans and dates are given as one value, `true` and `false` for a boolean.
+/// An array is given as a value for every element, the handler gets a repeated flag `--key a --key b`.
+///
+/// # Returns
+/// The values, or a message why the value cannot be an argument
+pub fn argument_values(value: &Value) -> std::result::Result<Vec<String>, String> {
+    match value {
+        Value::Array(elements) => {
+            let mut values = Vec::new();
+            for element in elements {
+                match scalar_value(element) {
+                    Some(value) => values.push(value),
+                    None => {
+                        return Err(
+                            "an array can only contain strings, numbers and booleans".to_string()
+                        )
+                    }
+                }
+            }
+            Ok(values)
+        }
+        value => match scalar_value(value) {
+            Some(value) => Ok(vec![value]),
+            None => Err(
+                "a table cannot be an argument, expected a string, number, boolean or array"
+                    .to_string(),
+            ),
+        },
+    }
+}
+
+/// Converts a TOML value that is not an array or table to a string, without quotes
+fn scalar_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(x) => Some(x.to_string()),
+        Value::Integer(x) => Some(x.to_string()),
+        Value::Float(x) => Some(x.to_string()),
+        Value::Boolean(x) => Some(x.to_string()),
+        Value::Datetime(x) => Some(x.to_string()),
+        Value::Array(_) | Value::Table(_) => None,
+    }
+}
+
 /// Convert a handler from a TOML file to a handler struct.
 /// `type` and `open_udp_port` are specials cases and needed in the configuration in osdd.
 /// All other arguments are store in a vec and given as argument to the executable.
 /// `type` is the executabe and docker name
 /// `open_udp_port` is to open een udp port in the docker container
+/// `incoming_address` and `outgoing_address` replace the unix domain sockets with the previous and next handler
+/// `user` is the `uid:gid` the docker container runs as
+/// A value of the wrong type is an error, it is never left out.
 fn read_handler(handler_config: (&String, &Value), handler_type: HandlerType) -> Result<Handler> {
-    let mut executable_option: Option<&str> = None;
+    let mut executable_option: Option<String> = None;
     let mut udp_port_option: Option<u16> = None;
+    let mut incoming_address: Option<String> = None;
+    let mut outgoing_address: Option<String> = None;
+    let mut user: Option<(u32, u32)> = None;
     let mut arguments = Vec::new();
 
+    //a setting of osdd itself must be a string
+    let string_value = |key: &str, value: &Value| -> Result<String> {
+        match value.as_str() {
+            Some(x) => Ok(x.to_string()),
+            None => Err(ConfigurationError(format!(
+                "{} of {} is not a string",
+                key, handler_config.0
+            ))
+            .into()),
+        }
+    };
+
     //read arguments from the handler_config.
-    if let Some(toml_arguments) = handler_config.1.as_table() {
-        for argument in toml_arguments {
-            if let Some(x) = argument.1.as_str() {
-                match argument.0.as_ref() {
-                    //Type is to define which type of handler it is
-                    "type" => {
-                        executable_option = argument.1.as_str();
-                    }
-                    "open_udp_port" => {
-                        udp_port_option = match argument.1.as_str() {
-                            Some(x) => Some(match x.parse::<u16>() {
-                                Ok(v) => v,
-                                Err(_) => {
-                                    return Err(ConfigurationError(format!(
-                                        "Cannot parse open udp port to a port in {}",
-                                        handler_config.0
-                                    ))
-                                    .into())
-                                }
-                            }),
-                            None => {
-                                return Err(ConfigurationError(format!(
-                                    "Cannot parse open udp port to a port in {}",
-                                    handler_config.0
-                                ))
-                                .into())
-                            }
-                        }
+    let toml_arguments = handler_config.1.as_table().chain_err(|| {
+        ConfigurationError(format!(
+            "Handler {} is not a table of settings",
+            handler_config.0
+        ))
+    })?;
+    for argument in toml_arguments {
+        match argument.0.as_ref() {
+            //Type is to define which type of handler it is
+            "type" => {
+                executable_option = Some(string_value(argument.0, argument.1)?);
+            }
+            "open_udp_port" => {
+                let port = match argument.1 {
+                    Value::Integer(x) => Some(x.to_string()),
+                    Value::String(x) => Some(x.to_string()),
+                    _ => None,
+                };
+                udp_port_option = match port.and_then(|x| x.parse::<u16>().ok()) {
+                    Some(v) => Some(v),
+                    None => {
+                        return Err(ConfigurationError(format!(
+                            "Cannot parse open udp port to a port in {}",
+                            handler_config.0
+                        ))
+                        .into())
                     }
-                    //All other arguments are arguments for the handler
-                    _ => arguments.push((argument.0.to_string(), x.to_string())),
+                }
+            }
+            //Addresses of the links with the previous and next handler, instead of unix domain sockets
+            "incoming_address" => incoming_address = Some(string_value(argument.0, argument.1)?),
+            "outgoing_address" => outgoing_address = Some(string_value(argument.0, argument.1)?),
+            //The user and group the handler runs as, its neighbours only accept this identity
+            "user" => {
+                let x = string_value(argument.0, argument.1)?;
+                user = match x.find(':') {
+                    Some(index) => match (x[..index].parse(), x[index + 1..].parse()) {
+                        (Ok(uid), Ok(gid)) => Some((uid, gid)),
+                        _ => None,
+                    },
+                    None => None,
+                };
+                if user.is_none() {
+                    return Err(ConfigurationError(format!(
+                        "Cannot parse user to uid:gid in {}",
+                        handler_config.0
+                    ))
+                    .into());
+                }
+            }
+            //All other arguments are arguments for the handler
+            _ => {
+                let values = argument_values(argument.1).map_err(|message| {
+                    Error::from(ConfigurationError(format!(
+                        "{} of {}: {}",
+                        argument.0, handler_config.0, message
+                    )))
+                })?;
+                for value in values {
+                    arguments.push((argument.0.to_string(), value));
                 }
             }
         }
@@ -155,11 +282,14 @@ fn read_handler(handler_config: (&String, &Value), handler_type: HandlerType) ->
     if let Some(executable) = executable_option {
         Ok(Handler {
             name: handler_config.0.to_string(),
-            executable: executable.to_string(),
+            executable,
             arguments,
             handler_type,
-            incoming_socket: None,
-            outgoing_socket: None,
+            incoming_sockets: Vec::new(),
+            outgoing_sockets: Vec::new(),
+            incoming_address,
+            outgoing_address,
+            user,
             udp_port_option,
         })
     } else {
@@ -170,3 +300,107 @@ fn read_handler(handler_config: (&String, &Value), handler_type: HandlerType) ->
         .into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::read_toml::*;
+
+    fn value(toml: &str) -> Value {
+        toml.parse::<Value>().unwrap()["x"].clone()
+    }
+
+    #[test]
+    fn argument_values_test() {
+        assert_eq!(
+            argument_values(&value("x = \"orders\"")),
+            Ok(vec!["orders".to_string()])
+        );
+        assert_eq!(
+            argument_values(&value("x = 1048576")),
+            Ok(vec!["1048576".to_string()])
+        );
+        assert_eq!(
+            argument_values(&value("x = 0.5")),
+            Ok(vec!["0.5".to_string()])
+        );
+        assert_eq!(
+            argument_values(&value("x = false")),
+            Ok(vec!["false".to_string()])
+        );
+        assert_eq!(
+            argument_values(&value("x = 2020-01-01T12:00:00Z")),
+            Ok(vec!["2020-01-01T12:00:00Z".to_string()])
+        );
+        assert_eq!(
+            argument_values(&value("x = [\"a\", 2, true]")),
+            Ok(vec!["a".to_string(), "2".to_string(), "true".to_string()])
+        );
+        assert_eq!(argument_values(&value("x = []")), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn argument_values_rejected_test() {
+        assert_eq!(
+            argument_values(&value("x = [[1], 2]")),
+            Err("an array can only contain strings, numbers and booleans".to_string())
+        );
+        assert_eq!(
+            argument_values(&value("x = [{ a = 1 }]")),
+            Err("an array can only contain strings, numbers and booleans".to_string())
+        );
+        assert_eq!(
+            argument_values(&value("x = { a = 1 }")),
+            Err(
+                "a table cannot be an argument, expected a string, number, boolean or array"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn edges_test() {
+        let chain: ChainToml =
+            toml::from_str("edges = [[\"kafka\", \"udp1\"], [\"kafka\", \"udp2\", \"block\"]]")
+                .unwrap();
+        let edges = chain.edges.unwrap();
+        assert_eq!(
+            (
+                edges[0].from.as_str(),
+                edges[0].to.as_str(),
+                &edges[0].lag_policy
+            ),
+            ("kafka", "udp1", &None)
+        );
+        assert_eq!(edges[1].lag_policy, Some("block".to_string()));
+    }
+
+    #[test]
+    fn read_handler_arguments_test() {
+        let config = "[ph]\ntype = \"ph_kafka\"\nmax_bytes = 1024\nverbose = true\nbroker = [\"a:9092\", \"b:9092\"]\n"
+            .parse::<Value>()
+            .unwrap();
+        let (name, handler_config) = config.as_table().unwrap().iter().next().unwrap();
+        let handler = read_handler((name, handler_config), HandlerType::ProtocolHandler).unwrap();
+        assert_eq!(handler.executable, "ph_kafka");
+        assert_eq!(
+            handler.arguments,
+            vec![
+                ("broker".to_string(), "a:9092".to_string()),
+                ("broker".to_string(), "b:9092".to_string()),
+                ("max_bytes".to_string(), "1024".to_string()),
+                ("verbose".to_string(), "true".to_string()),
+            ]
+        );
+
+        let config = "[ph]\ntype = \"ph_kafka\"\nbroker = { host = \"a\" }\n"
+            .parse::<Value>()
+            .unwrap();
+        let (name, handler_config) = config.as_table().unwrap().iter().next().unwrap();
+        assert_eq!(
+            read_handler((name, handler_config), HandlerType::ProtocolHandler)
+                .unwrap_err()
+                .to_string(),
+            "Configuration error: 'broker of ph: a table cannot be an argument, expected a string, number, boolean or array'"
+        );
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::docker_runner::handle_processes;
+use crate::errors::*;
+use crate::*;
+use std::collections::HashSet;
+
+/// A container on the proxy that belongs to osdd
+#[derive(Debug)]
+pub struct Container {
+    pub name: String,
+    pub running: bool,
+    ///The hash of the configuration the container was started with, empty when it has none
+    pub config_hash: String,
+}
+
+/// Returns all containers, running or not, with a name that starts with the prefix
+///
+/// # Arguments
+/// * `prefix` - The start of the names, `osdd.<instance>.<network>.`
+pub fn list_containers(prefix: &str) -> Result<Vec<Container>> {
+    let format = format!(
+        "{{{{.Names}}}} {{{{.State}}}} {{{{.Label \"{}\"}}}}",
+        CONFIG_LABEL
+    );
+    let output = run_docker(&["ps", "--all", "--format", &format])?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?;
+            if !name.starts_with(prefix) {
+                return None;
+            }
+            Some(Container {
+                name: name.to_string(),
+                running: fields.next() == Some("running"),
+                config_hash: fields.next().unwrap_or("").to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Runs docker with the given arguments and returns its stdout
+fn run_docker(arguments: &[&str]) -> Result<String> {
+    let output = Command::new("docker")
+        .args(arguments)
+        .output()
+        .chain_err(|| format!("Error running docker {}", arguments.join(" ")))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Error running docker {}: {}",
+            arguments.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Stops and removes a container, docker does not restart it anymore
+fn remove_container(name: &str) -> Result<()> {
+    run_docker(&["rm", "--force", name]).map(|_| ())
+}
+
+/// Removes a file from the socket directory, a file that does not exist is not an error
+fn remove_socket_file(socket_dir: &Path, file_name: &str) -> Result<bool> {
+    match fs::remove_file(socket_dir.join(file_name)) {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(Error::with_chain(
+            e,
+            format!("Error removing {}", socket_dir.join(file_name).display()),
+        )),
+    }
+}
+
+/// Starts the deployments that are not running with their current configuration,
+/// and removes the containers that are not in the configuration anymore.
+/// Running it twice does not change anything the second time.
+///
+/// # Arguments
+/// * `deployments` - The deployments of all handlers in the configuration
+/// * `prefix` - The start of the names of the containers of the configuration, `osdd.<instance>.<network>.`
+/// * `socket_dir` - The directory on the proxy the sockets are created in
+///
+/// # Returns
+/// A line for every change that was made
+pub fn up(
+    deployments: &[HandlerDeployment],
+    prefix: &str,
+    socket_dir: &Path,
+) -> Result<Vec<String>> {
+    let mut changes = Vec::new();
+    let containers = list_containers(prefix)?;
+    for container in &containers {
+        if !deployments.iter().any(|d| d.name == container.name) {
+            remove_container(&container.name)?;
+            changes.push(format!(
+                "Removed {}, it is not in the configuration",
+                container.name
+            ));
+        }
+    }
+    changes.extend(remove_stale_sockets(deployments, prefix, socket_dir)?);
+
+    let mut to_start = Vec::new();
+    for deployment in deployments {
+        match containers.iter().find(|c| c.name == deployment.name) {
+            Some(container)
+                if container.running && container.config_hash == deployment.config_hash() =>
+            {
+                continue
+            }
+            Some(container) => {
+                remove_container(&container.name)?;
+                changes.push(if container.running {
+                    format!("Recreated {}, its configuration changed", deployment.name)
+                } else {
+                    format!("Recreated {}, it was not running", deployment.name)
+                });
+            }
+            None => changes.push(format!("Started {}", deployment.name)),
+        }
+        to_start.push(deployment);
+    }
+    start(&to_start, socket_dir)?;
+    Ok(changes)
+}
+
+/// Stops and removes all containers with a name that starts with the prefix,
+/// and removes the files from the socket directory with a name that starts with the prefix.
+/// The sockets of other instances and networks in the same directory are kept.
+///
+/// # Returns
+/// A line for every container and file that was removed
+pub fn down(prefix: &str, socket_dir: &Path) -> Result<Vec<String>> {
+    let mut changes = Vec::new();
+    for container in list_containers(prefix)? {
+        remove_container(&container.name)?;
+        changes.push(format!("Removed {}", container.name));
+    }
+    for file_name in socket_files(socket_dir, prefix)? {
+        if remove_socket_file(socket_dir, &file_name)? {
+            changes.push(format!("Removed socket {}", file_name));
+        }
+    }
+    Ok(changes)
+}
+
+/// Stops the containers of the given deployments, removes their sockets and starts them again
+///
+/// # Returns
+/// A line for every container that was restarted
+pub fn restart(
+    deployments: &[&HandlerDeployment],
+    prefix: &str,
+    socket_dir: &Path,
+) -> Result<Vec<String>> {
+    let containers = list_containers(prefix)?;
+    for deployment in deployments {
+        if containers.iter().any(|c| c.name == deployment.name) {
+            remove_container(&deployment.name)?;
+        }
+    }
+    start(deployments, socket_dir)?;
+    Ok(deployments
+        .iter()
+        .map(|d| format!("Restarted {}", d.name))
+        .collect())
+}
+
+/// Removes the sockets the deployments create, so a handler never finds the socket of an earlier container,
+/// and starts their containers
+fn start(deployments: &[&HandlerDeployment], socket_dir: &Path) -> Result<()> {
+    for deployment in deployments {
+        for file_name in deployment.socket_file_names() {
+            remove_socket_file(socket_dir, &file_name)?;
+        }
+    }
+    handle_processes(deployments.iter().map(|d| d.command()).collect())
+}
+
+/// Removes the files from the socket directory with a name that starts with the prefix
+/// and that are not the socket of one of the deployments
+///
+/// # Arguments
+/// * `deployments` - The deployments of all handlers in the configuration
+/// * `prefix` - The start of the names of the sockets of the configuration, `osdd.<instance>.<network>.`
+/// * `socket_dir` - The directory on the proxy the sockets are created in
+///
+/// # Returns
+/// A line for every file that was removed
+pub fn remove_stale_sockets(
+    deployments: &[HandlerDeployment],
+    prefix: &str,
+    socket_dir: &Path,
+) -> Result<Vec<String>> {
+    let in_use: HashSet<String> = deployments
+        .iter()
+        .flat_map(HandlerDeployment::socket_file_names)
+        .collect();
+    let mut changes = Vec::new();
+    for file_name in socket_files(socket_dir, prefix)? {
+        if !in_use.contains(&file_name) && remove_socket_file(socket_dir, &file_name)? {
+            changes.push(format!("Removed stale socket {}", file_name));
+        }
+    }
+    Ok(changes)
+}
+
+/// Returns the names of the files in the socket directory that start with the prefix,
+/// none when the directory does not exist
+fn socket_files(socket_dir: &Path, prefix: &str) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(socket_dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(Error::with_chain(
+                e,
+                format!("Error reading {}", socket_dir.display()),
+            ))
+        }
+    };
+    let mut file_names = Vec::new();
+    for entry in entries {
+        let entry = entry.chain_err(|| format!("Error reading {}", socket_dir.display()))?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.starts_with(prefix) && !entry.path().is_dir() {
+            file_names.push(file_name);
+        }
+    }
+    Ok(file_names)
+}
@@ -0,0 +1,296 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::container_monitor::*;
+use crate::errors::ErrorKind::ConfigurationError;
+use crate::errors::*;
+use crate::process_supervisor::Supervisor;
+use crate::read_toml::*;
+use crate::validate::*;
+use crate::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Set by the SIGHUP handler, osdd reloads the configuration when it is set
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_reload(_signal: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Makes a SIGHUP request a reload of the configuration, instead of stopping osdd
+pub fn reload_on_sighup() -> Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = request_reload as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        //a system call interrupted by the signal is restarted instead of failing with EINTR
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut()) != 0 {
+            return Err(Error::with_chain(
+                std::io::Error::last_os_error(),
+                "Error installing the SIGHUP handler",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns if a reload was requested with SIGHUP since the last call
+pub fn take_reload_request() -> bool {
+    RELOAD_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Watches the modification time of the configuration file
+pub struct ConfigWatcher {
+    config_file: String,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    /// Starts watching the file from its current modification time
+    pub fn new(config_file: &str) -> ConfigWatcher {
+        ConfigWatcher {
+            config_file: config_file.to_string(),
+            modified: modified(config_file),
+        }
+    }
+
+    /// Returns if the file was modified since the last call.
+    /// A file that cannot be read, for example while an editor replaces it, is not changed.
+    pub fn changed(&mut self) -> bool {
+        match modified(&self.config_file) {
+            Some(modified) if Some(modified) != self.modified => {
+                self.modified = Some(modified);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The modification time of a file, none when it cannot be read
+fn modified(file: &str) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+/// The handlers osdd runs, with the configuration they were started with
+pub struct RunningDeployment {
+    settings: Settings,
+    stats_port: u16,
+    deployments: Vec<HandlerDeployment>,
+    supervisor: Option<Supervisor>,
+    statuses: Arc<HandlerStatuses>,
+}
+
+impl RunningDeployment {
+    /// Starts the handlers of a configuration.
+    /// Docker containers are brought in line with the configuration and monitored,
+    /// with the native runtime the handlers are started as supervised children.
+    pub fn start(toml_config: TomlConfig) -> Result<RunningDeployment> {
+        let stats_port = toml_config
+            .settings
+            .stats_multiplexer_listening_port
+            .parse::<u16>()?;
+        let mut running = RunningDeployment {
+            stats_port,
+            deployments: Vec::new(),
+            supervisor: match toml_config.settings.runtime()? {
+                Runtime::Docker => None,
+                Runtime::Native => Some(Supervisor::new(toml_config.settings.max_restarts()?)),
+            },
+            statuses: Arc::new(HandlerStatuses::new(Vec::new())),
+            settings: toml_config.settings,
+        };
+        running.apply(create_deployments_all_handlers(
+            toml_config.chains,
+            toml_config.handlers,
+            stats_port,
+            &running.settings,
+            running.settings.runtime()?,
+        )?)?;
+        if running.supervisor.is_none() {
+            monitor_containers(running.statuses.clone(), stats_port)?;
+        }
+        Ok(running)
+    }
+
+    /// Reads the configuration file again and starts, stops or recreates the handlers whose configuration changed.
+    /// The handlers of the chains that did not change keep running.
+    /// A configuration with errors, or with other settings, is rejected and nothing is changed.
+    pub fn reload(&mut self, config_file: &str) -> Result<()> {
+        log::info!("Reloading {}", config_file);
+        let diagnostics = validate_file(config_file)?;
+        for diagnostic in &diagnostics {
+            match diagnostic.severity {
+                Severity::Error => log::error!("{}: {}", config_file, diagnostic),
+                Severity::Warning => log::warn!("{}: {}", config_file, diagnostic),
+            }
+        }
+        if has_errors(&diagnostics) {
+            return Err(ConfigurationError(format!(
+                "{} has errors, the running handlers are not changed",
+                config_file
+            ))
+            .into());
+        }
+        let toml_config = read_toml(config_file)?;
+        if toml_config.settings != self.settings {
+            return Err(ConfigurationError(format!(
+                "The settings in {} changed, they are used after a restart of osdd, the running handlers are not changed",
+                config_file
+            ))
+            .into());
+        }
+        let deployments = create_deployments_all_handlers(
+            toml_config.chains,
+            toml_config.handlers,
+            self.stats_port,
+            &self.settings,
+            self.settings.runtime()?,
+        )?;
+        for change in chain_changes(&self.deployments, &deployments) {
+            log::info!("{}", change);
+        }
+        self.apply(deployments)
+    }
+
+    /// Brings the running handlers in line with the deployments
+    fn apply(&mut self, deployments: Vec<HandlerDeployment>) -> Result<()> {
+        let socket_dir = self.settings.host_socket_dir();
+        let changes = match &mut self.supervisor {
+            None => reconcile::up(&deployments, &self.settings.name_prefix(), &socket_dir)?,
+            Some(supervisor) => {
+                let mut changes = reconcile::remove_stale_sockets(
+                    &deployments,
+                    &self.settings.name_prefix(),
+                    &socket_dir,
+                )?;
+                changes.extend(supervisor.apply(&deployments)?);
+                changes
+            }
+        };
+        for change in changes {
+            log::info!("{}", change);
+        }
+        self.statuses
+            .set_names(deployments.iter().map(|d| d.name.to_string()).collect());
+        self.deployments = deployments;
+        Ok(())
+    }
+}
+
+/// Compares the chains of two deployments.
+///
+/// # Returns
+/// A line for every chain that was added, removed or changed
+pub fn chain_changes(old: &[HandlerDeployment], new: &[HandlerDeployment]) -> Vec<String> {
+    let chain_config = |deployments: &[HandlerDeployment], chain: &str| -> Vec<String> {
+        deployments
+            .iter()
+            .filter(|d| d.chain == chain)
+            .map(HandlerDeployment::config_hash)
+            .collect()
+    };
+    let mut chains: Vec<&str> = old
+        .iter()
+        .chain(new.iter())
+        .map(|d| d.chain.as_str())
+        .collect();
+    chains.sort_unstable();
+    chains.dedup();
+    let mut changes = Vec::new();
+    for chain in chains {
+        let (before, after) = (chain_config(old, chain), chain_config(new, chain));
+        if before.is_empty() {
+            changes.push(format!("Chain {} was added", chain));
+        } else if after.is_empty() {
+            changes.push(format!("Chain {} was removed", chain));
+        } else if before != after {
+            changes.push(format!("Chain {} changed", chain));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reload::*;
+    use tempdir::TempDir;
+
+    fn chain(chain: &str, topic_name: &str) -> HandlerDeployment {
+        let mut deployment = crate::tests::deployment("/tmp/osdd.1.ingress.orders_kafka_transport");
+        deployment.name = format!("osdd.1.ingress.{}.ph.kafka", chain);
+        deployment.chain = chain.to_string();
+        deployment.arguments = vec!["--topic_name".to_string(), topic_name.to_string()];
+        deployment
+    }
+
+    #[test]
+    fn chain_changes_test() {
+        let old = vec![
+            chain("orders", "orders"),
+            chain("invoices", "invoices"),
+            chain("returns", "returns"),
+        ];
+        let new = vec![
+            chain("orders", "orders"),
+            chain("invoices", "all_invoices"),
+            chain("payments", "payments"),
+        ];
+        assert_eq!(
+            chain_changes(&old, &new),
+            vec![
+                "Chain invoices changed",
+                "Chain payments was added",
+                "Chain returns was removed"
+            ]
+        );
+        assert!(chain_changes(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn chain_changes_handler_added_test() {
+        let old = vec![chain("orders", "orders")];
+        let mut filter = chain("orders", "orders");
+        filter.name = "osdd.1.ingress.orders.filter.filter".to_string();
+        let new = vec![chain("orders", "orders"), filter];
+        assert_eq!(chain_changes(&old, &new), vec!["Chain orders changed"]);
+    }
+
+    #[test]
+    fn config_watcher_test() {
+        let dir = TempDir::new("osdd_reload").unwrap();
+        let config_file = dir.path().join("Config.toml");
+        let config_file = config_file.to_str().unwrap();
+        let mut watcher = ConfigWatcher::new(config_file);
+        assert!(!watcher.changed());
+        fs::write(config_file, "[settings]\n").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        //a removed file, for example while an editor replaces it, is not a change
+        fs::remove_file(config_file).unwrap();
+        assert!(!watcher.changed());
+    }
+
+    #[test]
+    fn sighup_test() {
+        reload_on_sighup().unwrap();
+        assert!(!take_reload_request());
+        unsafe { libc::raise(libc::SIGHUP) };
+        assert!(take_reload_request());
+        assert!(!take_reload_request());
+    }
+}
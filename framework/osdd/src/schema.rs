@@ -0,0 +1,213 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::*;
+use crate::{Runtime, Settings};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+
+/// The arguments osdd gives every handler itself, they do not have to be in the configuration file
+pub const OSDD_ARGUMENTS: &[&str] = &[
+    "socket_path",
+    "socket_path_in",
+    "socket_path_out",
+    "link",
+    "stats_server_address",
+    "stats_server_port",
+    "from_host_sys_log",
+    "from_port_sys_log",
+    "to_host_sys_log",
+    "to_port_sys_log",
+    "handler_name",
+];
+
+/// The settings of a handler that osdd reads itself, they are not given to the handler
+pub const OSDD_HANDLER_SETTINGS: &[&str] = &[
+    "type",
+    "open_udp_port",
+    "incoming_address",
+    "outgoing_address",
+    "user",
+];
+
+/// An argument of a handler, as printed by the handler with `--describe`
+#[derive(Clone, Debug, Deserialize)]
+pub struct ArgumentSchema {
+    /// `integer`, `number`, `boolean`, `string` or `flag`
+    #[serde(rename = "type")]
+    pub argument_type: String,
+    pub default: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    pub help: Option<String>,
+}
+
+/// The arguments a handler accepts and its place in a chain, as printed by the handler with `--describe`
+#[derive(Clone, Debug, Deserialize)]
+pub struct HandlerSchema {
+    /// `ingress`, `egress`, `filter` or `transport`
+    pub role: String,
+    #[serde(default)]
+    pub arguments: BTreeMap<String, ArgumentSchema>,
+}
+
+impl HandlerSchema {
+    /// Checks a value of an argument against the type of the argument
+    ///
+    /// # Returns
+    /// The problem with the value, or none when the handler accepts it
+    pub fn check_value(&self, key: &str, value: &str) -> Option<String> {
+        let argument = self.arguments.get(key)?;
+        let (valid, expected) = match argument.argument_type.as_str() {
+            "integer" => (value.trim().parse::<i64>().is_ok(), "an integer"),
+            "number" => (value.trim().parse::<f64>().is_ok(), "a number"),
+            "boolean" => (value == "true" || value == "false", "true or false"),
+            _ => (true, ""),
+        };
+        if valid {
+            None
+        } else {
+            Some(format!("{} is not valid, expected {}", value, expected))
+        }
+    }
+
+    /// The argument with the name closest to `key`, for an argument the handler does not accept
+    pub fn suggestion(&self, key: &str) -> Option<&str> {
+        self.arguments
+            .keys()
+            .map(|name| (edit_distance(key, name), name))
+            .filter(|(distance, name)| *distance <= (name.len() / 3).max(1))
+            .min()
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// The arguments the handler needs that are not given by osdd itself
+    pub fn required_arguments(&self) -> Vec<&str> {
+        self.arguments
+            .iter()
+            .filter(|(name, argument)| {
+                argument.required && !OSDD_ARGUMENTS.contains(&name.as_str())
+            })
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+lazy_static! {
+    /// The schemas printed by the docker images, by image ID, so an image is only run once with `--describe`
+    static ref IMAGE_SCHEMAS: Mutex<HashMap<String, HandlerSchema>> = Mutex::new(HashMap::new());
+}
+
+/// Runs the executable of a handler with `--describe` and reads the schema of its arguments.
+/// With the docker runtime the executable runs in a container of its image,
+/// the schema of an image is kept so it is not run again for the next validation or reload.
+///
+/// # Arguments
+/// * `executable` - The type of the handler, the name of its executable and docker image
+/// * `settings` - The settings of the configuration, the executable is in its path with the native runtime
+/// * `runtime` - How the handler would be run
+///
+/// # Returns
+/// The schema, or a `DockerUnavailable` error when docker cannot be used at all
+pub fn describe_handler(
+    executable: &str,
+    settings: &Settings,
+    runtime: Runtime,
+) -> Result<HandlerSchema> {
+    match runtime {
+        Runtime::Native => run_describe(
+            executable,
+            Command::new(Path::new(&settings.path).join(executable)),
+        ),
+        Runtime::Docker => {
+            let image_id = image_id(executable)?;
+            if let Some(schema) = IMAGE_SCHEMAS
+                .lock()
+                .ok()
+                .and_then(|schemas| schemas.get(&image_id).cloned())
+            {
+                return Ok(schema);
+            }
+            let mut command = Command::new("docker");
+            command.args([
+                "run",
+                "--rm",
+                "--entrypoint",
+                &format!("./{}", executable),
+                &image_id,
+            ]);
+            let schema = run_describe(executable, command)?;
+            if let Ok(mut schemas) = IMAGE_SCHEMAS.lock() {
+                schemas.insert(image_id, schema.clone());
+            }
+            Ok(schema)
+        }
+    }
+}
+
+/// Asks docker for the ID of the image of a handler
+fn image_id(image: &str) -> Result<String> {
+    let output = match Command::new("docker")
+        .args(["image", "inspect", "--format", "{{.Id}}", image])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => return Err(ErrorKind::DockerUnavailable(e.to_string()).into()),
+    };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else if stderr.contains("No such image") {
+        Err(format!("There is no docker image {}", image).into())
+    } else {
+        Err(ErrorKind::DockerUnavailable(stderr.trim().to_string()).into())
+    }
+}
+
+/// Runs the command of a handler with `--describe` and parses the schema it prints
+fn run_describe(executable: &str, mut command: Command) -> Result<HandlerSchema> {
+    let output = command
+        .arg("--describe")
+        .output()
+        .chain_err(|| format!("Error running {} --describe", executable))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Error running {} --describe: {}",
+            executable,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    toml::from_str(&String::from_utf8_lossy(&output.stdout))
+        .chain_err(|| format!("{} --describe did not print a schema", executable))
+}
+
+/// The amount of characters that have to be inserted, removed or replaced to change `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let replace = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
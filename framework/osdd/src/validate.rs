@@ -0,0 +1,914 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::config_loader::*;
+use crate::errors::ErrorKind::*;
+use crate::errors::*;
+use crate::read_toml::argument_values;
+use crate::schema::*;
+use crate::*;
+use std::collections::HashMap;
+use std::fmt;
+use toml::value::Table;
+
+/// How bad a problem in the configuration is
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    /// osdd cannot run the configuration
+    Error,
+    /// osdd can run the configuration, but it is probably not what was meant
+    Warning,
+}
+
+/// A problem in the configuration file
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The TOML path of the problem, for example `chain.TestTopic.transport_handler`
+    pub location: String,
+    /// The line of the problem, when it can be found
+    pub line: Option<usize>,
+    /// The included file the line is in, none for the configuration file itself
+    pub file: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: ")?,
+            Severity::Warning => write!(f, "warning: ")?,
+        }
+        if !self.location.is_empty() {
+            write!(f, "{} ", self.location)?;
+        }
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "({} line {}) ", file, line)?,
+            (None, Some(line)) => write!(f, "(line {}) ", line)?,
+            _ => (),
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Returns a handler on a cycle of the edges, or none when the edges form a DAG
+fn find_cycle<'e>(edges: &[(&'e str, &'e str, String)]) -> Option<&'e str> {
+    //removes the handlers without incoming edges until only the handlers on or after a cycle remain
+    let mut remaining: Vec<(&str, &str)> = edges.iter().map(|(from, to, _)| (*from, *to)).collect();
+    loop {
+        let before = remaining.len();
+        let targets: Vec<&str> = remaining.iter().map(|(_, to)| *to).collect();
+        remaining.retain(|(from, _)| targets.contains(from));
+        if remaining.is_empty() {
+            return None;
+        }
+        if remaining.len() == before {
+            return remaining.iter().map(|(from, _)| *from).min();
+        }
+    }
+}
+
+/// Returns true when one of the diagnostics is an error
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|x| x.severity == Severity::Error)
+}
+
+/// Checks the configuration file, with its includes and templates, and returns every problem found in it
+pub fn validate_file(config_file: &str) -> Result<Vec<Diagnostic>> {
+    match load_config_file(config_file) {
+        Ok(config) => Ok(validate_config(&config)),
+        Err(e) => Ok(vec![Diagnostic {
+            severity: Severity::Error,
+            location: String::new(),
+            line: None,
+            file: None,
+            message: configuration_error(&e),
+        }]),
+    }
+}
+
+/// Checks the configuration and returns every problem found in it, sorted by file and line
+pub fn validate_config(config: &LoadedConfig) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        origins: &config.origins,
+        open_udp_ports: HashMap::new(),
+        settings: None,
+        schemas: HashMap::new(),
+        docker_unavailable: false,
+        diagnostics: Vec::new(),
+    };
+    validator.validate(&config.value);
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by(|a, b| {
+        (&a.file, a.line.unwrap_or(usize::MAX)).cmp(&(&b.file, b.line.unwrap_or(usize::MAX)))
+    });
+    diagnostics
+}
+
+/// Returns the message of a ConfigurationError without the kind of the error
+fn configuration_error(error: &Error) -> String {
+    match error.kind() {
+        ConfigurationError(message) => message.to_string(),
+        kind => kind.to_string(),
+    }
+}
+
+/// A handler defined in one of the handler tables
+struct DefinedHandler {
+    table: &'static str,
+    location: String,
+    ///The handler connects to its own incoming_address instead of a socket per previous handler
+    incoming_address: bool,
+    ///The handler listens on its own outgoing_address instead of a socket per next handler
+    outgoing_address: bool,
+}
+
+struct Validator<'a> {
+    origins: &'a HashMap<String, Origin>,
+    ///The handler setting that opened each udp port
+    open_udp_ports: HashMap<u16, String>,
+    ///The settings when they are valid, they are needed to run the handlers with `--describe`
+    settings: Option<(Settings, Runtime)>,
+    ///The schema of every handler type, or why it could not be read
+    schemas: HashMap<String, std::result::Result<HandlerSchema, String>>,
+    ///Docker could not be used to describe the handlers, their arguments are not checked
+    docker_unavailable: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    /// Adds a problem, at the line of `location` or else of the table it is in
+    fn report(&mut self, severity: Severity, location: &str, message: String) {
+        //an element of an array is on the line of the array
+        let mut path = location.split('[').next().unwrap_or(location);
+        let (file, line) = loop {
+            if let Some((file, line)) = self.origins.get(path) {
+                break (file.clone(), Some(*line));
+            }
+            match path.rfind('.') {
+                Some(index) => path = &path[..index],
+                None => break (None, None),
+            }
+        };
+        self.diagnostics.push(Diagnostic {
+            severity,
+            location: location.to_string(),
+            line,
+            file,
+            message,
+        });
+    }
+
+    fn error(&mut self, location: &str, message: String) {
+        self.report(Severity::Error, location, message);
+    }
+
+    fn validate(&mut self, toml_value: &Value) {
+        let tables = match toml_value.as_table() {
+            Some(tables) => tables,
+            None => return,
+        };
+        match tables.get("settings") {
+            Some(settings) => self.check_settings(settings),
+            None => self.error(
+                "settings",
+                "the [settings] table is missing, it is needed for every configuration".to_string(),
+            ),
+        }
+        let mut handlers: HashMap<String, DefinedHandler> = HashMap::new();
+        for table in HANDLER_TABLES.iter() {
+            if let Some(value) = tables.get(*table) {
+                self.check_handlers(table, value, &mut handlers);
+            }
+        }
+        let mut used: HashMap<String, String> = HashMap::new();
+        if let Some(chains) = tables.get("chain") {
+            self.check_chains(chains, &handlers, &mut used);
+        }
+        for (name, handler) in &handlers {
+            if !used.contains_key(name) {
+                self.report(
+                    Severity::Warning,
+                    &handler.location,
+                    "handler is not used in any chain, it is not started".to_string(),
+                );
+            }
+        }
+        for key in tables.keys() {
+            if key != "settings" && key != "chain" && !HANDLER_TABLES.contains(&key.as_str()) {
+                self.error(
+                    key,
+                    "unknown table, expected settings, chain, protocolhandler, filterhandler or transporthandler".to_string(),
+                );
+            }
+        }
+    }
+
+    fn check_settings(&mut self, value: &Value) {
+        let settings: Settings = match value.clone().try_into() {
+            Ok(settings) => settings,
+            Err(e) => return self.error("settings", e.to_string()),
+        };
+        self.check_port("settings.syslog_port", &settings.syslog_port);
+        self.check_port(
+            "settings.stats_multiplexer_listening_port",
+            &settings.stats_multiplexer_listening_port,
+        );
+        for server in &settings.stats_servers {
+            match server.rfind(':') {
+                Some(index) => {
+                    self.check_port("settings.stats_servers", &server[index + 1..]);
+                }
+                None => self.error(
+                    "settings.stats_servers",
+                    format!("{} is not a host:port address", server),
+                ),
+            }
+        }
+        if settings.network != "ingress" && settings.network != "egress" {
+            self.error(
+                "settings.network",
+                format!(
+                    "unknown network {}, expected ingress or egress",
+                    settings.network
+                ),
+            );
+        }
+        if let Err(e) = settings.runtime() {
+            self.error("settings.runtime", configuration_error(&e));
+        }
+        if let Err(e) = settings.max_restarts() {
+            self.error("settings.max_restarts", configuration_error(&e));
+        }
+        if let Ok(runtime) = settings.runtime() {
+            self.settings = Some((settings, runtime));
+        }
+    }
+
+    fn check_handlers(
+        &mut self,
+        table: &'static str,
+        value: &Value,
+        handlers: &mut HashMap<String, DefinedHandler>,
+    ) {
+        let handler_list = match value.as_table() {
+            Some(handler_list) => handler_list,
+            None => return self.error(table, format!("expected [{}.<name>] tables", table)),
+        };
+        for (name, handler) in handler_list {
+            let location = format!("{}.{}", table, name);
+            if let Some(other) = handlers.get(name) {
+                let message = format!("handler {} is also defined as {}", name, other.location);
+                self.error(&location, message);
+                continue;
+            }
+            handlers.insert(
+                name.to_string(),
+                DefinedHandler {
+                    table,
+                    location: location.clone(),
+                    incoming_address: handler.get("incoming_address").is_some(),
+                    outgoing_address: handler.get("outgoing_address").is_some(),
+                },
+            );
+            let arguments = match handler.as_table() {
+                Some(arguments) => arguments,
+                None => {
+                    self.error(
+                        &location,
+                        "expected a table of handler settings".to_string(),
+                    );
+                    continue;
+                }
+            };
+            if !arguments.contains_key("type") {
+                self.error(&location, "the type of the handler is missing".to_string());
+            }
+            for (key, argument) in arguments {
+                let argument_location = format!("{}.{}", location, key);
+                //the settings osdd reads itself are strings, a port can also be an integer
+                let values = if ["type", "incoming_address", "outgoing_address", "user"]
+                    .contains(&key.as_str())
+                {
+                    match argument.as_str() {
+                        Some(argument) => vec![argument.to_string()],
+                        None => {
+                            self.error(
+                                &argument_location,
+                                format!(
+                                    "{} is not a string, expected a value in double quotes",
+                                    argument
+                                ),
+                            );
+                            continue;
+                        }
+                    }
+                } else {
+                    match argument_values(argument) {
+                        Ok(values) => values,
+                        Err(message) => {
+                            self.error(&argument_location, message);
+                            continue;
+                        }
+                    }
+                };
+                if key == "open_udp_port" && !(argument.is_str() || argument.is_integer()) {
+                    self.error(&argument_location, format!("{} is not a port", argument));
+                    continue;
+                }
+                for argument in values.iter().map(String::as_str) {
+                    self.check_argument(key, &argument_location, argument);
+                }
+            }
+            if let Some(executable) = arguments.get("type").and_then(Value::as_str) {
+                self.check_schema(table, &location, executable, arguments);
+            }
+        }
+    }
+
+    /// Checks the arguments of a handler against the schema the handler prints with `--describe`.
+    /// A handler that cannot be described is a warning, its arguments are then not checked.
+    fn check_schema(&mut self, table: &str, location: &str, executable: &str, arguments: &Table) {
+        let (settings, runtime) = match &self.settings {
+            Some(settings) => settings,
+            None => return,
+        };
+        let expected_role = match table {
+            "filterhandler" => "filter",
+            "transporthandler" => "transport",
+            _ => settings.network.as_str(),
+        }
+        .to_string();
+        if self.docker_unavailable {
+            return;
+        }
+        if !self.schemas.contains_key(executable) {
+            match describe_handler(executable, settings, *runtime) {
+                //reported once, the configuration can be validated on a machine without docker
+                Err(Error(ErrorKind::DockerUnavailable(reason), _)) => {
+                    self.docker_unavailable = true;
+                    self.report(
+                        Severity::Warning,
+                        "settings.runtime",
+                        format!(
+                            "docker is not available, the arguments of the handlers are not checked: {}",
+                            reason
+                        ),
+                    );
+                    return;
+                }
+                //the error and its causes on one line
+                schema => {
+                    let schema = schema.map_err(|e| {
+                        let causes: Vec<String> = e.iter().map(ToString::to_string).collect();
+                        causes.join(": ")
+                    });
+                    self.schemas.insert(executable.to_string(), schema);
+                }
+            }
+        }
+        let mut problems: Vec<(Severity, String, String)> = Vec::new();
+        match &self.schemas[executable] {
+            Err(e) => problems.push((
+                Severity::Warning,
+                location.to_string(),
+                format!("the arguments of {} cannot be checked: {}", executable, e),
+            )),
+            Ok(schema) => {
+                if schema.role != expected_role {
+                    problems.push((
+                        Severity::Error,
+                        format!("{}.type", location),
+                        format!(
+                            "{} is a {} handler, expected a {} handler",
+                            executable, schema.role, expected_role
+                        ),
+                    ));
+                }
+                for (key, argument) in arguments {
+                    let argument_location = format!("{}.{}", location, key);
+                    if OSDD_HANDLER_SETTINGS.contains(&key.as_str()) {
+                        continue;
+                    }
+                    if !schema.arguments.contains_key(key) {
+                        let message = match schema.suggestion(key) {
+                            Some(name) => format!(
+                                "unknown argument, {} does not accept --{}, did you mean {}?",
+                                executable, key, name
+                            ),
+                            None => {
+                                format!(
+                                    "unknown argument, {} does not accept --{}",
+                                    executable, key
+                                )
+                            }
+                        };
+                        problems.push((Severity::Error, argument_location, message));
+                        continue;
+                    }
+                    for value in argument_values(argument).unwrap_or_default() {
+                        if let Some(message) = schema.check_value(key, &value) {
+                            problems.push((Severity::Error, argument_location.clone(), message));
+                        }
+                    }
+                }
+                for name in schema.required_arguments() {
+                    if !arguments.contains_key(name) {
+                        problems.push((
+                            Severity::Error,
+                            location.to_string(),
+                            format!("{} is missing, {} needs it", name, executable),
+                        ));
+                    }
+                }
+            }
+        }
+        for (severity, location, message) in problems {
+            self.report(severity, &location, message);
+        }
+    }
+
+    /// Checks one value of a handler argument
+    fn check_argument(&mut self, key: &str, argument_location: &str, argument: &str) {
+        if key == "open_udp_port" {
+            if let Some(port) = self.check_port(argument_location, argument) {
+                if let Some(other) = self
+                    .open_udp_ports
+                    .insert(port, argument_location.to_string())
+                {
+                    self.error(
+                        argument_location,
+                        format!("udp port {} is already opened by {}", port, other),
+                    );
+                }
+            }
+        } else if is_port_key(key) {
+            self.check_port(argument_location, argument);
+        } else if key == "incoming_address" || key == "outgoing_address" {
+            if !["unix://", "tcp://", "tls://"]
+                .iter()
+                .any(|scheme| argument.starts_with(scheme))
+            {
+                self.error(
+                    argument_location,
+                    format!("{} is not a unix://, tcp:// or tls:// address", argument),
+                );
+            }
+        } else if key == "user" {
+            let ids: Vec<&str> = argument.split(':').collect();
+            if ids.len() != 2 || ids.iter().any(|id| id.parse::<u32>().is_err()) {
+                self.error(argument_location, format!("{} is not a uid:gid", argument));
+            }
+        }
+    }
+
+    fn check_chains(
+        &mut self,
+        value: &Value,
+        handlers: &HashMap<String, DefinedHandler>,
+        used: &mut HashMap<String, String>,
+    ) {
+        let chains = match value.as_table() {
+            Some(chains) => chains,
+            None => return self.error("chain", "expected [chain.<name>] tables".to_string()),
+        };
+        for (name, chain) in chains {
+            let location = format!("chain.{}", name);
+            let keys = match chain.as_table() {
+                Some(keys) => keys,
+                None => {
+                    self.error(&location, "expected a table of chain settings".to_string());
+                    continue;
+                }
+            };
+            let linear = ["protocol_handler", "filter_handlers", "transport_handler"];
+            if keys.contains_key("edges") {
+                for key in linear.iter().filter(|key| keys.contains_key(**key)) {
+                    self.error(
+                        &format!("{}.{}", location, key),
+                        "cannot be combined with edges, add the handler to the edges instead"
+                            .to_string(),
+                    );
+                }
+            } else {
+                for key in &linear {
+                    if !keys.contains_key(*key) {
+                        self.error(&location, format!("{} is missing", key));
+                    }
+                }
+            }
+            for (key, value) in keys {
+                let key_location = format!("{}.{}", location, key);
+                match key.as_ref() {
+                    "protocol_handler" => {
+                        self.check_chain_handler(&key_location, value, 0, handlers, used)
+                    }
+                    "transport_handler" => {
+                        self.check_chain_handler(&key_location, value, 2, handlers, used)
+                    }
+                    "filter_handlers" => match value.as_array() {
+                        Some(filters) => {
+                            for (index, filter) in filters.iter().enumerate() {
+                                let filter_location = format!("{}[{}]", key_location, index);
+                                self.check_chain_handler(
+                                    &filter_location,
+                                    filter,
+                                    1,
+                                    handlers,
+                                    used,
+                                );
+                            }
+                        }
+                        None => self.error(
+                            &key_location,
+                            "expected an array of filter handlers".to_string(),
+                        ),
+                    },
+                    "edges" => self.check_edges(&key_location, value, keys, handlers, used),
+                    "link" => match value.as_str() {
+                        Some("socket") | Some("shared_memory") => (),
+                        _ => self.error(
+                            &key_location,
+                            format!("unknown link {}, expected socket or shared_memory", value),
+                        ),
+                    },
+                    _ => self.error(&key_location, "unknown chain setting".to_string()),
+                }
+            }
+        }
+    }
+
+    /// Checks the edges of a chain, they must form a DAG from protocol handlers through filters to transport handlers
+    fn check_edges(
+        &mut self,
+        location: &str,
+        value: &Value,
+        chain: &Table,
+        handlers: &HashMap<String, DefinedHandler>,
+        used: &mut HashMap<String, String>,
+    ) {
+        let edges = match value.as_array() {
+            Some(edges) if !edges.is_empty() => edges,
+            _ => {
+                return self.error(
+                    location,
+                    "expected an array of [from, to] or [from, to, lag_policy] edges".to_string(),
+                )
+            }
+        };
+        //the handlers of every edge that could be checked, with the location of the edge
+        let mut checked: Vec<(&str, &str, String)> = Vec::new();
+        for (index, edge) in edges.iter().enumerate() {
+            let edge_location = format!("{}[{}]", location, index);
+            let pair = match edge.as_array() {
+                Some(pair) if pair.len() == 2 || pair.len() == 3 => pair,
+                _ => {
+                    self.error(
+                        &edge_location,
+                        format!(
+                            "{} is not a [from, to] or [from, to, lag_policy] edge",
+                            edge
+                        ),
+                    );
+                    continue;
+                }
+            };
+            if let Some(lag_policy) = pair.get(2) {
+                if !["block", "drop", "disconnect"].contains(&lag_policy.as_str().unwrap_or("")) {
+                    self.error(
+                        &edge_location,
+                        format!(
+                            "unknown lag policy {}, expected \"block\", \"drop\" or \"disconnect\"",
+                            lag_policy
+                        ),
+                    );
+                }
+            }
+            //a protocol handler only sends and a transport handler only receives
+            let from = self.check_edge_handler(&edge_location, &pair[0], &[0, 1], handlers);
+            let to = self.check_edge_handler(&edge_location, &pair[1], &[1, 2], handlers);
+            if let (Some(from), Some(to)) = (from, to) {
+                if from == to {
+                    self.error(
+                        &edge_location,
+                        format!("handler {} is linked to itself", from),
+                    );
+                } else if checked.iter().any(|(f, t, _)| *f == from && *t == to) {
+                    self.error(
+                        &edge_location,
+                        format!("{} is already linked to {}", from, to),
+                    );
+                } else {
+                    checked.push((from, to, edge_location));
+                }
+            }
+        }
+
+        let mut names: Vec<&str> = Vec::new();
+        for (from, to, edge_location) in &checked {
+            for name in &[*from, *to] {
+                if !names.contains(name) {
+                    names.push(name);
+                    self.mark_used(edge_location, name, used);
+                }
+            }
+        }
+        let shared_memory = chain.get("link").and_then(Value::as_str) == Some("shared_memory");
+        for name in &names {
+            let incoming = checked.iter().filter(|(_, to, _)| to == name).count();
+            let outgoing = checked.iter().filter(|(from, _, _)| from == name).count();
+            let handler = &handlers[*name];
+            if handler.table == HANDLER_TABLES[1] && (incoming == 0 || outgoing == 0) {
+                self.error(
+                    location,
+                    format!(
+                        "filter {} needs an edge from a handler and an edge to a handler",
+                        name
+                    ),
+                );
+            }
+            for (count, address, direction) in &[
+                (incoming, handler.incoming_address, "incoming"),
+                (outgoing, handler.outgoing_address, "outgoing"),
+            ] {
+                if *count > 1 && *address {
+                    self.error(
+                        location,
+                        format!(
+                            "handler {} has an {}_address, it cannot be linked to several handlers",
+                            name, direction
+                        ),
+                    );
+                } else if *count > 1 && shared_memory {
+                    self.error(
+                        location,
+                        format!(
+                            "handler {} is linked to several handlers, which a shared_memory link cannot do",
+                            name
+                        ),
+                    );
+                }
+            }
+        }
+        if let Some(name) = find_cycle(&checked) {
+            self.error(
+                location,
+                format!("the edges form a cycle through handler {}", name),
+            );
+        }
+    }
+
+    /// Checks a handler of an edge, `positions` are the indexes of the handler tables allowed there
+    fn check_edge_handler<'v>(
+        &mut self,
+        location: &str,
+        value: &'v Value,
+        positions: &[usize],
+        handlers: &HashMap<String, DefinedHandler>,
+    ) -> Option<&'v str> {
+        let expected: Vec<&str> = positions.iter().map(|x| HANDLER_TABLES[*x]).collect();
+        let expected = expected.join(" or ");
+        let name = match value.as_str() {
+            Some(name) => name,
+            None => {
+                self.error(
+                    location,
+                    format!(
+                        "{} is not a string, expected the name of a {}",
+                        value, expected
+                    ),
+                );
+                return None;
+            }
+        };
+        match handlers.get(name) {
+            None => self.error(
+                location,
+                format!("handler {} is not defined, expected a {}", name, expected),
+            ),
+            Some(handler)
+                if !positions
+                    .iter()
+                    .any(|x| HANDLER_TABLES[*x] == handler.table) =>
+            {
+                self.error(
+                    location,
+                    format!(
+                        "handler {} is defined as {}, expected a {}",
+                        name, handler.location, expected
+                    ),
+                )
+            }
+            Some(_) => return Some(name),
+        }
+        None
+    }
+
+    /// Checks a handler in a chain, `position` is the index of the handler table expected there
+    fn check_chain_handler(
+        &mut self,
+        location: &str,
+        value: &Value,
+        position: usize,
+        handlers: &HashMap<String, DefinedHandler>,
+        used: &mut HashMap<String, String>,
+    ) {
+        let expected = HANDLER_TABLES[position];
+        let name = match value.as_str() {
+            Some(name) => name,
+            None => {
+                return self.error(
+                    location,
+                    format!(
+                        "{} is not a string, expected the name of a {}",
+                        value, expected
+                    ),
+                )
+            }
+        };
+        match handlers.get(name) {
+            None => self.error(
+                location,
+                format!(
+                    "handler {} is not defined, expected a [{}.{}] table",
+                    name, expected, name
+                ),
+            ),
+            Some(handler) if handler.table != expected => self.error(
+                location,
+                format!(
+                    "handler {} is defined as {}, expected a {}",
+                    name, handler.location, expected
+                ),
+            ),
+            Some(_) => (),
+        }
+        self.mark_used(location, name, used);
+    }
+
+    /// Remembers the chain a handler is used in, a handler used in several chains is started once for every chain
+    fn mark_used(&mut self, location: &str, name: &str, used: &mut HashMap<String, String>) {
+        //the sockets of a handler are assigned per chain, so it can only be used once in a chain
+        if let Some(other) = used.insert(name.to_string(), location.to_string()) {
+            let chain = location.rsplitn(2, '.').last().unwrap_or("");
+            if other.rsplitn(2, '.').last() == Some(chain) {
+                self.error(
+                    location,
+                    format!(
+                        "handler {} is already used in {}, a handler can only be used once in a chain",
+                        name, other
+                    ),
+                );
+            } else {
+                self.report(
+                    Severity::Warning,
+                    location,
+                    format!(
+                        "handler {} is also used in {}, it is started once for every chain",
+                        name, other
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Checks that the value is a port a socket can be bound or sent to
+    fn check_port(&mut self, location: &str, value: &str) -> Option<u16> {
+        match value.trim().parse::<u16>() {
+            Ok(port) if port != 0 => Some(port),
+            _ => {
+                self.error(
+                    location,
+                    format!("{} is not a valid port, expected 1 to 65535", value),
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validate::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempdir::TempDir;
+
+    const PROTOCOL_HANDLER_SCHEMA: &str = "role = \"ingress\"\n\
+        [arguments.topic_name]\ntype = \"string\"\nrequired = true\n\
+        [arguments.max_bytes]\ntype = \"integer\"\n";
+    const TRANSPORT_HANDLER_SCHEMA: &str = "role = \"transport\"\n\
+        [arguments.port]\ntype = \"integer\"\n";
+
+    /// Writes the configuration file and handlers that print their schema in a new directory,
+    /// and returns the diagnostics of the configuration file
+    fn validate(handlers_and_chains: &str) -> Vec<String> {
+        let dir = TempDir::new("validate_test").expect("Can't create dir");
+        for (executable, schema) in &[
+            ("ph_test", PROTOCOL_HANDLER_SCHEMA),
+            ("transport_test", TRANSPORT_HANDLER_SCHEMA),
+        ] {
+            let path = dir.path().join(executable);
+            fs::write(&path, format!("#!/bin/sh\ncat <<'EOF'\n{}EOF\n", schema))
+                .expect("Can't write handler");
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                .expect("Can't make handler executable");
+        }
+        let config = format!(
+            "[settings]\npath = \"{}\"\nstats_servers = [\"127.0.0.1:8125\"]\n\
+             syslog_host = \"127.0.0.1\"\nsyslog_port = \"514\"\nlog_level = \"Info\"\n\
+             instance = \"1\"\nnetwork = \"ingress\"\nstats_multiplexer_listening_port = \"8125\"\n\
+             runtime = \"native\"\n{}",
+            dir.path().display(),
+            handlers_and_chains
+        );
+        let config_file = dir.path().join("Config.toml");
+        fs::write(&config_file, config).expect("Can't write config");
+        validate_file(&config_file.to_string_lossy())
+            .expect("Can't validate config")
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn valid_config_test() {
+        let diagnostics = validate(
+            "[protocolhandler.kafka]\ntype = \"ph_test\"\ntopic_name = \"orders\"\n\
+             max_bytes = 1024\nopen_udp_port = \"7000\"\n\
+             [transporthandler.udp]\ntype = \"transport_test\"\nport = \"7001\"\n\
+             [chain.orders]\nprotocol_handler = \"kafka\"\nfilter_handlers = []\n\
+             transport_handler = \"udp\"\n",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn diagnostics_test() {
+        let diagnostics = validate(
+            "[protocolhandler.kafka]\ntype = \"ph_test\"\ntopic_nme = \"orders\"\n\
+             max_bytes = \"lots\"\nopen_udp_port = \"7000\"\n\
+             [protocolhandler.other]\ntype = \"ph_test\"\ntopic_name = \"x\"\nopen_udp_port = 7000\n\
+             [transporthandler.udp]\ntype = \"transport_test\"\nport = \"70000\"\n\
+             [chain.orders]\nprotocol_handler = \"kafka\"\nfilter_handlers = [\"missing\"]\n\
+             transport_handler = \"udp\"\n",
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                "error: protocolhandler.kafka (line 11) topic_name is missing, ph_test needs it",
+                "error: protocolhandler.kafka.topic_nme (line 13) unknown argument, \
+                 ph_test does not accept --topic_nme, did you mean topic_name?",
+                "error: protocolhandler.kafka.max_bytes (line 14) lots is not valid, expected an integer",
+                "warning: protocolhandler.other (line 16) handler is not used in any chain, it is not started",
+                "error: protocolhandler.other.open_udp_port (line 19) \
+                 udp port 7000 is already opened by protocolhandler.kafka.open_udp_port",
+                "error: transporthandler.udp.port (line 22) 70000 is not a valid port, expected 1 to 65535",
+                "error: chain.orders.filter_handlers[0] (line 25) \
+                 handler missing is not defined, expected a [filterhandler.missing] table",
+            ]
+        );
+    }
+
+    #[test]
+    fn edges_test() {
+        let diagnostics = validate(
+            "[protocolhandler.kafka]\ntype = \"ph_test\"\ntopic_name = \"orders\"\n\
+             [filterhandler.first]\ntype = \"filter_test\"\n\
+             [filterhandler.second]\ntype = \"filter_test\"\n\
+             [transporthandler.udp]\ntype = \"transport_test\"\n\
+             [chain.orders]\nedges = [[\"kafka\", \"first\", \"wait\"], [\"first\", \"second\"], \
+             [\"second\", \"first\"], [\"second\", \"udp\"], [\"udp\", \"kafka\"]]\n\
+             [unknown]\n",
+        );
+        //filter_test is not in the path, so the arguments of the filters cannot be checked
+        let errors: Vec<&String> = diagnostics
+            .iter()
+            .filter(|x| x.starts_with("error"))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "error: chain.orders.edges[0] (line 21) \
+                 unknown lag policy \"wait\", expected \"block\", \"drop\" or \"disconnect\"",
+                "error: chain.orders.edges[4] (line 21) \
+                 handler udp is defined as transporthandler.udp, expected a protocolhandler or filterhandler",
+                "error: chain.orders.edges[4] (line 21) \
+                 handler kafka is defined as protocolhandler.kafka, expected a filterhandler or transporthandler",
+                "error: chain.orders.edges (line 21) the edges form a cycle through handler first",
+                "error: unknown (line 22) unknown table, \
+                 expected settings, chain, protocolhandler, filterhandler or transporthandler",
+            ]
+        );
+    }
+}
@@ -0,0 +1,261 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::ErrorKind::AddressError;
+use crate::errors::*;
+use std::fmt;
+use std::str::FromStr;
+
+///The address of a socket link between two handlers.
+///A plain path or a `unix://` URI is a unix domain socket, `tcp://host:port` is a TCP connection
+///and `tls://host:port?cert=..&key=..&ca=..` is a TCP connection with mutual TLS.
+///A `unix://` URI can set the permissions of the socket and the identity of the peer,
+///for example `unix:///tmp/socket?mode=660&peer_uid=1000&peer_gid=1000`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocketAddress {
+    ///The path of a unix domain socket.
+    Unix(String, UnixPermissions),
+    ///The host and port of a TCP connection.
+    Tcp(String),
+    ///The host and port of a TCP connection with mutual TLS.
+    Tls(String, TlsFiles),
+}
+
+///The local certificate files used for a TLS link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsFiles {
+    ///PEM file with the certificate chain of this handler.
+    pub cert: String,
+    ///PEM file with the private key of this handler, PKCS8 or RSA.
+    pub key: String,
+    ///PEM file with the certificate authorities the peer certificate must be signed by.
+    pub ca: String,
+    ///The name the certificate of the peer is checked against, the host of the address when not set.
+    ///Only used by the side that connects.
+    pub server_name: Option<String>,
+}
+
+///The permissions of a unix domain socket and the identity its peer must have.
+///The side that listens sets the mode and ownership of the socket file.
+///Both sides check the credentials of their peer, a peer that does not match is refused.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UnixPermissions {
+    ///The mode of the socket file, in octal.
+    pub mode: Option<u32>,
+    ///The user id that owns the socket file.
+    pub owner: Option<u32>,
+    ///The group id that owns the socket file.
+    pub group: Option<u32>,
+    ///The user id the peer must run as.
+    pub peer_uid: Option<u32>,
+    ///The group id the peer must run as.
+    pub peer_gid: Option<u32>,
+    ///The process id the peer must have.
+    pub peer_pid: Option<u32>,
+}
+
+impl FromStr for SocketAddress {
+    type Err = Error;
+
+    fn from_str(address: &str) -> Result<SocketAddress> {
+        if let Some(rest) = address.strip_prefix("unix://") {
+            let (path, parameters) = split_query(rest);
+            let mut permissions = UnixPermissions::default();
+            for (name, value) in parameters {
+                let number = |radix: u32| {
+                    u32::from_str_radix(&value, radix).map_err(|_| {
+                        AddressError(format!("{} of {} is not a number", name, address))
+                    })
+                };
+                match name {
+                    "mode" => permissions.mode = Some(number(8)?),
+                    "owner" => permissions.owner = Some(number(10)?),
+                    "group" => permissions.group = Some(number(10)?),
+                    "peer_uid" => permissions.peer_uid = Some(number(10)?),
+                    "peer_gid" => permissions.peer_gid = Some(number(10)?),
+                    "peer_pid" => permissions.peer_pid = Some(number(10)?),
+                    _ => {
+                        return Err(AddressError(format!(
+                            "Unknown parameter {} in {}, expected mode, owner, group, peer_uid, peer_gid or peer_pid",
+                            name, address
+                        ))
+                        .into())
+                    }
+                }
+            }
+            Ok(SocketAddress::Unix(path.to_string(), permissions))
+        } else if let Some(host_port) = address.strip_prefix("tcp://") {
+            Ok(SocketAddress::Tcp(check_host_port(host_port)?))
+        } else if let Some(rest) = address.strip_prefix("tls://") {
+            let (host_port, parameters) = split_query(rest);
+            let mut cert = None;
+            let mut key = None;
+            let mut ca = None;
+            let mut server_name = None;
+            for (name, value) in parameters {
+                match name {
+                    "cert" => cert = Some(value),
+                    "key" => key = Some(value),
+                    "ca" => ca = Some(value),
+                    "server_name" => server_name = Some(value),
+                    _ => {
+                        return Err(AddressError(format!(
+                            "Unknown parameter {} in {}, expected cert, key, ca or server_name",
+                            name, address
+                        ))
+                        .into())
+                    }
+                }
+            }
+            let missing = |name: &str| AddressError(format!("{} has no {} file", address, name));
+            Ok(SocketAddress::Tls(
+                check_host_port(host_port)?,
+                TlsFiles {
+                    cert: cert.ok_or_else(|| missing("cert"))?,
+                    key: key.ok_or_else(|| missing("key"))?,
+                    ca: ca.ok_or_else(|| missing("ca"))?,
+                    server_name,
+                },
+            ))
+        } else if address.contains("://") {
+            Err(AddressError(format!(
+                "Unknown scheme in {}, expected unix://, tcp:// or tls://",
+                address
+            ))
+            .into())
+        } else {
+            Ok(SocketAddress::Unix(
+                address.to_string(),
+                UnixPermissions::default(),
+            ))
+        }
+    }
+}
+
+impl fmt::Display for SocketAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SocketAddress::Unix(path, _) => write!(f, "unix://{}", path),
+            SocketAddress::Tcp(host_port) => write!(f, "tcp://{}", host_port),
+            SocketAddress::Tls(host_port, _) => write!(f, "tls://{}", host_port),
+        }
+    }
+}
+
+impl SocketAddress {
+    ///Returns the host of a TCP or TLS address.
+    pub fn host(&self) -> Option<&str> {
+        match self {
+            SocketAddress::Unix(..) => None,
+            SocketAddress::Tcp(host_port) | SocketAddress::Tls(host_port, _) => {
+                host_port.rfind(':').map(|index| {
+                    host_port[..index]
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                })
+            }
+        }
+    }
+}
+
+///Splits an address into the part before the `?` and the `name=value` parameters after it.
+pub(crate) fn split_query(address: &str) -> (&str, Vec<(&str, String)>) {
+    let (start, query) = match address.find('?') {
+        Some(index) => (&address[..index], &address[index + 1..]),
+        None => (address, ""),
+    };
+    let parameters = query
+        .split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| match parameter.find('=') {
+            Some(index) => (&parameter[..index], parameter[index + 1..].to_string()),
+            None => (parameter, String::new()),
+        })
+        .collect();
+    (start, parameters)
+}
+
+///Checks that the address has a host and a port.
+fn check_host_port(host_port: &str) -> Result<String> {
+    match host_port.rfind(':') {
+        Some(index) if index > 0 && host_port[index + 1..].parse::<u16>().is_ok() => {
+            Ok(host_port.to_string())
+        }
+        _ => Err(AddressError(format!("{} is not a host and port", host_port)).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_address_test() {
+        assert_eq!(
+            "/tmp/x".parse::<SocketAddress>().unwrap(),
+            SocketAddress::Unix("/tmp/x".to_string(), UnixPermissions::default())
+        );
+        assert_eq!(
+            "unix:///tmp/x".parse::<SocketAddress>().unwrap(),
+            SocketAddress::Unix("/tmp/x".to_string(), UnixPermissions::default())
+        );
+        assert_eq!(
+            "unix:///tmp/x?mode=660&group=1001&peer_uid=1000&peer_gid=1001"
+                .parse::<SocketAddress>()
+                .unwrap(),
+            SocketAddress::Unix(
+                "/tmp/x".to_string(),
+                UnixPermissions {
+                    mode: Some(0o660),
+                    group: Some(1001),
+                    peer_uid: Some(1000),
+                    peer_gid: Some(1001),
+                    ..Default::default()
+                }
+            )
+        );
+        let address = "tcp://filter-host:7000".parse::<SocketAddress>().unwrap();
+        assert_eq!(address, SocketAddress::Tcp("filter-host:7000".to_string()));
+        assert_eq!(address.host(), Some("filter-host"));
+        assert_eq!(
+            "tls://10.0.0.2:7000?cert=/c.pem&key=/k.pem&ca=/ca.pem&server_name=filter"
+                .parse::<SocketAddress>()
+                .unwrap(),
+            SocketAddress::Tls(
+                "10.0.0.2:7000".to_string(),
+                TlsFiles {
+                    cert: "/c.pem".to_string(),
+                    key: "/k.pem".to_string(),
+                    ca: "/ca.pem".to_string(),
+                    server_name: Some("filter".to_string()),
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn wrong_address_test() {
+        assert!("udp://host:1".parse::<SocketAddress>().is_err());
+        assert!("tcp://host".parse::<SocketAddress>().is_err());
+        assert!("unix:///tmp/x?mode=888".parse::<SocketAddress>().is_err());
+        assert!("unix:///tmp/x?peer=1".parse::<SocketAddress>().is_err());
+        assert!("tcp://host:port".parse::<SocketAddress>().is_err());
+        assert!("tls://host:1?cert=/c.pem&key=/k.pem"
+            .parse::<SocketAddress>()
+            .is_err());
+        assert!("tls://host:1?cert=/c.pem&key=/k.pem&ca=/ca.pem&other=1"
+            .parse::<SocketAddress>()
+            .is_err());
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::*;
+use crate::link::{LinkReader, LinkType, LinkWriter};
+use bip_utils::element_stream::{ElementSink, ElementStream};
+use bip_utils::{bip_buffer_with_len, BipBufferReader, BipBufferWriter};
+use futures_core::Stream;
+use futures_sink::Sink;
+use statistics_handler::StatsAllHandlers;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+///Receives the elements of the previous handler in the chain as a Stream, for handlers that run on an async runtime.
+///A thread receives the elements with a LinkReader and sends them to a bip_buffer the Stream reads from.
+///When the Stream is not polled the bip_buffer fills up and the thread stops receiving from the link.
+pub struct AsyncLinkReader {
+    elements: ElementStream,
+    error: Arc<Mutex<Option<Error>>>,
+    ended: bool,
+}
+
+impl AsyncLinkReader {
+    ///Creates a new instance of AsyncLinkReader and starts connecting to the link in a thread.
+    ///This function does not block, the Stream is pending until the previous handler has created the link.
+    /// # Arguments
+    /// * `link_type` - The kind of link to connect to.
+    /// * `path` - The address of the socket or the path of the ring file.
+    /// * `capacity` - The size of the bip_buffer in bytes, larger elements are dropped.
+    /// * `stats_data` - The statistics used to count the reconnections and the dropped elements.
+    pub fn connect(
+        link_type: LinkType,
+        path: &str,
+        capacity: usize,
+        stats_data: Option<Arc<StatsAllHandlers>>,
+    ) -> AsyncLinkReader {
+        let (writer, reader) = bip_buffer_with_len(capacity);
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = error.clone();
+        let wakeup = writer.wakeup().clone();
+        let path = path.to_string();
+        std::thread::spawn(move || {
+            if let Err(e) = receive_elements(link_type, &path, writer, capacity, stats_data) {
+                log::error!("AsyncLinkReader stopped receiving from {}: {}", path, e);
+                *thread_error
+                    .lock()
+                    .expect("Error locking AsyncLinkReader mutex") = Some(e);
+                wakeup.notify_data();
+            }
+        });
+        AsyncLinkReader {
+            elements: ElementStream::new(reader),
+            error,
+            ended: false,
+        }
+    }
+}
+
+///Receives the elements from the link and sends them to the bip_buffer until an error occurs.
+fn receive_elements(
+    link_type: LinkType,
+    path: &str,
+    writer: BipBufferWriter,
+    capacity: usize,
+    stats_data: Option<Arc<StatsAllHandlers>>,
+) -> Result<()> {
+    let mut link_reader = LinkReader::new(link_type, path, writer, capacity)?;
+    if let Some(stats_data) = stats_data {
+        link_reader.set_stats_data(stats_data);
+    }
+    loop {
+        link_reader.receive_data()?;
+    }
+}
+
+impl Stream for AsyncLinkReader {
+    type Item = Result<Vec<u8>>;
+
+    ///Returns the next element, or the error that stopped the thread after the elements received before it.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Vec<u8>>>> {
+        let reader = self.get_mut();
+        if reader.ended {
+            return Poll::Ready(None);
+        }
+        match Pin::new(&mut reader.elements).poll_next(cx) {
+            Poll::Ready(Some(element)) => Poll::Ready(Some(Ok(element))),
+            Poll::Ready(None) => {
+                reader.ended = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => {
+                let mut error = reader
+                    .error
+                    .lock()
+                    .expect("Error locking AsyncLinkReader mutex");
+                if error.is_none() {
+                    return Poll::Pending;
+                }
+                //the thread has stopped, the elements it sent before are returned first.
+                match Pin::new(&mut reader.elements).poll_next(cx) {
+                    Poll::Ready(Some(element)) => Poll::Ready(Some(Ok(element))),
+                    _ => {
+                        reader.ended = true;
+                        Poll::Ready(error.take().map(Err))
+                    }
+                }
+            }
+        }
+    }
+}
+
+///Sends elements to the next handler in the chain as a Sink, for handlers that run on an async runtime.
+///The Sink writes the elements to a bip_buffer a thread sends to the link with a LinkWriter.
+///Every element must be a frame, see `frame::encode_frame`. When the bip_buffer is full the Sink is not ready.
+///Flushing the Sink writes its element to the bip_buffer, the thread sends it when the next handler is connected.
+pub struct AsyncLinkWriter {
+    elements: ElementSink,
+    error: Arc<Mutex<Option<Error>>>,
+    stopped: bool,
+}
+
+impl AsyncLinkWriter {
+    ///Creates a new instance of AsyncLinkWriter and starts creating the link in a thread.
+    ///This function does not block, elements are sent when the next handler has connected.
+    /// # Arguments
+    /// * `link_type` - The kind of link to create.
+    /// * `path` - The address of the socket or the path the ring file is created on.
+    /// * `capacity` - The size of the bip_buffer and the shared memory ring in bytes, larger elements are refused.
+    /// * `stats_data` - The statistics used to count the reconnections.
+    pub fn start(
+        link_type: LinkType,
+        path: &str,
+        capacity: usize,
+        stats_data: Option<Arc<StatsAllHandlers>>,
+    ) -> AsyncLinkWriter {
+        let (writer, reader) = bip_buffer_with_len(capacity);
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = error.clone();
+        let wakeup = reader.wakeup().clone();
+        let path = path.to_string();
+        std::thread::spawn(move || {
+            if let Err(e) = send_elements(link_type, &path, reader, capacity, stats_data) {
+                log::error!("AsyncLinkWriter stopped sending to {}: {}", path, e);
+                *thread_error
+                    .lock()
+                    .expect("Error locking AsyncLinkWriter mutex") = Some(e);
+                wakeup.notify_space();
+            }
+        });
+        AsyncLinkWriter {
+            elements: ElementSink::new(writer, capacity),
+            error,
+            stopped: false,
+        }
+    }
+
+    ///Returns the error that stopped the thread, the first time it is asked for.
+    fn stopped_error(&mut self) -> Option<Error> {
+        if self.stopped {
+            return Some("The link of the AsyncLinkWriter has stopped".into());
+        }
+        let error = self
+            .error
+            .lock()
+            .expect("Error locking AsyncLinkWriter mutex")
+            .take();
+        self.stopped = error.is_some();
+        error
+    }
+
+    ///Polls the ElementSink, unless the thread has stopped and nothing will make space in the bip_buffer.
+    fn poll_elements<F>(&mut self, cx: &mut Context<'_>, poll: F) -> Poll<Result<()>>
+    where
+        F: Fn(Pin<&mut ElementSink>, &mut Context<'_>) -> Poll<bip_utils::errors::Result<()>>,
+    {
+        if let Some(e) = self.stopped_error() {
+            return Poll::Ready(Err(e));
+        }
+        match poll(Pin::new(&mut self.elements), cx) {
+            Poll::Ready(result) => Poll::Ready(result.map_err(Error::from)),
+            Poll::Pending => match self.stopped_error() {
+                Some(e) => Poll::Ready(Err(e)),
+                None => Poll::Pending,
+            },
+        }
+    }
+}
+
+///Sends the elements of the bip_buffer to the link until an error occurs.
+fn send_elements(
+    link_type: LinkType,
+    path: &str,
+    mut reader: BipBufferReader,
+    capacity: usize,
+    stats_data: Option<Arc<StatsAllHandlers>>,
+) -> Result<()> {
+    let mut link_writer = LinkWriter::start(link_type, path, capacity)?;
+    if let Some(stats_data) = stats_data {
+        link_writer.set_stats_data(stats_data);
+    }
+    loop {
+        link_writer.send_data(&mut reader)?;
+    }
+}
+
+impl Sink<Vec<u8>> for AsyncLinkWriter {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut()
+            .poll_elements(cx, |elements, cx| elements.poll_ready(cx))
+    }
+
+    fn start_send(self: Pin<&mut Self>, element: Vec<u8>) -> Result<()> {
+        Ok(Pin::new(&mut self.get_mut().elements).start_send(element)?)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut()
+            .poll_elements(cx, |elements, cx| elements.poll_flush(cx))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut()
+            .poll_elements(cx, |elements, cx| elements.poll_close(cx))
+    }
+}
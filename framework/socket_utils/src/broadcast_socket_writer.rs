@@ -0,0 +1,371 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::address::SocketAddress;
+use crate::errors::*;
+use crate::frame::check_frame;
+use crate::stream::{Listener, Stream};
+use bip_utils::get_element_length;
+use bip_utils::overflow::BufferMonitor;
+use bip_utils::wait_for_data;
+use bip_utils::BipBufferReader;
+use statistics_handler::{BufferStats, StatsAllHandlers};
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+///The default amount of elements a reader can fall behind before its LagPolicy is applied.
+pub const DEFAULT_MAX_LAG: usize = 128;
+
+///The policy used when a reader of a BroadcastSocketWriter has fallen `max_lag` elements behind.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LagPolicy {
+    ///Wait until the reader has caught up, this slows down all other readers.
+    Block,
+    ///Drop the elements for the slow reader, the other readers are not affected.
+    Drop,
+    ///Close the connection with the slow reader.
+    Disconnect,
+}
+
+impl FromStr for LagPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> std::result::Result<LagPolicy, String> {
+        match policy {
+            "block" => Ok(LagPolicy::Block),
+            "drop" => Ok(LagPolicy::Drop),
+            "disconnect" => Ok(LagPolicy::Disconnect),
+            _ => Err(format!(
+                "Unknown lag policy {}, expected block, drop or disconnect",
+                policy
+            )),
+        }
+    }
+}
+
+///A reader connected to one of the listeners of a BroadcastSocketWriter.
+///The elements for the reader are queued and written to its socket by a thread per reader.
+#[derive(Clone)]
+struct Subscriber {
+    id: u64,
+    listener: usize,
+    sender: SyncSender<Arc<[u8]>>,
+}
+
+///The readers of a BroadcastSocketWriter, shared with the threads accepting new readers.
+#[derive(Default)]
+struct Subscribers {
+    next_id: u64,
+    connected: Vec<Subscriber>,
+}
+
+///A listener of a BroadcastSocketWriter and the LagPolicy of the readers connecting to it.
+struct BroadcastListener {
+    listener: Arc<Listener>,
+    address: SocketAddress,
+    lag_policy: LagPolicy,
+    stats: Option<Arc<BufferStats>>,
+}
+
+///Sends every element of a bip_buffer to all connected readers.
+///The readers connect to one of the listeners, every listener has its own LagPolicy.
+///A chain uses a listener with the Block policy for the next handler, which then behaves like a BufferedSocketWriter,
+///and a listener with the Drop or Disconnect policy to attach monitoring or archival consumers.
+pub struct BroadcastSocketWriter {
+    listeners: Vec<BroadcastListener>,
+    subscribers: Arc<(Mutex<Subscribers>, Condvar)>,
+    max_lag: usize,
+    started: bool,
+    monitor: Option<Arc<BufferMonitor>>,
+    stats_data: Option<Arc<StatsAllHandlers>>,
+}
+
+impl BroadcastSocketWriter {
+    ///Creates a new instance of the BroadcastSocketWriter without listeners.
+    /// # Arguments
+    /// * `max_lag` - The amount of elements a reader can fall behind before its LagPolicy is applied.
+    pub fn new(max_lag: usize) -> BroadcastSocketWriter {
+        BroadcastSocketWriter {
+            listeners: Vec::new(),
+            subscribers: Arc::new((Mutex::new(Subscribers::default()), Condvar::new())),
+            max_lag,
+            started: false,
+            monitor: None,
+            stats_data: None,
+        }
+    }
+
+    ///Starts accepting readers on `path` in a separate thread.
+    ///Elements are only sent when every listener with the Block policy has at least one reader,
+    ///so the next handler of a chain does not miss elements while it restarts.
+    /// # Arguments
+    /// * `path` - The address the socket listens on, a path or a `unix://`, `tcp://` or `tls://` URI.
+    /// * `lag_policy` - The LagPolicy of the readers connecting to this listener.
+    pub fn listen(&mut self, path: &str, lag_policy: LagPolicy) -> Result<()> {
+        let address: SocketAddress = path.parse()?;
+        let listener = Arc::new(Listener::bind(&address)?);
+        let index = self.listeners.len();
+        let stats = self
+            .stats_data
+            .as_ref()
+            .map(|stats_data| register_listener(stats_data, index));
+        self.listeners.push(BroadcastListener {
+            listener: listener.clone(),
+            address: address.clone(),
+            lag_policy,
+            stats,
+        });
+
+        let subscribers = self.subscribers.clone();
+        let max_lag = self.max_lag;
+        thread::Builder::new()
+            .name(format!("broadcast_accept_{}", index))
+            .spawn(move || loop {
+                match listener.accept() {
+                    Ok(stream) => add_subscriber(&subscribers, index, stream, max_lag, &address),
+                    Err(e) => {
+                        log::error!(
+                            "BroadcastSocketWriter stopped listening at {}: {}",
+                            address,
+                            e
+                        );
+                        return;
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    ///Sets the BufferMonitor of the bip_buffer this BroadcastSocketWriter reads from.
+    /// # Arguments
+    /// * `monitor` - The BufferMonitor shared with the writer of the bip_buffer.
+    pub fn set_buffer_monitor(&mut self, monitor: Arc<BufferMonitor>) {
+        self.monitor = Some(monitor);
+    }
+
+    ///Sets the statistics used to count the reconnections and the elements dropped for slow readers.
+    /// # Arguments
+    /// * `stats_data` - The struct used to store statistics data.
+    pub fn set_stats_data(&mut self, stats_data: Arc<StatsAllHandlers>) {
+        for (index, listener) in self.listeners.iter_mut().enumerate() {
+            listener.stats = Some(register_listener(&stats_data, index));
+        }
+        self.stats_data = Some(stats_data);
+    }
+
+    ///Used to send data to all readers. The data that is sent is read using `reader`.
+    ///Every element in the bip_buffer must be a frame, elements that are not are discarded.
+    ///This function blocks until every listener with the Block policy has a reader.
+    /// # Arguments
+    /// * `reader` - The BipBufferReader used to get data from the bip_buffer.
+    /// # Returns
+    /// `usize` - The amount of bytes sent, 0 when the element was discarded.
+    pub fn send_data(&mut self, reader: &mut BipBufferReader) -> Result<usize> {
+        self.wait_for_blocking_readers();
+        //read a usize from the buffer
+        let element_length: usize = match &self.monitor {
+            Some(monitor) => monitor.get_element_length(reader),
+            None => get_element_length(reader),
+        };
+        //read data from the buffer
+        wait_for_data(reader, element_length);
+        let element: Option<Arc<[u8]>> = match check_frame(&reader.valid()[..element_length]) {
+            Ok(()) => Some(Arc::from(&reader.valid()[..element_length])),
+            Err(e) => {
+                log::error!("Element of {} bytes discarded: {}", element_length, e);
+                None
+            }
+        };
+        reader.consume(element_length);
+        if let Some(monitor) = &self.monitor {
+            monitor.consumed(element_length);
+        }
+        match element {
+            Some(element) => {
+                self.broadcast(element);
+                Ok(element_length)
+            }
+            None => Ok(0),
+        }
+    }
+
+    ///Sends `element` to all readers, applying the LagPolicy of each reader.
+    fn broadcast(&self, element: Arc<[u8]>) {
+        let (lock, _) = &*self.subscribers;
+        //the senders are cloned so a blocking reader does not stop new readers from being accepted.
+        let subscribers = lock
+            .lock()
+            .expect("Error locking broadcast readers")
+            .connected
+            .clone();
+        let mut lost = Vec::new();
+        for Subscriber {
+            id,
+            listener,
+            sender,
+        } in subscribers
+        {
+            let listener = &self.listeners[listener];
+            let result = match listener.lag_policy {
+                LagPolicy::Block => sender.send(element.clone()).map_err(|_| false),
+                LagPolicy::Drop | LagPolicy::Disconnect => {
+                    sender.try_send(element.clone()).map_err(|e| match e {
+                        TrySendError::Full(_) => true,
+                        TrySendError::Disconnected(_) => false,
+                    })
+                }
+            };
+            match result {
+                Ok(()) => (),
+                //the reader has fallen max_lag elements behind.
+                Err(true) => {
+                    if let Some(stats) = &listener.stats {
+                        stats.dropped_bytes.add(element.len() as u64);
+                        stats.dropped_packets.add(1);
+                    }
+                    if listener.lag_policy == LagPolicy::Disconnect {
+                        log::warn!(
+                            "Disconnecting reader {} of {} that fell {} elements behind",
+                            id,
+                            listener.address,
+                            self.max_lag
+                        );
+                        lost.push(id);
+                    }
+                }
+                //the thread of the reader has stopped because the reader is gone.
+                Err(false) => lost.push(id),
+            }
+        }
+        if !lost.is_empty() {
+            lock.lock()
+                .expect("Error locking broadcast readers")
+                .connected
+                .retain(|subscriber| !lost.contains(&subscriber.id));
+        }
+    }
+
+    ///Waits until every listener with the Block policy has at least one reader.
+    ///Waiting after the first element was sent counts as a reconnection.
+    fn wait_for_blocking_readers(&mut self) {
+        let shared = self.subscribers.clone();
+        let (lock, condvar) = &*shared;
+        let mut subscribers = lock.lock().expect("Error locking broadcast readers");
+        let mut waited = false;
+        while let Some((_, listener)) =
+            self.listeners.iter().enumerate().find(|(index, listener)| {
+                listener.lag_policy == LagPolicy::Block
+                    && !subscribers
+                        .connected
+                        .iter()
+                        .any(|subscriber| subscriber.listener == *index)
+            })
+        {
+            log::info!(
+                "BroadcastSocketWriter waiting for a reader at {}",
+                listener.address
+            );
+            subscribers = condvar
+                .wait(subscribers)
+                .expect("Error waiting for broadcast readers");
+            waited = true;
+        }
+        drop(subscribers);
+        if waited && self.started {
+            if let Some(stats_data) = &self.stats_data {
+                stats_data.reconnections.add(1);
+            }
+        }
+        self.started = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.subscribers
+            .0
+            .lock()
+            .expect("Error locking broadcast readers")
+            .connected
+            .clear();
+        for listener in &self.listeners {
+            if let Err(e) = listener.listener.remove() {
+                log::warn!("Error while cleaning up {}: {}", listener.address, e);
+            }
+        }
+        log::info!("BroadcastSocketWriter has been shutdown");
+    }
+}
+
+///Registers the statistics of the elements dropped for the readers of listener `index`.
+fn register_listener(stats_data: &StatsAllHandlers, index: usize) -> Arc<BufferStats> {
+    stats_data.register_buffer(&format!("broadcast_listener_{}", index))
+}
+
+///Adds a reader that connected to listener `index` and starts the thread writing its elements.
+fn add_subscriber(
+    subscribers: &Arc<(Mutex<Subscribers>, Condvar)>,
+    index: usize,
+    stream: Stream,
+    max_lag: usize,
+    address: &SocketAddress,
+) {
+    let (sender, receiver) = sync_channel(max_lag);
+    let (lock, condvar) = &**subscribers;
+    let mut subscribers = lock.lock().expect("Error locking broadcast readers");
+    let id = subscribers.next_id;
+    subscribers.next_id += 1;
+    let name = format!("{} reader {}", address, id);
+    if let Err(e) = thread::Builder::new()
+        .name(format!("broadcast_reader_{}", id))
+        .spawn(move || write_elements(stream, receiver, &name))
+    {
+        log::error!("Error starting thread for reader at {}: {}", address, e);
+        return;
+    }
+    log::info!("Reader {} connected to {}", id, address);
+    subscribers.connected.push(Subscriber {
+        id,
+        listener: index,
+        sender,
+    });
+    condvar.notify_all();
+}
+
+///Writes the elements queued for a reader to its socket until the reader is gone or is disconnected.
+fn write_elements(mut stream: Stream, receiver: Receiver<Arc<[u8]>>, name: &str) {
+    for element in receiver {
+        if let Err(e) = stream.write_all(&element) {
+            log::warn!("Lost {}: {}", name, e);
+            break;
+        }
+    }
+    if let Err(e) = stream.shutdown() {
+        log::debug!("Error shutting down {}: {}", name, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lag_policy_from_str_test() {
+        assert_eq!("block".parse(), Ok(LagPolicy::Block));
+        assert_eq!("drop".parse(), Ok(LagPolicy::Drop));
+        assert_eq!("disconnect".parse(), Ok(LagPolicy::Disconnect));
+        assert!("other".parse::<LagPolicy>().is_err());
+    }
+}
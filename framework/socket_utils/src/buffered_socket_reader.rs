@@ -11,86 +11,170 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-
+use crate::address::SocketAddress;
 use crate::errors::*;
+use crate::frame::{FrameHeader, FRAME_HEADER_LEN};
+use crate::is_peer_lost;
+use crate::stream::{connect, Stream};
+use bip_utils::max_element_bytes;
+use bip_utils::try_write_with;
+use bip_utils::BipBufferWriter;
 use framework_constants::*;
-use spsc_bip_buffer::BipBufferWriter;
-use std::io::Read;
-use std::net::Shutdown;
-use std::os::unix::net::UnixStream;
+use statistics_handler::StatsAllHandlers;
+use std::io::{sink, Read};
+use std::sync::Arc;
 
 pub struct BufferedSocketReader {
-    stream: UnixStream,
+    frames: FrameReader,
     writer: BipBufferWriter,
+    element_buffer: Vec<u8>,
 }
 
 impl BufferedSocketReader {
     ///Creates a new instance of BufferedSocketReader
     ///This function will block until the socket has been created by a SocketWriter.
     /// # Arguments
-    /// * `path` - The path of the socket the reader should connect to.
+    /// * `path` - The address of the socket the reader should connect to, a path or a `unix://`, `tcp://` or `tls://` URI.
     /// * `writer` - The BipBufferWriter used to send the received data to a bip_buffer.
-    pub fn new(path: &str, writer: BipBufferWriter) -> Result<BufferedSocketReader> {
-        //wait for socket to exist
-        while !std::path::Path::new(path).exists() {
-            std::thread::sleep(std::time::Duration::from_secs(2));
-            log::warn!("BufferedSocketReader: socketfile does not yet exist.");
-        }
-        //wait for accept() to be called on socket
-        loop {
-            if let Ok(stream) = UnixStream::connect(path) {
-                stream
-                    .set_nonblocking(false)
-                    .chain_err(|| "non blocking for BufferedSocketReader could not be set")?;
-                stream
-                    .set_write_timeout(None)
-                    .chain_err(|| "write timeout for BufferedSocketReader could not be set!")?;
-                return Ok(BufferedSocketReader { stream, writer });
-            } else {
-                std::thread::sleep(std::time::Duration::from_millis(200));
-                log::warn!("BufferedSocketReader: accept has not yet been called on this socket");
-            }
-        }
+    /// * `capacity` - The size of the bip_buffer in bytes.
+    pub fn new(
+        path: &str,
+        writer: BipBufferWriter,
+        capacity: usize,
+    ) -> Result<BufferedSocketReader> {
+        Ok(BufferedSocketReader {
+            frames: FrameReader::new(path, capacity)?,
+            writer,
+            element_buffer: Vec::new(),
+        })
+    }
+
+    ///Sets the statistics used to count the reconnections and the oversized elements of this BufferedSocketReader.
+    /// # Arguments
+    /// * `stats_data` - The struct used to store statistics data.
+    pub fn set_stats_data(&mut self, stats_data: Arc<StatsAllHandlers>) {
+        self.frames.stats_data = Some(stats_data);
     }
 
     ///This function fetches data from the socket.
     ///This data is then sent to the bip_buffer using the bipBufferWriter.
     ///This function will block until space is available in the bip_buffer.
+    ///Every frame is sent to the bip_buffer as one element, a frame of another version is an error.
+    ///When the peer is lost, the reader connects again. An element that was partly received is discarded,
+    ///the peer decides whether it is sent again.
+    ///A frame that does not fit in the bip_buffer is read from the socket and dropped.
+    /// # Returns
+    /// * `usize` - The length of the received element, 0 when it was dropped.
     pub fn receive_data(&mut self) -> Result<usize> {
-        //receive length field
-        let mut exact_length_buffer = [0; BIP_BUFFER_LEN_FIELD_LEN];
-        self.stream
-            .read_exact(&mut exact_length_buffer)
-            .chain_err(|| "Error reading exact when reading element length from stream")?;
-        let element_length = usize::from_le_bytes(exact_length_buffer);
-
-        //reserve total buffer space
-        let mut reservation = loop {
-            match self
-                .writer
-                .reserve(element_length + BIP_BUFFER_LEN_FIELD_LEN)
-            {
-                None => std::thread::sleep(std::time::Duration::from_millis(20)),
-                Some(reservation) => break reservation,
-            }
-        };
-
-        reservation[..BIP_BUFFER_LEN_FIELD_LEN].copy_from_slice(&exact_length_buffer);
-        let element_reservation =
-            &mut reservation[BIP_BUFFER_LEN_FIELD_LEN..element_length + BIP_BUFFER_LEN_FIELD_LEN];
-        //receive data packet
-        self.stream
-            .read_exact(element_reservation)
-            .chain_err(|| "Error reading exact when reading element from stream")?;
+        let element_length = self.frames.receive_frame(&mut self.element_buffer)?;
+        if element_length == 0 {
+            return Ok(0);
+        }
+        let element = &self.element_buffer;
+        try_write_with(
+            &mut self.writer,
+            element_length,
+            self.frames.capacity,
+            None,
+            |reservation| reservation.copy_from_slice(element),
+        )?;
         Ok(element_length)
     }
+
     ///Stops the BufferedSocketReader. Calls Shutdown::Both on the underlying stream.
-    pub fn stop(&self) -> Result<()> {
+    pub fn stop(&mut self) -> Result<()> {
         log::warn!("Error shutting down socket for BufferedSocketReader");
-        self.stream
-            .shutdown(Shutdown::Both)
+        self.frames
+            .stream
+            .shutdown()
             .chain_err(|| "Error shutting down socket for BufferedSocketReader")?;
         log::info!("BufferedSocketReader has been shutdown");
         Ok(())
     }
 }
+
+///Receives the frames of a socket, without sending them anywhere.
+///Used by the BufferedSocketReader and by every thread of a MergingSocketReader.
+pub(crate) struct FrameReader {
+    stream: Stream,
+    address: SocketAddress,
+    capacity: usize,
+    pub(crate) stats_data: Option<Arc<StatsAllHandlers>>,
+}
+
+impl FrameReader {
+    ///Connects to the socket, this function will block until the socket has been created.
+    /// # Arguments
+    /// * `path` - The address of the socket the reader should connect to.
+    /// * `capacity` - The size of the bip_buffer the frames are sent to, larger frames are dropped.
+    pub(crate) fn new(path: &str, capacity: usize) -> Result<FrameReader> {
+        let address: SocketAddress = path.parse()?;
+        Ok(FrameReader {
+            stream: connect(&address)?,
+            address,
+            capacity,
+            stats_data: None,
+        })
+    }
+
+    ///Receives the next frame into `frame`, its header included, and connects again when the peer is lost.
+    ///`frame` is resized to the frame, a frame that is cut off by a lost peer is never returned.
+    /// # Returns
+    /// * `usize` - The length of the frame, 0 when it does not fit in the bip_buffer and was dropped.
+    pub(crate) fn receive_frame(&mut self, frame: &mut Vec<u8>) -> Result<usize> {
+        loop {
+            match self.receive_element(frame) {
+                Ok(element_length) => return Ok(element_length),
+                Err(Error(ErrorKind::Io(ref e), _)) if is_peer_lost(e) => {
+                    log::warn!("BufferedSocketReader lost its peer: {}", e);
+                    self.reconnect()?;
+                }
+                Err(e) => return Err(e).chain_err(|| "Error reading element from stream"),
+            }
+        }
+    }
+
+    ///Receives a single frame from the socket.
+    fn receive_element(&mut self, frame: &mut Vec<u8>) -> Result<usize> {
+        //receive frame header
+        let mut header_buffer = [0; FRAME_HEADER_LEN];
+        self.stream.read_exact(&mut header_buffer)?;
+        let element_length = FrameHeader::read(&header_buffer)?.frame_len()?;
+        if element_length + BIP_BUFFER_LEN_FIELD_LEN > max_element_bytes(self.capacity) {
+            return self.drop_element(element_length);
+        }
+
+        //receive the rest of the frame
+        frame.resize(element_length, 0);
+        frame[..FRAME_HEADER_LEN].copy_from_slice(&header_buffer);
+        self.stream.read_exact(&mut frame[FRAME_HEADER_LEN..])?;
+        Ok(element_length)
+    }
+
+    ///Reads the rest of a frame that does not fit in the bip_buffer from the socket and drops it.
+    fn drop_element(&mut self, element_length: usize) -> Result<usize> {
+        log::warn!(
+            "Dropped frame of {} bytes, it does not fit in the bip_buffer of {} bytes",
+            element_length,
+            self.capacity
+        );
+        let rest = (element_length - FRAME_HEADER_LEN) as u64;
+        std::io::copy(&mut Read::by_ref(&mut self.stream).take(rest), &mut sink())?;
+        if let Some(stats_data) = &self.stats_data {
+            stats_data.oversized_packets.add(1);
+            stats_data.dropped_packets.add(1);
+            stats_data.dropped_bytes.add(element_length as u64);
+        }
+        Ok(0)
+    }
+
+    ///Connects to the socket again after the peer was lost.
+    fn reconnect(&mut self) -> Result<()> {
+        log::info!("BufferedSocketReader connecting again to {}", self.address);
+        self.stream = connect(&self.address)?;
+        if let Some(stats_data) = &self.stats_data {
+            stats_data.reconnections.add(1);
+        }
+        Ok(())
+    }
+}
@@ -12,55 +12,160 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::address::SocketAddress;
 use crate::errors::*;
+use crate::frame::check_frame;
+use crate::is_peer_lost;
+use crate::stream::{Listener, Stream};
 use bip_utils::get_element_length;
+use bip_utils::overflow::BufferMonitor;
 use bip_utils::wait_for_data;
-use framework_constants::BIP_BUFFER_LEN_FIELD_LEN;
-use spsc_bip_buffer::BipBufferReader;
+use bip_utils::BipBufferReader;
+use statistics_handler::StatsAllHandlers;
 use std::io::Write;
-use std::net::Shutdown;
-use std::os::unix::net::{UnixListener, UnixStream};
+use std::str::FromStr;
+use std::sync::Arc;
+
+///The policy used for the element that is being sent when the peer of a BufferedSocketWriter is lost.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InFlightPolicy {
+    ///Send the element again to the next peer.
+    Keep,
+    ///Discard the element, the next peer starts with the element after it.
+    Discard,
+}
+
+impl FromStr for InFlightPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> std::result::Result<InFlightPolicy, String> {
+        match policy {
+            "keep" => Ok(InFlightPolicy::Keep),
+            "discard" => Ok(InFlightPolicy::Discard),
+            _ => Err(format!(
+                "Unknown in flight policy {}, expected keep or discard",
+                policy
+            )),
+        }
+    }
+}
 
 pub struct BufferedSocketWriter {
-    stream: UnixStream,
-    path: String,
+    listener: Listener,
+    stream: Stream,
+    address: SocketAddress,
+    monitor: Option<Arc<BufferMonitor>>,
+    in_flight_policy: InFlightPolicy,
+    stats_data: Option<Arc<StatsAllHandlers>>,
 }
 
 impl BufferedSocketWriter {
     ///Creates a new instance of the SocketWriter and starts accepting connections to the socket.
+    ///This function will block until the first peer has connected.
     /// # Arguments
-    /// * `path` - The path the socket is created on.
+    /// * `path` - The address the socket listens on, a path or a `unix://`, `tcp://` or `tls://` URI.
     pub fn start_listening(path: &str) -> Result<BufferedSocketWriter> {
-        if let Err(e) = std::fs::remove_file(path) {
-            log::error!("Error removing socket file at {}: {}", path, e);
-        };
+        let address: SocketAddress = path.parse()?;
+        let listener = Listener::bind(&address)?;
         Ok(BufferedSocketWriter {
-            stream: create_stream(&path)?,
-            path: path.to_string(),
+            stream: listener.accept()?,
+            listener,
+            address,
+            monitor: None,
+            in_flight_policy: InFlightPolicy::Keep,
+            stats_data: None,
         })
     }
+
+    ///Sets the BufferMonitor of the bip_buffer this BufferedSocketWriter reads from.
+    ///The monitor is used to discard the oldest elements when the writer of the bip_buffer asks for it.
+    /// # Arguments
+    /// * `monitor` - The BufferMonitor shared with the writer of the bip_buffer.
+    pub fn set_buffer_monitor(&mut self, monitor: Arc<BufferMonitor>) {
+        self.monitor = Some(monitor);
+    }
+
+    ///Sets the policy used for the element that is being sent when the peer is lost, Keep by default.
+    /// # Arguments
+    /// * `in_flight_policy` - The InFlightPolicy to use.
+    pub fn set_in_flight_policy(&mut self, in_flight_policy: InFlightPolicy) {
+        self.in_flight_policy = in_flight_policy;
+    }
+
+    ///Sets the statistics used to count the reconnections of this BufferedSocketWriter.
+    /// # Arguments
+    /// * `stats_data` - The struct used to store statistics data.
+    pub fn set_stats_data(&mut self, stats_data: Arc<StatsAllHandlers>) {
+        self.stats_data = Some(stats_data);
+    }
+
     ///Used to send data to the socket. The data that is sent is read using `reader`.
+    ///Every element in the bip_buffer must be a frame, elements that are not are discarded.
+    ///When the peer is lost, a new connection is accepted and the InFlightPolicy is applied to the element.
     /// # Arguments
     /// * `reader` - The BipBufferReader used to get data from the bip_buffer.
-    #[allow(clippy::unused_io_amount)]
+    /// # Returns
+    /// `usize` - The amount of bytes sent, 0 when the element was discarded.
     pub fn send_data(&mut self, reader: &mut BipBufferReader) -> Result<usize> {
         //read a usize from the buffer
-        let element_length: usize = get_element_length(reader);
+        let element_length: usize = match &self.monitor {
+            Some(monitor) => monitor.get_element_length(reader),
+            None => get_element_length(reader),
+        };
         //read data from the buffer
         wait_for_data(reader, element_length);
-        let incoming = reader.valid();
-        self.stream
-            .write(&element_length.to_le_bytes())
-            .chain_err(|| "Buffered Socket Writer could not send to socket")?;
-        self.stream
-            .write(&incoming[..element_length])
-            .chain_err(|| "Buffered Socket Writer could not send to socket")?;
+        let mut sent = match check_frame(&reader.valid()[..element_length]) {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("Element of {} bytes discarded: {}", element_length, e);
+                false
+            }
+        };
+        while sent {
+            match self.stream.write_all(&reader.valid()[..element_length]) {
+                Ok(()) => break,
+                Err(e) if is_peer_lost(&e) => {
+                    log::warn!("BufferedSocketWriter lost its peer: {}", e);
+                    self.reconnect()?;
+                    if self.in_flight_policy == InFlightPolicy::Discard {
+                        log::warn!("Element of {} bytes discarded", element_length);
+                        sent = false;
+                    }
+                }
+                Err(e) => {
+                    return Err(Error::with_chain(
+                        e,
+                        "Buffered Socket Writer could not send to socket",
+                    ))
+                }
+            }
+        }
         reader.consume(element_length);
-        Ok(element_length + BIP_BUFFER_LEN_FIELD_LEN)
+        if let Some(monitor) = &self.monitor {
+            monitor.consumed(element_length);
+        }
+        if sent {
+            Ok(element_length)
+        } else {
+            Ok(0)
+        }
     }
 
-    pub fn stop(&self) {
-        match self.stream.shutdown(Shutdown::Both) {
+    ///Accepts the connection of the next peer.
+    fn reconnect(&mut self) -> Result<()> {
+        log::info!(
+            "BufferedSocketWriter waiting for a new peer at {}",
+            self.address
+        );
+        self.stream = self.listener.accept()?;
+        if let Some(stats_data) = &self.stats_data {
+            stats_data.reconnections.add(1);
+        }
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        match self.stream.shutdown() {
             Ok(_) => {
                 log::info!("BufferedSocketWriter has been shutdown");
             }
@@ -68,7 +173,7 @@ impl BufferedSocketWriter {
                 log::warn!("{:?}", e);
             }
         }
-        match std::fs::remove_file(&self.path) {
+        match self.listener.remove() {
             Ok(_) => {
                 log::info!("Cleanup succesfull. Shutdown complete.");
             }
@@ -79,24 +184,14 @@ impl BufferedSocketWriter {
     }
 }
 
-fn create_stream(path: &str) -> Result<UnixStream> {
-    match UnixListener::bind(path) {
-        Ok(listener) => match listener.accept() {
-            Ok((stream, address)) => {
-                log::info!("Client connected from: {:?}", address);
-                stream
-                    .set_nonblocking(false)
-                    .chain_err(|| "non blocking for BufferedSocketWriter could not be set!")?;
-                stream
-                    .set_read_timeout(None)
-                    .chain_err(|| "read timeout for BufferedSocketWriter could not be set!")?;
-                Ok(stream)
-            }
-            Err(e) => Err(Error::with_chain(e, "Failed to accept incoming connection")),
-        },
-        Err(e) => Err(Error::with_chain(
-            e,
-            "Error while binding unix domain socket path",
-        )),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_flight_policy_from_str_test() {
+        assert_eq!("keep".parse(), Ok(InFlightPolicy::Keep));
+        assert_eq!("discard".parse(), Ok(InFlightPolicy::Discard));
+        assert!("other".parse::<InFlightPolicy>().is_err());
     }
 }
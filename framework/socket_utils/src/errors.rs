@@ -19,6 +19,9 @@ error_chain! {
     types {
         Error, ErrorKind, ResultExt, Result;
     }
+    links {
+        Bip(bip_utils::errors::Error, bip_utils::errors::ErrorKind);
+    }
     foreign_links {
         ConfigError(::std::num::ParseIntError);
         Io(::std::io::Error);
@@ -34,5 +37,25 @@ error_chain! {
             description("Unix domain Socket error")
             display("Unix Domain Socket error: '{}'", t)
         }
+        SharedMemoryError(t: String){
+            description("Shared memory error")
+            display("Shared memory error: '{}'", t)
+        }
+        FrameError(t: String){
+            description("Frame error")
+            display("Frame error: '{}'", t)
+        }
+        AddressError(t: String){
+            description("Socket address error")
+            display("Socket address error: '{}'", t)
+        }
+        TlsError(t: String){
+            description("TLS error")
+            display("TLS error: '{}'", t)
+        }
+        PeerCredentialError(t: String){
+            description("Peer credential error")
+            display("Peer credential error: '{}'", t)
+        }
     }
 }
@@ -0,0 +1,414 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The frame format used between handlers.
+//!
+//! Every element a handler passes to the next handler is a frame:
+//!
+//! | Offset | Size | Field                                              |
+//! |--------|------|----------------------------------------------------|
+//! | 0      | 4    | magic, `OSDD` in ascii                             |
+//! | 4      | 1    | version, currently 1                               |
+//! | 5      | 1    | flags, bit 0 is set when metadata is present       |
+//! | 6      | 2    | length of the metadata section, little endian      |
+//! | 8      | 8    | length of the payload, little endian               |
+//! | 16     | ...  | metadata section, followed by the payload          |
+//!
+//! The metadata section is a list of entries of a 1 byte tag, a 2 byte little endian length and the value.
+//! Entries with an unknown tag are kept, so a handler forwards metadata it does not know about.
+
+use crate::errors::ErrorKind::FrameError;
+use crate::errors::*;
+use std::convert::TryFrom;
+
+///The magic at the start of every frame.
+pub const FRAME_MAGIC: [u8; 4] = *b"OSDD";
+///The version of the frame format written by this library.
+pub const FRAME_VERSION: u8 = 1;
+///The length of the fixed frame header.
+pub const FRAME_HEADER_LEN: usize = 16;
+///Set in the flags when the frame has a metadata section.
+pub const FLAG_METADATA: u8 = 0x01;
+///The maximum length of the frame header and metadata section together.
+pub const MAX_FRAME_PREFIX_LEN: usize = FRAME_HEADER_LEN + u16::MAX as usize;
+
+const TAG_CHAIN_ID: u8 = 1;
+const TAG_INGRESS_TIMESTAMP: u8 = 2;
+const TAG_CONTENT_TYPE: u8 = 3;
+const TAG_TRACE_ID: u8 = 4;
+const ENTRY_HEADER_LEN: usize = 3;
+
+///The fixed header at the start of every frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FrameHeader {
+    pub version: u8,
+    pub flags: u8,
+    pub metadata_len: u16,
+    pub payload_len: u64,
+}
+
+impl FrameHeader {
+    ///Reads the header at the start of `bytes`.
+    ///Fails when the magic or the version does not match, which means both ends do not speak the same protocol.
+    /// # Arguments
+    /// * `bytes` - The bytes starting with the frame header.
+    pub fn read(bytes: &[u8]) -> Result<FrameHeader> {
+        if bytes.len() < FRAME_HEADER_LEN {
+            return Err(FrameError(format!("Frame header of {} bytes", bytes.len())).into());
+        }
+        if bytes[..4] != FRAME_MAGIC {
+            return Err(FrameError(format!("Wrong magic {:x?}", &bytes[..4])).into());
+        }
+        if bytes[4] != FRAME_VERSION {
+            return Err(FrameError(format!(
+                "Frame version {} is not supported, expected {}",
+                bytes[4], FRAME_VERSION
+            ))
+            .into());
+        }
+        let mut metadata_len = [0; 2];
+        metadata_len.copy_from_slice(&bytes[6..8]);
+        let mut payload_len = [0; 8];
+        payload_len.copy_from_slice(&bytes[8..16]);
+        Ok(FrameHeader {
+            version: bytes[4],
+            flags: bytes[5],
+            metadata_len: u16::from_le_bytes(metadata_len),
+            payload_len: u64::from_le_bytes(payload_len),
+        })
+    }
+
+    ///Writes the header to the start of `bytes`.
+    pub fn write(&self, bytes: &mut [u8]) {
+        bytes[..4].copy_from_slice(&FRAME_MAGIC);
+        bytes[4] = self.version;
+        bytes[5] = self.flags;
+        bytes[6..8].copy_from_slice(&self.metadata_len.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.payload_len.to_le_bytes());
+    }
+
+    ///Returns the length of the frame, including this header.
+    ///Fails when the length does not fit in a usize, the payload length is read from the peer.
+    pub fn frame_len(&self) -> Result<usize> {
+        usize::try_from(self.payload_len)
+            .ok()
+            .and_then(|payload_len| {
+                payload_len.checked_add(FRAME_HEADER_LEN + self.metadata_len as usize)
+            })
+            .ok_or_else(|| {
+                FrameError(format!("Payload length {} is too large", self.payload_len)).into()
+            })
+    }
+}
+
+///The optional metadata of a frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    ///The name of the chain the frame entered the diode on.
+    pub chain_id: Option<String>,
+    ///The time the frame entered the diode, in microseconds since the unix epoch.
+    pub ingress_timestamp: Option<u64>,
+    ///The kind of payload, for instance "kafka" or "udp".
+    pub content_type: Option<String>,
+    ///An identifier to follow the frame through the handlers.
+    pub trace_id: Option<String>,
+    ///Entries with a tag this version does not know about, kept to forward them.
+    pub unknown: Vec<(u8, Vec<u8>)>,
+}
+
+impl Metadata {
+    ///Creates the metadata of the frames a handler produces.
+    ///The chain is taken from the handler name osdd gives the handler: osdd.instance.network.chain.type.name.
+    /// # Arguments
+    /// * `handler_name` - The name of the handler.
+    /// * `content_type` - The kind of payload the handler produces.
+    pub fn for_handler(handler_name: &str, content_type: &str) -> Metadata {
+        let parts: Vec<&str> = handler_name.split('.').collect();
+        Metadata {
+            chain_id: match parts.as_slice() {
+                ["osdd", _, _, chain, ..] => Some(chain.to_string()),
+                _ => None,
+            },
+            content_type: Some(content_type.to_string()),
+            ..Metadata::default()
+        }
+    }
+
+    ///Returns true when there is no metadata to send.
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    ///Returns the length of the encoded metadata section.
+    pub fn encoded_len(&self) -> usize {
+        self.entries()
+            .iter()
+            .map(|(_, value)| ENTRY_HEADER_LEN + value.len())
+            .sum()
+    }
+
+    ///Returns the entries of the metadata section in the order they are written.
+    fn entries(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut entries = Vec::new();
+        if let Some(chain_id) = &self.chain_id {
+            entries.push((TAG_CHAIN_ID, chain_id.as_bytes().to_vec()));
+        }
+        if let Some(ingress_timestamp) = self.ingress_timestamp {
+            entries.push((
+                TAG_INGRESS_TIMESTAMP,
+                ingress_timestamp.to_le_bytes().to_vec(),
+            ));
+        }
+        if let Some(content_type) = &self.content_type {
+            entries.push((TAG_CONTENT_TYPE, content_type.as_bytes().to_vec()));
+        }
+        if let Some(trace_id) = &self.trace_id {
+            entries.push((TAG_TRACE_ID, trace_id.as_bytes().to_vec()));
+        }
+        entries.extend(self.unknown.iter().cloned());
+        entries
+    }
+
+    ///Writes the encoded metadata section to the start of `bytes`.
+    ///Fails when an entry is longer than its 2 byte length field can hold.
+    fn write(&self, bytes: &mut [u8]) -> Result<()> {
+        let mut offset = 0;
+        for (tag, value) in self.entries() {
+            let value_len = u16::try_from(value.len()).map_err(|_| {
+                FrameError(format!(
+                    "Metadata entry of {} bytes is too large",
+                    value.len()
+                ))
+            })?;
+            bytes[offset] = tag;
+            bytes[offset + 1..offset + ENTRY_HEADER_LEN].copy_from_slice(&value_len.to_le_bytes());
+            offset += ENTRY_HEADER_LEN;
+            bytes[offset..offset + value.len()].copy_from_slice(&value);
+            offset += value.len();
+        }
+        Ok(())
+    }
+
+    ///Reads an encoded metadata section.
+    /// # Arguments
+    /// * `bytes` - The metadata section.
+    pub fn read(bytes: &[u8]) -> Result<Metadata> {
+        let mut metadata = Metadata::default();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            if offset + ENTRY_HEADER_LEN > bytes.len() {
+                return Err(FrameError("Truncated metadata entry".to_string()).into());
+            }
+            let tag = bytes[offset];
+            let mut len = [0; 2];
+            len.copy_from_slice(&bytes[offset + 1..offset + ENTRY_HEADER_LEN]);
+            let start = offset + ENTRY_HEADER_LEN;
+            let end = start + u16::from_le_bytes(len) as usize;
+            if end > bytes.len() {
+                return Err(FrameError("Truncated metadata entry".to_string()).into());
+            }
+            let value = &bytes[start..end];
+            match tag {
+                TAG_CHAIN_ID => metadata.chain_id = Some(read_string(value)?),
+                TAG_INGRESS_TIMESTAMP => {
+                    if value.len() != 8 {
+                        return Err(FrameError("Wrong ingress timestamp length".to_string()).into());
+                    }
+                    let mut timestamp = [0; 8];
+                    timestamp.copy_from_slice(value);
+                    metadata.ingress_timestamp = Some(u64::from_le_bytes(timestamp));
+                }
+                TAG_CONTENT_TYPE => metadata.content_type = Some(read_string(value)?),
+                TAG_TRACE_ID => metadata.trace_id = Some(read_string(value)?),
+                _ => metadata.unknown.push((tag, value.to_vec())),
+            }
+            offset = end;
+        }
+        Ok(metadata)
+    }
+}
+
+fn read_string(value: &[u8]) -> Result<String> {
+    String::from_utf8(value.to_vec())
+        .map_err(|_| FrameError("Metadata value is not valid utf-8".to_string()).into())
+}
+
+///A frame read from a buffer.
+#[derive(Debug, PartialEq)]
+pub struct Frame<'a> {
+    pub header: FrameHeader,
+    pub metadata: Metadata,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    ///Reads the frame in `bytes`, which must hold exactly one frame.
+    /// # Arguments
+    /// * `bytes` - The encoded frame.
+    pub fn read(bytes: &'a [u8]) -> Result<Frame<'a>> {
+        check_frame(bytes)?;
+        let header = FrameHeader::read(bytes)?;
+        let metadata_end = FRAME_HEADER_LEN + header.metadata_len as usize;
+        Ok(Frame {
+            header,
+            metadata: Metadata::read(&bytes[FRAME_HEADER_LEN..metadata_end])?,
+            payload: &bytes[metadata_end..],
+        })
+    }
+}
+
+///Checks that `element` holds exactly one frame of a supported version, without reading the metadata.
+/// # Arguments
+/// * `element` - The element to check.
+pub fn check_frame(element: &[u8]) -> Result<()> {
+    let frame_len = FrameHeader::read(element)?.frame_len()?;
+    if frame_len != element.len() {
+        return Err(FrameError(format!(
+            "Frame of {} bytes in an element of {} bytes",
+            frame_len,
+            element.len()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+///Returns the payload of the frame in `element`, without reading the metadata.
+/// # Arguments
+/// * `element` - The element holding exactly one frame.
+pub fn frame_payload(element: &[u8]) -> Result<&[u8]> {
+    check_frame(element)?;
+    let header = FrameHeader::read(element)?;
+    Ok(&element[FRAME_HEADER_LEN + header.metadata_len as usize..])
+}
+
+///Returns the length of the frame header and metadata section for `metadata`.
+///A handler can receive the payload at this offset in its buffer and write the prefix in front of it afterwards.
+/// # Arguments
+/// * `metadata` - The metadata of the frame.
+pub fn frame_prefix_len(metadata: &Metadata) -> usize {
+    FRAME_HEADER_LEN + metadata.encoded_len()
+}
+
+///Writes the frame header and the metadata section to the start of `buffer`.
+/// # Arguments
+/// * `buffer` - The buffer, at least `frame_prefix_len(metadata)` bytes long.
+/// * `metadata` - The metadata of the frame.
+/// * `payload_len` - The length of the payload that follows the prefix.
+/// # Returns
+/// * `usize` - The length of the prefix, an error when the metadata does not fit in a frame.
+pub fn write_frame_prefix(
+    buffer: &mut [u8],
+    metadata: &Metadata,
+    payload_len: usize,
+) -> Result<usize> {
+    let prefix_len = frame_prefix_len(metadata);
+    let metadata_len = u16::try_from(prefix_len - FRAME_HEADER_LEN).map_err(|_| {
+        FrameError(format!(
+            "Metadata section of {} bytes is too large",
+            prefix_len - FRAME_HEADER_LEN
+        ))
+    })?;
+    metadata.write(&mut buffer[FRAME_HEADER_LEN..prefix_len])?;
+    FrameHeader {
+        version: FRAME_VERSION,
+        flags: if metadata_len > 0 { FLAG_METADATA } else { 0 },
+        metadata_len,
+        payload_len: payload_len as u64,
+    }
+    .write(buffer);
+    Ok(prefix_len)
+}
+
+///Encodes a frame with `metadata` and `payload`.
+/// # Arguments
+/// * `metadata` - The metadata of the frame.
+/// * `payload` - The payload of the frame.
+pub fn encode_frame(metadata: &Metadata, payload: &[u8]) -> Result<Vec<u8>> {
+    let prefix_len = frame_prefix_len(metadata);
+    let mut frame = vec![0; prefix_len + payload.len()];
+    write_frame_prefix(&mut frame, metadata, payload.len())?;
+    frame[prefix_len..].copy_from_slice(payload);
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::*;
+
+    #[test]
+    fn frame_without_metadata_test() {
+        let frame = encode_frame(&Metadata::default(), &[7; 10]).expect("Can't encode frame");
+        assert_eq!(frame.len(), FRAME_HEADER_LEN + 10);
+        assert_eq!(&frame[..4], b"OSDD");
+        let read = Frame::read(&frame).expect("Can't read frame");
+        assert_eq!(read.header.flags, 0);
+        assert_eq!(read.metadata, Metadata::default());
+        assert_eq!(read.payload, &[7; 10][..]);
+    }
+
+    #[test]
+    fn frame_with_metadata_test() {
+        let mut metadata = Metadata::for_handler("osdd.test.ingress.chain1.ph.kafka1", "kafka");
+        metadata.ingress_timestamp = Some(1_600_000_000_000_000);
+        metadata.trace_id = Some("trace".to_string());
+        metadata.unknown.push((200, vec![1, 2, 3]));
+        let frame = encode_frame(&metadata, &[1, 2, 3, 4]).expect("Can't encode frame");
+        let read = Frame::read(&frame).expect("Can't read frame");
+        assert_eq!(read.header.flags, FLAG_METADATA);
+        assert_eq!(read.metadata.chain_id, Some("chain1".to_string()));
+        assert_eq!(read.metadata.content_type, Some("kafka".to_string()));
+        //unknown entries are kept, so they are forwarded unchanged.
+        assert_eq!(read.metadata, metadata);
+        assert_eq!(read.payload, &[1, 2, 3, 4][..]);
+        assert_eq!(
+            encode_frame(&read.metadata, read.payload).expect("Can't encode frame"),
+            frame
+        );
+    }
+
+    #[test]
+    fn wrong_frame_test() {
+        let mut frame = encode_frame(&Metadata::default(), &[0; 10]).expect("Can't encode frame");
+        assert!(Frame::read(&frame[..20]).is_err());
+        frame[4] = FRAME_VERSION + 1;
+        assert!(FrameHeader::read(&frame).is_err());
+        frame[0] = 0;
+        assert!(FrameHeader::read(&frame).is_err());
+        assert!(Metadata::for_handler("handler", "udp").chain_id.is_none());
+    }
+
+    #[test]
+    fn frame_length_overflow_test() {
+        let mut frame = encode_frame(&Metadata::default(), &[0; 10]).expect("Can't encode frame");
+        frame[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        let header = FrameHeader::read(&frame).expect("Can't read header");
+        assert!(header.frame_len().is_err());
+        assert!(check_frame(&frame).is_err());
+    }
+
+    #[test]
+    fn metadata_too_large_test() {
+        let mut metadata = Metadata {
+            trace_id: Some("t".repeat(u16::MAX as usize + 1)),
+            ..Metadata::default()
+        };
+        assert!(encode_frame(&metadata, &[0; 10]).is_err());
+        metadata.trace_id = Some("t".repeat(40_000));
+        metadata.unknown.push((200, vec![0; 40_000]));
+        assert!(encode_frame(&metadata, &[0; 10]).is_err());
+        let mut buffer = vec![0; frame_prefix_len(&metadata)];
+        assert!(write_frame_prefix(&mut buffer, &metadata, 10).is_err());
+    }
+}
@@ -12,15 +12,38 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod address;
+pub mod async_link;
+pub mod broadcast_socket_writer;
 pub mod buffered_socket_reader;
 pub mod buffered_socket_writer;
 pub mod errors;
+pub mod frame;
+pub mod link;
+pub mod merging_socket_reader;
+pub mod shm_ring;
+pub mod shm_ring_reader;
+pub mod shm_ring_writer;
 pub mod socket_reader;
 pub mod socket_writer;
+pub mod stream;
 
 pub const SOCKET_PATH_INGRESS: &str = "/tmp/handler_to_transport";
 pub const SOCKET_PATH_EGRESS: &str = "/tmp/transport_to_handler";
 
+///Returns true when the error means the peer on the other side of the socket is gone.
+///A BufferedSocketWriter then accepts a new connection, a BufferedSocketReader connects again.
+pub(crate) fn is_peer_lost(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::NotConnected
+            | std::io::ErrorKind::UnexpectedEof
+    )
+}
+
 #[cfg(test)]
 mod test {
     mod regular {
@@ -59,17 +82,21 @@ mod test {
     mod buffered {
         use crate::buffered_socket_reader::BufferedSocketReader;
         use crate::buffered_socket_writer::BufferedSocketWriter;
+        use crate::frame::*;
         use bip_utils::read_from_bip_buffer;
         use bip_utils::write_to_bip_buffer;
         use framework_constants::*;
+        use statistics_handler::StatsAllHandlers;
+        use tempdir::TempDir;
         #[test]
         fn read_write_single_element_test() {
             let path = "/tmp/read_write_single_element_buffered";
 
             let (mut in_writer, mut in_reader) =
-                spsc_bip_buffer::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
-            //add data to bip_buffer
-            let buffer = vec![2; MAX_BUFFER_SIZE_BYTES];
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            //add a frame to bip_buffer
+            let buffer = encode_frame(&Metadata::default(), &vec![2; MAX_BUFFER_SIZE_BYTES])
+                .expect("Can't encode frame");
             write_to_bip_buffer(&mut in_writer, &buffer);
             //start socket writer and socket reader
             std::thread::spawn(move || {
@@ -83,18 +110,497 @@ mod test {
             });
 
             let (out_writer, mut out_reader) =
-                spsc_bip_buffer::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
 
             let mut socket_reader =
-                BufferedSocketReader::new(path, out_writer).expect("Can't create socket reader");
+                BufferedSocketReader::new(path, out_writer, MAX_BIP_BUFFER_MESSAGE_SIZE * 10)
+                    .expect("Can't create socket reader");
             socket_reader.receive_data().expect("can't receive data");
             socket_reader.stop().expect("can't stop socket reader");
 
             //assert on data
-            let mut received_buffer = [0; MAX_BUFFER_SIZE_BYTES];
+            let mut received_buffer = vec![0; buffer.len()];
             read_from_bip_buffer(&mut out_reader, &mut received_buffer);
             assert_eq!(&buffer[..], &received_buffer[..]);
             assert_eq!(&buffer[..].len(), &received_buffer[..].len());
         }
+
+        #[test]
+        fn reconnect_test() {
+            let dir = TempDir::new("reconnect_test").expect("Can't create dir");
+            let path = dir.path().join("reconnect_buffered").display().to_string();
+            let writer_path = path.clone();
+
+            let (mut in_writer, mut in_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let first_frame =
+                encode_frame(&Metadata::default(), &[1; 100]).expect("Can't encode frame");
+            let second_frame =
+                encode_frame(&Metadata::default(), &[2; 200]).expect("Can't encode frame");
+            write_to_bip_buffer(&mut in_writer, &first_frame);
+            write_to_bip_buffer(&mut in_writer, &second_frame);
+            let (first_stopped, wait_for_first) = std::sync::mpsc::channel();
+            //the second element is sent after the first peer is gone, it is kept for the next peer.
+            let writer_handle = std::thread::spawn(move || {
+                let stats_data = std::sync::Arc::new(StatsAllHandlers::default());
+                let mut socket_writer = BufferedSocketWriter::start_listening(&writer_path)
+                    .expect("can't create socket writer");
+                socket_writer.set_stats_data(stats_data.clone());
+                socket_writer
+                    .send_data(&mut in_reader)
+                    .expect("Cant send data");
+                wait_for_first.recv().expect("Error waiting for first peer");
+                socket_writer
+                    .send_data(&mut in_reader)
+                    .expect("Cant send data");
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                socket_writer.stop();
+                stats_data.reconnections.load()
+            });
+
+            let (first_writer, mut first_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let mut socket_reader =
+                BufferedSocketReader::new(&path, first_writer, MAX_BIP_BUFFER_MESSAGE_SIZE * 10)
+                    .expect("Can't create socket reader");
+            assert_eq!(
+                socket_reader.receive_data().expect("can't receive data"),
+                first_frame.len()
+            );
+            socket_reader.stop().expect("can't stop socket reader");
+            drop(socket_reader);
+            first_stopped.send(()).expect("Error signaling writer");
+
+            let (second_writer, mut second_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let mut socket_reader =
+                BufferedSocketReader::new(&path, second_writer, MAX_BIP_BUFFER_MESSAGE_SIZE * 10)
+                    .expect("Can't create socket reader");
+            assert_eq!(
+                socket_reader.receive_data().expect("can't receive data"),
+                second_frame.len()
+            );
+
+            let mut received_buffer = vec![0; second_frame.len()];
+            assert_eq!(
+                read_from_bip_buffer(&mut first_reader, &mut received_buffer),
+                first_frame.len()
+            );
+            assert_eq!(
+                read_from_bip_buffer(&mut second_reader, &mut received_buffer),
+                second_frame.len()
+            );
+            assert_eq!(received_buffer, second_frame);
+            assert_eq!(writer_handle.join().expect("Error joining writer"), 1);
+        }
+        #[test]
+        fn oversized_element_test() {
+            let dir = TempDir::new("oversized_element_test").expect("Can't create dir");
+            let path = dir
+                .path()
+                .join("oversized_element_buffered")
+                .display()
+                .to_string();
+            let writer_path = path.clone();
+
+            let (mut in_writer, mut in_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let large_frame =
+                encode_frame(&Metadata::default(), &[1; 1000]).expect("Can't encode frame");
+            let small_frame =
+                encode_frame(&Metadata::default(), &[2; 100]).expect("Can't encode frame");
+            write_to_bip_buffer(&mut in_writer, &large_frame);
+            write_to_bip_buffer(&mut in_writer, &small_frame);
+            std::thread::spawn(move || {
+                let mut socket_writer = BufferedSocketWriter::start_listening(&writer_path)
+                    .expect("can't create socket writer");
+                for _ in 0..2 {
+                    socket_writer
+                        .send_data(&mut in_reader)
+                        .expect("Cant send data");
+                }
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                socket_writer.stop();
+            });
+
+            //the large frame does not fit in the bip_buffer of the reader, it is dropped.
+            let (out_writer, mut out_reader) = bip_utils::bip_buffer_with_len(500);
+            let stats_data = std::sync::Arc::new(StatsAllHandlers::default());
+            let mut socket_reader = BufferedSocketReader::new(&path, out_writer, 500)
+                .expect("Can't create socket reader");
+            socket_reader.set_stats_data(stats_data.clone());
+            assert_eq!(socket_reader.receive_data().expect("can't receive data"), 0);
+            assert_eq!(
+                socket_reader.receive_data().expect("can't receive data"),
+                small_frame.len()
+            );
+            assert_eq!(stats_data.oversized_packets.load(), 1);
+
+            let mut received_buffer = vec![0; small_frame.len()];
+            read_from_bip_buffer(&mut out_reader, &mut received_buffer);
+            assert_eq!(received_buffer, small_frame);
+        }
+    }
+    mod tcp {
+        use crate::buffered_socket_reader::BufferedSocketReader;
+        use crate::buffered_socket_writer::BufferedSocketWriter;
+        use crate::frame::*;
+        use bip_utils::read_from_bip_buffer;
+        use bip_utils::write_to_bip_buffer;
+        use framework_constants::*;
+        use std::net::TcpListener;
+        #[test]
+        fn read_write_tcp_test() {
+            //the port the system gives out is free, it is passed to both sides
+            let port = TcpListener::bind("127.0.0.1:0")
+                .and_then(|listener| listener.local_addr())
+                .expect("Can't find a free port")
+                .port();
+            let address = format!("tcp://127.0.0.1:{}", port);
+            let writer_address = address.clone();
+
+            let (mut in_writer, mut in_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let buffer = encode_frame(&Metadata::default(), &vec![3; MAX_BUFFER_SIZE_BYTES])
+                .expect("Can't encode frame");
+            write_to_bip_buffer(&mut in_writer, &buffer);
+            std::thread::spawn(move || {
+                let mut socket_writer = BufferedSocketWriter::start_listening(&writer_address)
+                    .expect("can't create socket writer");
+                socket_writer
+                    .send_data(&mut in_reader)
+                    .expect("Cant send data");
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                socket_writer.stop();
+            });
+
+            let (out_writer, mut out_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let mut socket_reader =
+                BufferedSocketReader::new(&address, out_writer, MAX_BIP_BUFFER_MESSAGE_SIZE * 10)
+                    .expect("Can't create socket reader");
+            assert_eq!(
+                socket_reader.receive_data().expect("can't receive data"),
+                buffer.len()
+            );
+            socket_reader.stop().expect("can't stop socket reader");
+
+            let mut received_buffer = vec![0; buffer.len()];
+            read_from_bip_buffer(&mut out_reader, &mut received_buffer);
+            assert_eq!(received_buffer, buffer);
+        }
+    }
+    mod broadcast {
+        use crate::broadcast_socket_writer::LagPolicy;
+        use crate::buffered_socket_reader::BufferedSocketReader;
+        use crate::frame::*;
+        use crate::link::*;
+        use bip_utils::read_from_bip_buffer;
+        use bip_utils::write_to_bip_buffer;
+        use framework_constants::*;
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+        use tempdir::TempDir;
+        #[test]
+        fn read_write_tap_test() {
+            let dir = TempDir::new("read_write_tap_test").expect("Can't create dir");
+            let path = dir.path().join("next_handler").display().to_string();
+            let tap_path = dir.path().join("monitor").display().to_string();
+
+            let (mut in_writer, mut in_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let first_frame =
+                encode_frame(&Metadata::default(), &[1; 100]).expect("Can't encode frame");
+            let second_frame =
+                encode_frame(&Metadata::default(), &[2; 200]).expect("Can't encode frame");
+            write_to_bip_buffer(&mut in_writer, &first_frame);
+            write_to_bip_buffer(&mut in_writer, &second_frame);
+            let mut link_writer =
+                LinkWriter::start_with_tap(LinkType::Socket, &path, &tap_path, LagPolicy::Drop, 10)
+                    .expect("can't create link writer");
+
+            //the tap connects first, the elements are only sent once the next handler has connected.
+            let (tap_writer, mut tap_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let mut tap_socket_reader =
+                BufferedSocketReader::new(&tap_path, tap_writer, MAX_BIP_BUFFER_MESSAGE_SIZE * 10)
+                    .expect("Can't create tap reader");
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            let writer_handle = std::thread::spawn(move || {
+                link_writer
+                    .send_data(&mut in_reader)
+                    .expect("Cant send data");
+                link_writer
+                    .send_data(&mut in_reader)
+                    .expect("Cant send data");
+                link_writer
+            });
+            let (out_writer, mut out_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let mut socket_reader =
+                BufferedSocketReader::new(&path, out_writer, MAX_BIP_BUFFER_MESSAGE_SIZE * 10)
+                    .expect("Can't create socket reader");
+
+            for frame in &[&first_frame, &second_frame] {
+                assert_eq!(
+                    socket_reader.receive_data().expect("can't receive data"),
+                    frame.len()
+                );
+                assert_eq!(
+                    tap_socket_reader
+                        .receive_data()
+                        .expect("can't receive data"),
+                    frame.len()
+                );
+                let mut received_buffer = vec![0; frame.len()];
+                read_from_bip_buffer(&mut out_reader, &mut received_buffer);
+                assert_eq!(&received_buffer, *frame);
+                read_from_bip_buffer(&mut tap_reader, &mut received_buffer);
+                assert_eq!(&received_buffer, *frame);
+            }
+            if let LinkWriter::Broadcast(mut writer) =
+                writer_handle.join().expect("Error joining writer")
+            {
+                writer.stop();
+            }
+        }
+        #[test]
+        fn fan_out_fan_in_test() {
+            let dir = TempDir::new("fan_out_fan_in_test").expect("Can't create dir");
+            let first = dir.path().join("first").display().to_string();
+            let second = dir.path().join("second").display().to_string();
+            //both handlers block the link, so they do not miss elements while they connect
+            let writer_paths = format!("{}?lag_policy=block,{}?lag_policy=block", first, second);
+            let reader_paths = format!("{},{}", first, second);
+
+            let (mut in_writer, mut in_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let frames = vec![
+                encode_frame(&Metadata::default(), &[1; 100]).expect("Can't encode frame"),
+                encode_frame(&Metadata::default(), &[2; 200]).expect("Can't encode frame"),
+            ];
+            for frame in &frames {
+                write_to_bip_buffer(&mut in_writer, frame);
+            }
+            //every element is sent to both sockets, the reader merges them again.
+            let mut link_writer = LinkWriter::start(LinkType::Socket, &writer_paths, 0)
+                .expect("can't create link writer");
+            let writer_handle = std::thread::spawn(move || {
+                for _ in 0..2 {
+                    link_writer
+                        .send_data(&mut in_reader)
+                        .expect("Cant send data");
+                }
+                link_writer
+            });
+            let (out_writer, mut out_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let mut link_reader = LinkReader::new(
+                LinkType::Socket,
+                &reader_paths,
+                out_writer,
+                MAX_BIP_BUFFER_MESSAGE_SIZE * 10,
+            )
+            .expect("Can't create link reader");
+
+            let mut received = Vec::new();
+            for _ in 0..4 {
+                let element_length = link_reader.receive_data().expect("can't receive data");
+                let mut received_buffer = vec![0; element_length];
+                read_from_bip_buffer(&mut out_reader, &mut received_buffer);
+                received.push(received_buffer);
+            }
+            for frame in &frames {
+                assert_eq!(received.iter().filter(|x| *x == frame).count(), 2);
+            }
+            if let LinkWriter::Broadcast(mut writer) =
+                writer_handle.join().expect("Error joining writer")
+            {
+                writer.stop();
+            }
+        }
+
+        #[test]
+        fn fan_out_shared_memory_test() {
+            assert!(LinkWriter::start(LinkType::SharedMemory, "/tmp/a,/tmp/b", 1024).is_err());
+        }
+
+        #[test]
+        fn fan_out_lag_policy_test() {
+            let dir = TempDir::new("fan_out_lag_policy_test").expect("Can't create dir");
+            let path = |name: &str| dir.path().join(name).display().to_string();
+            let error = LinkWriter::start(
+                LinkType::Socket,
+                &format!("{}?lag_policy=wait,{}", path("a"), path("b")),
+                0,
+            )
+            .err()
+            .expect("An unknown lag policy is accepted");
+            assert!(error
+                .to_string()
+                .contains("Unknown lag policy wait, expected block, drop or disconnect"));
+            //the other parameters of an address are kept
+            let writer = LinkWriter::start(
+                LinkType::Socket,
+                &format!(
+                    "unix://{}?lag_policy=disconnect&mode=600,{}",
+                    path("c"),
+                    path("d")
+                ),
+                0,
+            )
+            .expect("can't create link writer");
+            let mode = fs::metadata(path("c"))
+                .expect("No socket")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+            if let LinkWriter::Broadcast(mut writer) = writer {
+                writer.stop();
+            }
+        }
+    }
+    mod async_link {
+        use crate::async_link::*;
+        use crate::frame::*;
+        use crate::link::LinkType;
+        use framework_constants::*;
+        use futures_core::Stream;
+        use futures_sink::Sink;
+        use std::future::{poll_fn, Future};
+        use std::pin::Pin;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake};
+        use std::thread::Thread;
+
+        ///Wakes up the thread blocked on a future.
+        struct ThreadWaker(Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        ///Runs a future to completion on the current thread.
+        fn block_on<F: Future>(future: F) -> F::Output {
+            let mut future = Box::pin(future);
+            let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+            let mut cx = Context::from_waker(&waker);
+            loop {
+                match future.as_mut().poll(&mut cx) {
+                    Poll::Ready(output) => return output,
+                    Poll::Pending => std::thread::park(),
+                }
+            }
+        }
+
+        #[test]
+        fn read_write_async_link_test() {
+            let path = "/tmp/read_write_async_link";
+            let frames: Vec<Vec<u8>> = (0..20u8)
+                .map(|i| {
+                    encode_frame(&Metadata::default(), &vec![i; 1000]).expect("Can't encode frame")
+                })
+                .collect();
+
+            //a bip_buffer of a few frames makes the sink wait for the link.
+            let capacity = (frames[0].len() + BIP_BUFFER_LEN_FIELD_LEN) * 3;
+            let mut writer = AsyncLinkWriter::start(LinkType::Socket, path, capacity, None);
+            let sent_frames = frames.clone();
+            let sender = std::thread::spawn(move || {
+                for frame in sent_frames {
+                    block_on(poll_fn(|cx| Pin::new(&mut writer).poll_ready(cx)))
+                        .expect("Sink not ready");
+                    Pin::new(&mut writer)
+                        .start_send(frame)
+                        .expect("Can't send frame");
+                }
+                block_on(poll_fn(|cx| Pin::new(&mut writer).poll_flush(cx)))
+                    .expect("Can't flush sink");
+            });
+
+            let mut reader = AsyncLinkReader::connect(LinkType::Socket, path, capacity, None);
+            for frame in frames {
+                let received = block_on(poll_fn(|cx| Pin::new(&mut reader).poll_next(cx)))
+                    .expect("Stream ended")
+                    .expect("Can't receive frame");
+                assert_eq!(received, frame);
+            }
+            sender.join().expect("Error joining sender");
+        }
+    }
+    mod shared_memory {
+        use crate::frame::*;
+        use crate::link::*;
+        use bip_utils::read_from_bip_buffer;
+        use bip_utils::write_to_bip_buffer;
+        use framework_constants::*;
+        #[test]
+        fn read_write_link_test() {
+            let path = "/tmp/read_write_link_shared_memory";
+
+            let (mut in_writer, mut in_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let first_frame =
+                encode_frame(&Metadata::default(), &[1; 100]).expect("Can't encode frame");
+            let metadata = Metadata {
+                trace_id: Some("second".to_string()),
+                ..Default::default()
+            };
+            let second_frame = encode_frame(&metadata, &vec![2; MAX_BUFFER_SIZE_BYTES])
+                .expect("Can't encode frame");
+            write_to_bip_buffer(&mut in_writer, &first_frame);
+            write_to_bip_buffer(&mut in_writer, &second_frame);
+            //a ring smaller than both elements together makes the writer wait for the reader.
+            let mut link_writer = LinkWriter::start(
+                LinkType::SharedMemory,
+                path,
+                second_frame.len() + BIP_BUFFER_LEN_FIELD_LEN,
+            )
+            .expect("can't create link writer");
+            let writer_handle = std::thread::spawn(move || {
+                link_writer
+                    .send_data(&mut in_reader)
+                    .expect("Cant send data");
+                link_writer
+                    .send_data(&mut in_reader)
+                    .expect("Cant send data");
+            });
+
+            let (out_writer, mut out_reader) =
+                bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let mut link_reader = LinkReader::new(
+                LinkType::SharedMemory,
+                path,
+                out_writer,
+                MAX_BIP_BUFFER_MESSAGE_SIZE * 10,
+            )
+            .expect("Can't create link reader");
+            assert_eq!(
+                link_reader.receive_data().expect("can't receive data"),
+                first_frame.len()
+            );
+            assert_eq!(
+                link_reader.receive_data().expect("can't receive data"),
+                second_frame.len()
+            );
+            writer_handle.join().expect("Error joining writer");
+
+            let mut received_buffer = vec![0; second_frame.len()];
+            assert_eq!(
+                read_from_bip_buffer(&mut out_reader, &mut received_buffer),
+                first_frame.len()
+            );
+            assert_eq!(&received_buffer[..first_frame.len()], &first_frame[..]);
+            assert_eq!(
+                read_from_bip_buffer(&mut out_reader, &mut received_buffer),
+                second_frame.len()
+            );
+            let frame = Frame::read(&received_buffer).expect("Can't read frame");
+            assert_eq!(frame.metadata, metadata);
+            assert_eq!(frame.payload, &vec![2; MAX_BUFFER_SIZE_BYTES][..]);
+            std::fs::remove_file(path).expect("Can't remove ring file");
+        }
     }
 }
@@ -0,0 +1,281 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::address::split_query;
+use crate::broadcast_socket_writer::{BroadcastSocketWriter, LagPolicy, DEFAULT_MAX_LAG};
+use crate::buffered_socket_reader::BufferedSocketReader;
+use crate::buffered_socket_writer::{BufferedSocketWriter, InFlightPolicy};
+use crate::errors::ErrorKind::AddressError;
+use crate::errors::*;
+use crate::merging_socket_reader::MergingSocketReader;
+use crate::shm_ring_reader::ShmRingReader;
+use crate::shm_ring_writer::ShmRingWriter;
+use bip_utils::overflow::BufferMonitor;
+use bip_utils::{BipBufferReader, BipBufferWriter};
+use statistics_handler::StatsAllHandlers;
+use std::str::FromStr;
+use std::sync::Arc;
+
+///The kind of link between two adjacent handlers in a chain.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LinkType {
+    ///A stream socket, a unix domain socket or a TCP connection with or without TLS depending on the address.
+    Socket,
+    ///A ring buffer in a file that is mapped into the memory of both handlers.
+    SharedMemory,
+}
+
+impl FromStr for LinkType {
+    type Err = String;
+
+    fn from_str(link_type: &str) -> std::result::Result<LinkType, String> {
+        match link_type {
+            "socket" => Ok(LinkType::Socket),
+            "shared_memory" => Ok(LinkType::SharedMemory),
+            _ => Err(format!(
+                "Unknown link {}, expected socket or shared_memory",
+                link_type
+            )),
+        }
+    }
+}
+
+///Splits the addresses of a link to several handlers, they are separated by commas.
+///Only a socket link can connect more than two handlers.
+fn split_paths(link_type: LinkType, path: &str) -> Result<Vec<&str>> {
+    let paths: Vec<&str> = path.split(',').collect();
+    if paths.len() > 1 && link_type != LinkType::Socket {
+        return Err(AddressError(format!(
+            "A {:?} link cannot connect to several handlers: {}",
+            link_type, path
+        ))
+        .into());
+    }
+    Ok(paths)
+}
+
+///The LagPolicy of a handler a link fans out to when its address does not set one.
+pub const FAN_OUT_LAG_POLICY: LagPolicy = LagPolicy::Drop;
+
+///Takes the `lag_policy` parameter off the address of a next handler, for example `/tmp/socket?lag_policy=block`.
+///It sets what happens when that handler falls behind the other handlers a link fans out to.
+/// # Returns
+/// The address without the parameter and the LagPolicy, `default` when the address does not set one.
+fn split_lag_policy(path: &str, default: LagPolicy) -> Result<(String, LagPolicy)> {
+    let (address, parameters) = split_query(path);
+    let mut lag_policy = default;
+    let mut rest = Vec::new();
+    for (name, value) in parameters {
+        if name == "lag_policy" {
+            lag_policy = value
+                .parse()
+                .map_err(|e: String| AddressError(format!("{} in {}", e, path)))?;
+        } else {
+            rest.push(format!("{}={}", name, value));
+        }
+    }
+    if rest.is_empty() {
+        Ok((address.to_string(), lag_policy))
+    } else {
+        Ok((format!("{}?{}", address, rest.join("&")), lag_policy))
+    }
+}
+
+///Sends the elements of a bip_buffer to the next handler in the chain.
+pub enum LinkWriter {
+    Socket(BufferedSocketWriter),
+    SharedMemory(ShmRingWriter),
+    Broadcast(BroadcastSocketWriter),
+}
+
+impl LinkWriter {
+    ///Creates the link the next handler connects to.
+    ///A socket link blocks until the next handler has connected.
+    ///Several socket addresses separated by commas send every element to all of the next handlers.
+    ///The `lag_policy` parameter of an address sets what happens when that handler falls behind,
+    ///`FAN_OUT_LAG_POLICY` when it is not set. Elements are only sent when the handlers with the block policy are connected.
+    /// # Arguments
+    /// * `link_type` - The kind of link to create.
+    /// * `path` - The address of the socket or the path the ring file is created on.
+    /// * `capacity` - The amount of bytes a shared memory ring can hold.
+    pub fn start(link_type: LinkType, path: &str, capacity: usize) -> Result<LinkWriter> {
+        let paths = split_paths(link_type, path)?;
+        if paths.len() > 1 {
+            let mut writer = BroadcastSocketWriter::new(DEFAULT_MAX_LAG);
+            for path in paths {
+                let (path, lag_policy) = split_lag_policy(path, FAN_OUT_LAG_POLICY)?;
+                writer.listen(&path, lag_policy)?;
+            }
+            return Ok(LinkWriter::Broadcast(writer));
+        }
+        //a single next handler cannot fall behind others
+        let (path, _) = split_lag_policy(path, FAN_OUT_LAG_POLICY)?;
+        let path = path.as_str();
+        match link_type {
+            LinkType::Socket => Ok(LinkWriter::Socket(BufferedSocketWriter::start_listening(
+                path,
+            )?)),
+            LinkType::SharedMemory => Ok(LinkWriter::SharedMemory(ShmRingWriter::create(
+                path, capacity,
+            )?)),
+        }
+    }
+
+    ///Creates a socket link the next handler connects to, and a tap that other readers can connect to.
+    ///Every element is sent to the next handler and to all readers of the tap.
+    ///A single next handler has the block policy, several have the policy of their address like with `start`.
+    ///Elements queued for the next handler when it is lost are not sent again.
+    /// # Arguments
+    /// * `link_type` - The kind of link to create, only a socket link can be tapped.
+    /// * `path` - The address of the socket for the next handler, or the addresses separated by commas for several.
+    /// * `tap_path` - The address of the socket for the readers of the tap.
+    /// * `lag_policy` - The LagPolicy of the readers of the tap.
+    /// * `max_lag` - The amount of elements a reader can fall behind before its LagPolicy is applied.
+    pub fn start_with_tap(
+        link_type: LinkType,
+        path: &str,
+        tap_path: &str,
+        lag_policy: LagPolicy,
+        max_lag: usize,
+    ) -> Result<LinkWriter> {
+        if link_type != LinkType::Socket {
+            return Err(AddressError(format!(
+                "Cannot add tap {} to a {:?} link",
+                tap_path, link_type
+            ))
+            .into());
+        }
+        let mut writer = BroadcastSocketWriter::new(max_lag);
+        let paths = split_paths(link_type, path)?;
+        //a single next handler does not miss elements for the tap, several get the policy of their address
+        let default = if paths.len() > 1 {
+            FAN_OUT_LAG_POLICY
+        } else {
+            LagPolicy::Block
+        };
+        for path in paths {
+            let (path, lag_policy) = split_lag_policy(path, default)?;
+            writer.listen(&path, lag_policy)?;
+        }
+        writer.listen(tap_path, lag_policy)?;
+        Ok(LinkWriter::Broadcast(writer))
+    }
+
+    ///Sets the BufferMonitor of the bip_buffer this LinkWriter reads from.
+    /// # Arguments
+    /// * `monitor` - The BufferMonitor shared with the writer of the bip_buffer.
+    pub fn set_buffer_monitor(&mut self, monitor: Arc<BufferMonitor>) {
+        match self {
+            LinkWriter::Socket(writer) => writer.set_buffer_monitor(monitor),
+            LinkWriter::SharedMemory(writer) => writer.set_buffer_monitor(monitor),
+            LinkWriter::Broadcast(writer) => writer.set_buffer_monitor(monitor),
+        }
+    }
+
+    ///Sets the policy used for the element that is being sent when the peer is lost.
+    ///A shared memory ring keeps its elements when the next handler restarts, so it has no policy.
+    ///A tapped link does not send the elements queued for a lost peer again, so it has no policy either.
+    /// # Arguments
+    /// * `in_flight_policy` - The InFlightPolicy to use.
+    pub fn set_in_flight_policy(&mut self, in_flight_policy: InFlightPolicy) {
+        if let LinkWriter::Socket(writer) = self {
+            writer.set_in_flight_policy(in_flight_policy);
+        }
+    }
+
+    ///Sets the statistics used to count the reconnections of this LinkWriter.
+    /// # Arguments
+    /// * `stats_data` - The struct used to store statistics data.
+    pub fn set_stats_data(&mut self, stats_data: Arc<StatsAllHandlers>) {
+        match self {
+            LinkWriter::Socket(writer) => writer.set_stats_data(stats_data),
+            LinkWriter::Broadcast(writer) => writer.set_stats_data(stats_data),
+            LinkWriter::SharedMemory(_) => (),
+        }
+    }
+
+    ///Sends the next element read using `reader` to the next handler.
+    /// # Arguments
+    /// * `reader` - The BipBufferReader used to get data from the bip_buffer.
+    /// # Returns
+    /// `usize` - The amount of bytes sent, 0 when the element was discarded.
+    pub fn send_data(&mut self, reader: &mut BipBufferReader) -> Result<usize> {
+        match self {
+            LinkWriter::Socket(writer) => writer.send_data(reader),
+            LinkWriter::SharedMemory(writer) => writer.send_data(reader),
+            LinkWriter::Broadcast(writer) => writer.send_data(reader),
+        }
+    }
+}
+
+///Receives the elements of the previous handler in the chain and sends them to a bip_buffer.
+pub enum LinkReader {
+    Socket(BufferedSocketReader),
+    SharedMemory(ShmRingReader),
+    Merge(MergingSocketReader),
+}
+
+impl LinkReader {
+    ///Connects to the link created by the previous handler.
+    ///This function will block until the previous handler has created the link.
+    ///Several socket addresses separated by commas merge the elements of all of the previous handlers.
+    /// # Arguments
+    /// * `link_type` - The kind of link to connect to.
+    /// * `path` - The address of the socket or the path of the ring file.
+    /// * `writer` - The BipBufferWriter used to send the received data to a bip_buffer.
+    /// * `capacity` - The size of the bip_buffer in bytes, larger elements are dropped.
+    pub fn new(
+        link_type: LinkType,
+        path: &str,
+        writer: BipBufferWriter,
+        capacity: usize,
+    ) -> Result<LinkReader> {
+        let paths = split_paths(link_type, path)?;
+        if paths.len() > 1 {
+            return Ok(LinkReader::Merge(MergingSocketReader::new(
+                &paths, writer, capacity,
+            )?));
+        }
+        match link_type {
+            LinkType::Socket => Ok(LinkReader::Socket(BufferedSocketReader::new(
+                path, writer, capacity,
+            )?)),
+            LinkType::SharedMemory => Ok(LinkReader::SharedMemory(ShmRingReader::new(
+                path, writer, capacity,
+            )?)),
+        }
+    }
+
+    ///Sets the statistics used to count the reconnections of this LinkReader.
+    /// # Arguments
+    /// * `stats_data` - The struct used to store statistics data.
+    pub fn set_stats_data(&mut self, stats_data: Arc<StatsAllHandlers>) {
+        match self {
+            LinkReader::Socket(reader) => reader.set_stats_data(stats_data),
+            LinkReader::SharedMemory(reader) => reader.set_stats_data(stats_data),
+            LinkReader::Merge(reader) => reader.set_stats_data(stats_data),
+        }
+    }
+
+    ///Receives the next element and sends it to the bip_buffer.
+    /// # Returns
+    /// * `usize` - The length of the received element, 0 when it did not fit in the bip_buffer.
+    pub fn receive_data(&mut self) -> Result<usize> {
+        match self {
+            LinkReader::Socket(reader) => reader.receive_data(),
+            LinkReader::SharedMemory(reader) => reader.receive_data(),
+            LinkReader::Merge(reader) => reader.receive_data(),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::buffered_socket_reader::FrameReader;
+use crate::errors::*;
+use bip_utils::{try_write_to_bip_buffer, BipBufferWriter};
+use statistics_handler::StatsAllHandlers;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+///A frame received by one of the threads of a MergingSocketReader, empty when it was dropped.
+type ReceivedFrame = (usize, Result<Vec<u8>>);
+
+///Receives the elements of several previous handlers and sends them to one bip_buffer.
+///Every socket is read by its own thread, so a slow or restarting handler does not stop the others.
+///A thread receives a frame into a buffer that is reused once the frame was sent to the bip_buffer,
+///so an input only takes the memory of the frames it has in flight instead of a bip_buffer of its own.
+///The elements of one socket keep their order, the elements of different sockets are merged in the order they were received.
+pub struct MergingSocketReader {
+    ///The readers of the sockets, until they are moved to their threads by the first `receive_data`.
+    readers: Vec<FrameReader>,
+    received: Option<Receiver<ReceivedFrame>>,
+    ///Returns the buffer of a frame that was sent to the bip_buffer to the thread of its socket.
+    free_buffers: Vec<Sender<Vec<u8>>>,
+    writer: BipBufferWriter,
+    capacity: usize,
+}
+
+impl MergingSocketReader {
+    ///Creates a new instance of MergingSocketReader.
+    ///This function will block until every socket has been created by its writer.
+    /// # Arguments
+    /// * `paths` - The addresses of the sockets the reader should connect to.
+    /// * `writer` - The BipBufferWriter used to send the received data to a bip_buffer.
+    /// * `capacity` - The size of the bip_buffer in bytes.
+    pub fn new(
+        paths: &[&str],
+        writer: BipBufferWriter,
+        capacity: usize,
+    ) -> Result<MergingSocketReader> {
+        let mut readers = Vec::new();
+        for path in paths {
+            readers.push(FrameReader::new(path, capacity)?);
+        }
+        Ok(MergingSocketReader {
+            readers,
+            received: None,
+            free_buffers: Vec::new(),
+            writer,
+            capacity,
+        })
+    }
+
+    ///Sets the statistics used to count the reconnections and the oversized elements of every socket.
+    /// # Arguments
+    /// * `stats_data` - The struct used to store statistics data.
+    pub fn set_stats_data(&mut self, stats_data: Arc<StatsAllHandlers>) {
+        for reader in &mut self.readers {
+            reader.stats_data = Some(stats_data.clone());
+        }
+    }
+
+    ///Receives the next element of any of the sockets and sends it to the bip_buffer.
+    ///The threads reading the sockets are started by the first call.
+    /// # Returns
+    /// * `usize` - The length of the received element, 0 when it was dropped.
+    pub fn receive_data(&mut self) -> Result<usize> {
+        if self.received.is_none() {
+            self.received = Some(self.start()?);
+        }
+        let (index, result) = self
+            .received
+            .as_ref()
+            .expect("The threads of the MergingSocketReader were started")
+            .recv()
+            .chain_err(|| "All sockets of the MergingSocketReader stopped")?;
+        let frame = result.chain_err(|| format!("Error reading socket {}", index))?;
+        let element_length = frame.len();
+        if element_length > 0 {
+            try_write_to_bip_buffer(&mut self.writer, &frame, self.capacity)?;
+        }
+        //the thread makes a new buffer when it stopped or is already using one
+        let _ = self.free_buffers[index].send(frame);
+        Ok(element_length)
+    }
+
+    ///Starts a thread for every socket that receives its frames and reports them to `receive_data`.
+    ///The threads wait when as many frames as there are sockets have not been taken yet.
+    fn start(&mut self) -> Result<Receiver<ReceivedFrame>> {
+        let (sender, received) = sync_channel(self.readers.len());
+        for (index, reader) in self.readers.drain(..).enumerate() {
+            let sender = sender.clone();
+            let (free_buffer, free_buffers) = channel();
+            self.free_buffers.push(free_buffer);
+            thread::Builder::new()
+                .name(format!("merging_reader_{}", index))
+                .spawn(move || receive_frames(index, reader, sender, free_buffers))?;
+        }
+        Ok(received)
+    }
+}
+
+///Receives the frames of one socket until an error occurs or the MergingSocketReader is gone.
+fn receive_frames(
+    index: usize,
+    mut reader: FrameReader,
+    sender: SyncSender<ReceivedFrame>,
+    free_buffers: Receiver<Vec<u8>>,
+) {
+    loop {
+        let mut frame = free_buffers.try_recv().unwrap_or_default();
+        let result = reader.receive_frame(&mut frame).map(|element_length| {
+            frame.truncate(element_length);
+            frame
+        });
+        let failed = result.is_err();
+        if sender.send((index, result)).is_err() || failed {
+            return;
+        }
+    }
+}
@@ -0,0 +1,519 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::address::{SocketAddress, UnixPermissions};
+use crate::errors::ErrorKind::{AddressError, PeerCredentialError, SharedMemoryError};
+use crate::errors::*;
+use crate::stream::set_permissions;
+use framework_constants::BIP_BUFFER_LEN_FIELD_LEN;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+///Marks a ring file as initialized, "OSDDRING" in ascii.
+const RING_MAGIC: u64 = 0x4f53_4444_5249_4e47;
+///The size of the header at the start of the ring file, the data follows the header.
+const HEADER_LEN: usize = 256;
+///The amount of spins before a waiting side parks on the futex.
+const SPIN_COUNT: u32 = 1_000;
+///A parked side checks its condition again after this time, even when it was not woken up.
+pub(crate) const PARK_TIMEOUT: Duration = Duration::from_millis(100);
+
+///The header shared by both sides of the ring.
+///The positions only grow, the index in the data is the position modulo the capacity.
+///Every side writes its own cache line so the sides do not slow each other down.
+#[repr(C)]
+struct RingHeader {
+    magic: AtomicU64,
+    capacity: AtomicU64,
+    _pad_settings: [u8; 48],
+    //written by the writer
+    head: AtomicU64,
+    data_futex: AtomicU32,
+    data_waiters: AtomicU32,
+    _pad_head: [u8; 48],
+    //written by the reader
+    tail: AtomicU64,
+    space_futex: AtomicU32,
+    space_waiters: AtomicU32,
+    _pad_tail: [u8; 48],
+}
+
+///A ring buffer in a file that is mapped into the memory of both handlers.
+///Elements are framed like in the bip_buffer: a usize length field followed by the data.
+///The ring has a single writer and a single reader.
+pub struct ShmRing {
+    ptr: *mut u8,
+    len: usize,
+    capacity: usize,
+    inode: u64,
+    //the mapping stays valid after the file is closed, the file is kept to keep the inode in use.
+    _file: File,
+}
+
+//The ring only hands out data through the atomics in the header, so both sides can live in other threads.
+unsafe impl Send for ShmRing {}
+
+impl ShmRing {
+    ///Creates a new ring file at `path`, an existing file is replaced.
+    ///The ring is prepared in a file only this user can open, the mode and ownership in `permissions`
+    ///are set before it is moved to `path`. A reader never opens the ring with other permissions.
+    /// # Arguments
+    /// * `path` - The path the ring file is created on.
+    /// * `permissions` - The mode and ownership of the ring file, the peer ids are only checked by the reader.
+    /// * `capacity` - The amount of bytes the ring can hold.
+    pub fn create(path: &str, permissions: &UnixPermissions, capacity: usize) -> Result<ShmRing> {
+        let new_path = format!("{}.new", path);
+        if let Err(e) = std::fs::remove_file(&new_path) {
+            log::debug!("No ring file removed at {}: {}", new_path, e);
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&new_path)
+            .chain_err(|| SharedMemoryError(format!("Error creating ring file {}", path)))?;
+        file.set_len((HEADER_LEN + capacity) as u64)
+            .chain_err(|| SharedMemoryError(format!("Error sizing ring file {}", path)))?;
+        let ring = ShmRing::map(file, capacity)?;
+        ring.header()
+            .capacity
+            .store(capacity as u64, Ordering::SeqCst);
+        ring.header().magic.store(RING_MAGIC, Ordering::SeqCst);
+        set_permissions(&new_path, permissions)?;
+        std::fs::rename(&new_path, path)
+            .chain_err(|| SharedMemoryError(format!("Error moving ring file to {}", path)))?;
+        Ok(ring)
+    }
+
+    ///Opens the ring file at `path`.
+    ///This function will block until the ring file has been created by the writer.
+    /// # Arguments
+    /// * `path` - The path of the ring file.
+    /// * `permissions` - The identity of the writer, checked against the owner of the ring file.
+    pub fn open(path: &str, permissions: &UnixPermissions) -> Result<ShmRing> {
+        loop {
+            match ShmRing::try_open(path, permissions)? {
+                Some(ring) => return Ok(ring),
+                None => {
+                    log::warn!("ShmRing: ring file {} is not yet created.", path);
+                    std::thread::sleep(std::time::Duration::from_millis(200));
+                }
+            }
+        }
+    }
+
+    fn try_open(path: &str, permissions: &UnixPermissions) -> Result<Option<ShmRing>> {
+        let file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::with_chain(
+                    e,
+                    SharedMemoryError(format!("Error opening ring file {}", path)),
+                ))
+            }
+        };
+        let metadata = file.metadata()?;
+        check_owner(path, &metadata, permissions)?;
+        let len = metadata.len() as usize;
+        if len <= HEADER_LEN {
+            return Ok(None);
+        }
+        let ring = ShmRing::map(file, len - HEADER_LEN)?;
+        if ring.header().magic.load(Ordering::SeqCst) != RING_MAGIC {
+            return Ok(None);
+        }
+        if ring.header().capacity.load(Ordering::SeqCst) as usize != ring.capacity {
+            return Err(SharedMemoryError(format!("Ring file {} has a wrong size", path)).into());
+        }
+        Ok(Some(ring))
+    }
+
+    fn map(file: File, capacity: usize) -> Result<ShmRing> {
+        assert!(std::mem::size_of::<RingHeader>() <= HEADER_LEN);
+        let len = HEADER_LEN + capacity;
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::with_chain(
+                std::io::Error::last_os_error(),
+                SharedMemoryError("Error mapping ring file".to_string()),
+            ));
+        }
+        Ok(ShmRing {
+            ptr: ptr as *mut u8,
+            len,
+            capacity,
+            inode: file.metadata()?.ino(),
+            _file: file,
+        })
+    }
+
+    fn header(&self) -> &RingHeader {
+        unsafe { &*(self.ptr as *const RingHeader) }
+    }
+
+    ///Returns the amount of bytes the ring can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    ///Returns true when the file at `path` is not the file this ring is mapped from,
+    ///which happens when the writer created the ring again after a restart.
+    pub fn is_replaced(&self, path: &str) -> bool {
+        match std::fs::metadata(path) {
+            Ok(metadata) => metadata.ino() != self.inode,
+            Err(_) => false,
+        }
+    }
+
+    ///Writes a single element to the ring. Used by the writer only.
+    ///This function will block until there is space in the ring.
+    /// # Arguments
+    /// * `element` - The data of the element, without length field.
+    pub fn push(&self, element: &[u8]) -> Result<()> {
+        let element_bytes = (BIP_BUFFER_LEN_FIELD_LEN + element.len()) as u64;
+        if element_bytes > self.capacity as u64 {
+            return Err(SharedMemoryError(format!(
+                "Element of {} bytes does not fit in ring of {} bytes",
+                element.len(),
+                self.capacity
+            ))
+            .into());
+        }
+        let header = self.header();
+        let head = header.head.load(Ordering::SeqCst);
+        wait(&header.space_futex, &header.space_waiters, || {
+            head + element_bytes - header.tail.load(Ordering::SeqCst) <= self.capacity as u64
+        });
+        self.write_at(head, &element.len().to_le_bytes());
+        self.write_at(head + BIP_BUFFER_LEN_FIELD_LEN as u64, element);
+        header.head.store(head + element_bytes, Ordering::SeqCst);
+        notify(&header.data_futex, &header.data_waiters);
+        Ok(())
+    }
+
+    ///Returns the length of the next element in the ring. Used by the reader only.
+    ///Waits at most PARK_TIMEOUT for an element.
+    ///The length is read from shared memory, so it is checked against the capacity of the ring and the bytes written by the writer.
+    ///When it is invalid the ring is corrupted, all its bytes are dropped and an error is returned.
+    /// # Returns
+    /// * `Option<usize>` - The length of the next element, None when there is no element.
+    pub fn peek_length(&self) -> Result<Option<usize>> {
+        let header = self.header();
+        let tail = header.tail.load(Ordering::SeqCst);
+        let available = || header.head.load(Ordering::SeqCst) > tail;
+        if !wait_once(&header.data_futex, &header.data_waiters, available) {
+            return Ok(None);
+        }
+        let head = header.head.load(Ordering::SeqCst);
+        let mut length_bytes = [0; BIP_BUFFER_LEN_FIELD_LEN];
+        self.read_at(tail, &mut length_bytes);
+        let element_length = usize::from_le_bytes(length_bytes);
+        let element_bytes = element_length.checked_add(BIP_BUFFER_LEN_FIELD_LEN);
+        match element_bytes {
+            Some(element_bytes)
+                if element_bytes <= self.capacity
+                    && element_bytes as u64 <= head.saturating_sub(tail) =>
+            {
+                Ok(Some(element_length))
+            }
+            _ => {
+                header.tail.store(head, Ordering::SeqCst);
+                notify(&header.space_futex, &header.space_waiters);
+                Err(SharedMemoryError(format!(
+                    "Invalid element length {} in ring of {} bytes, dropped {} bytes",
+                    element_length,
+                    self.capacity,
+                    head - tail
+                ))
+                .into())
+            }
+        }
+    }
+
+    ///Copies the data of the next element into `buffer` and removes it from the ring.
+    ///Used by the reader only, after `peek_length` returned the length of the element.
+    /// # Arguments
+    /// * `buffer` - The buffer to be filled, its length must be the length of the element.
+    pub fn pop_into(&self, buffer: &mut [u8]) {
+        let tail = self.header().tail.load(Ordering::SeqCst);
+        self.read_at(tail + BIP_BUFFER_LEN_FIELD_LEN as u64, buffer);
+        self.skip(buffer.len());
+    }
+
+    ///Removes the next element from the ring without copying its data.
+    ///Used by the reader only, after `peek_length` returned the length of the element.
+    /// # Arguments
+    /// * `element_length` - The length of the element.
+    pub fn skip(&self, element_length: usize) {
+        let header = self.header();
+        let tail = header.tail.load(Ordering::SeqCst);
+        let element_bytes = (BIP_BUFFER_LEN_FIELD_LEN + element_length) as u64;
+        assert!(element_bytes <= header.head.load(Ordering::SeqCst).saturating_sub(tail));
+        header.tail.store(tail + element_bytes, Ordering::SeqCst);
+        notify(&header.space_futex, &header.space_waiters);
+    }
+
+    fn write_at(&self, position: u64, bytes: &[u8]) {
+        let start = (position % self.capacity as u64) as usize;
+        let first = bytes.len().min(self.capacity - start);
+        unsafe {
+            let data = self.ptr.add(HEADER_LEN);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), data.add(start), first);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr().add(first), data, bytes.len() - first);
+        }
+    }
+
+    fn read_at(&self, position: u64, bytes: &mut [u8]) {
+        assert!(bytes.len() <= self.capacity);
+        let start = (position % self.capacity as u64) as usize;
+        let first = bytes.len().min(self.capacity - start);
+        unsafe {
+            let data = self.ptr.add(HEADER_LEN);
+            std::ptr::copy_nonoverlapping(data.add(start), bytes.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(data, bytes.as_mut_ptr().add(first), bytes.len() - first);
+        }
+    }
+}
+
+impl Drop for ShmRing {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+///Wakes up the other side when it is parked on `futex`.
+fn notify(futex: &AtomicU32, waiters: &AtomicU32) {
+    futex.fetch_add(1, Ordering::SeqCst);
+    if waiters.load(Ordering::SeqCst) > 0 {
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                futex as *const AtomicU32,
+                libc::FUTEX_WAKE,
+                i32::MAX,
+            );
+        }
+    }
+}
+
+///Waits until `ready` returns true.
+fn wait<F: FnMut() -> bool>(futex: &AtomicU32, waiters: &AtomicU32, mut ready: F) {
+    while !wait_once(futex, waiters, &mut ready) {}
+}
+
+///Spins for a while and then parks at most PARK_TIMEOUT on `futex` until `ready` returns true.
+///The futex is not private, so it also works between processes.
+fn wait_once<F: FnMut() -> bool>(futex: &AtomicU32, waiters: &AtomicU32, mut ready: F) -> bool {
+    for _ in 0..SPIN_COUNT {
+        if ready() {
+            return true;
+        }
+        std::hint::spin_loop();
+    }
+    waiters.fetch_add(1, Ordering::SeqCst);
+    //a notify after loading the futex value makes the futex wait return right away.
+    let futex_value = futex.load(Ordering::SeqCst);
+    if !ready() {
+        let timeout = libc::timespec {
+            tv_sec: PARK_TIMEOUT.as_secs() as libc::time_t,
+            tv_nsec: PARK_TIMEOUT.subsec_nanos() as libc::c_long,
+        };
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                futex as *const AtomicU32,
+                libc::FUTEX_WAIT,
+                futex_value,
+                &timeout as *const libc::timespec,
+            );
+        }
+    }
+    waiters.fetch_sub(1, Ordering::SeqCst);
+    ready()
+}
+
+///Splits the address of a ring into the path of the ring file and its permissions.
+///The address is a plain path or a `unix://` URI like the address of a unix domain socket.
+///A ring file cannot tell which process opened it, so a `peer_pid` is refused.
+pub fn split_ring_address(address: &str) -> Result<(String, UnixPermissions)> {
+    match address.parse::<SocketAddress>()? {
+        SocketAddress::Unix(path, permissions) if permissions.peer_pid.is_none() => {
+            Ok((path, permissions))
+        }
+        SocketAddress::Unix(..) => Err(AddressError(format!(
+            "The peer_pid of ring {} cannot be checked",
+            address
+        ))
+        .into()),
+        _ => Err(AddressError(format!(
+            "A shared memory ring needs a path, not {}",
+            address
+        ))
+        .into()),
+    }
+}
+
+///Checks the owner of the ring file against the identity of the writer.
+///The writer creates the ring file, so the file is owned by its user and group.
+fn check_owner(
+    path: &str,
+    metadata: &std::fs::Metadata,
+    permissions: &UnixPermissions,
+) -> Result<()> {
+    let matches =
+        |expected: Option<u32>, actual: u32| expected.is_none() || expected == Some(actual);
+    if matches(permissions.peer_uid, metadata.uid())
+        && matches(permissions.peer_gid, metadata.gid())
+    {
+        Ok(())
+    } else {
+        Err(PeerCredentialError(format!(
+            "ring file {} with uid {} and gid {} is not created by the expected peer",
+            path,
+            metadata.uid(),
+            metadata.gid()
+        ))
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shm_ring::*;
+
+    #[test]
+    fn push_pop_wrap_around_test() {
+        let path = "/tmp/push_pop_wrap_around_test_ring";
+        let writer =
+            ShmRing::create(path, &UnixPermissions::default(), 100).expect("Can't create ring");
+        let reader = ShmRing::open(path, &UnixPermissions::default()).expect("Can't open ring");
+        //elements of 30 bytes + length field wrap around the end of the ring of 100 bytes.
+        for i in 0..10u8 {
+            let element = [i; 30];
+            writer.push(&element).expect("Can't push element");
+            assert_eq!(reader.peek_length().expect("Invalid length"), Some(30));
+            let mut buffer = [0; 30];
+            reader.pop_into(&mut buffer);
+            assert_eq!(&buffer[..], &element[..]);
+        }
+        assert_eq!(reader.peek_length().expect("Invalid length"), None);
+        assert!(writer.push(&[0; 100]).is_err());
+        std::fs::remove_file(path).expect("Can't remove ring file");
+    }
+
+    #[test]
+    fn blocked_writer_test() {
+        let path = "/tmp/blocked_writer_test_ring";
+        let writer =
+            ShmRing::create(path, &UnixPermissions::default(), 100).expect("Can't create ring");
+        let reader = ShmRing::open(path, &UnixPermissions::default()).expect("Can't open ring");
+        let writer_thread = std::thread::spawn(move || {
+            //the second and third element only fit once the reader made space.
+            for i in 0..3u8 {
+                writer.push(&[i; 60]).expect("Can't push element");
+            }
+        });
+        for i in 0..3u8 {
+            let length = loop {
+                if let Some(length) = reader.peek_length().expect("Invalid length") {
+                    break length;
+                }
+            };
+            let mut buffer = vec![0; length];
+            reader.pop_into(&mut buffer);
+            assert_eq!(buffer, vec![i; 60]);
+        }
+        writer_thread.join().expect("Error joining writer thread");
+        std::fs::remove_file(path).expect("Can't remove ring file");
+    }
+
+    #[test]
+    fn corrupted_length_test() {
+        let path = "/tmp/corrupted_length_test_ring";
+        let writer =
+            ShmRing::create(path, &UnixPermissions::default(), 100).expect("Can't create ring");
+        let reader = ShmRing::open(path, &UnixPermissions::default()).expect("Can't open ring");
+        //a length field that is larger than the ring.
+        writer.push(&[0; 30]).expect("Can't push element");
+        writer.write_at(0, &usize::MAX.to_le_bytes());
+        assert!(reader.peek_length().is_err());
+        assert_eq!(reader.peek_length().expect("Invalid length"), None);
+        //a length field that is larger than the bytes written by the writer.
+        writer.push(&[1; 30]).expect("Can't push element");
+        writer.write_at(38, &60usize.to_le_bytes());
+        assert!(reader.peek_length().is_err());
+        //the ring is in sync again after the invalid bytes were dropped.
+        writer.push(&[2; 30]).expect("Can't push element");
+        assert_eq!(reader.peek_length().expect("Invalid length"), Some(30));
+        reader.skip(30);
+        assert_eq!(reader.peek_length().expect("Invalid length"), None);
+        std::fs::remove_file(path).expect("Can't remove ring file");
+    }
+
+    #[test]
+    fn replaced_ring_test() {
+        let path = "/tmp/replaced_ring_test_ring";
+        let _writer =
+            ShmRing::create(path, &UnixPermissions::default(), 100).expect("Can't create ring");
+        let reader = ShmRing::open(path, &UnixPermissions::default()).expect("Can't open ring");
+        assert!(!reader.is_replaced(path));
+        let _restarted_writer =
+            ShmRing::create(path, &UnixPermissions::default(), 100).expect("Can't create ring");
+        assert!(reader.is_replaced(path));
+        std::fs::remove_file(path).expect("Can't remove ring file");
+    }
+
+    #[test]
+    fn ring_permissions_test() {
+        let path = "/tmp/ring_permissions_test_ring";
+        let (_, permissions) =
+            split_ring_address(&format!("unix://{}?mode=640", path)).expect("Can't split address");
+        let _writer = ShmRing::create(path, &permissions, 100).expect("Can't create ring");
+        let metadata = std::fs::metadata(path).expect("Can't read ring file");
+        assert_eq!(metadata.mode() & 0o777, 0o640);
+        assert!(!std::path::Path::new(&format!("{}.new", path)).exists());
+
+        //the reader only opens a ring file created by the expected writer
+        let owner = UnixPermissions {
+            peer_uid: Some(metadata.uid()),
+            peer_gid: Some(metadata.gid()),
+            ..UnixPermissions::default()
+        };
+        assert!(ShmRing::open(path, &owner).is_ok());
+        let other = UnixPermissions {
+            peer_uid: Some(metadata.uid().wrapping_add(1)),
+            ..UnixPermissions::default()
+        };
+        assert!(ShmRing::open(path, &other).is_err());
+        assert!(split_ring_address(&format!("unix://{}?peer_pid=1", path)).is_err());
+        assert!(split_ring_address("tcp://127.0.0.1:1234").is_err());
+        std::fs::remove_file(path).expect("Can't remove ring file");
+    }
+}
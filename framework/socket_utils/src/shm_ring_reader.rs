@@ -0,0 +1,113 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::address::UnixPermissions;
+use crate::errors::*;
+use crate::shm_ring::{split_ring_address, ShmRing};
+use bip_utils::max_element_bytes;
+use bip_utils::try_write_with;
+use bip_utils::BipBufferWriter;
+use framework_constants::BIP_BUFFER_LEN_FIELD_LEN;
+use statistics_handler::StatsAllHandlers;
+use std::sync::Arc;
+
+///Receives the elements of the previous handler through a ShmRing and sends them to a bip_buffer.
+pub struct ShmRingReader {
+    ring: ShmRing,
+    path: String,
+    permissions: UnixPermissions,
+    writer: BipBufferWriter,
+    capacity: usize,
+    stats_data: Option<Arc<StatsAllHandlers>>,
+}
+
+impl ShmRingReader {
+    ///Creates a new instance of ShmRingReader.
+    ///This function will block until the ring file has been created by a ShmRingWriter.
+    /// # Arguments
+    ///A `unix://` address with `peer_uid` or `peer_gid` only accepts a ring file created by that user or group,
+    ///for example `unix:///tmp/ring?peer_uid=1000&peer_gid=1000`.
+    /// # Arguments
+    /// * `address` - The path of the ring file, or a `unix://` URI with the identity of the writer.
+    /// * `writer` - The BipBufferWriter used to send the received data to a bip_buffer.
+    /// * `capacity` - The size of the bip_buffer in bytes.
+    pub fn new(address: &str, writer: BipBufferWriter, capacity: usize) -> Result<ShmRingReader> {
+        let (path, permissions) = split_ring_address(address)?;
+        Ok(ShmRingReader {
+            ring: ShmRing::open(&path, &permissions)?,
+            path,
+            permissions,
+            writer,
+            capacity,
+            stats_data: None,
+        })
+    }
+
+    ///Sets the statistics used to count the reconnections and the oversized elements of this ShmRingReader.
+    /// # Arguments
+    /// * `stats_data` - The struct used to store statistics data.
+    pub fn set_stats_data(&mut self, stats_data: Arc<StatsAllHandlers>) {
+        self.stats_data = Some(stats_data);
+    }
+
+    ///Moves the next element from the ring to the bip_buffer.
+    ///This function will block until an element is available in the ring and space is available in the bip_buffer.
+    ///When the previous handler restarted and created a new ring, the new ring is opened.
+    ///An element that does not fit in the bip_buffer is taken from the ring and dropped.
+    ///An invalid element length in the ring is an error, the bytes in the ring are dropped.
+    /// # Returns
+    /// * `usize` - The length of the received element, 0 when it was dropped.
+    pub fn receive_data(&mut self) -> Result<usize> {
+        let element_length = loop {
+            match self.ring.peek_length()? {
+                Some(element_length) => break element_length,
+                None if self.ring.is_replaced(&self.path) => self.reconnect()?,
+                None => (),
+            }
+        };
+        if element_length + BIP_BUFFER_LEN_FIELD_LEN > max_element_bytes(self.capacity) {
+            log::warn!(
+                "Dropped element of {} bytes, it does not fit in the bip_buffer of {} bytes",
+                element_length,
+                self.capacity
+            );
+            self.ring.skip(element_length);
+            if let Some(stats_data) = &self.stats_data {
+                stats_data.oversized_packets.add(1);
+                stats_data.dropped_packets.add(1);
+                stats_data.dropped_bytes.add(element_length as u64);
+            }
+            return Ok(0);
+        }
+        let ring = &mut self.ring;
+        try_write_with(
+            &mut self.writer,
+            element_length,
+            self.capacity,
+            None,
+            |element| ring.pop_into(element),
+        )?;
+        Ok(element_length)
+    }
+
+    ///Opens the ring again after the previous handler created a new one.
+    fn reconnect(&mut self) -> Result<()> {
+        log::info!("ShmRingReader opening new ring at {}", self.path);
+        self.ring = ShmRing::open(&self.path, &self.permissions)?;
+        if let Some(stats_data) = &self.stats_data {
+            stats_data.reconnections.add(1);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::*;
+use crate::frame::check_frame;
+use crate::shm_ring::{split_ring_address, ShmRing};
+use bip_utils::get_element_length;
+use bip_utils::overflow::BufferMonitor;
+use bip_utils::wait_for_data;
+use bip_utils::BipBufferReader;
+use std::sync::Arc;
+
+///Sends the elements of a bip_buffer to the next handler through a ShmRing.
+///The elements stay in the ring when the next handler restarts, so no element is lost or sent twice.
+pub struct ShmRingWriter {
+    ring: ShmRing,
+    monitor: Option<Arc<BufferMonitor>>,
+}
+
+impl ShmRingWriter {
+    ///Creates the ring file the next handler reads from.
+    ///A `unix://` address sets the mode and ownership of the ring file, for example `unix:///tmp/ring?mode=660`.
+    /// # Arguments
+    /// * `address` - The path the ring file is created on, or a `unix://` URI with its permissions.
+    /// * `capacity` - The amount of bytes the ring can hold.
+    pub fn create(address: &str, capacity: usize) -> Result<ShmRingWriter> {
+        let (path, permissions) = split_ring_address(address)?;
+        Ok(ShmRingWriter {
+            ring: ShmRing::create(&path, &permissions, capacity)?,
+            monitor: None,
+        })
+    }
+
+    ///Sets the BufferMonitor of the bip_buffer this ShmRingWriter reads from.
+    /// # Arguments
+    /// * `monitor` - The BufferMonitor shared with the writer of the bip_buffer.
+    pub fn set_buffer_monitor(&mut self, monitor: Arc<BufferMonitor>) {
+        self.monitor = Some(monitor);
+    }
+
+    ///Used to send data to the ring. The data that is sent is read using `reader`.
+    ///Every element in the bip_buffer must be a frame, elements that are not are discarded.
+    ///This function will block until there is space in the ring.
+    /// # Arguments
+    /// * `reader` - The BipBufferReader used to get data from the bip_buffer.
+    /// # Returns
+    /// `usize` - The amount of bytes sent, 0 when the element was discarded.
+    pub fn send_data(&mut self, reader: &mut BipBufferReader) -> Result<usize> {
+        let element_length: usize = match &self.monitor {
+            Some(monitor) => monitor.get_element_length(reader),
+            None => get_element_length(reader),
+        };
+        wait_for_data(reader, element_length);
+        let sent = match check_frame(&reader.valid()[..element_length]) {
+            Ok(()) => {
+                self.ring.push(&reader.valid()[..element_length])?;
+                true
+            }
+            Err(e) => {
+                log::error!("Element of {} bytes discarded: {}", element_length, e);
+                false
+            }
+        };
+        reader.consume(element_length);
+        if let Some(monitor) = &self.monitor {
+            monitor.consumed(element_length);
+        }
+        if sent {
+            Ok(element_length)
+        } else {
+            Ok(0)
+        }
+    }
+}
@@ -14,7 +14,7 @@
 
 use crate::errors::ErrorKind::UnixDomainSocketError;
 use crate::errors::*;
-use framework_constants::*;
+use crate::frame::{FrameHeader, FRAME_HEADER_LEN};
 use std::io::Read;
 use std::net::Shutdown;
 use std::os::unix::net::UnixStream;
@@ -50,20 +50,25 @@ impl SocketReader {
             }
         }
     }
-    ///This function fetches data from the socket.
-    ///This data is then copied to the supplied buffer.
+    ///This function fetches a frame from the socket.
+    ///The payload of the frame is then copied to the supplied buffer, the metadata is skipped.
     pub fn receive_data(&mut self, buffer: &mut [u8]) -> Result<usize> {
-        let mut element_length_bytes: [u8; BIP_BUFFER_LEN_FIELD_LEN] = Default::default();
+        let mut header_bytes = [0; FRAME_HEADER_LEN];
         self.stream
-            .read_exact(&mut element_length_bytes)
-            .chain_err(|| "Error reading exact when reading element length from stream")?;
-        let element_length = usize::from_le_bytes(element_length_bytes);
+            .read_exact(&mut header_bytes)
+            .chain_err(|| "Error reading exact when reading frame header from stream")?;
+        let header = FrameHeader::read(&header_bytes)?;
+        let element_length = header.payload_len as usize;
         if element_length > buffer.len() {
             return Err(UnixDomainSocketError(
                 "Element length received by socket reader > buffer size".to_string(),
             )
             .into());
         }
+        let mut metadata = vec![0; header.metadata_len as usize];
+        self.stream
+            .read_exact(&mut metadata)
+            .chain_err(|| "Error reading exact when reading metadata from stream")?;
         let element = &mut buffer[..element_length];
         //receive data packet
         self.stream
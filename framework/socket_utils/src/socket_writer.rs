@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use crate::errors::*;
-use framework_constants::*;
+use crate::frame::{encode_frame, Metadata};
 use std::io::Write;
 use std::net::Shutdown;
 use std::os::unix::net::{UnixListener, UnixStream};
@@ -59,17 +59,11 @@ impl SocketWriter {
         })
     }
 
-    ///Sends data from `buffer` to the socket.
-    #[allow(clippy::unused_io_amount)]
+    ///Sends data from `buffer` to the socket, as the payload of a frame without metadata.
     pub fn send_data(&mut self, buffer: &mut [u8]) -> Result<()> {
-        let mut stream_buffer = vec![0; buffer.len() + BIP_BUFFER_LEN_FIELD_LEN];
-
-        let byte_len = buffer.len().to_le_bytes();
-        stream_buffer[..BIP_BUFFER_LEN_FIELD_LEN].copy_from_slice(&byte_len);
-        stream_buffer[BIP_BUFFER_LEN_FIELD_LEN..buffer.len() + BIP_BUFFER_LEN_FIELD_LEN]
-            .copy_from_slice(&buffer);
+        let stream_buffer = encode_frame(&Metadata::default(), buffer)?;
         self.stream
-            .write(&stream_buffer)
+            .write_all(&stream_buffer)
             .chain_err(|| "Socket writer could not write to socket")?;
         Ok(())
     }
@@ -0,0 +1,476 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::address::{SocketAddress, TlsFiles, UnixPermissions};
+use crate::errors::ErrorKind::{PeerCredentialError, TlsError};
+use crate::errors::*;
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::{
+    AllowAnyAuthenticatedClient, Certificate, ClientConfig, ClientSession, PrivateKey,
+    RootCertStore, ServerConfig, ServerSession, Session, StreamOwned,
+};
+use std::ffi::{CString, OsString};
+use std::fs::{DirBuilder, File};
+use std::io::{BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+
+///A connected stream of a socket link, over a unix domain socket, TCP or TLS.
+pub enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+    TlsServer(Box<StreamOwned<ServerSession, TcpStream>>),
+    TlsClient(Box<StreamOwned<ClientSession, TcpStream>>),
+}
+
+impl Stream {
+    ///Shuts down the stream, a TLS stream first tells the peer it is closing.
+    pub fn shutdown(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Unix(stream) => stream.shutdown(Shutdown::Both),
+            Stream::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            Stream::TlsServer(stream) => {
+                stream.sess.send_close_notify();
+                stream.flush()?;
+                stream.sock.shutdown(Shutdown::Both)
+            }
+            Stream::TlsClient(stream) => {
+                stream.sess.send_close_notify();
+                stream.flush()?;
+                stream.sock.shutdown(Shutdown::Both)
+            }
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Unix(stream) => stream.read(buf),
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::TlsServer(stream) => stream.read(buf),
+            Stream::TlsClient(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Unix(stream) => stream.write(buf),
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::TlsServer(stream) => stream.write(buf),
+            Stream::TlsClient(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Unix(stream) => stream.flush(),
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::TlsServer(stream) => stream.flush(),
+            Stream::TlsClient(stream) => stream.flush(),
+        }
+    }
+}
+
+///Accepts the peers of a socket link.
+pub enum Listener {
+    Unix(UnixListener, String, UnixPermissions),
+    Tcp(TcpListener),
+    Tls(TcpListener, Arc<ServerConfig>),
+}
+
+impl Listener {
+    ///Starts listening on `address`. An existing unix domain socket file is replaced.
+    /// # Arguments
+    /// * `address` - The address to listen on.
+    pub fn bind(address: &SocketAddress) -> Result<Listener> {
+        match address {
+            SocketAddress::Unix(path, permissions) => Ok(Listener::Unix(
+                bind_unix(path, permissions)?,
+                path.to_string(),
+                permissions.clone(),
+            )),
+            SocketAddress::Tcp(host_port) => Ok(Listener::Tcp(
+                TcpListener::bind(host_port)
+                    .chain_err(|| format!("Error while binding tcp address {}", host_port))?,
+            )),
+            SocketAddress::Tls(host_port, files) => {
+                let mut config =
+                    ServerConfig::new(AllowAnyAuthenticatedClient::new(read_roots(&files.ca)?));
+                let (cert_chain, key) = read_cert_and_key(files)?;
+                config
+                    .set_single_cert(cert_chain, key)
+                    .map_err(|e| TlsError(format!("Invalid certificate or key: {}", e)))?;
+                Ok(Listener::Tls(
+                    TcpListener::bind(host_port)
+                        .chain_err(|| format!("Error while binding tls address {}", host_port))?,
+                    Arc::new(config),
+                ))
+            }
+        }
+    }
+
+    ///Accepts the connection of the next peer.
+    ///A TLS peer that fails the handshake, for example because its certificate is not trusted, is refused
+    ///and the next peer is accepted. So is a unix domain socket peer that runs as another user than expected.
+    pub fn accept(&self) -> Result<Stream> {
+        loop {
+            let stream = match self {
+                Listener::Unix(listener, _, permissions) => {
+                    let (stream, address) = listener
+                        .accept()
+                        .chain_err(|| "Failed to accept incoming connection")?;
+                    if let Err(e) = check_peer(&stream, permissions) {
+                        log::warn!("Refused client: {}", e);
+                        continue;
+                    }
+                    log::info!("Client connected from: {:?}", address);
+                    stream
+                        .set_nonblocking(false)
+                        .chain_err(|| "non blocking for unix socket could not be set!")?;
+                    Stream::Unix(stream)
+                }
+                Listener::Tcp(listener) => Stream::Tcp(accept_tcp(listener)?),
+                Listener::Tls(listener, config) => {
+                    let mut stream = accept_tcp(listener)?;
+                    let mut session = ServerSession::new(config);
+                    if let Err(e) = handshake(&mut session, &mut stream) {
+                        log::warn!("TLS handshake with client failed: {}", e);
+                        continue;
+                    }
+                    Stream::TlsServer(Box::new(StreamOwned::new(session, stream)))
+                }
+            };
+            return Ok(stream);
+        }
+    }
+
+    ///Removes the socket file of a unix domain socket.
+    pub fn remove(&self) -> std::io::Result<()> {
+        match self {
+            Listener::Unix(_, path, _) => std::fs::remove_file(path),
+            _ => Ok(()),
+        }
+    }
+}
+
+///Connects to the socket link at `address`.
+///This function will block until the peer is listening on the address.
+/// # Arguments
+/// * `address` - The address to connect to.
+pub fn connect(address: &SocketAddress) -> Result<Stream> {
+    match address {
+        SocketAddress::Unix(path, permissions) => {
+            //wait for socket to exist
+            while !std::path::Path::new(path).exists() {
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                log::warn!("BufferedSocketReader: socketfile does not yet exist.");
+            }
+            //wait for accept() to be called on socket
+            loop {
+                if let Ok(stream) = UnixStream::connect(path) {
+                    stream
+                        .set_nonblocking(false)
+                        .chain_err(|| "non blocking for BufferedSocketReader could not be set")?;
+                    stream
+                        .set_write_timeout(None)
+                        .chain_err(|| "write timeout for BufferedSocketReader could not be set!")?;
+                    check_peer(&stream, permissions)?;
+                    return Ok(Stream::Unix(stream));
+                } else {
+                    std::thread::sleep(std::time::Duration::from_millis(200));
+                    log::warn!(
+                        "BufferedSocketReader: accept has not yet been called on this socket"
+                    );
+                }
+            }
+        }
+        SocketAddress::Tcp(host_port) => Ok(Stream::Tcp(connect_tcp(host_port)?)),
+        SocketAddress::Tls(host_port, files) => {
+            let mut config = ClientConfig::new();
+            config.root_store = read_roots(&files.ca)?;
+            let (cert_chain, key) = read_cert_and_key(files)?;
+            config
+                .set_single_client_cert(cert_chain, key)
+                .map_err(|e| TlsError(format!("Invalid certificate or key: {}", e)))?;
+            let server_name = match &files.server_name {
+                Some(server_name) => server_name.as_str(),
+                None => address.host().unwrap_or_default(),
+            };
+            let dns_name = webpki::DNSNameRef::try_from_ascii_str(server_name).map_err(|_| {
+                TlsError(format!(
+                    "{} is not a valid server name, set server_name in the address",
+                    server_name
+                ))
+            })?;
+            let mut session = ClientSession::new(&Arc::new(config), dns_name);
+            let mut stream = connect_tcp(host_port)?;
+            handshake(&mut session, &mut stream)
+                .chain_err(|| TlsError(format!("TLS handshake with {} failed", host_port)))?;
+            Ok(Stream::TlsClient(Box::new(StreamOwned::new(
+                session, stream,
+            ))))
+        }
+    }
+}
+
+///Binds a unix domain socket at `path` that nobody else can connect to before its permissions are set.
+///The socket is bound in a directory that only this user can enter, and then moved to `path`,
+///which replaces an existing socket file.
+fn bind_unix(path: &str, permissions: &UnixPermissions) -> Result<UnixListener> {
+    let socket_path = Path::new(path);
+    let file_name = socket_path
+        .file_name()
+        .chain_err(|| format!("Invalid socket path {}", path))?;
+    let mut private_dir_name = OsString::from(".");
+    private_dir_name.push(file_name);
+    private_dir_name.push(format!(".{}", std::process::id()));
+    let private_dir = socket_path.with_file_name(private_dir_name);
+    //a directory left behind by an earlier process with the same id
+    if let Err(e) = std::fs::remove_dir_all(&private_dir) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(Error::with_chain(
+                e,
+                format!("Cannot remove {}", private_dir.display()),
+            ));
+        }
+    }
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&private_dir)
+        .chain_err(|| format!("Cannot create {}", private_dir.display()))?;
+    let private_path = private_dir.join(file_name);
+    let result = UnixListener::bind(&private_path)
+        .chain_err(|| "Error while binding unix domain socket path")
+        .and_then(|listener| {
+            set_permissions(&private_path.to_string_lossy(), permissions)?;
+            std::fs::rename(&private_path, socket_path)
+                .chain_err(|| format!("Cannot move socket file to {}", path))?;
+            Ok(listener)
+        });
+    if let Err(e) = std::fs::remove_dir_all(&private_dir) {
+        log::warn!("Cannot remove {}: {}", private_dir.display(), e);
+    }
+    result
+}
+
+///Sets the mode and ownership of the socket or ring file at `path`.
+pub(crate) fn set_permissions(path: &str, permissions: &UnixPermissions) -> Result<()> {
+    if let Some(mode) = permissions.mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .chain_err(|| format!("Cannot set mode {:o} of {}", mode, path))?;
+    }
+    if permissions.owner.is_some() || permissions.group.is_some() {
+        let c_path = CString::new(path).chain_err(|| format!("Invalid path {}", path))?;
+        //an id of -1 leaves the owner or group unchanged.
+        let owner = permissions.owner.unwrap_or(u32::MAX);
+        let group = permissions.group.unwrap_or(u32::MAX);
+        if unsafe { libc::chown(c_path.as_ptr(), owner, group) } != 0 {
+            return Err(Error::with_chain(
+                std::io::Error::last_os_error(),
+                format!("Cannot change the owner of {}", path),
+            ));
+        }
+    }
+    Ok(())
+}
+
+///Checks the credentials of the process on the other side of `stream` against the expected identity.
+fn check_peer(stream: &UnixStream, permissions: &UnixPermissions) -> Result<()> {
+    if permissions.peer_uid.is_none()
+        && permissions.peer_gid.is_none()
+        && permissions.peer_pid.is_none()
+    {
+        return Ok(());
+    }
+    let credentials = peer_credentials(stream)
+        .chain_err(|| PeerCredentialError("Cannot read the credentials of the peer".to_string()))?;
+    let matches =
+        |expected: Option<u32>, actual: u32| expected.is_none() || expected == Some(actual);
+    if matches(permissions.peer_uid, credentials.uid)
+        && matches(permissions.peer_gid, credentials.gid)
+        && matches(permissions.peer_pid, credentials.pid as u32)
+    {
+        Ok(())
+    } else {
+        Err(PeerCredentialError(format!(
+            "peer with uid {}, gid {} and pid {} is not the expected peer",
+            credentials.uid, credentials.gid, credentials.pid
+        ))
+        .into())
+    }
+}
+
+///Reads the credentials of the process on the other side of `stream` with SO_PEERCRED.
+fn peer_credentials(stream: &UnixStream) -> std::io::Result<libc::ucred> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result == 0 {
+        Ok(credentials)
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn accept_tcp(listener: &TcpListener) -> Result<TcpStream> {
+    let (stream, address) = listener
+        .accept()
+        .chain_err(|| "Failed to accept incoming connection")?;
+    log::info!("Client connected from: {}", address);
+    stream
+        .set_nodelay(true)
+        .chain_err(|| "no delay for tcp socket could not be set!")?;
+    Ok(stream)
+}
+
+///Connects to `host_port`, trying again until the peer is listening.
+fn connect_tcp(host_port: &str) -> Result<TcpStream> {
+    loop {
+        match TcpStream::connect(host_port) {
+            Ok(stream) => {
+                stream
+                    .set_nodelay(true)
+                    .chain_err(|| "no delay for tcp socket could not be set!")?;
+                return Ok(stream);
+            }
+            Err(e) => {
+                log::warn!(
+                    "BufferedSocketReader: cannot connect to {}: {}",
+                    host_port,
+                    e
+                );
+                std::thread::sleep(std::time::Duration::from_millis(200));
+            }
+        }
+    }
+}
+
+///Completes the TLS handshake before the stream is used.
+fn handshake<S: Session>(session: &mut S, stream: &mut TcpStream) -> std::io::Result<()> {
+    while session.is_handshaking() {
+        session.complete_io(stream)?;
+    }
+    Ok(())
+}
+
+fn open_pem(path: &str) -> Result<BufReader<File>> {
+    Ok(BufReader::new(
+        File::open(path).chain_err(|| TlsError(format!("Cannot open {}", path)))?,
+    ))
+}
+
+fn read_roots(ca: &str) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    match roots.add_pem_file(&mut open_pem(ca)?) {
+        Ok((valid, _)) if valid > 0 => Ok(roots),
+        _ => Err(TlsError(format!("No valid certificate authority in {}", ca)).into()),
+    }
+}
+
+fn read_cert_and_key(files: &TlsFiles) -> Result<(Vec<Certificate>, PrivateKey)> {
+    let cert_chain = match certs(&mut open_pem(&files.cert)?) {
+        Ok(cert_chain) if !cert_chain.is_empty() => cert_chain,
+        _ => return Err(TlsError(format!("No certificate in {}", files.cert)).into()),
+    };
+    let mut keys = pkcs8_private_keys(&mut open_pem(&files.key)?).unwrap_or_default();
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut open_pem(&files.key)?).unwrap_or_default();
+    }
+    match keys.into_iter().next() {
+        Some(key) => Ok((cert_chain, key)),
+        None => Err(TlsError(format!("No private key in {}", files.key)).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn peer_credentials_test() {
+        let dir = TempDir::new("peer_credentials_test").expect("Can't create dir");
+        let path = dir.path().join("stream").to_string_lossy().to_string();
+        let uid = unsafe { libc::getuid() };
+        let address: SocketAddress = format!("unix://{}?mode=600&peer_uid={}", path, uid)
+            .parse()
+            .expect("Can't parse address");
+        let listener = Listener::bind(&address).expect("Can't bind listener");
+        let mode = std::fs::metadata(&path)
+            .expect("Can't read socket file")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let accept_handle = std::thread::spawn(move || {
+            listener.accept().expect("Can't accept peer");
+            listener
+        });
+
+        //the listener runs as the same user, so it is refused when another user is expected.
+        let wrong_peer: SocketAddress = format!("unix://{}?peer_uid={}", path, uid + 1)
+            .parse()
+            .expect("Can't parse address");
+        assert!(connect(&wrong_peer).is_err());
+        let listener = accept_handle.join().expect("Error joining accept thread");
+
+        let accept_handle = std::thread::spawn(move || {
+            listener.accept().expect("Can't accept peer");
+            listener
+        });
+        connect(&address).expect("Can't connect to listener");
+        let listener = accept_handle.join().expect("Error joining accept thread");
+        listener.remove().expect("Can't remove socket file");
+    }
+
+    #[test]
+    fn bind_replaces_socket_file_test() {
+        let dir = TempDir::new("bind_replaces_socket_file_test").expect("Can't create dir");
+        let path = dir.path().join("stream").to_string_lossy().to_string();
+        std::fs::write(&path, "").expect("Can't create file");
+        let address: SocketAddress = format!("unix://{}?mode=660", path)
+            .parse()
+            .expect("Can't parse address");
+        let listener = Listener::bind(&address).expect("Can't bind listener");
+        let accept_handle = std::thread::spawn(move || {
+            listener.accept().expect("Can't accept peer");
+        });
+        connect(&address).expect("Can't connect to listener");
+        accept_handle.join().expect("Error joining accept thread");
+        //the directory the socket was bound in is removed
+        let file_names: Vec<_> = std::fs::read_dir(dir.path())
+            .expect("Can't read dir")
+            .map(|entry| entry.expect("Can't read dir").file_name())
+            .collect();
+        assert_eq!(file_names, vec![OsString::from("stream")]);
+    }
+}
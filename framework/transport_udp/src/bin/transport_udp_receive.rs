@@ -12,22 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::bip_buffer_len_for;
+use bip_utils::bip_buffer_with_len;
+use bip_utils::overflow::BufferMonitor;
+use bip_utils::wakeup::set_wait_strategy;
 use framework_constants::*;
+use handler_schema::describe_if_requested;
 use logging::set_syslog;
-use socket_utils::buffered_socket_writer::BufferedSocketWriter;
-use spsc_bip_buffer::bip_buffer_with_len;
+use socket_utils::link::LinkWriter;
 use statistics_handler::*;
 use std::process;
 use std::process::Command;
 use std::sync::Arc;
+use std::time::Duration;
 use structopt::*;
 use transport_udp::errors::ErrorKind::CommandError;
 use transport_udp::errors::Result;
 use transport_udp::errors::*;
-use transport_udp::rx::rx_arguments::OptReceiver;
+use transport_udp::rx::rx_arguments::{OptReceiver, RECEIVER_SCHEMA};
 use transport_udp::rx::udp_receiver::UdpReceiver;
 
 fn main() {
+    describe_if_requested(&RECEIVER_SCHEMA);
     std::panic::set_hook(Box::new(|panic_info| {
         if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
             log::error!("{}", s);
@@ -63,6 +69,7 @@ fn udp_receive() -> Result<()> {
     )
     .chain_err(|| "Error initializing syslog")?;
     opt.log_config_info();
+    set_wait_strategy(opt.wait_strategy);
 
     //sets the niceness of the application.
     Command::new("renice")
@@ -70,8 +77,9 @@ fn udp_receive() -> Result<()> {
         .spawn()
         .chain_err(|| CommandError("renice".to_string()))?;
 
-    let (writer, mut reader) =
-        bip_buffer_with_len(opt.bip_buffer_element_count * MAX_BIP_BUFFER_MESSAGE_SIZE);
+    let bip_buffer_len =
+        bip_buffer_len_for(opt.bip_buffer_element_count, MAX_BIP_BUFFER_MESSAGE_SIZE);
+    let (writer, mut reader) = bip_buffer_with_len(bip_buffer_len);
 
     let receiver = Arc::new(UdpReceiver::new(&format!(
         "{}:{}",
@@ -80,6 +88,16 @@ fn udp_receive() -> Result<()> {
     let stats_addr = format!("{}:{}", opt.host_stats_server, opt.port_stats_server);
 
     let statistics_client = StatsdClient::<StatsAllHandlers>::new_standard();
+    let monitor = Arc::new(BufferMonitor::new(
+        opt.overflow_policy,
+        Duration::from_millis(opt.overflow_wait_ms),
+        bip_buffer_len,
+        statistics_client
+            .data
+            .register_buffer("udp_receiver_to_socket"),
+    ));
+    let monitor_clone = monitor.clone();
+    let stats_data = statistics_client.get_data_clone();
     statistics_client
         .run(stats_addr, opt.handler_name)
         .chain_err(|| "Error while running statitics")?;
@@ -89,7 +107,7 @@ fn udp_receive() -> Result<()> {
         let receiver = Arc::clone(&receiver);
         clean_unwrap(
             receiver
-                .run(writer, statistics_client.data)
+                .run(writer, monitor_clone, statistics_client.data)
                 .chain_err(|| "Error in thread udp_receiver"),
         )
     })?;
@@ -98,8 +116,20 @@ fn udp_receive() -> Result<()> {
     //build the socket_writer thread.
     let socket_writer_thread_builder =
         std::thread::Builder::new().name("socket_writer_thread".into());
-    let mut buffered_socket_writer = BufferedSocketWriter::start_listening(&path)
-        .chain_err(|| "Error creating buffered socket writer")?;
+    let mut buffered_socket_writer = match &opt.tap_address {
+        Some(tap_address) => LinkWriter::start_with_tap(
+            opt.link,
+            &path,
+            tap_address,
+            opt.tap_lag_policy,
+            opt.tap_max_lag,
+        ),
+        None => LinkWriter::start(opt.link, &path, bip_buffer_len),
+    }
+    .chain_err(|| "Error creating buffered socket writer")?;
+    buffered_socket_writer.set_buffer_monitor(monitor);
+    buffered_socket_writer.set_in_flight_policy(opt.in_flight_policy);
+    buffered_socket_writer.set_stats_data(stats_data);
     let socket_writer_handle = socket_writer_thread_builder.spawn(move || loop {
         clean_unwrap(
             buffered_socket_writer
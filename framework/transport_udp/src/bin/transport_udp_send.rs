@@ -12,10 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::bip_buffer_len_for;
+use bip_utils::bip_buffer_with_len;
+use bip_utils::wakeup::set_wait_strategy;
 use framework_constants::*;
+use handler_schema::describe_if_requested;
 use logging::set_syslog;
-use socket_utils::buffered_socket_reader::BufferedSocketReader;
-use spsc_bip_buffer::bip_buffer_with_len;
+use socket_utils::link::LinkReader;
 use statistics_handler::*;
 use std::process;
 use std::process::Command;
@@ -24,10 +27,11 @@ use structopt::*;
 use transport_udp::errors::ErrorKind::*;
 use transport_udp::errors::Result;
 use transport_udp::errors::*;
-use transport_udp::tx::tx_arguments::OptSender;
+use transport_udp::tx::tx_arguments::{OptSender, SENDER_SCHEMA};
 use transport_udp::tx::udp_sender::UdpSender;
 
 fn main() {
+    describe_if_requested(&SENDER_SCHEMA);
     std::panic::set_hook(Box::new(|panic_info| {
         if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
             log::error!("{}", s);
@@ -62,8 +66,10 @@ fn udp_send() -> Result<()> {
     )
     .chain_err(|| "Error initializing syslog")?;
     opt.log_config_info();
-    let (writer, reader) =
-        bip_buffer_with_len(opt.bip_buffer_element_count * MAX_BIP_BUFFER_MESSAGE_SIZE);
+    set_wait_strategy(opt.wait_strategy);
+    let bip_buffer_len =
+        bip_buffer_len_for(opt.bip_buffer_element_count, MAX_BIP_BUFFER_MESSAGE_SIZE);
+    let (writer, reader) = bip_buffer_with_len(bip_buffer_len);
 
     //create statistics client
     let stats_addr = format!("{}:{}", opt.host_stats_server, opt.port_stats_server);
@@ -77,11 +83,14 @@ fn udp_send() -> Result<()> {
         &format!("{}:{}", opt.sender_addr, opt.sender_port),
         reader,
         opt.send_delay_ms,
-        stats_data,
+        opt.timestamp_elements,
+        opt.interleave_depth,
+        stats_data.clone(),
     )?;
-    let mut unix_socket_reader: BufferedSocketReader =
-        BufferedSocketReader::new(&opt.socket_path, writer)
+    let mut unix_socket_reader: LinkReader =
+        LinkReader::new(opt.link, &opt.socket_path, writer, bip_buffer_len)
             .chain_err(|| "Error creating buffered socket reader")?;
+    unix_socket_reader.set_stats_data(stats_data);
     Command::new("renice")
         .args(&["-n", "-10", "-p", &process::id().to_string()])
         .spawn()
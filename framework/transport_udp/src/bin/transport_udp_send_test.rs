@@ -12,13 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::bip_buffer_with_len;
 use bip_utils::write_to_bip_buffer;
+use bip_utils::BipBufferWriter;
 use framework_constants::BIP_BUFFER_LEN_FIELD_LEN;
 use framework_constants::MAX_BUFFER_SIZE_BYTES;
 use framework_constants::MAX_PAYLOAD_SIZE_BYTES;
 use logging::set_syslog;
-use spsc_bip_buffer::bip_buffer_with_len;
-use spsc_bip_buffer::BipBufferWriter;
 use statistics_handler::*;
 use std::thread::JoinHandle;
 use structopt::*;
@@ -46,6 +46,8 @@ fn main() {
         &format!("{}:{}", opt.sender_addr, opt.sender_port),
         reader,
         opt.send_delay_ms,
+        opt.timestamp_elements,
+        opt.interleave_depth,
         stats_data,
     )
     .expect("Error while setting udp sender");
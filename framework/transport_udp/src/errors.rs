@@ -38,5 +38,9 @@ error_chain! {
             description("Cannot execute command")
             display("Cannot execute command: {}", t)
         }
+        ArgumentError(t: String) {
+            description("Invalid argument")
+            display("Invalid argument: {}", t)
+        }
     }
 }
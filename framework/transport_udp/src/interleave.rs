@@ -0,0 +1,85 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use framework_constants::INTERLEAVE_HEADER_SIZE_BYTES;
+use std::convert::TryInto;
+
+///The header in front of the data of every interleaved message.
+///Elements are interleaved in groups, the element id of the first element in a group identifies the group.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InterleaveHeader {
+    ///The id of the element, incremented for every interleaved element since the last startup.
+    pub element_id: u32,
+    ///The position of the element in its group.
+    pub group_index: u16,
+    ///The amount of elements in the group.
+    pub group_size: u16,
+}
+
+impl InterleaveHeader {
+    ///Returns the element id of the first element in the group.
+    pub fn group_id(&self) -> u32 {
+        self.element_id.wrapping_sub(self.group_index as u32)
+    }
+
+    ///This function is used to write the interleave header to the start of the given buffer.
+    /// # Arguments
+    /// * `buffer` - The payload of the message, containing INTERLEAVE_HEADER_SIZE_BYTES of free space at the front.
+    pub fn write(&self, buffer: &mut [u8]) {
+        buffer[0..4].copy_from_slice(&self.element_id.to_le_bytes());
+        buffer[4..6].copy_from_slice(&self.group_index.to_le_bytes());
+        buffer[6..INTERLEAVE_HEADER_SIZE_BYTES].copy_from_slice(&self.group_size.to_le_bytes());
+    }
+
+    ///This function is used to read the interleave header from the start of the given buffer.
+    /// # Arguments
+    /// * `buffer` - The payload of the message, should contain at least INTERLEAVE_HEADER_SIZE_BYTES.
+    pub fn read(buffer: &[u8]) -> InterleaveHeader {
+        InterleaveHeader {
+            element_id: u32::from_le_bytes(
+                buffer[0..4]
+                    .try_into()
+                    .expect("slice with incorrect length"),
+            ),
+            group_index: u16::from_le_bytes(
+                buffer[4..6]
+                    .try_into()
+                    .expect("slice with incorrect length"),
+            ),
+            group_size: u16::from_le_bytes(
+                buffer[6..INTERLEAVE_HEADER_SIZE_BYTES]
+                    .try_into()
+                    .expect("slice with incorrect length"),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleave_header_test() {
+        let header = InterleaveHeader {
+            element_id: 70_000,
+            group_index: 3,
+            group_size: 4,
+        };
+        let mut buffer = [0; INTERLEAVE_HEADER_SIZE_BYTES];
+        header.write(&mut buffer);
+        assert_eq!(InterleaveHeader::read(&buffer), header);
+        assert_eq!(header.group_id(), 69_997);
+    }
+}
@@ -13,7 +13,11 @@
 // limitations under the License.
 
 pub mod errors;
+///This module contains the header used to interleave the messages of multiple elements.
+pub mod interleave;
 ///This module contains the receiving code of the application.
 pub mod rx;
+///This module contains the totals the sender reports through the diode and their reconciliation.
+pub mod sender_stats;
 ///This module contains the sending code of the application.
 pub mod tx;
@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::interleave::InterleaveHeader;
+use crate::rx::interleaved_group::InterleavedGroup;
 use crate::rx::*;
-use bip_utils::write_to_bip_buffer;
+use crate::sender_stats::{LossReconciler, SenderTotals};
+use bip_utils::overflow::BufferMonitor;
 use statistics_handler::StatsAllHandlers;
 use std::net::UdpSocket;
 use std::sync::Arc;
@@ -25,9 +28,12 @@ use State::*;
 pub enum State {
     ///The WaitingForFirstData state is used when a message with the DataFirst MessageType is expected.
     WaitingForFirstData,
-    ///The WaitingForData state is used when a message with the Data MessageType is expected.
+    ///The WaitingForData state is used while the messages of an element are being reassembled.
     ///This state is given a usize that contains the total amount of messages that need to be combined.
     WaitingForData(usize),
+    ///The WaitingForInterleavedData state is used while the elements of an interleaved group are being reassembled.
+    ///This state is given a usize that contains the amount of elements in the group.
+    WaitingForInterleavedData(usize),
 }
 
 ///The InnerUdpReceiver is used by the UdpReceiver. It contains the real logic used in the UdpReceiver.
@@ -35,10 +41,14 @@ pub enum State {
 pub struct InnerUdpReceiver {
     socket: UdpSocket,
     bip_writer: BipBufferWriter,
+    monitor: Arc<BufferMonitor>,
     packet_buffer: Vec<u8>,
-    combined_buffer: Vec<Vec<u8>>,
     current_sequence_number: u32,
     state: State,
+    pending_packet: Option<PacketData>,
+    ingress_timestamp: Option<u64>,
+    reconciler: LossReconciler,
+    interleaved_group: Option<InterleavedGroup>,
     stats_data: Arc<StatsAllHandlers>,
 }
 
@@ -47,25 +57,29 @@ impl InnerUdpReceiver {
     /// # Arguments
     /// * `socket` - The udpSocket, used to receive data on.
     /// * `bip_writer` - The BipBufferWriter used to write combined data to the bip_buffer.
+    /// * `monitor` - The BufferMonitor used to apply the overflow policy of the bip_buffer.
     /// * `stats_data` - The struct used to store statistics data.
     /// # Returns
     /// `InnerUdpReceiver`
     pub fn new(
         socket: UdpSocket,
         bip_writer: BipBufferWriter,
+        monitor: Arc<BufferMonitor>,
         stats_data: Arc<StatsAllHandlers>,
     ) -> InnerUdpReceiver {
-        //create message buffer to store the received messages.
-        let combined_buffer: Vec<Vec<u8>> = vec![Vec::with_capacity(MAX_PAYLOAD_SIZE_BYTES); 20];
         let packet_buffer = vec![0; MAX_BUFFER_SIZE_BYTES];
         let current_sequence_number = 0;
         InnerUdpReceiver {
             socket,
             bip_writer,
+            monitor,
             packet_buffer,
-            combined_buffer,
             current_sequence_number,
             state: State::WaitingForFirstData,
+            pending_packet: None,
+            ingress_timestamp: None,
+            reconciler: LossReconciler::default(),
+            interleaved_group: None,
             stats_data,
         }
     }
@@ -76,23 +90,26 @@ impl InnerUdpReceiver {
     /// `JoinHandle<()>` - The JoinHandle of the started thread.
     pub fn run(mut self) {
         loop {
-            receive_packet(&self.socket, &mut self.packet_buffer);
-            let packet_header = read_packet_header(&self.packet_buffer);
-            let lost_packets = check_for_packetloss(
-                packet_header.sequence_number,
-                &mut self.current_sequence_number,
-                self.stats_data.clone(),
-            );
-            self.update_in_stats(&packet_header);
-            //When packetloss occurs the current data can not be used anymore.
-            //State is set to WaitingForFirstData
-            if lost_packets > 0 {
-                self.state = WaitingForFirstData;
-                log::warn!(
-                    "Packetloss detected, {} packets lost. State set to WaitingForFirstData",
-                    lost_packets
-                );
-            }
+            //A packet that interrupted the reassembly of an element is handled before receiving a new one.
+            let packet_header = match self.pending_packet.take() {
+                Some(packet_header) => packet_header,
+                None => {
+                    let (packet_header, lost_packets) = receive_next_packet(
+                        &self.socket,
+                        &mut self.packet_buffer,
+                        &mut self.current_sequence_number,
+                        &mut self.reconciler.received.packets,
+                        &self.stats_data,
+                    );
+                    if lost_packets > 0 {
+                        log::warn!(
+                            "Packetloss detected, {} packets lost while waiting for first data",
+                            lost_packets
+                        );
+                    }
+                    packet_header
+                }
+            };
             if !self.update_state(&packet_header) {
                 break;
             }
@@ -101,36 +118,38 @@ impl InnerUdpReceiver {
 
     ///This function is used to change the state depending MessageType of the incoming packet.
     fn update_state(&mut self, packet_header: &PacketData) -> bool {
-        self.state = match (self.state, packet_header.message_type) {
-            //Data when it is expected
-            (WaitingForData(total), Data) => self.handle_data_message(&packet_header, total),
-
-            //Data when it is not expected
-            (_, Data) => {
+        //any other message ends the interleaved group that is being reassembled.
+        match packet_header.message_type {
+            InterleavedFirst | Interleaved => {}
+            _ => self.flush_interleaved_group(),
+        }
+        self.state = match packet_header.message_type {
+            //Data outside of an element that is being reassembled, its DataFirst was lost.
+            Data => {
                 log::trace!("Data message discarded");
                 WaitingForFirstData
             }
 
-            //DataFirst when it is expected
-            (WaitingForFirstData, DataFirst) => self.handle_data_first_message(&packet_header),
+            //DataFirst starts a new element
+            DataFirst => self.handle_data_first_message(&packet_header),
 
-            //DataFirst when it is not expected
-            (_, DataFirst) => {
-                log::warn!(
-                    "Received datafirst message when it was not expected, 
-                    data messages were discarded"
-                );
-                self.handle_data_first_message(&packet_header)
-            }
+            //Timestamp of the element that follows
+            Timestamp => self.handle_timestamp_message(&packet_header),
+
+            //Totals of the sender, reconciled with what was received
+            SenderStats => self.handle_sender_stats_message(&packet_header),
+
+            //Message of an element in an interleaved group
+            InterleavedFirst | Interleaved => self.handle_interleaved_message(&packet_header),
 
             //Startup always sets sequence number to 0
-            (_, StartUp) => self.handle_startup_message(),
+            StartUp => self.handle_startup_message(),
 
             //Heartbeat received, return previous state and log heartbeat
-            (_, HeartBeat) => self.handle_heartbeat_message(),
+            HeartBeat => self.handle_heartbeat_message(),
 
             //Shutdown received, break the loop and stop the application
-            (_, MessageType::Shutdown) => {
+            MessageType::Shutdown => {
                 self.handle_shutdown_message();
                 return false;
             }
@@ -139,46 +158,208 @@ impl InnerUdpReceiver {
     }
 
     ///This function is used to handle a message that has the DataFirst MessageType.
+    ///When an ingress timestamp was received for this element, its latency is recorded once it is written.
     fn handle_data_first_message(&mut self, packet_header: &PacketData) -> State {
+        let ingress_timestamp = self.ingress_timestamp.take();
         if packet_header.remaining_messages > 0 {
-            self.combined_buffer[0].clear();
-            self.combined_buffer[0].extend_from_slice(
-                &self.packet_buffer
-                    [HEADER_SIZE_BYTES..packet_header.payload_length as usize + HEADER_SIZE_BYTES],
-            );
-            //the first count of remaining messages + 1 = total amount of messages
-            WaitingForData(packet_header.remaining_messages + 1)
+            self.reassemble_element(packet_header, ingress_timestamp)
         } else {
             //datafirst is the only message
-            write_to_bip_buffer(
+            write_element(
                 &mut self.bip_writer,
+                &self.monitor,
+                &mut self.reconciler,
+                &self.stats_data,
                 &self.packet_buffer
                     [HEADER_SIZE_BYTES..packet_header.payload_length as usize + HEADER_SIZE_BYTES],
+                ingress_timestamp,
             );
-            //update bytes out statistic
-            self.stats_data
-                .out_bytes
-                .add((packet_header.payload_length as usize + BIP_BUFFER_LEN_FIELD_LEN) as u64);
             WaitingForFirstData
         }
     }
 
-    ///This function is used to handle a message that has the Data MessageType.
-    fn handle_data_message(&mut self, packet_header: &PacketData, total_messages: usize) -> State {
-        //copy the data from the message into the combined buffer.
-        let combined_buffer_position =
-            &mut self.combined_buffer[total_messages - packet_header.remaining_messages - 1];
-        combined_buffer_position.clear();
-        combined_buffer_position.extend_from_slice(
-            &self.packet_buffer
-                [HEADER_SIZE_BYTES..packet_header.payload_length as usize + HEADER_SIZE_BYTES],
+    ///This function is used to reassemble an element that consists of multiple messages.
+    ///The size of the element is at most the messages of the DataFirst message times MAX_PAYLOAD_SIZE_BYTES,
+    ///so a reservation of that size is taken in the bip_buffer and every message is copied straight into place.
+    ///When packetloss occurs or another MessageType arrives, the element is abandoned and skipped by the reader.
+    ///When there is no space in the bip_buffer, the messages are still received to count the dropped element.
+    ///The interrupting packet is stored in `pending_packet` to be handled by the run loop.
+    fn reassemble_element(
+        &mut self,
+        first_packet: &PacketData,
+        ingress_timestamp: Option<u64>,
+    ) -> State {
+        if first_packet.payload_length as usize != MAX_PAYLOAD_SIZE_BYTES {
+            log::warn!("Received datafirst message with an invalid payload length, discarded");
+            return WaitingForFirstData;
+        }
+        let total_messages = first_packet.remaining_messages + 1;
+        let max_element_length = total_messages * MAX_PAYLOAD_SIZE_BYTES;
+        //the reservation also holds the length field of its unused part, see `try_write_in_place`.
+        if max_element_length + BIP_BUFFER_LEN_FIELD_LEN > self.monitor.max_element_length() {
+            log::warn!(
+                "Element of {} messages discarded, it does not fit in the bip_buffer",
+                total_messages
+            );
+            self.stats_data.oversized_packets.add(1);
+            self.stats_data.dropped_packets.add(1);
+            return WaitingForFirstData;
+        }
+        self.state = WaitingForData(total_messages);
+
+        let monitor = self.monitor.clone();
+        let mut messages = ElementMessages {
+            socket: &self.socket,
+            packet_buffer: &mut self.packet_buffer,
+            current_sequence_number: &mut self.current_sequence_number,
+            received_packets: &mut self.reconciler.received.packets,
+            stats_data: &self.stats_data,
+            pending_packet: &mut self.pending_packet,
+        };
+        let mut element_length = None;
+        let reserved =
+            monitor.write_in_place(&mut self.bip_writer, max_element_length, |element| {
+                element_length = messages.receive(first_packet, Some(element));
+                element_length
+            });
+        if !reserved {
+            element_length = messages.receive(first_packet, None);
+            if let Some(element_length) = element_length {
+                monitor.dropped(element_length);
+            }
+        }
+        if let Some(element_length) = element_length {
+            count_element(
+                &mut self.reconciler,
+                &self.stats_data,
+                element_length,
+                reserved,
+                ingress_timestamp,
+            );
+        }
+        WaitingForFirstData
+    }
+
+    ///This function is used to handle a message that has the Timestamp MessageType.
+    ///The ingress timestamp is kept until the DataFirst message of the next element arrives.
+    fn handle_timestamp_message(&mut self, packet_header: &PacketData) -> State {
+        if packet_header.payload_length as usize != TIMESTAMP_SIZE_BYTES {
+            log::warn!("Received timestamp message with an invalid payload length, discarded");
+            return WaitingForFirstData;
+        }
+        let mut timestamp_bytes = [0; TIMESTAMP_SIZE_BYTES];
+        timestamp_bytes.copy_from_slice(
+            &self.packet_buffer[HEADER_SIZE_BYTES..HEADER_SIZE_BYTES + TIMESTAMP_SIZE_BYTES],
         );
-        if packet_header.remaining_messages == 0 {
-            //data element in combined_buffer is complete
-            self.combine_and_write_to_bip(packet_header, total_messages);
-            return State::WaitingForFirstData;
+        self.ingress_timestamp = Some(u64::from_le_bytes(timestamp_bytes));
+        WaitingForFirstData
+    }
+
+    ///This function is used to handle a message that has the InterleavedFirst or Interleaved MessageType.
+    ///A message of another group ends the current group, the group is written when all its elements are complete.
+    fn handle_interleaved_message(&mut self, packet_header: &PacketData) -> State {
+        let payload_length = packet_header.payload_length as usize;
+        if payload_length < INTERLEAVE_HEADER_SIZE_BYTES {
+            log::warn!("Received interleaved message with an invalid payload length, discarded");
+            return self.state;
+        }
+        let interleave_header = InterleaveHeader::read(&self.packet_buffer[HEADER_SIZE_BYTES..]);
+        let group_size = interleave_header.group_size as usize;
+        if group_size == 0 || group_size > MAX_INTERLEAVE_DEPTH {
+            log::warn!("Received interleaved message with an invalid group size, discarded");
+            return self.state;
+        }
+        let is_other_group = match &self.interleaved_group {
+            Some(group) => {
+                group.group_id() != interleave_header.group_id() || group.group_size() != group_size
+            }
+            None => false,
+        };
+        if is_other_group {
+            self.flush_interleaved_group();
+        }
+        //the InterleavedFirst message sizes the element, an element that is larger than the bip_buffer accepts is not reassembled.
+        if matches!(packet_header.message_type, MessageType::InterleavedFirst)
+            && packet_header.remaining_messages * MAX_INTERLEAVED_DATA_SIZE_BYTES
+                > self.monitor.max_element_length()
+        {
+            log::warn!(
+                "Interleaved element of {} messages discarded, it does not fit in the bip_buffer",
+                packet_header.remaining_messages + 1
+            );
+            self.stats_data.oversized_packets.add(1);
+            self.stats_data.dropped_packets.add(1);
+            return self.state;
+        }
+
+        let ingress_timestamp = &mut self.ingress_timestamp;
+        let group = self.interleaved_group.get_or_insert_with(|| {
+            InterleavedGroup::new(
+                interleave_header.group_id(),
+                group_size,
+                ingress_timestamp.take(),
+            )
+        });
+        if !group.add_message(
+            &interleave_header,
+            packet_header.message_type,
+            packet_header.remaining_messages,
+            &self.packet_buffer[HEADER_SIZE_BYTES + INTERLEAVE_HEADER_SIZE_BYTES
+                ..HEADER_SIZE_BYTES + payload_length],
+        ) {
+            log::debug!("Interleaved message does not fit in its element, discarded");
+        }
+        if group.is_complete() {
+            self.flush_interleaved_group();
+            WaitingForFirstData
+        } else {
+            WaitingForInterleavedData(group_size)
         }
-        State::WaitingForData(total_messages)
+    }
+
+    ///This function is used to write the complete elements of the interleaved group to the bip_buffer in order.
+    ///Elements that are not complete are discarded.
+    fn flush_interleaved_group(&mut self) {
+        let group = match self.interleaved_group.take() {
+            Some(group) => group,
+            None => return,
+        };
+        let ingress_timestamp = group.ingress_timestamp();
+        let group_size = group.group_size();
+        let mut discarded = 0;
+        for element in group.into_elements() {
+            match element {
+                Some(element) => write_element(
+                    &mut self.bip_writer,
+                    &self.monitor,
+                    &mut self.reconciler,
+                    &self.stats_data,
+                    &element,
+                    ingress_timestamp,
+                ),
+                None => discarded += 1,
+            }
+        }
+        if discarded > 0 {
+            log::warn!(
+                "{} of {} interleaved elements discarded, not all their messages arrived",
+                discarded,
+                group_size
+            );
+        }
+    }
+
+    ///This function is used to handle a message that has the SenderStats MessageType.
+    fn handle_sender_stats_message(&mut self, packet_header: &PacketData) -> State {
+        if packet_header.payload_length as usize != SENDER_STATS_SIZE_BYTES {
+            log::warn!("Received sender stats message with an invalid payload length, discarded");
+            return WaitingForFirstData;
+        }
+        let sent = SenderTotals::from_bytes(
+            &self.packet_buffer[HEADER_SIZE_BYTES..HEADER_SIZE_BYTES + SENDER_STATS_SIZE_BYTES],
+        );
+        self.reconciler.reconcile(sent, &self.stats_data);
+        WaitingForFirstData
     }
 
     ///This function is used to handle a message that has the Heartbeat MessageType.
@@ -193,6 +374,7 @@ impl InnerUdpReceiver {
             self.current_sequence_number = 0;
             log::info!("Startup message has been received, sequence number was reset to 0");
         }
+        self.reconciler.reset();
         State::WaitingForFirstData
     }
 
@@ -200,63 +382,245 @@ impl InnerUdpReceiver {
     fn handle_shutdown_message(&self) {
         log::warn!("Shutdown message received, breaking loop!");
     }
+}
 
-    ///This function is used to combine all packets that belong to one set of data.
-    ///The combined messages are written to the BipBuffer.
-    fn combine_and_write_to_bip(&mut self, packet_header: &PacketData, total_messages: usize) {
-        let total_bytes = ((total_messages - 1) * MAX_PAYLOAD_SIZE_BYTES)
-            + packet_header.payload_length as usize
-            + BIP_BUFFER_LEN_FIELD_LEN;
-        if let Some(mut reservation) = self.bip_writer.reserve(total_bytes) {
-            //write length field
-            let element_bytes = (total_bytes - BIP_BUFFER_LEN_FIELD_LEN).to_le_bytes();
-            reservation[..BIP_BUFFER_LEN_FIELD_LEN].copy_from_slice(&element_bytes);
-            //write parts
-            let mut start_index = BIP_BUFFER_LEN_FIELD_LEN;
-            let mut end_index = MAX_PAYLOAD_SIZE_BYTES + start_index;
-            for message in 0..total_messages - 1 {
-                reservation[start_index..end_index].copy_from_slice(&self.combined_buffer[message]);
-                start_index = end_index;
-                end_index += MAX_PAYLOAD_SIZE_BYTES;
+///This function is used to write a complete element to the bip_buffer.
+///The element is counted as received, whether or not it fits in the bip_buffer.
+/// # Arguments
+/// * `bip_writer` - The BipBufferWriter used to write the element to the bip_buffer.
+/// * `monitor` - The BufferMonitor used to apply the overflow policy of the bip_buffer.
+/// * `reconciler` - The LossReconciler that counts the received elements.
+/// * `stats_data` - The struct used to store statistics data.
+/// * `element` - The data of the element.
+/// * `ingress_timestamp` - The ingress timestamp of the element, used to record its latency.
+fn write_element(
+    bip_writer: &mut BipBufferWriter,
+    monitor: &BufferMonitor,
+    reconciler: &mut LossReconciler,
+    stats_data: &StatsAllHandlers,
+    element: &[u8],
+    ingress_timestamp: Option<u64>,
+) {
+    let written = monitor.write(bip_writer, element);
+    count_element(
+        reconciler,
+        stats_data,
+        element.len(),
+        written,
+        ingress_timestamp,
+    );
+}
+
+///This function is used to count a complete element as received, and as written to the bip_buffer or dropped.
+/// # Arguments
+/// * `reconciler` - The LossReconciler that counts the received elements.
+/// * `stats_data` - The struct used to store statistics data.
+/// * `element_length` - The length of the element.
+/// * `written` - Whether the element was written to the bip_buffer.
+/// * `ingress_timestamp` - The ingress timestamp of the element, used to record its latency.
+fn count_element(
+    reconciler: &mut LossReconciler,
+    stats_data: &StatsAllHandlers,
+    element_length: usize,
+    written: bool,
+    ingress_timestamp: Option<u64>,
+) {
+    reconciler.received.elements += 1;
+    reconciler.received.bytes += element_length as u64;
+    let element_bytes = element_length + BIP_BUFFER_LEN_FIELD_LEN;
+    if written {
+        //update bytes out statistic
+        stats_data.out_bytes.add(element_bytes as u64);
+        if let Some(ingress_timestamp) = ingress_timestamp {
+            stats_data.latency.record(ingress_timestamp);
+        }
+    } else {
+        stats_data.dropped_bytes.add(element_bytes as u64);
+    }
+}
+
+///The parts of the InnerUdpReceiver used to receive the messages of an element while its reservation is taken.
+struct ElementMessages<'a> {
+    socket: &'a UdpSocket,
+    packet_buffer: &'a mut [u8],
+    current_sequence_number: &'a mut u32,
+    received_packets: &'a mut u64,
+    stats_data: &'a Arc<StatsAllHandlers>,
+    pending_packet: &'a mut Option<PacketData>,
+}
+
+impl ElementMessages<'_> {
+    ///Receives the messages of the element that starts with `first_packet`, which is in the packet buffer already.
+    ///The payload of every message is copied to its place in `element`, when it is given.
+    /// # Returns
+    /// `Option<usize>` - The length of the element, None when a message was lost or another MessageType arrived.
+    fn receive(
+        &mut self,
+        first_packet: &PacketData,
+        mut element: Option<&mut [u8]>,
+    ) -> Option<usize> {
+        let total_messages = first_packet.remaining_messages + 1;
+        let mut element_bytes = 0;
+        let mut expected_remaining = first_packet.remaining_messages;
+        let mut packet_header = *first_packet;
+        loop {
+            //copy the payload of the message to its place in the element.
+            let payload_length = packet_header.payload_length as usize;
+            if let Some(element) = element.as_mut() {
+                element[element_bytes..element_bytes + payload_length].copy_from_slice(
+                    &self.packet_buffer[HEADER_SIZE_BYTES..payload_length + HEADER_SIZE_BYTES],
+                );
             }
-            //write last, possibly < MAX_PAYLOAD_SIZE_BYTE, part.
-            reservation[start_index..].copy_from_slice(
-                &self.combined_buffer[total_messages - 1][..packet_header.payload_length as usize],
-            );
+            element_bytes += payload_length;
+            if expected_remaining == 0 {
+                return Some(element_bytes);
+            }
+            expected_remaining -= 1;
 
-            //update bytes out statistic
-            self.stats_data.out_bytes.add(total_bytes as u64);
-            reservation.send();
-        } else {
-            self.stats_data.dropped_bytes.add(total_bytes as u64);
-            log::warn!("Data dropped when writing to bip_buffer in receiver: No space in buffer!");
+            let (next_packet_header, lost_packets) = receive_next_packet(
+                self.socket,
+                self.packet_buffer,
+                self.current_sequence_number,
+                self.received_packets,
+                self.stats_data,
+            );
+            //only the last message of an element can contain less than MAX_PAYLOAD_SIZE_BYTES.
+            let next_payload_length = next_packet_header.payload_length as usize;
+            let is_next_message = match next_packet_header.message_type {
+                Data => {
+                    next_packet_header.remaining_messages == expected_remaining
+                        && (next_payload_length == MAX_PAYLOAD_SIZE_BYTES
+                            || (expected_remaining == 0
+                                && next_payload_length <= MAX_PAYLOAD_SIZE_BYTES))
+                }
+                _ => false,
+            };
+            if lost_packets > 0 || !is_next_message {
+                if lost_packets > 0 {
+                    log::warn!(
+                        "Packetloss detected, {} packets lost. Element of {} messages discarded",
+                        lost_packets,
+                        total_messages
+                    );
+                } else {
+                    log::warn!(
+                        "Received {:?} message when it was not expected, element of {} messages discarded",
+                        next_packet_header.message_type,
+                        total_messages
+                    );
+                }
+                *self.pending_packet = Some(next_packet_header);
+                return None;
+            }
+            packet_header = next_packet_header;
         }
     }
+}
 
-    ///This function will update the in_bytes and in_packets counter of the statistics struct.
-    fn update_in_stats(&self, packet_header: &PacketData) {
-        self.stats_data.in_packets.add(1);
-        self.stats_data
-            .in_bytes
-            .add(packet_header.payload_length as u64);
+///This function is used to receive the next packet and read its packet header.
+///Every received packet is checked for packetloss and counted in the in statistics.
+///Packets with a sequence number are also counted in `received_packets`, to be reconciled with the sender.
+/// # Returns
+/// `(PacketData, usize)` - The header of the received packet and the amount of packets lost before it.
+fn receive_next_packet(
+    socket: &UdpSocket,
+    packet_buffer: &mut [u8],
+    current_sequence_number: &mut u32,
+    received_packets: &mut u64,
+    stats_data: &Arc<StatsAllHandlers>,
+) -> (PacketData, usize) {
+    receive_packet(socket, packet_buffer);
+    let packet_header = read_packet_header(packet_buffer);
+    let lost_packets = check_for_packetloss(
+        packet_header.sequence_number,
+        current_sequence_number,
+        stats_data.clone(),
+    );
+    update_in_stats(stats_data, &packet_header);
+    match packet_header.message_type {
+        StartUp | MessageType::Shutdown => {}
+        _ => *received_packets += 1,
     }
+    (packet_header, lost_packets)
+}
+
+///This function will update the in_bytes and in_packets counter of the statistics struct.
+fn update_in_stats(stats_data: &StatsAllHandlers, packet_header: &PacketData) {
+    stats_data.in_packets.add(1);
+    stats_data.in_bytes.add(packet_header.payload_length as u64);
 }
 
 #[cfg(test)]
 mod test {
     mod update_state {
         use crate::rx::inner_udp_receiver::*;
+        use crate::tx::write_packet_header;
+        use bip_utils::overflow::OverflowPolicy;
+        use bip_utils::BipBufferReader;
         use statistics_handler::*;
         use std::net::SocketAddr;
         use std::net::UdpSocket;
-        #[test]
-        fn update_state_1_message_test() {
-            //create all needed parameters for inner_receiver
+        use std::thread::JoinHandle;
+
+        ///Creates an InnerUdpReceiver on any free port together with the reader of its bip_buffer.
+        fn create_inner_receiver() -> (InnerUdpReceiver, BipBufferReader, SocketAddr) {
             let socket = UdpSocket::bind(SocketAddr::from(([127, 0, 0, 1], 0))) //any free port
                 .expect("Error binding port for test");
+            let receiver_addr = socket.local_addr().expect("Error getting local address");
             let statistics_client = StatsdClient::<StatsAllHandlers>::new_standard();
             let stats_data = statistics_client.data;
-            let (writer, _) = spsc_bip_buffer::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE);
+            let (writer, reader) = bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+            let monitor = Arc::new(BufferMonitor::new(
+                OverflowPolicy::DropNewest,
+                std::time::Duration::from_millis(0),
+                MAX_BIP_BUFFER_MESSAGE_SIZE * 10,
+                stats_data.register_buffer("receiver_test"),
+            ));
+            (
+                InnerUdpReceiver::new(socket, writer, monitor, stats_data),
+                reader,
+                receiver_addr,
+            )
+        }
+
+        ///Sends Data messages with sequence number 1 and up to the receiver on a seperate thread.
+        ///Every message is a full message, except for the last one which contains `last_payload_length` bytes.
+        fn send_data_messages(
+            receiver_addr: SocketAddr,
+            message_count: usize,
+            last_payload_length: usize,
+        ) -> JoinHandle<()> {
+            std::thread::spawn(move || {
+                let socket = UdpSocket::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+                    .expect("Error binding port for test");
+                socket
+                    .connect(receiver_addr)
+                    .expect("Error connecting to receiver");
+                let mut buffer = vec![0; MAX_BUFFER_SIZE_BYTES];
+                for i in 0..message_count {
+                    let remaining_messages = message_count - i - 1;
+                    let payload_length = if remaining_messages == 0 {
+                        last_payload_length
+                    } else {
+                        MAX_PAYLOAD_SIZE_BYTES
+                    };
+                    let message = &mut buffer[..payload_length + HEADER_SIZE_BYTES];
+                    write_packet_header(
+                        message,
+                        i as u32 + 1,
+                        MessageType::Data.as_u8(),
+                        &mut (remaining_messages as u16 + 1),
+                    );
+                    socket.send(message).expect("Error sending test message");
+                    //give the receiver time to read the message from the socket.
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+            })
+        }
+
+        #[test]
+        fn update_state_1_message_test() {
+            let (mut inner_receiver, _, _) = create_inner_receiver();
             let packet_header = PacketData {
                 message_type: MessageType::Data,
                 payload_length: 0,
@@ -265,84 +629,169 @@ mod test {
             };
 
             //check initial state
-            let mut inner_receiver = InnerUdpReceiver::new(socket, writer, stats_data);
             assert_eq!(inner_receiver.state, State::WaitingForFirstData);
             //update and check state for first message
             inner_receiver.update_state(&packet_header);
             assert_eq!(inner_receiver.state, State::WaitingForFirstData);
         }
 
-        #[test]
-        fn update_state_2_messages_test() {
-            //create all needed parameters for inner_receiver
-            let socket = UdpSocket::bind(SocketAddr::from(([127, 0, 0, 1], 0))) //any free port
-                .expect("Error binding port for test");
-            let statistics_client = StatsdClient::<StatsAllHandlers>::new_standard();
-            let stats_data = statistics_client.data;
-            let (writer, _) =
-                spsc_bip_buffer::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
-            let mut packet_header = PacketData {
+        ///Reassembles an element of `total_messages` messages and checks the element written to the bip_buffer.
+        fn reassemble_test(total_messages: usize) {
+            let (mut inner_receiver, mut reader, receiver_addr) = create_inner_receiver();
+            let packet_header = PacketData {
                 message_type: MessageType::DataFirst,
                 payload_length: MAX_PAYLOAD_SIZE_BYTES as u16,
-                remaining_messages: 1,
+                remaining_messages: total_messages - 1,
                 sequence_number: 0,
             };
+            let sender_handle = send_data_messages(receiver_addr, total_messages - 1, 10);
 
             //check initial state
-            let mut inner_receiver = InnerUdpReceiver::new(socket, writer, stats_data);
             assert_eq!(inner_receiver.state, State::WaitingForFirstData);
-            //update state for first message
-            inner_receiver.update_state(&packet_header);
-            assert_eq!(inner_receiver.state, State::WaitingForData(2));
-            //update packet header and state
-            packet_header.remaining_messages = 0;
-            packet_header.sequence_number = 1;
-            packet_header.message_type = MessageType::Data;
+            //update state for first message, the other messages are received while reassembling
             inner_receiver.update_state(&packet_header);
+            sender_handle.join().expect("Error joining sender thread");
 
-            //check final state
+            //check final state and the element in the bip_buffer
             assert_eq!(inner_receiver.state, State::WaitingForFirstData);
+            assert!(inner_receiver.pending_packet.is_none());
+            let element_length = bip_utils::get_element_length(&mut reader);
+            assert_eq!(
+                element_length,
+                (total_messages - 1) * MAX_PAYLOAD_SIZE_BYTES + 10
+            );
+        }
+
+        #[test]
+        fn update_state_2_messages_test() {
+            reassemble_test(2);
         }
 
         #[test]
         fn update_state_16_messages_test() {
-            //create all needed parameters for inner_receiver
-            let socket = UdpSocket::bind(SocketAddr::from(([127, 0, 0, 1], 0))) //any free port
-                .expect("Error binding port for test");
-            let statistics_client = StatsdClient::<StatsAllHandlers>::new_standard();
-            let stats_data = statistics_client.data;
-            let (writer, _) =
-                spsc_bip_buffer::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
-            let mut packet_header = PacketData {
+            reassemble_test(16);
+        }
+
+        #[test]
+        fn update_state_25_messages_test() {
+            //more messages then the old combined buffer could hold
+            reassemble_test(25);
+        }
+
+        #[test]
+        fn update_state_packetloss_test() {
+            let (mut inner_receiver, mut reader, receiver_addr) = create_inner_receiver();
+            let packet_header = PacketData {
                 message_type: MessageType::DataFirst,
                 payload_length: MAX_PAYLOAD_SIZE_BYTES as u16,
-                remaining_messages: 15,
+                remaining_messages: 3,
                 sequence_number: 0,
             };
+            //send a message with sequence number 2, message 1 is lost
+            let sender_socket = UdpSocket::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+                .expect("Error binding port for test");
+            let mut buffer = vec![0; MAX_BUFFER_SIZE_BYTES];
+            write_packet_header(&mut buffer, 2, MessageType::Data.as_u8(), &mut 3);
+            sender_socket
+                .send_to(&buffer, receiver_addr)
+                .expect("Error sending test message");
 
-            //check initial state
-            let mut inner_receiver = InnerUdpReceiver::new(socket, writer, stats_data);
+            inner_receiver.update_state(&packet_header);
+
+            //the element is abandoned and the interrupting packet is kept for the run loop
             assert_eq!(inner_receiver.state, State::WaitingForFirstData);
-            //update state for first message
+            assert!(inner_receiver.pending_packet.is_some());
+            let mut length_field = [0; BIP_BUFFER_LEN_FIELD_LEN];
+            length_field.copy_from_slice(&reader.valid()[..BIP_BUFFER_LEN_FIELD_LEN]);
+            assert_ne!(
+                usize::from_le_bytes(length_field) & bip_utils::DISCARDED_ELEMENT_FLAG,
+                0
+            );
+            assert_eq!(inner_receiver.reconciler.received.elements, 0);
+        }
+
+        #[test]
+        fn update_state_timestamp_test() {
+            let (mut inner_receiver, mut reader, _) = create_inner_receiver();
+            let ingress_timestamp = statistics_handler::latency::now_micros() - 1000;
+            inner_receiver.packet_buffer
+                [HEADER_SIZE_BYTES..HEADER_SIZE_BYTES + TIMESTAMP_SIZE_BYTES]
+                .copy_from_slice(&ingress_timestamp.to_le_bytes());
+            let timestamp_header = PacketData {
+                message_type: MessageType::Timestamp,
+                payload_length: TIMESTAMP_SIZE_BYTES as u16,
+                remaining_messages: 0,
+                sequence_number: 1,
+            };
+            inner_receiver.update_state(&timestamp_header);
+            assert_eq!(inner_receiver.ingress_timestamp, Some(ingress_timestamp));
+
+            //the timestamp belongs to the next element and is used once it is written
+            let packet_header = PacketData {
+                message_type: MessageType::DataFirst,
+                payload_length: 10,
+                remaining_messages: 0,
+                sequence_number: 2,
+            };
             inner_receiver.update_state(&packet_header);
-            assert_eq!(inner_receiver.state, State::WaitingForData(16));
-
-            //update packet header and state for other messages
-            for i in 0..14 {
-                packet_header.remaining_messages -= 1;
-                packet_header.message_type = MessageType::Data;
-                packet_header.sequence_number = i as u32 + 1;
-                inner_receiver.update_state(&packet_header);
-                assert_eq!(inner_receiver.state, State::WaitingForData(16));
-            }
+            assert_eq!(inner_receiver.ingress_timestamp, None);
+            assert_eq!(inner_receiver.stats_data.latency.clock_skew.load(), 0);
+            assert_eq!(bip_utils::get_element_length(&mut reader), 10);
+        }
 
-            //check state and update packet header for final packet
-            packet_header.remaining_messages -= 1;
-            packet_header.sequence_number = 15;
-            packet_header.message_type = MessageType::Data;
-            packet_header.payload_length = 10;
+        ///Places an interleaved message of a group of 2 elements in the packet buffer of the receiver.
+        fn receive_interleaved_message(
+            inner_receiver: &mut InnerUdpReceiver,
+            group_index: u16,
+            data_length: usize,
+            remaining_messages: usize,
+        ) {
+            InterleaveHeader {
+                element_id: 7 + group_index as u32,
+                group_index,
+                group_size: 2,
+            }
+            .write(&mut inner_receiver.packet_buffer[HEADER_SIZE_BYTES..]);
+            let packet_header = PacketData {
+                message_type: MessageType::InterleavedFirst,
+                payload_length: (INTERLEAVE_HEADER_SIZE_BYTES + data_length) as u16,
+                remaining_messages,
+                sequence_number: group_index as u32 + 1,
+            };
             inner_receiver.update_state(&packet_header);
+        }
+
+        #[test]
+        fn update_state_interleaved_test() {
+            let (mut inner_receiver, mut reader, _) = create_inner_receiver();
+            //the second element of the group arrives first
+            receive_interleaved_message(&mut inner_receiver, 1, 20, 0);
+            assert_eq!(inner_receiver.state, State::WaitingForInterleavedData(2));
+            assert_eq!(reader.valid().len(), 0);
+            receive_interleaved_message(&mut inner_receiver, 0, 10, 0);
             assert_eq!(inner_receiver.state, State::WaitingForFirstData);
+
+            //the elements are written in the order they were sent
+            let element_length = bip_utils::get_element_length(&mut reader);
+            assert_eq!(element_length, 10);
+            reader.consume(element_length);
+            assert_eq!(bip_utils::get_element_length(&mut reader), 20);
+        }
+
+        #[test]
+        fn update_state_interleaved_oversized_test() {
+            let (mut inner_receiver, mut reader, _) = create_inner_receiver();
+            //an element of u16::MAX messages is not allocated, it does not fit in the bip_buffer
+            receive_interleaved_message(
+                &mut inner_receiver,
+                0,
+                MAX_INTERLEAVED_DATA_SIZE_BYTES,
+                u16::MAX as usize,
+            );
+            assert_eq!(inner_receiver.stats_data.oversized_packets.load(), 1);
+            receive_interleaved_message(&mut inner_receiver, 1, 20, 0);
+            assert_eq!(inner_receiver.state, State::WaitingForInterleavedData(2));
+            assert_eq!(reader.valid().len(), 0);
         }
     }
 }
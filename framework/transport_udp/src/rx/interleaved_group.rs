@@ -0,0 +1,202 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::interleave::InterleaveHeader;
+use framework_constants::*;
+
+///An element of an interleaved group that is being reassembled.
+struct PartialElement {
+    buffer: Vec<u8>,
+    received: Vec<bool>,
+    received_count: usize,
+    length: usize,
+}
+
+impl PartialElement {
+    fn new(total_messages: usize) -> PartialElement {
+        PartialElement {
+            buffer: vec![0; total_messages * MAX_INTERLEAVED_DATA_SIZE_BYTES],
+            received: vec![false; total_messages],
+            received_count: 0,
+            length: 0,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.received_count == self.received.len()
+    }
+}
+
+///The InterleavedGroup is used by the InnerUdpReceiver to reassemble the elements of an interleaved group concurrently.
+///The messages of an element can arrive in any order, the size of an element is known from its InterleavedFirst message.
+pub struct InterleavedGroup {
+    group_id: u32,
+    elements: Vec<Option<PartialElement>>,
+    ingress_timestamp: Option<u64>,
+}
+
+impl InterleavedGroup {
+    ///Creates a new InterleavedGroup.
+    /// # Arguments
+    /// * `group_id` - The element id of the first element in the group.
+    /// * `group_size` - The amount of elements in the group.
+    /// * `ingress_timestamp` - The ingress timestamp that was received for this group.
+    /// # Returns
+    /// `InterleavedGroup`
+    pub fn new(
+        group_id: u32,
+        group_size: usize,
+        ingress_timestamp: Option<u64>,
+    ) -> InterleavedGroup {
+        let mut elements = Vec::with_capacity(group_size);
+        elements.resize_with(group_size, || None);
+        InterleavedGroup {
+            group_id,
+            elements,
+            ingress_timestamp,
+        }
+    }
+
+    ///Returns the element id of the first element in the group.
+    pub fn group_id(&self) -> u32 {
+        self.group_id
+    }
+
+    ///Returns the amount of elements in the group.
+    pub fn group_size(&self) -> usize {
+        self.elements.len()
+    }
+
+    ///Returns the ingress timestamp that was received for this group.
+    pub fn ingress_timestamp(&self) -> Option<u64> {
+        self.ingress_timestamp
+    }
+
+    ///This function is used to add the data of an interleaved message to its element.
+    /// # Arguments
+    /// * `header` - The interleave header of the message.
+    /// * `message_type` - The MessageType of the message, InterleavedFirst starts an element.
+    /// * `remaining_messages` - The amount of messages of the element that follow this message.
+    /// * `data` - The data of the message, without the interleave header.
+    /// # Returns
+    /// `bool` - False when the message does not fit in its element and is discarded.
+    pub fn add_message(
+        &mut self,
+        header: &InterleaveHeader,
+        message_type: MessageType,
+        remaining_messages: usize,
+        data: &[u8],
+    ) -> bool {
+        let slot = match self.elements.get_mut(header.group_index as usize) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        if let (MessageType::InterleavedFirst, None) = (message_type, &slot) {
+            *slot = Some(PartialElement::new(remaining_messages + 1));
+        }
+        //without its InterleavedFirst message the size of the element is unknown.
+        let element = match slot.as_mut() {
+            Some(element) => element,
+            None => return false,
+        };
+        let total_messages = element.received.len();
+        //only the last message of an element can contain less than MAX_INTERLEAVED_DATA_SIZE_BYTES.
+        if remaining_messages >= total_messages
+            || data.len() > MAX_INTERLEAVED_DATA_SIZE_BYTES
+            || (remaining_messages > 0 && data.len() != MAX_INTERLEAVED_DATA_SIZE_BYTES)
+        {
+            return false;
+        }
+        let position = total_messages - 1 - remaining_messages;
+        if element.received[position] {
+            //duplicate message
+            return true;
+        }
+        let offset = position * MAX_INTERLEAVED_DATA_SIZE_BYTES;
+        element.buffer[offset..offset + data.len()].copy_from_slice(data);
+        element.received[position] = true;
+        element.received_count += 1;
+        if remaining_messages == 0 {
+            element.length = offset + data.len();
+        }
+        true
+    }
+
+    ///Returns true when every element of the group is complete.
+    pub fn is_complete(&self) -> bool {
+        self.elements.iter().all(|element| match element {
+            Some(element) => element.is_complete(),
+            None => false,
+        })
+    }
+
+    ///Returns the elements of the group in order, None for every element that is not complete.
+    pub fn into_elements(self) -> Vec<Option<Vec<u8>>> {
+        self.elements
+            .into_iter()
+            .map(|element| match element {
+                Some(mut element) if element.is_complete() => {
+                    element.buffer.truncate(element.length);
+                    Some(element.buffer)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(group_index: u16) -> InterleaveHeader {
+        InterleaveHeader {
+            element_id: 10 + group_index as u32,
+            group_index,
+            group_size: 2,
+        }
+    }
+
+    #[test]
+    fn interleaved_group_test() {
+        let full_data = vec![1; MAX_INTERLEAVED_DATA_SIZE_BYTES];
+        let mut group = InterleavedGroup::new(10, 2, None);
+        //element 0 consists of 2 messages, element 1 of 1 message
+        assert!(group.add_message(&header(0), MessageType::InterleavedFirst, 1, &full_data));
+        assert!(group.add_message(&header(1), MessageType::InterleavedFirst, 0, &[2; 10]));
+        assert!(!group.is_complete());
+        assert!(group.add_message(&header(0), MessageType::Interleaved, 0, &[3; 5]));
+        assert!(group.is_complete());
+
+        let elements = group.into_elements();
+        let first = elements[0].as_ref().expect("element 0 should be complete");
+        assert_eq!(first.len(), MAX_INTERLEAVED_DATA_SIZE_BYTES + 5);
+        assert_eq!(first[MAX_INTERLEAVED_DATA_SIZE_BYTES], 3);
+        assert_eq!(elements[1], Some(vec![2; 10]));
+    }
+
+    #[test]
+    fn interleaved_group_loss_test() {
+        let mut group = InterleavedGroup::new(10, 2, None);
+        //the InterleavedFirst message of element 0 is lost
+        assert!(!group.add_message(&header(0), MessageType::Interleaved, 0, &[3; 5]));
+        assert!(group.add_message(&header(1), MessageType::InterleavedFirst, 0, &[2; 10]));
+        //an element that is not the last message should be full
+        assert!(!group.add_message(&header(1), MessageType::InterleavedFirst, 1, &[2; 10]));
+
+        let elements = group.into_elements();
+        assert_eq!(elements[0], None);
+        assert_eq!(elements[1], Some(vec![2; 10]));
+    }
+}
@@ -12,8 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::BipBufferWriter;
 use framework_constants::*;
-use spsc_bip_buffer::BipBufferWriter;
 use statistics_handler::StatsAllHandlers;
 use std::net::UdpSocket;
 use std::sync::Arc;
@@ -27,6 +27,9 @@ pub mod rx_arguments;
 ///This module contains the inner_udp_receiver struct.
 pub mod inner_udp_receiver;
 
+///This module contains the InterleavedGroup used to reassemble interleaved elements.
+pub mod interleaved_group;
+
 ///This function is used to receive a UDP packet on the given socket.
 ///The received data is placed in the supplied buffer.
 /// # Arguments
@@ -114,7 +117,7 @@ pub fn check_for_packetloss(
 }
 
 ///This struct is used to store all header information of a UDP packet.
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub struct PacketData {
     message_type: MessageType,
     sequence_number: u32,
@@ -12,6 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::overflow::OverflowPolicy;
+use bip_utils::wakeup::WaitStrategy;
+use handler_schema::{Argument, ArgumentType, HandlerSchema, Role};
+use socket_utils::broadcast_socket_writer::LagPolicy;
+use socket_utils::buffered_socket_writer::InFlightPolicy;
+use socket_utils::link::LinkType;
 use structopt::StructOpt;
 
 ///This struct contains all structopt definitions used by the UdpReceiver.
@@ -54,6 +60,38 @@ pub struct OptReceiver {
     #[structopt(long = "bip_buffer_element_count", default_value = "10")]
     pub bip_buffer_element_count: usize,
 
+    ///How threads wait for data or space in the bip buffer: spin, park or adaptive.
+    #[structopt(long = "wait_strategy", default_value = "adaptive")]
+    pub wait_strategy: WaitStrategy,
+
+    ///The kind of link to the adjacent handlers in the chain: socket or shared_memory.
+    #[structopt(long = "link", default_value = "socket")]
+    pub link: LinkType,
+
+    ///Policy used when the bip buffer is full, can be block, drop_newest or drop_oldest.
+    #[structopt(long = "overflow_policy", default_value = "drop_newest")]
+    pub overflow_policy: OverflowPolicy,
+
+    ///The maximum time in milliseconds the drop_oldest policy waits for space in the bip buffer.
+    #[structopt(long = "overflow_wait_ms", default_value = "100")]
+    pub overflow_wait_ms: u64,
+
+    ///What happens to the element being sent when the next handler is lost: keep or discard.
+    #[structopt(long = "in_flight_policy", default_value = "keep")]
+    pub in_flight_policy: InFlightPolicy,
+
+    ///An address monitoring or archival consumers can connect to, they receive every element sent to the next handler.
+    #[structopt(long = "tap_address")]
+    pub tap_address: Option<String>,
+
+    ///What happens when a consumer of the tap falls behind: block, drop or disconnect.
+    #[structopt(long = "tap_lag_policy", default_value = "drop")]
+    pub tap_lag_policy: LagPolicy,
+
+    ///The amount of elements a consumer of the tap can fall behind before the tap_lag_policy is applied.
+    #[structopt(long = "tap_max_lag", default_value = "128")]
+    pub tap_max_lag: usize,
+
     ///From syslog server host
     #[structopt(long = "from_host_sys_log", default_value = "0.0.0.0")]
     pub from_host_sys_log: String,
@@ -79,6 +117,72 @@ pub struct OptReceiver {
     pub handler_name: String,
 }
 
+///The arguments of transport_udp_receive, printed with `--describe`.
+pub const RECEIVER_SCHEMA: HandlerSchema = HandlerSchema {
+    role: Role::Transport,
+    arguments: &[
+        Argument::new("socket_path", ArgumentType::String)
+            .default("/tmp/transport_to_handler")
+            .help("Location of the socket"),
+        Argument::new("receiver_address", ArgumentType::String)
+            .default("192.168.0.1")
+            .help("Address the receiver is hosted on"),
+        Argument::new("receiver_port", ArgumentType::Integer)
+            .default("1234")
+            .help("Port the receiver should be listening on"),
+        Argument::new("stats_server_address", ArgumentType::String)
+            .default("10.0.0.2")
+            .help("The address of the stats server"),
+        Argument::new("stats_server_port", ArgumentType::Integer)
+            .default("8125")
+            .help("The port of the stats server"),
+        Argument::new("bip_buffer_element_count", ArgumentType::Integer)
+            .default("10")
+            .help("The maximum amount of elements the bip buffer can store. The size of a single element is 1Mb"),
+        Argument::new("wait_strategy", ArgumentType::String)
+            .default("adaptive")
+            .help("How threads wait for data or space in the bip buffer: spin, park or adaptive"),
+        Argument::new("link", ArgumentType::String)
+            .default("socket")
+            .help("The kind of link to the adjacent handlers in the chain: socket or shared_memory"),
+        Argument::new("overflow_policy", ArgumentType::String)
+            .default("drop_newest")
+            .help("Policy used when the bip buffer is full, can be block, drop_newest or drop_oldest"),
+        Argument::new("overflow_wait_ms", ArgumentType::Integer)
+            .default("100")
+            .help("The maximum time in milliseconds the drop_oldest policy waits for space in the bip buffer"),
+        Argument::new("in_flight_policy", ArgumentType::String)
+            .default("keep")
+            .help("What happens to the element being sent when the next handler is lost: keep or discard"),
+        Argument::new("tap_address", ArgumentType::String)
+            .help("An address monitoring or archival consumers can connect to, they receive every element sent to the next handler"),
+        Argument::new("tap_lag_policy", ArgumentType::String)
+            .default("drop")
+            .help("What happens when a consumer of the tap falls behind: block, drop or disconnect"),
+        Argument::new("tap_max_lag", ArgumentType::Integer)
+            .default("128")
+            .help("The amount of elements a consumer of the tap can fall behind before the tap_lag_policy is applied"),
+        Argument::new("from_host_sys_log", ArgumentType::String)
+            .default("0.0.0.0")
+            .help("From syslog server host"),
+        Argument::new("from_port_sys_log", ArgumentType::Integer)
+            .default("8342")
+            .help("From syslog server port"),
+        Argument::new("to_host_sys_log", ArgumentType::String)
+            .default("127.0.0.1")
+            .help("To syslog udp host"),
+        Argument::new("to_port_sys_log", ArgumentType::Integer)
+            .default("8082")
+            .help("To syslog udp port"),
+        Argument::new("log_level", ArgumentType::String)
+            .default("Warn")
+            .help("Log level for logging"),
+        Argument::new("handler_name", ArgumentType::String)
+            .default("transport_udp_receive")
+            .help("Name of the handler"),
+    ],
+};
+
 impl OptReceiver {
     ///This function is used to log the complete configuration of the UdpReceiver.
     pub fn log_config_info(&self) {
@@ -92,6 +196,9 @@ impl OptReceiver {
             format!("{}:{}", &self.host_stats_server, &self.port_stats_server)
         );
         log::info!("Sharing data over socket at {}\r\n", &self.socket_path);
+        if let Some(tap_address) = &self.tap_address {
+            log::info!("Tapping data at {}\r\n", tap_address);
+        }
         log::info!(
             "Using syslog for logging at {}\r\n",
             format!("{}:{}", &self.from_host_sys_log, &self.from_port_sys_log)
@@ -100,3 +207,14 @@ impl OptReceiver {
         log::info!("---------------------------------------\r\n\r\n");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use handler_schema::check_schema;
+
+    #[test]
+    fn schema_test() {
+        assert_eq!(check_schema(OptReceiver::clap(), &RECEIVER_SCHEMA), Ok(()));
+    }
+}
@@ -15,6 +15,7 @@
 use crate::errors::Result;
 use crate::rx::inner_udp_receiver::InnerUdpReceiver;
 use crate::rx::*;
+use bip_utils::overflow::BufferMonitor;
 use statistics_handler::StatsAllHandlers;
 use std::net::UdpSocket;
 use std::sync::Arc;
@@ -33,16 +34,18 @@ impl UdpReceiver {
     ///The joinhandle to this struct is returned by the run function.
     /// # Arguments
     /// * `bip_writer` - BipBufferWriter used by the InnerUdpReceiver.
+    /// * `monitor` - The BufferMonitor used to apply the overflow policy of the bip_buffer.
     /// * `stats_data` - The struct used to store statistics data.
     /// # Returns
     /// `JoinHandle<()>` - The joinhandle to the InnerUdpReceiver.
     pub fn run(
         &self,
         bip_writer: BipBufferWriter,
+        monitor: Arc<BufferMonitor>,
         stats_data: Arc<StatsAllHandlers>,
     ) -> Result<()> {
         let socket = self.socket.try_clone()?;
-        let inner_udp_receiver = InnerUdpReceiver::new(socket, bip_writer, stats_data);
+        let inner_udp_receiver = InnerUdpReceiver::new(socket, bip_writer, monitor, stats_data);
         inner_udp_receiver.run();
         Ok(())
     }
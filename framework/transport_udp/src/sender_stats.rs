@@ -0,0 +1,186 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use framework_constants::SENDER_STATS_SIZE_BYTES;
+use statistics_handler::StatsAllHandlers;
+use std::convert::TryInto;
+
+///The cumulative totals of everything the UdpSender has sent since its last startup.
+///The sender sends them through the diode in a SenderStats message, the receiver counts the same totals for what arrived.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SenderTotals {
+    ///Packets that used a sequence number, including Timestamp and SenderStats messages.
+    pub packets: u64,
+    ///Complete elements.
+    pub elements: u64,
+    ///Bytes of the complete elements, without the bip_buffer length field.
+    pub bytes: u64,
+}
+
+impl SenderTotals {
+    ///Serializes the totals into the payload of a SenderStats message.
+    pub fn to_bytes(&self) -> [u8; SENDER_STATS_SIZE_BYTES] {
+        let mut buffer = [0; SENDER_STATS_SIZE_BYTES];
+        buffer[0..8].copy_from_slice(&self.packets.to_le_bytes());
+        buffer[8..16].copy_from_slice(&self.elements.to_le_bytes());
+        buffer[16..24].copy_from_slice(&self.bytes.to_le_bytes());
+        buffer
+    }
+
+    ///Deserializes the totals from the payload of a SenderStats message.
+    /// # Arguments
+    /// * `buffer` - The payload of the message, should contain at least SENDER_STATS_SIZE_BYTES.
+    pub fn from_bytes(buffer: &[u8]) -> SenderTotals {
+        let read_u64 = |start: usize| {
+            u64::from_le_bytes(
+                buffer[start..start + 8]
+                    .try_into()
+                    .expect("slice with incorrect length"),
+            )
+        };
+        SenderTotals {
+            packets: read_u64(0),
+            elements: read_u64(8),
+            bytes: read_u64(16),
+        }
+    }
+
+    fn saturating_sub(&self, other: &SenderTotals) -> SenderTotals {
+        SenderTotals {
+            packets: self.packets.saturating_sub(other.packets),
+            elements: self.elements.saturating_sub(other.elements),
+            bytes: self.bytes.saturating_sub(other.bytes),
+        }
+    }
+}
+
+///Reconciles the totals reported by the sender with the totals counted by the receiver.
+///Packets are sent in order, so all data counted in a report has either arrived or is lost when the report arrives.
+#[derive(Debug, Default)]
+pub struct LossReconciler {
+    ///The totals counted by the receiver, updated by the InnerUdpReceiver.
+    pub received: SenderTotals,
+    last_sent: Option<SenderTotals>,
+    last_received: SenderTotals,
+}
+
+impl LossReconciler {
+    ///Reconciles a report of the sender with everything received since the previous report.
+    ///The first report after a (re)start of either side is only used as the starting point.
+    /// # Arguments
+    /// * `sent` - The totals reported by the sender.
+    /// * `stats_data` - The struct used to store statistics data.
+    /// # Returns
+    /// `Option<SenderTotals>` - The data lost since the previous report, None when there was nothing to compare with.
+    pub fn reconcile(
+        &mut self,
+        sent: SenderTotals,
+        stats_data: &StatsAllHandlers,
+    ) -> Option<SenderTotals> {
+        let lost = match self.last_sent {
+            //the sender restarted without its startup messages arriving.
+            Some(last_sent) if sent.packets < last_sent.packets => None,
+            Some(last_sent) => {
+                let sent_interval = sent.saturating_sub(&last_sent);
+                let received_interval = self.received.saturating_sub(&self.last_received);
+                let lost = sent_interval.saturating_sub(&received_interval);
+                stats_data.loss.elements_lost.add(lost.elements);
+                stats_data.loss.bytes_lost.add(lost.bytes);
+                if sent_interval.bytes > 0 {
+                    stats_data.loss.set_transfer_efficiency(
+                        (sent_interval.bytes - lost.bytes) as f64 * 100.0
+                            / sent_interval.bytes as f64,
+                    );
+                }
+                if lost.elements > 0 {
+                    log::warn!(
+                        "Sender reported {} elements that did not arrive, {} bytes lost",
+                        lost.elements,
+                        lost.bytes
+                    );
+                }
+                Some(lost)
+            }
+            None => None,
+        };
+        self.last_sent = Some(sent);
+        self.last_received = self.received;
+        lost
+    }
+
+    ///Forgets the previous report, used when the sender (re)starts.
+    pub fn reset(&mut self) {
+        self.last_sent = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialization_test() {
+        let totals = SenderTotals {
+            packets: 12,
+            elements: 3,
+            bytes: u64::MAX,
+        };
+        assert_eq!(SenderTotals::from_bytes(&totals.to_bytes()), totals);
+    }
+
+    #[test]
+    fn reconcile_test() {
+        let stats_data = StatsAllHandlers::default();
+        let mut reconciler = LossReconciler::default();
+        //the first report is only used as the starting point
+        let mut sent = SenderTotals {
+            packets: 10,
+            elements: 5,
+            bytes: 500,
+        };
+        reconciler.received = SenderTotals {
+            packets: 4,
+            elements: 2,
+            bytes: 200,
+        };
+        assert_eq!(reconciler.reconcile(sent, &stats_data), None);
+
+        //one element of 100 bytes (2 packets) is lost in the next interval
+        sent.packets += 6;
+        sent.elements += 3;
+        sent.bytes += 300;
+        reconciler.received.packets += 4;
+        reconciler.received.elements += 2;
+        reconciler.received.bytes += 200;
+        let lost = reconciler.reconcile(sent, &stats_data);
+        assert_eq!(
+            lost,
+            Some(SenderTotals {
+                packets: 2,
+                elements: 1,
+                bytes: 100
+            })
+        );
+        assert_eq!(stats_data.loss.elements_lost.load(), 1);
+        assert_eq!(stats_data.loss.bytes_lost.load(), 100);
+
+        //a restarted sender starts counting from 0 again
+        sent = SenderTotals {
+            packets: 1,
+            elements: 0,
+            bytes: 0,
+        };
+        assert_eq!(reconciler.reconcile(sent, &stats_data), None);
+    }
+}
@@ -12,28 +12,46 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::interleave::InterleaveHeader;
 use crate::tx::send_data;
+use crate::tx::special_message::send_timestamp_message;
 use crate::tx::write_packet_header;
 use bip_utils::get_element_length;
 use bip_utils::wait_for_data;
+use bip_utils::BipBufferReader;
 use framework_constants::*;
-use spsc_bip_buffer::BipBufferReader;
+use socket_utils::frame::Frame;
+use statistics_handler::latency::now_micros;
 use statistics_handler::*;
+use std::convert::TryInto;
 use std::net::UdpSocket;
 use std::sync::Arc;
 
 ///This function is used to split the data read from a bip_buffer.
 ///The data is split into packets that can be sent over UDP(payload < 65507 bytes)
+///When `timestamp_elements` is set, a Timestamp message with the ingress timestamp of the frame is sent in front of every element.
+/// # Returns
+/// `usize` - The length of the element that was sent.
 pub fn split_and_send_data(
     socket: &UdpSocket,
     reader: &mut BipBufferReader,
     sequence_number: &mut u32,
     send_delay_ms: u64,
+    timestamp_elements: bool,
     stats_data: Arc<StatsAllHandlers>,
-) {
+) -> usize {
     let element_length = get_element_length(reader);
     stats_data.in_bytes.add(element_length as u64);
     wait_for_data(reader, element_length);
+    if timestamp_elements {
+        let ingress_timestamp = ingress_timestamp(&reader.valid()[..element_length]);
+        send_timestamp_message(
+            socket,
+            sequence_number,
+            ingress_timestamp,
+            stats_data.clone(),
+        );
+    }
     let element_buffer = &mut reader.valid()[..element_length];
     let mut remaining_messages: u16 = (element_length / MAX_PAYLOAD_SIZE_BYTES) as u16;
     let bytes_remaining = element_length % MAX_PAYLOAD_SIZE_BYTES;
@@ -49,6 +67,7 @@ pub fn split_and_send_data(
         stats_data,
     );
     reader.consume(element_length);
+    element_length
 }
 
 fn split_and_send_full_messages(
@@ -110,3 +129,148 @@ fn split_and_send_full_messages(
         );
     }
 }
+
+///This function is used to interleave the messages of up to `interleave_depth` elements read from a bip_buffer.
+///Only elements that are already available in the bip_buffer are added to the group, so at least one element is sent.
+///The first message of every element is sent, then the second message of every element and so on.
+///A burst of lost packets now damages a small part of many elements instead of all of one.
+///When `timestamp_elements` is set, the ingress timestamp of the first element is sent in front of the group.
+/// # Returns
+/// `(usize, usize)` - The amount of elements sent and the total length of these elements.
+#[allow(clippy::too_many_arguments)]
+pub fn interleave_and_send_data(
+    socket: &UdpSocket,
+    reader: &mut BipBufferReader,
+    sequence_number: &mut u32,
+    element_id: &mut u32,
+    send_delay_ms: u64,
+    interleave_depth: usize,
+    timestamp_elements: bool,
+    stats_data: Arc<StatsAllHandlers>,
+) -> (usize, usize) {
+    //wait for the first element, the length field is only consumed when the group is sent.
+    wait_for_data(reader, BIP_BUFFER_LEN_FIELD_LEN);
+    let first_element_length = read_length_field(reader.valid());
+    wait_for_data(reader, BIP_BUFFER_LEN_FIELD_LEN + first_element_length);
+
+    //find the start and length of every element in the group.
+    let available = reader.valid();
+    let mut elements: Vec<(usize, usize)> = Vec::with_capacity(interleave_depth);
+    let mut offset = 0;
+    while elements.len() < interleave_depth && offset + BIP_BUFFER_LEN_FIELD_LEN <= available.len()
+    {
+        let element_length = read_length_field(&available[offset..]);
+        let start_index = offset + BIP_BUFFER_LEN_FIELD_LEN;
+        if start_index + element_length > available.len() {
+            break;
+        }
+        elements.push((start_index, element_length));
+        offset = start_index + element_length;
+    }
+    let group_bytes: usize = elements.iter().map(|element| element.1).sum();
+    stats_data.in_bytes.add(group_bytes as u64);
+    if timestamp_elements {
+        let (start_index, element_length) = elements[0];
+        send_timestamp_message(
+            socket,
+            sequence_number,
+            ingress_timestamp(&available[start_index..start_index + element_length]),
+            stats_data.clone(),
+        );
+    }
+
+    let message_counts: Vec<usize> = elements
+        .iter()
+        .map(|element| interleaved_message_count(element.1))
+        .collect();
+    let max_message_count = message_counts.iter().cloned().max().unwrap_or(0);
+    let mut message_buffer = [0; MAX_BUFFER_SIZE_BYTES];
+    let data_start = HEADER_SIZE_BYTES + INTERLEAVE_HEADER_SIZE_BYTES;
+    for message_index in 0..max_message_count {
+        for (group_index, (start_index, element_length)) in elements.iter().enumerate() {
+            if message_index >= message_counts[group_index] {
+                continue;
+            }
+            let data_offset = message_index * MAX_INTERLEAVED_DATA_SIZE_BYTES;
+            let data_length = std::cmp::min(
+                MAX_INTERLEAVED_DATA_SIZE_BYTES,
+                element_length - data_offset,
+            );
+            let data =
+                &available[start_index + data_offset..start_index + data_offset + data_length];
+            message_buffer[data_start..data_start + data_length].copy_from_slice(data);
+            InterleaveHeader {
+                element_id: element_id.wrapping_add(group_index as u32),
+                group_index: group_index as u16,
+                group_size: elements.len() as u16,
+            }
+            .write(&mut message_buffer[HEADER_SIZE_BYTES..data_start]);
+            let message_type = if message_index == 0 {
+                MessageType::InterleavedFirst
+            } else {
+                MessageType::Interleaved
+            };
+            //write_packet_header decrements the remaining messages before writing them.
+            let remaining_messages = message_counts[group_index] - message_index;
+            write_packet_header(
+                &mut message_buffer[..data_start + data_length],
+                *sequence_number,
+                message_type.as_u8(),
+                &mut (remaining_messages as u16),
+            );
+            send_data(
+                socket,
+                &mut message_buffer[..data_start + data_length],
+                sequence_number,
+                send_delay_ms,
+                stats_data.clone(),
+            );
+        }
+    }
+    reader.consume(offset);
+    *element_id = element_id.wrapping_add(elements.len() as u32);
+    (elements.len(), group_bytes)
+}
+
+///Returns the ingress timestamp in the metadata of the frame in `element`.
+///An element without one is stamped with the current time, so its latency across the diode is still measured.
+fn ingress_timestamp(element: &[u8]) -> u64 {
+    Frame::read(element)
+        .ok()
+        .and_then(|frame| frame.metadata.ingress_timestamp)
+        .unwrap_or_else(now_micros)
+}
+
+///Returns the amount of interleaved messages needed to send an element of `element_length` bytes.
+fn interleaved_message_count(element_length: usize) -> usize {
+    std::cmp::max(1, element_length.div_ceil(MAX_INTERLEAVED_DATA_SIZE_BYTES))
+}
+
+///Reads the bip_buffer length field at the start of the given buffer.
+fn read_length_field(buffer: &[u8]) -> usize {
+    usize::from_le_bytes(
+        buffer[..BIP_BUFFER_LEN_FIELD_LEN]
+            .try_into()
+            .expect("slice with incorrect length"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket_utils::frame::{encode_frame, Metadata};
+
+    #[test]
+    fn ingress_timestamp_test() {
+        let mut metadata = Metadata::for_handler("osdd.test.ingress.chain1.ph.udp1", "udp");
+        metadata.ingress_timestamp = Some(1_600_000_000_000_000);
+        let frame = encode_frame(&metadata, &[1; 10]).expect("Can't encode frame");
+        assert_eq!(ingress_timestamp(&frame), 1_600_000_000_000_000);
+
+        //a frame without an ingress timestamp is stamped when it is sent
+        let before = now_micros();
+        let frame = encode_frame(&Metadata::default(), &[1; 10]).expect("Can't encode frame");
+        assert!(ingress_timestamp(&frame) >= before);
+        assert!(ingress_timestamp(&[1; 10]) >= before);
+    }
+}
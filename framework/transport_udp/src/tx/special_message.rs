@@ -12,11 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::sender_stats::SenderTotals;
+use crate::tx::send_data;
 use crate::tx::write_packet_header;
 use framework_constants::MessageType;
 use framework_constants::HEADER_SIZE_BYTES;
+use framework_constants::SENDER_STATS_SIZE_BYTES;
 use framework_constants::SPECIAL_MESSAGE_COUNT;
+use framework_constants::TIMESTAMP_SIZE_BYTES;
+use statistics_handler::StatsAllHandlers;
 use std::net::UdpSocket;
+use std::sync::Arc;
 
 ///This function is used to send packets with MessageType::StartUp to the UdpReceiver.
 pub fn send_startup_messages(socket: &UdpSocket, sequence_number: &mut u32) {
@@ -43,6 +49,55 @@ pub fn send_shutdown_messages(socket: &UdpSocket) {
     }
 }
 
+///This function is used to send the ingress timestamp of the next element to the receiver.
+///The timestamp is sent as microseconds since the unix epoch, the message is not delayed.
+/// # Arguments
+/// * `socket` - The socket used to send the message.
+/// * `sequence_number` - The sequence number for this message, incremented when the message is sent.
+/// * `ingress_timestamp` - The time the next element entered the diode.
+/// * `stats_data` - The struct used to store statistics data.
+pub fn send_timestamp_message(
+    socket: &UdpSocket,
+    sequence_number: &mut u32,
+    ingress_timestamp: u64,
+    stats_data: Arc<StatsAllHandlers>,
+) {
+    let mut buf = [0; HEADER_SIZE_BYTES + TIMESTAMP_SIZE_BYTES];
+    buf[HEADER_SIZE_BYTES..].copy_from_slice(&ingress_timestamp.to_le_bytes());
+    write_packet_header(
+        &mut buf,
+        *sequence_number,
+        MessageType::Timestamp.as_u8(),
+        &mut 0,
+    );
+    send_data(socket, &mut buf, sequence_number, 0, stats_data);
+}
+
+///This function is used to report the totals of the sender to the receiver.
+///The SenderStats message itself is included in the reported packets.
+/// # Arguments
+/// * `socket` - The socket used to send the message.
+/// * `sequence_number` - The sequence number for this message, incremented when the message is sent.
+/// * `totals` - The totals of the sender since its last startup.
+/// * `stats_data` - The struct used to store statistics data.
+pub fn send_sender_stats_message(
+    socket: &UdpSocket,
+    sequence_number: &mut u32,
+    totals: &mut SenderTotals,
+    stats_data: Arc<StatsAllHandlers>,
+) {
+    totals.packets += 1;
+    let mut buf = [0; HEADER_SIZE_BYTES + SENDER_STATS_SIZE_BYTES];
+    buf[HEADER_SIZE_BYTES..].copy_from_slice(&totals.to_bytes());
+    write_packet_header(
+        &mut buf,
+        *sequence_number,
+        MessageType::SenderStats.as_u8(),
+        &mut 0,
+    );
+    send_data(socket, &mut buf, sequence_number, 0, stats_data);
+}
+
 ///This function is used to send packets with MessageType::HeartBeat to the UdpReceiver.
 fn _send_heartbeat_message(socket: &UdpSocket, sequence_number: u32) {
     log::info!("Heartbeat message has been sent.");
@@ -12,6 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::wakeup::WaitStrategy;
+use handler_schema::{Argument, ArgumentType, HandlerSchema, Role};
+use socket_utils::link::LinkType;
 use structopt::StructOpt;
 
 ///This struct contains all structopt definitions used by the UdpSender.
@@ -70,10 +73,30 @@ pub struct OptSender {
     ///The size of a single element is 1Mb.
     pub bip_buffer_element_count: usize,
 
+    ///How threads wait for data or space in the bip buffer: spin, park or adaptive.
+    #[structopt(long = "wait_strategy", default_value = "adaptive")]
+    pub wait_strategy: WaitStrategy,
+
+    ///The kind of link to the adjacent handlers in the chain: socket or shared_memory.
+    #[structopt(long = "link", default_value = "socket")]
+    pub link: LinkType,
+
     #[structopt(long = "send_delay_ms", default_value = "5")]
     ///Send delay in milliseconds used for every UDP message.
     pub send_delay_ms: u64,
 
+    #[structopt(
+        long = "timestamp_elements",
+        default_value = "false",
+        parse(try_from_str)
+    )]
+    ///Sends an ingress timestamp before every element, used by the receiver to measure latency and jitter.
+    pub timestamp_elements: bool,
+
+    #[structopt(long = "interleave_depth", default_value = "1")]
+    ///The amount of elements whose messages are interleaved, 1 disables interleaving.
+    pub interleave_depth: usize,
+
     #[structopt(long = "from_host_sys_log", default_value = "0.0.0.0")]
     ///From syslog server address
     pub from_host_sys_log: String,
@@ -99,6 +122,70 @@ pub struct OptSender {
     pub handler_name: String,
 }
 
+///The arguments of transport_udp_send, printed with `--describe`.
+pub const SENDER_SCHEMA: HandlerSchema = HandlerSchema {
+    role: Role::Transport,
+    arguments: &[
+        Argument::new("socket_path", ArgumentType::String)
+            .default("/tmp/handler_to_transport")
+            .help("Location of the socket"),
+        Argument::new("receiver_address", ArgumentType::String)
+            .default("192.168.0.2")
+            .help("Address the receiver is hosted on"),
+        Argument::new("receiver_port", ArgumentType::Integer)
+            .default("1234")
+            .help("Port the receiver should be listening on"),
+        Argument::new("sender_address", ArgumentType::String)
+            .default("192.168.0.1")
+            .help("Address the sender is hosted on"),
+        Argument::new("sender_port", ArgumentType::Integer)
+            .default("1234")
+            .help("Port the sender is listening on"),
+        Argument::new("stats_server_address", ArgumentType::String)
+            .default("10.0.0.1")
+            .help("The address of the stats server"),
+        Argument::new("stats_server_port", ArgumentType::Integer)
+            .default("8125")
+            .help("The port of the stats server"),
+        Argument::new("bip_buffer_element_count", ArgumentType::Integer)
+            .default("10")
+            .help("The maximum amount of elements the bip buffer can store. The size of a single element is 1Mb"),
+        Argument::new("wait_strategy", ArgumentType::String)
+            .default("adaptive")
+            .help("How threads wait for data or space in the bip buffer: spin, park or adaptive"),
+        Argument::new("link", ArgumentType::String)
+            .default("socket")
+            .help("The kind of link to the adjacent handlers in the chain: socket or shared_memory"),
+        Argument::new("send_delay_ms", ArgumentType::Integer)
+            .default("5")
+            .help("Send delay in milliseconds used for every UDP message"),
+        Argument::new("timestamp_elements", ArgumentType::Boolean)
+            .default("false")
+            .help("Sends an ingress timestamp before every element, used by the receiver to measure latency and jitter"),
+        Argument::new("interleave_depth", ArgumentType::Integer)
+            .default("1")
+            .help("The amount of elements whose messages are interleaved, 1 disables interleaving"),
+        Argument::new("from_host_sys_log", ArgumentType::String)
+            .default("0.0.0.0")
+            .help("From syslog server address"),
+        Argument::new("from_port_sys_log", ArgumentType::Integer)
+            .default("8343")
+            .help("From syslog server port"),
+        Argument::new("to_host_sys_log", ArgumentType::String)
+            .default("127.0.0.1")
+            .help("To syslog udp address"),
+        Argument::new("to_port_sys_log", ArgumentType::Integer)
+            .default("8082")
+            .help("To syslog udp port"),
+        Argument::new("log_level", ArgumentType::String)
+            .default("Warn")
+            .help("Log level for logging"),
+        Argument::new("handler_name", ArgumentType::String)
+            .default("transport_udp_send")
+            .help("Name of the handler"),
+    ],
+};
+
 impl OptSender {
     ///This function is used to log the complete configuration of the UdpSender.
     pub fn log_config_info(&self) {
@@ -120,3 +207,14 @@ impl OptSender {
         log::info!("---------------------------------------\r\n\r\n");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use handler_schema::check_schema;
+
+    #[test]
+    fn schema_test() {
+        assert_eq!(check_schema(OptSender::clap(), &SENDER_SCHEMA), Ok(()));
+    }
+}
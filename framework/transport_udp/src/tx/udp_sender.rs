@@ -12,11 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::errors::ErrorKind::UdpSocketError;
+use crate::errors::ErrorKind::{ArgumentError, UdpSocketError};
 use crate::errors::*;
-use crate::tx::message_split::split_and_send_data;
+use crate::sender_stats::SenderTotals;
+use crate::tx::message_split::{interleave_and_send_data, split_and_send_data};
 use crate::tx::special_message::*;
-use spsc_bip_buffer::BipBufferReader;
+use bip_utils::wait_for_data_timeout;
+use bip_utils::BipBufferReader;
+use framework_constants::{BIP_BUFFER_LEN_FIELD_LEN, MAX_INTERLEAVE_DEPTH};
 use statistics_handler::*;
 use std::net::UdpSocket;
 use std::sync::atomic::AtomicBool;
@@ -24,12 +27,21 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+///The interval at which the totals of the sender are reported to the receiver.
+const SENDER_STATS_INTERVAL: Duration = Duration::from_secs(1);
+///The maximum time the sender thread waits for an element, so it keeps reporting its totals while idle.
+const IDLE_WAIT: Duration = Duration::from_millis(100);
 
 pub struct UdpSender {
     socket: UdpSocket,
     reader: Arc<Mutex<BipBufferReader>>,
     should_stop: Arc<AtomicBool>,
     send_delay_ms: u64,
+    timestamp_elements: bool,
+    interleave_depth: usize,
     stats_data: Arc<StatsAllHandlers>,
 }
 
@@ -38,13 +50,24 @@ impl UdpSender {
         host: &str,
         reader: BipBufferReader,
         send_delay_ms: u64,
+        timestamp_elements: bool,
+        interleave_depth: usize,
         stats_data: Arc<StatsAllHandlers>,
     ) -> Result<UdpSender> {
+        if interleave_depth == 0 || interleave_depth > MAX_INTERLEAVE_DEPTH {
+            return Err(ArgumentError(format!(
+                "interleave_depth should be between 1 and {}, got {}",
+                MAX_INTERLEAVE_DEPTH, interleave_depth
+            ))
+            .into());
+        }
         Ok(UdpSender {
             socket: { init_socket(host)? },
             reader: Arc::new(Mutex::new(reader)),
             should_stop: Arc::new(AtomicBool::new(false)),
             send_delay_ms,
+            timestamp_elements,
+            interleave_depth,
             stats_data,
         })
     }
@@ -60,6 +83,8 @@ impl UdpSender {
         let should_stop = Arc::clone(&self.should_stop);
         let receiver_addr = String::from(rec_addr);
         let send_delay_ms = self.send_delay_ms;
+        let timestamp_elements = self.timestamp_elements;
+        let interleave_depth = self.interleave_depth;
         let stats_data = self.stats_data.clone();
         std::thread::Builder::new()
             .name("udp_sender_thread".into())
@@ -71,6 +96,8 @@ impl UdpSender {
                         should_stop,
                         reader_mutex,
                         send_delay_ms,
+                        timestamp_elements,
+                        interleave_depth,
                         stats_data,
                     )
                     .chain_err(|| "Error in udp_sender thread"),
@@ -100,12 +127,15 @@ fn init_socket(host: &str) -> Result<UdpSocket> {
     Ok(socket)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn udp_sender_thread(
     socket: UdpSocket,
     receiver_addr: String,
     should_stop: Arc<AtomicBool>,
     reader_mutex: Arc<Mutex<BipBufferReader>>,
     send_delay_ms: u64,
+    timestamp_elements: bool,
+    interleave_depth: usize,
     stats_data: Arc<StatsAllHandlers>,
 ) -> Result<()> {
     socket
@@ -113,15 +143,50 @@ pub fn udp_sender_thread(
         .chain_err(|| format!("Failed connect to socket address: {}", receiver_addr))?;
     let mut sequence_number: u32 = 0;
     send_startup_messages(&socket, &mut sequence_number);
+    let mut totals = SenderTotals::default();
+    let mut element_id: u32 = 0;
+    let mut last_report = Instant::now();
     while !(should_stop.load(Ordering::SeqCst)) {
+        //report the totals, also while idle so the receiver can reconcile the last elements.
+        if last_report.elapsed() >= SENDER_STATS_INTERVAL {
+            send_sender_stats_message(
+                &socket,
+                &mut sequence_number,
+                &mut totals,
+                stats_data.clone(),
+            );
+            last_report = Instant::now();
+        }
         let mut reader = reader_mutex.lock().expect("Error locking mutex");
-        split_and_send_data(
-            &socket,
-            &mut reader,
-            &mut sequence_number,
-            send_delay_ms,
-            stats_data.clone(),
-        );
+        if !wait_for_data_timeout(&mut reader, BIP_BUFFER_LEN_FIELD_LEN, IDLE_WAIT) {
+            continue;
+        }
+        let first_sequence_number = sequence_number;
+        let (elements, bytes) = if interleave_depth > 1 {
+            interleave_and_send_data(
+                &socket,
+                &mut reader,
+                &mut sequence_number,
+                &mut element_id,
+                send_delay_ms,
+                interleave_depth,
+                timestamp_elements,
+                stats_data.clone(),
+            )
+        } else {
+            let element_length = split_and_send_data(
+                &socket,
+                &mut reader,
+                &mut sequence_number,
+                send_delay_ms,
+                timestamp_elements,
+                stats_data.clone(),
+            );
+            (1, element_length)
+        };
+        totals.packets += sequence_number.wrapping_sub(first_sequence_number) as u64;
+        totals.elements += elements as u64;
+        totals.bytes += bytes as u64;
     }
     Ok(())
 }
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::overflow::*;
 use bip_utils::read_from_bip_buffer;
 use bip_utils::write_to_bip_buffer;
 use framework_constants::*;
@@ -25,23 +26,29 @@ fn send_message() {
     let sender_ip: &str = "0.0.0.0:9541";
     let receiver = UdpReceiver::new(receiver_ip).expect("Error creating receiver");
     let (receiver_writer, mut receiver_reader) =
-        spsc_bip_buffer::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+        bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
 
     //create statistics handler
     let statistics_client = StatsdClient::<StatsAllHandlers>::new_standard();
     let stats_data = statistics_client.data;
 
     let stats_data2 = stats_data.clone();
+    let monitor = std::sync::Arc::new(BufferMonitor::new(
+        OverflowPolicy::DropNewest,
+        std::time::Duration::from_millis(0),
+        MAX_BIP_BUFFER_MESSAGE_SIZE * 10,
+        stats_data.register_buffer("receiver_test"),
+    ));
 
     std::thread::spawn(move || {
         receiver
-            .run(receiver_writer, stats_data2)
+            .run(receiver_writer, monitor, stats_data2)
             .expect("error while running receiver");
     });
     //send over udp
     let (mut sender_writer, sender_reader) =
-        spsc_bip_buffer::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
-    let sender = UdpSender::new(sender_ip, sender_reader, 5, stats_data.clone())
+        bip_utils::bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * 10);
+    let sender = UdpSender::new(sender_ip, sender_reader, 5, false, 1, stats_data.clone())
         .expect("cant create udp sender");
     sender.run(receiver_ip).expect("error");
 
@@ -12,6 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::overflow::OverflowPolicy;
+use bip_utils::wakeup::WaitStrategy;
+use handler_schema::{Argument, ArgumentType, HandlerSchema, Role};
+use socket_utils::buffered_socket_writer::InFlightPolicy;
+use socket_utils::link::LinkType;
 use structopt::StructOpt;
 ///Commandline arguments used to run ph_kafka_ingress.
 #[derive(StructOpt)]
@@ -75,8 +80,92 @@ pub struct OptIngress {
     ///The size of a single element is 1Mb.
     #[structopt(long = "bip_buffer_element_count", default_value = "2")]
     pub bip_buffer_element_count: usize,
+
+    ///How threads wait for data or space in the bip buffer: spin, park or adaptive.
+    #[structopt(long = "wait_strategy", default_value = "adaptive")]
+    pub wait_strategy: WaitStrategy,
+
+    ///The kind of link to the adjacent handlers in the chain: socket or shared_memory.
+    #[structopt(long = "link", default_value = "socket")]
+    pub link: LinkType,
+
+    ///Policy used when the bip buffer is full, can be block, drop_newest or drop_oldest.
+    #[structopt(long = "overflow_policy", default_value = "block")]
+    pub overflow_policy: OverflowPolicy,
+
+    ///The maximum time in milliseconds the drop_oldest policy waits for space in the bip buffer.
+    #[structopt(long = "overflow_wait_ms", default_value = "100")]
+    pub overflow_wait_ms: u64,
+
+    ///What happens to the element being sent when the next handler is lost: keep or discard.
+    #[structopt(long = "in_flight_policy", default_value = "keep")]
+    pub in_flight_policy: InFlightPolicy,
 }
 
+///The arguments of ph_kafka_ingress, printed with `--describe`.
+pub const INGRESS_SCHEMA: HandlerSchema = HandlerSchema {
+    role: Role::Ingress,
+    arguments: &[
+        Argument::new("socket_path", ArgumentType::String)
+            .default("/tmp/handler_to_transport")
+            .help("Location of the socket"),
+        Argument::new("max_bytes_per_partition", ArgumentType::Integer)
+            .default("1000000")
+            .help("Max bytes per message settings for consumer"),
+        Argument::new("stats_server_address", ArgumentType::String)
+            .default("127.0.0.1")
+            .help("StatsD server host"),
+        Argument::new("stats_server_port", ArgumentType::Integer)
+            .default("8125")
+            .help("StatsD server port"),
+        Argument::new("topic_name", ArgumentType::String)
+            .default("TestTopic")
+            .help("Topic to read from the kafka server"),
+        Argument::new("host_kafka_server", ArgumentType::String)
+            .default("10.0.0.1")
+            .help("kafka server host"),
+        Argument::new("port_kafka_server", ArgumentType::Integer)
+            .default("9092")
+            .help("Kafka server port"),
+        Argument::new("from_host_sys_log", ArgumentType::String)
+            .default("0.0.0.0")
+            .help("From syslog server host"),
+        Argument::new("from_port_sys_log", ArgumentType::Integer)
+            .default("8127")
+            .help("From syslog server port"),
+        Argument::new("to_host_sys_log", ArgumentType::String)
+            .default("127.0.0.1")
+            .help("To syslog udp host"),
+        Argument::new("to_port_sys_log", ArgumentType::Integer)
+            .default("8082")
+            .help("To syslog udp port"),
+        Argument::new("log_level", ArgumentType::String)
+            .default("Warn")
+            .help("Log level for logging"),
+        Argument::new("handler_name", ArgumentType::String)
+            .default("ph_kafka_ingress")
+            .help("Name of the handler"),
+        Argument::new("bip_buffer_element_count", ArgumentType::Integer)
+            .default("2")
+            .help("The maximum amount of elements the bip buffer can store. The size of a single element is 1Mb"),
+        Argument::new("wait_strategy", ArgumentType::String)
+            .default("adaptive")
+            .help("How threads wait for data or space in the bip buffer: spin, park or adaptive"),
+        Argument::new("link", ArgumentType::String)
+            .default("socket")
+            .help("The kind of link to the adjacent handlers in the chain: socket or shared_memory"),
+        Argument::new("overflow_policy", ArgumentType::String)
+            .default("block")
+            .help("Policy used when the bip buffer is full, can be block, drop_newest or drop_oldest"),
+        Argument::new("overflow_wait_ms", ArgumentType::Integer)
+            .default("100")
+            .help("The maximum time in milliseconds the drop_oldest policy waits for space in the bip buffer"),
+        Argument::new("in_flight_policy", ArgumentType::String)
+            .default("keep")
+            .help("What happens to the element being sent when the next handler is lost: keep or discard"),
+    ],
+};
+
 ///Commandline arguments used to run ph_kafka_egress.
 #[derive(StructOpt)]
 pub struct OptEgress {
@@ -108,6 +197,14 @@ pub struct OptEgress {
     #[structopt(long = "bip_buffer_element_count", default_value = "10")]
     pub bip_buffer_element_count: usize,
 
+    ///How threads wait for data or space in the bip buffer: spin, park or adaptive.
+    #[structopt(long = "wait_strategy", default_value = "adaptive")]
+    pub wait_strategy: WaitStrategy,
+
+    ///The kind of link to the adjacent handlers in the chain: socket or shared_memory.
+    #[structopt(long = "link", default_value = "socket")]
+    pub link: LinkType,
+
     ///Topic to replace
     #[structopt(short, long = "in_replacement", default_value = "TestTopic")]
     //Use this command to replace a specific topic name. This is the inputlist
@@ -142,3 +239,70 @@ pub struct OptEgress {
     #[structopt(long = "handler_name", default_value = "ph_kafka_egress")]
     pub handler_name: String,
 }
+
+///The arguments of ph_kafka_egress, printed with `--describe`.
+pub const EGRESS_SCHEMA: HandlerSchema = HandlerSchema {
+    role: Role::Egress,
+    arguments: &[
+        Argument::new("socket_path", ArgumentType::String)
+            .default("/tmp/transport_to_handler")
+            .help("Location of the socket"),
+        Argument::new("stats_server_address", ArgumentType::String)
+            .default("localhost")
+            .help("StatsD server host"),
+        Argument::new("stats_server_port", ArgumentType::Integer)
+            .default("8125")
+            .help("StatsD server port"),
+        Argument::new("host_kafka_server", ArgumentType::String)
+            .default("10.0.0.2")
+            .help("kafka server host"),
+        Argument::new("port_kafka_server", ArgumentType::Integer)
+            .default("9092")
+            .help("Kafka server port"),
+        Argument::new("bip_buffer_element_count", ArgumentType::Integer)
+            .default("10")
+            .help("The maximum amount of elements the bip buffer can store. The size of a single element is 1Mb"),
+        Argument::new("wait_strategy", ArgumentType::String)
+            .default("adaptive")
+            .help("How threads wait for data or space in the bip buffer: spin, park or adaptive"),
+        Argument::new("link", ArgumentType::String)
+            .default("socket")
+            .help("The kind of link to the adjacent handlers in the chain: socket or shared_memory"),
+        Argument::new("in_replacement", ArgumentType::String)
+            .default("TestTopic")
+            .help("Topic to replace"),
+        Argument::new("out_replacement", ArgumentType::String)
+            .default("TestTopic2")
+            .help("replace topic"),
+        Argument::new("from_host_sys_log", ArgumentType::String)
+            .default("0.0.0.0")
+            .help("From syslog server host"),
+        Argument::new("from_port_sys_log", ArgumentType::Integer)
+            .default("8129")
+            .help("From syslog server port"),
+        Argument::new("to_host_sys_log", ArgumentType::String)
+            .default("127.0.0.1")
+            .help("To syslog udp host"),
+        Argument::new("to_port_sys_log", ArgumentType::Integer)
+            .default("8082")
+            .help("To syslog udp port"),
+        Argument::new("log_level", ArgumentType::String)
+            .default("Warn")
+            .help("Log level for logging"),
+        Argument::new("handler_name", ArgumentType::String)
+            .default("ph_kafka_egress")
+            .help("Name of the handler"),
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use handler_schema::check_schema;
+
+    #[test]
+    fn schema_test() {
+        assert_eq!(check_schema(OptIngress::clap(), &INGRESS_SCHEMA), Ok(()));
+        assert_eq!(check_schema(OptEgress::clap(), &EGRESS_SCHEMA), Ok(()));
+    }
+}
@@ -12,19 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::bip_buffer_len_for;
+use bip_utils::bip_buffer_with_len;
+use bip_utils::wakeup::set_wait_strategy;
+use handler_schema::describe_if_requested;
 use logging::set_syslog;
 use ph_kafka::errors::Result;
 use ph_kafka::errors::*;
 use ph_kafka::producer::EgressProducer;
 use ph_kafka::*;
-use socket_utils::buffered_socket_reader::*;
-use spsc_bip_buffer::*;
+use socket_utils::link::LinkReader;
 use statistics_handler::*;
 use std::process::Command;
 use std::thread;
 use structopt::StructOpt;
 
 fn main() {
+    describe_if_requested(&arguments::EGRESS_SCHEMA);
     std::panic::set_hook(Box::new(|panic_info| {
         if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
             log::error!("{}", s);
@@ -57,6 +61,7 @@ fn kafka_egress() -> Result<()> {
     )
     .chain_err(|| "Error initializing syslog")?;
     log::info!("start {}", &opt.handler_name);
+    set_wait_strategy(opt.wait_strategy);
 
     //[OSDD-46]
     Command::new("/bin/sh")
@@ -68,13 +73,15 @@ fn kafka_egress() -> Result<()> {
         .spawn()?;
 
     let stats_addr = format!("{}:{}", opt.host_stats_server, opt.port_stats_server);
-    let (bip_writer, mut bip_reader) =
-        bip_buffer_with_len(opt.bip_buffer_element_count * MAX_BIP_BUFFER_MESSAGE_SIZE);
-    let mut socket_reader = BufferedSocketReader::new(&opt.socket_path, bip_writer)
+    let bip_buffer_len =
+        bip_buffer_len_for(opt.bip_buffer_element_count, MAX_BIP_BUFFER_MESSAGE_SIZE);
+    let (bip_writer, mut bip_reader) = bip_buffer_with_len(bip_buffer_len);
+    let mut socket_reader = LinkReader::new(opt.link, &opt.socket_path, bip_writer, bip_buffer_len)
         .chain_err(|| "Error while create socket reader")?;
 
     //Start stats thread
     let stats: StatsdClient<StatsAllHandlers> = StatsdClient::<StatsAllHandlers>::new_standard();
+    socket_reader.set_stats_data(stats.get_data_clone());
     stats
         .run(stats_addr, opt.handler_name)
         .chain_err(|| "Error while running statitics")?;
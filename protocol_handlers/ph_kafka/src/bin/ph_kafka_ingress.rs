@@ -12,21 +12,29 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bip_utils::bip_buffer_len_for;
+use bip_utils::bip_buffer_with_len;
+use bip_utils::overflow::BufferMonitor;
+use bip_utils::wakeup::set_wait_strategy;
 use error_chain::ChainedError;
+use handler_schema::describe_if_requested;
 use logging::*;
 use ph_kafka::consumer::serialize_between_bip_buffers;
 use ph_kafka::consumer::IngressConsumer;
 use ph_kafka::errors::Result;
 use ph_kafka::errors::*;
 use ph_kafka::*;
-use socket_utils::buffered_socket_writer::*;
-use spsc_bip_buffer::bip_buffer_with_len;
+use socket_utils::frame::Metadata;
+use socket_utils::link::LinkWriter;
 use statistics_handler::*;
 use std::process::Command;
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use structopt::StructOpt;
 
 fn main() {
+    describe_if_requested(&arguments::INGRESS_SCHEMA);
     std::panic::set_hook(Box::new(|panic_info| {
         if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
             log::error!("{}", s);
@@ -56,6 +64,7 @@ fn outer_kafka_ingress() -> Result<()> {
     )
     .chain_err(|| "Error initializing syslog")?;
     log::info!("start {}", &opt.handler_name);
+    set_wait_strategy(opt.wait_strategy);
 
     //[OSDD-46]
     Command::new("/bin/sh")
@@ -80,13 +89,15 @@ fn outer_kafka_ingress() -> Result<()> {
 
 fn inner_kafka_ingress() -> Result<()> {
     let opt = arguments::OptIngress::from_args();
-    let mut socket_writer = BufferedSocketWriter::start_listening(&opt.socket_path)
+    let bip_buffer_len =
+        bip_buffer_len_for(opt.bip_buffer_element_count, MAX_BIP_BUFFER_MESSAGE_SIZE);
+    let mut socket_writer = LinkWriter::start(opt.link, &opt.socket_path, bip_buffer_len)
         .chain_err(|| "Error creating socket writer")?;
+    let (mut bip_writer_first, mut bip_reader_first) = bip_buffer_with_len(bip_buffer_len);
+    let (mut bip_writer_second, mut bip_reader_second) = bip_buffer_with_len(bip_buffer_len);
 
-    let (mut bip_writer_first, mut bip_reader_first) =
-        bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * opt.bip_buffer_element_count as usize);
-    let (mut bip_writer_second, mut bip_reader_second) =
-        bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * opt.bip_buffer_element_count as usize);
+    //Every message is sent in an envelope, in a frame that carries this metadata.
+    let mut metadata = Metadata::for_handler(&opt.handler_name, envelope::CONTENT_TYPE);
 
     //Start stats thread
     let stats_addr = format!("{}:{}", opt.host_stats_server, opt.port_stats_server);
@@ -96,6 +107,25 @@ fn inner_kafka_ingress() -> Result<()> {
         .run(stats_addr, opt.handler_name)
         .chain_err(|| "Error while running statitics")?;
 
+    //The monitors apply the overflow policy when writing to the bipbuffers.
+    let overflow_wait = Duration::from_millis(opt.overflow_wait_ms);
+    let monitor_first = Arc::new(BufferMonitor::new(
+        opt.overflow_policy,
+        overflow_wait,
+        bip_buffer_len,
+        stats.data.register_buffer("kafka_poll_to_serialize"),
+    ));
+    let monitor_second = Arc::new(BufferMonitor::new(
+        opt.overflow_policy,
+        overflow_wait,
+        bip_buffer_len,
+        stats.data.register_buffer("serialize_to_socket"),
+    ));
+    socket_writer.set_buffer_monitor(monitor_second.clone());
+    socket_writer.set_in_flight_policy(opt.in_flight_policy);
+    socket_writer.set_stats_data(stats.get_data_clone());
+    let monitor_first_clone = monitor_first.clone();
+
     //Create ingress_consumer
     let topicname = opt.topic_name;
     let mut ingress_consumer = IngressConsumer::new(
@@ -132,8 +162,11 @@ fn inner_kafka_ingress() -> Result<()> {
         .spawn(move || loop {
             serialize_between_bip_buffers(
                 &topicname,
+                &mut metadata,
                 &mut bip_reader_first,
                 &mut bip_writer_second,
+                &monitor_first,
+                &monitor_second,
             )
             .chain_err(|| "Error in thread serialize_between_bip_buffers")
             .chain_unwrap();
@@ -144,7 +177,7 @@ fn inner_kafka_ingress() -> Result<()> {
         .name("kafka_poll_bipwriter".into())
         .spawn(move || {
             ingress_consumer
-                .get_kafka_data_send_bip_buffer(&mut bip_writer_first)
+                .get_kafka_data_send_bip_buffer(&mut bip_writer_first, &monitor_first_clone)
                 .chain_err(|| "Error in thread get_kafka_data_send_bip_buffer")
                 .chain_unwrap();
         })?;
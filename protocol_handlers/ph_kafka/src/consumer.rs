@@ -15,21 +15,22 @@
 use crate::errors::ErrorKind::SendToKafka;
 use crate::errors::*;
 use crate::MAX_BIP_BUFFER_MESSAGE_SIZE;
-use bip_utils::read_from_bip_buffer;
-use bip_utils::write_to_bip_buffer;
-use error_chain::*;
+use crate::OFFSET_HEADER_NAME;
+use bip_utils::overflow::BufferMonitor;
+use bip_utils::BipBufferReader;
+use bip_utils::BipBufferWriter;
+use envelope::{Envelope, Protocol};
 use kafka::consumer::{Consumer, FetchOffset};
-use log::trace;
-use spsc_bip_buffer::BipBufferReader;
-use spsc_bip_buffer::BipBufferWriter;
+use log::{trace, warn};
+use socket_utils::frame::{frame_prefix_len, write_frame_prefix, Metadata, MAX_FRAME_PREFIX_LEN};
+use statistics_handler::latency::now_micros;
 use statistics_handler::*;
 use std::sync::Arc;
 
 use std::str;
 
-use crate::KafkaMessage;
-
 const OFFSET_HEADER: usize = 8;
+const KEY_LENGTH_HEADER: usize = 4;
 
 /// A struct with a Kafka consumer and settings read form the command line arguments
 pub struct IngressConsumer {
@@ -71,13 +72,15 @@ impl IngressConsumer {
     /// Calculcates message behind after every poll
     /// # Arguments
     /// * `bip_writer` - The BipBufferWriter used to send data to the BipBuffer.
+    /// * `monitor` - The BufferMonitor used to apply the overflow policy of the BipBuffer.
     pub fn get_kafka_data_send_bip_buffer(
         &mut self,
         bip_writer: &mut BipBufferWriter,
+        monitor: &BufferMonitor,
     ) -> Result<()> {
         let mut buf = [0; MAX_BIP_BUFFER_MESSAGE_SIZE];
         loop {
-            self.poll_kafka(&mut buf, bip_writer)?;
+            self.poll_kafka(&mut buf, bip_writer, monitor)?;
             //[OSDD-21]: At least once/At most once/exaclty once configureerbaar
             self.consumer.commit_consumed()?;
 
@@ -100,10 +103,12 @@ impl IngressConsumer {
     /// Calculcates message behind after every poll
     /// # Arguments
     /// * `bip_writer` - The BipBufferWriter used to send data to the BipBuffer.
+    /// * `monitor` - The BufferMonitor used to apply the overflow policy of the BipBuffer.
     fn poll_kafka(
         &mut self,
         buf: &mut [u8; MAX_BIP_BUFFER_MESSAGE_SIZE],
         bip_writer: &mut BipBufferWriter,
+        monitor: &BufferMonitor,
     ) -> Result<()> {
         match self.consumer.poll() {
             Ok(message_sets) => {
@@ -111,15 +116,32 @@ impl IngressConsumer {
                     for message in message_set.messages() {
                         let message_length = message.value.len();
                         let message_offset = message.offset;
-
-                        let offset_in_bytes: [u8; OFFSET_HEADER] = message_offset.to_be_bytes();
-                        //fill first 8 bytes with the ofsset
-                        buf[..OFFSET_HEADER].clone_from_slice(&offset_in_bytes[..OFFSET_HEADER]);
-                        //fill other bytes with message
-                        buf[OFFSET_HEADER..message_length + OFFSET_HEADER]
-                            .clone_from_slice(message.value);
-                        //send message to bipbuffer
-                        write_to_bip_buffer(bip_writer, &buf[..message_length + OFFSET_HEADER]);
+                        let key_length = message.key.len();
+                        let header_length = OFFSET_HEADER + KEY_LENGTH_HEADER + key_length;
+                        if header_length + message_length > buf.len() {
+                            warn!(
+                                "Dropped kafka message of {} bytes, it is too large",
+                                message_length
+                            );
+                            self.stats_data.dropped_packets.add(1);
+                            self.stats_data.dropped_bytes.add(message_length as u64);
+                        } else {
+                            //fill first 8 bytes with the ofsset
+                            buf[..OFFSET_HEADER].clone_from_slice(&message_offset.to_be_bytes());
+                            //followed by the length of the key and the key
+                            buf[OFFSET_HEADER..OFFSET_HEADER + KEY_LENGTH_HEADER]
+                                .clone_from_slice(&(key_length as u32).to_be_bytes());
+                            buf[OFFSET_HEADER + KEY_LENGTH_HEADER..header_length]
+                                .clone_from_slice(message.key);
+                            //fill other bytes with message
+                            buf[header_length..header_length + message_length]
+                                .clone_from_slice(message.value);
+                            //send message to bipbuffer
+                            if !monitor.write(bip_writer, &buf[..header_length + message_length]) {
+                                self.stats_data.dropped_packets.add(1);
+                                self.stats_data.dropped_bytes.add(message_length as u64);
+                            }
+                        }
 
                         match self
                             .consumer
@@ -140,30 +162,48 @@ impl IngressConsumer {
     }
 }
 
-///Read data from the bipbuffer, serialize_packet and send it to another bipbuffer
+///Read data from the bipbuffer, put it in an envelope and send it in a frame to another bipbuffer
 /// # Arguments
+/// * `topic` - The topic name that is written in the envelope.
+/// * `metadata` - The frame metadata, the ingress timestamp is set for every message.
 /// * `bip_reader` - The BipBufferWriter used to get data from the BipBuffer.
 /// * `bip_writer` - The BipBufferWriter used to send data to the BipBuffer.
+/// * `reader_monitor` - The BufferMonitor of the BipBuffer that is read from.
+/// * `writer_monitor` - The BufferMonitor of the BipBuffer that is written to.
 pub fn serialize_between_bip_buffers(
     topic: &str,
+    metadata: &mut Metadata,
     bip_reader: &mut BipBufferReader,
     bip_writer: &mut BipBufferWriter,
+    reader_monitor: &BufferMonitor,
+    writer_monitor: &BufferMonitor,
 ) -> Result<()> {
-    let mut buf: [u8; MAX_BIP_BUFFER_MESSAGE_SIZE] = [0; MAX_BIP_BUFFER_MESSAGE_SIZE];
-    let length = read_from_bip_buffer(bip_reader, &mut buf);
+    let mut buf: [u8; MAX_FRAME_PREFIX_LEN + MAX_BIP_BUFFER_MESSAGE_SIZE] =
+        [0; MAX_FRAME_PREFIX_LEN + MAX_BIP_BUFFER_MESSAGE_SIZE];
+    let length = reader_monitor.read(bip_reader, &mut buf);
     let mut offset_bytes: [u8; OFFSET_HEADER] = [0; OFFSET_HEADER];
-    for byte in offset_bytes.iter_mut() {
-        *byte = buf[*byte as usize];
+    offset_bytes.clone_from_slice(&buf[..OFFSET_HEADER]);
+    let mut key_length_bytes: [u8; KEY_LENGTH_HEADER] = [0; KEY_LENGTH_HEADER];
+    key_length_bytes.clone_from_slice(&buf[OFFSET_HEADER..OFFSET_HEADER + KEY_LENGTH_HEADER]);
+    let header_length =
+        OFFSET_HEADER + KEY_LENGTH_HEADER + u32::from_be_bytes(key_length_bytes) as usize;
+
+    let now = now_micros();
+    let mut envelope = Envelope::new(Protocol::Kafka, &buf[header_length..length]);
+    envelope.topic = Some(topic.to_string());
+    if header_length > OFFSET_HEADER + KEY_LENGTH_HEADER {
+        envelope.key = Some(buf[OFFSET_HEADER + KEY_LENGTH_HEADER..header_length].to_vec());
     }
-    let kafka_msg = KafkaMessage::new(
-        &buf[OFFSET_HEADER..length],
-        i64::from_be_bytes(offset_bytes),
-        &topic,
-    );
+    envelope.headers.push((
+        OFFSET_HEADER_NAME.to_string(),
+        i64::from_be_bytes(offset_bytes).to_string().into_bytes(),
+    ));
+    envelope.received_timestamp = now;
 
-    match kafka_msg.serialize_packet(&mut buf) {
-        Ok(length) => write_to_bip_buffer(bip_writer, &buf[..length as usize]),
-        Err(e) => bail!(e),
-    };
+    metadata.ingress_timestamp = Some(now);
+    let prefix_len = frame_prefix_len(metadata);
+    let length = envelope.serialize_packet(&mut buf[prefix_len..])?;
+    write_frame_prefix(&mut buf, metadata, length)?;
+    writer_monitor.write(bip_writer, &buf[..prefix_len + length]);
     Ok(())
 }
@@ -32,10 +32,13 @@ error_chain! {
     types {
         Error, ErrorKind, ResultExt, Result;
     }
+    links {
+        Envelope(envelope::errors::Error, envelope::errors::ErrorKind);
+    }
     foreign_links {
         KafkaError(kafka::Error);
         Io(::std::io::Error);
-        SerializePacket(bincode::Error);
+        SocketUtils(socket_utils::errors::Error);
     }
     errors {
         SendToKafka(t: String) {
@@ -12,9 +12,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bincode::{deserialize, serialize_into, serialized_size};
-//use bincode::{deserialize, serialize};
-use serde::{Deserialize, Serialize};
 /// Arguments for ph_kafka
 pub mod arguments;
 /// Implementation of Kafka consumer
@@ -23,44 +20,9 @@ pub mod consumer;
 pub mod errors;
 /// Implementation of Kafka producer
 pub mod producer;
-use crate::errors::*;
 
 /// The maximum size in bytes of a single bipbuffer message.
 pub const MAX_BIP_BUFFER_MESSAGE_SIZE: usize = 1_050_000;
 
-/// A struct for kafka message
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
-pub struct KafkaMessage {
-    /// The offset of the received message
-    pub offset: i64,
-    /// The message itself
-    pub payload: Vec<u8>,
-    /// The topic name of where the message came from
-    pub topic: String,
-}
-
-impl KafkaMessage {
-    pub fn new(payload: &[u8], offset: i64, topic: &str) -> KafkaMessage {
-        KafkaMessage {
-            offset,
-            payload: payload.to_vec(),
-            topic: topic.to_string(),
-        }
-    }
-    pub fn serialize_packet(&self, buf: &mut [u8]) -> Result<u64> {
-        match serialize_into(buf, self) {
-            Ok(_) => match serialized_size(self) {
-                Ok(v) => Ok(v),
-                Err(e) => Err(Error::with_chain(e, "Failed serializing KafkaMessage")),
-            },
-            Err(e) => Err(Error::with_chain(e, "Failed serializing KafkaMessage")),
-        }
-    }
-
-    pub fn deserialize_packet(buf: &[u8]) -> Result<KafkaMessage> {
-        match deserialize(buf) {
-            Ok(v) => Ok(v),
-            Err(e) => Err(Error::with_chain(e, "Failed deserializing KafkaMessage")),
-        }
-    }
-}
+/// The envelope header that holds the offset of the message in its Kafka partition.
+pub const OFFSET_HEADER_NAME: &str = "kafka.offset";
@@ -14,16 +14,17 @@
 
 use crate::errors::ErrorKind::*;
 use crate::errors::*;
-use crate::KafkaMessage;
-use bip_utils::read_from_bip_buffer;
+use bip_utils::read_fitting_from_bip_buffer;
+use bip_utils::BipBufferReader;
+use envelope::Envelope;
 use kafka::producer::{Producer, Record};
 use log::{info, warn};
-use spsc_bip_buffer::BipBufferReader;
+use socket_utils::frame::{Frame, MAX_FRAME_PREFIX_LEN};
 use statistics_handler::*;
 use std::str;
 use std::sync::Arc;
 
-const BUFFER_SIZE_BYTES: usize = 1_050_000;
+const BUFFER_SIZE_BYTES: usize = 1_050_000 + MAX_FRAME_PREFIX_LEN;
 
 /// A struct with a Kafka producer and settings read form the command line arguments
 pub struct EgressProducer {
@@ -57,6 +58,7 @@ impl EgressProducer {
     }
 
     ///Reads data from the bipbuffer and send it to kafka
+    ///The latency of every frame that carries an ingress timestamp is recorded when it is read.
     /// # Arguments
     /// * `bip_reader` - The BipBufferReader used to get data from the bip_buffer.
     pub fn get_data_from_bipbuffer_and_send_data_to_kafka(
@@ -66,38 +68,71 @@ impl EgressProducer {
         let mut buffer = [0; BUFFER_SIZE_BYTES];
 
         loop {
-            let element_length = read_from_bip_buffer(&mut bip_reader, &mut buffer);
-            self.deserialize_incoming_data_and_send_to_kafka(&buffer[..element_length])?;
+            let element_length =
+                read_fitting_from_bip_buffer(&mut bip_reader, &mut buffer, &self.stats_data)
+                    .chain_err(|| "Error reading from bip_buffer")?;
+            match Frame::read(&buffer[..element_length]) {
+                Ok(frame) => {
+                    if let Some(ingress_timestamp) = frame.metadata.ingress_timestamp {
+                        self.stats_data.latency.record(ingress_timestamp);
+                    }
+                    self.deserialize_incoming_data_and_send_to_kafka(frame.payload)?
+                }
+                Err(e) => {
+                    warn!("Dropped element of {} bytes: {}", element_length, e);
+                    self.stats_data.dropped_packets.add(1);
+                    self.stats_data.dropped_bytes.add(element_length as u64);
+                }
+            }
         }
     }
 
-    /// Give a u8 array, deserialize and send it to kafka
+    /// Give a u8 array, deserialize the envelope and send its payload to kafka
+    /// The topic and key of the envelope are used for the Kafka record, an envelope without a topic is dropped.
     /// # Arguments
-    /// * `bip_reader` - The BipBufferReader used to get data from the bip_buffer.
+    /// * `incoming_data` - The serialized envelope.
     fn deserialize_incoming_data_and_send_to_kafka(&mut self, incoming_data: &[u8]) -> Result<()> {
         self.stats_data.in_bytes.add(incoming_data.len() as u64);
         self.stats_data.in_packets.add(1);
 
-        if let Ok(kafka_message) = KafkaMessage::deserialize_packet(&incoming_data) {
-            let topic = self.replace_topic(kafka_message.topic.to_string());
-            let kafka_message_length = kafka_message.payload.len();
-            match self
+        let envelope = match Envelope::deserialize_packet(&incoming_data) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                warn!("invalid Envelope received: {}", e);
+                self.stats_data.dropped_packets.add(1);
+                return Ok(());
+            }
+        };
+        let topic = match envelope.topic {
+            Some(topic) => self.replace_topic(topic),
+            None => {
+                warn!(
+                    "Envelope without a topic received from {:?}",
+                    envelope.protocol
+                );
+                self.stats_data.dropped_packets.add(1);
+                return Ok(());
+            }
+        };
+        let payload_length = envelope.payload.len();
+        let sent = match envelope.key {
+            Some(key) => self
                 .producer
-                .send(&Record::from_value(&topic, kafka_message.payload))
-            {
-                Ok(_) => {
-                    self.stats_data.out_bytes.add(kafka_message_length as u64);
-                    self.stats_data.out_packets.add(1);
-                }
-                Err(e) => {
-                    warn!("Error while sending data to kafka: {}", e);
-                    self.stats_data.dropped_packets.add(1);
-                    return Err(SendToKafka(e.to_string()).into());
-                }
+                .send(&Record::from_key_value(&topic, key, envelope.payload)),
+            None => self
+                .producer
+                .send(&Record::from_value(&topic, envelope.payload)),
+        };
+        match sent {
+            Ok(_) => {
+                self.stats_data.out_bytes.add(payload_length as u64);
+                self.stats_data.out_packets.add(1);
+            }
+            Err(e) => {
+                warn!("Error while sending data to kafka: {}", e);
+                self.stats_data.dropped_packets.add(1);
+                return Err(SendToKafka(e.to_string()).into());
             }
-        } else {
-            warn!("invalid KafkaMessage received");
-            self.stats_data.dropped_packets.add(1);
         }
 
         Ok(())
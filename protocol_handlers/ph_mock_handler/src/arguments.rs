@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use handler_schema::{Argument, ArgumentType, HandlerSchema, Role};
 use structopt::StructOpt;
 ///Commandline arguments used to run the mock ingress.
 #[derive(StructOpt)]
@@ -49,6 +50,33 @@ pub struct OptIngress {
     pub handler_name: String,
 }
 
+///The arguments of ph_mock_ingress, printed with `--describe`.
+pub const INGRESS_SCHEMA: HandlerSchema = HandlerSchema {
+    role: Role::Ingress,
+    arguments: &[
+        Argument::new("socket_path", ArgumentType::String)
+            .default("/tmp/transport_to_handler")
+            .help("Location of the socket"),
+        Argument::new("stats_server_address", ArgumentType::String).default("10.0.0.2"),
+        Argument::new("stats_server_port", ArgumentType::Integer).default("8125"),
+        Argument::new("from_host_sys_log", ArgumentType::String)
+            .default("0.0.0.0")
+            .help("From syslog server host"),
+        Argument::new("from_port_sys_log", ArgumentType::Integer)
+            .default("8345")
+            .help("From syslog server port"),
+        Argument::new("to_host_sys_log", ArgumentType::String)
+            .default("127.0.0.1")
+            .help("To syslog udp host"),
+        Argument::new("to_port_sys_log", ArgumentType::Integer)
+            .default("8082")
+            .help("To syslog udp port"),
+        Argument::new("handler_name", ArgumentType::String)
+            .default("mock_handler_ingress")
+            .help("Log level for logging"),
+    ],
+};
+
 ///Commandline arguments used to run the mock egress.
 #[derive(StructOpt)]
 pub struct OptEgress {
@@ -84,3 +112,42 @@ pub struct OptEgress {
     #[structopt(long = "handler_name", default_value = "mock_handler_egress")]
     pub handler_name: String,
 }
+
+///The arguments of ph_mock_egress, printed with `--describe`.
+pub const EGRESS_SCHEMA: HandlerSchema = HandlerSchema {
+    role: Role::Egress,
+    arguments: &[
+        Argument::new("socket_path", ArgumentType::String)
+            .default("/tmp/transport_to_handler")
+            .help("Location of the socket"),
+        Argument::new("stats_server_address", ArgumentType::String).default("10.0.0.2"),
+        Argument::new("stats_server_port", ArgumentType::Integer).default("8125"),
+        Argument::new("from_host_sys_log", ArgumentType::String)
+            .default("0.0.0.0")
+            .help("From syslog server host"),
+        Argument::new("from_port_sys_log", ArgumentType::Integer)
+            .default("8346")
+            .help("From syslog server port"),
+        Argument::new("to_host_sys_log", ArgumentType::String)
+            .default("127.0.0.1")
+            .help("To syslog udp host"),
+        Argument::new("to_port_sys_log", ArgumentType::Integer)
+            .default("8082")
+            .help("To syslog udp port"),
+        Argument::new("handler_name", ArgumentType::String)
+            .default("mock_handler_egress")
+            .help("Log level for logging"),
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use handler_schema::check_schema;
+
+    #[test]
+    fn schema_test() {
+        assert_eq!(check_schema(OptIngress::clap(), &INGRESS_SCHEMA), Ok(()));
+        assert_eq!(check_schema(OptEgress::clap(), &EGRESS_SCHEMA), Ok(()));
+    }
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use handler_schema::describe_if_requested;
 use ph_mock_handler::set_syslog;
 use ph_mock_handler::*;
 use socket_utils::socket_reader::SocketReader;
@@ -61,6 +62,7 @@ impl MockHandlerEgress {
 ///Creates an EgressMockHandler struct.
 ///This struct starts reading data from the socket path supplied in the arguments.
 fn main() {
+    describe_if_requested(&arguments::EGRESS_SCHEMA);
     let opt = arguments::OptEgress::from_args();
     set_syslog(
         opt.from_host_sys_log,
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use handler_schema::describe_if_requested;
 use ph_mock_handler::*;
 use socket_utils::socket_writer::SocketWriter;
 use std::sync::atomic::AtomicBool;
@@ -63,6 +64,7 @@ impl MockHandlerIngress {
 ///Creates an IngressMockHandler struct.
 ///This struct starts sending UDP packets to the socket path supplied in the arguments.
 fn main() {
+    describe_if_requested(&arguments::INGRESS_SCHEMA);
     let opt = arguments::OptIngress::from_args();
     set_syslog(
         opt.from_host_sys_log,
@@ -12,18 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bip_utils::read_from_bip_buffer;
+use bip_utils::bip_buffer_len_for;
+use bip_utils::bip_buffer_with_len;
+use bip_utils::read_fitting_from_bip_buffer;
+use bip_utils::wakeup::set_wait_strategy;
+use envelope::Envelope;
+use error_chain::ChainedError;
+use handler_schema::describe_if_requested;
 use logging::*;
 use ph_udp::errors::*;
 use ph_udp::*;
-use socket_utils::buffered_socket_reader::BufferedSocketReader;
-use spsc_bip_buffer::bip_buffer_with_len;
+use socket_utils::frame::Frame;
+use socket_utils::link::LinkReader;
 use statistics_handler::*;
 use std::net::UdpSocket;
 use std::thread;
 use structopt::StructOpt;
 
 fn main() {
+    describe_if_requested(&arguments::EGRESS_SCHEMA);
     std::panic::set_hook(Box::new(|panic_info| {
         if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
             log::error!("{}", s);
@@ -53,16 +60,19 @@ fn outer_udp_egress() -> Result<()> {
     )
     .chain_err(|| "Error initializing syslog")?;
     log::info!("start {}", &opt.handler_name);
+    set_wait_strategy(opt.wait_strategy);
 
-    let (bip_writer, mut bip_reader) =
-        bip_buffer_with_len(opt.bip_buffer_element_count * MAX_BIP_BUFFER_MESSAGE_SIZE);
-    let mut socket_reader = BufferedSocketReader::new(&opt.socket_path, bip_writer)
+    let bip_buffer_len =
+        bip_buffer_len_for(opt.bip_buffer_element_count, MAX_BIP_BUFFER_MESSAGE_SIZE);
+    let (bip_writer, mut bip_reader) = bip_buffer_with_len(bip_buffer_len);
+    let mut socket_reader = LinkReader::new(opt.link, &opt.socket_path, bip_writer, bip_buffer_len)
         .chain_err(|| "Error while creating socket reader")?;
 
     //Start stats thread
     let stats_addr = format!("{}:{}", opt.host_stats_server, opt.port_stats_server);
     let stats: StatsdClient<StatsAllHandlers> =
         StatsdClient::<StatsAllHandlers>::new_with_custom_fields(None, None);
+    socket_reader.set_stats_data(stats.get_data_clone());
     stats
         .run(stats_addr, opt.handler_name)
         .chain_err(|| "Error while running statitics")?;
@@ -94,16 +104,47 @@ fn outer_udp_egress() -> Result<()> {
         .chain_err(|| "error while parsing udp socket")?;
 
     let stats_data = stats.get_data_clone();
-    //65535 is max udp pakket size
-    let mut buffer = [0; MAX_UDP_SIZE];
+    //the payload of the envelope in the frame is sent, the envelope may come from a chain of any protocol
+    //the latency of every frame that carries an ingress timestamp is recorded when it is read.
+    let mut buffer = vec![0; MAX_BIP_BUFFER_MESSAGE_SIZE];
     let stats_server: std::net::SocketAddr = udp_receiver_server
         .parse()
         .chain_err(|| "Cannot parse stats server and host to socket address")?;
     let udp_sender = thread::Builder::new()
         .name("udp_sender".into())
         .spawn(move || loop {
-            let element_length = read_from_bip_buffer(&mut bip_reader, &mut buffer);
-            match socket.send_to(&buffer[0..element_length], stats_server) {
+            let element_length =
+                read_fitting_from_bip_buffer(&mut bip_reader, &mut buffer, &stats_data)
+                    .chain_err(|| "Error in udp_sender thread")
+                    .chain_unwrap();
+            let envelope = match Frame::read(&buffer[..element_length])
+                .chain_err(|| "Invalid frame")
+                .and_then(|frame| {
+                    if let Some(ingress_timestamp) = frame.metadata.ingress_timestamp {
+                        stats_data.latency.record(ingress_timestamp);
+                    }
+                    Envelope::deserialize_packet(frame.payload).chain_err(|| "Invalid envelope")
+                }) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    stats_data.dropped_packets.add(1);
+                    stats_data.dropped_bytes.add(element_length as u64);
+                    log::error!(
+                        "Dropped element of {} bytes: {}",
+                        element_length,
+                        e.display_chain()
+                    );
+                    continue;
+                }
+            };
+            let element_length = envelope.payload.len();
+            if element_length > MAX_UDP_SIZE {
+                stats_data.dropped_packets.add(1);
+                stats_data.dropped_bytes.add(element_length as u64);
+                log::warn!("Payload of {} bytes is too large for udp", element_length);
+                continue;
+            }
+            match socket.send_to(&envelope.payload, stats_server) {
                 Ok(_) => {
                     stats_data.out_bytes.add(element_length as u64);
                     stats_data.out_packets.add(1);
@@ -12,19 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bip_utils::write_to_bip_buffer;
+use bip_utils::bip_buffer_len_for;
+use bip_utils::bip_buffer_with_len;
+use bip_utils::overflow::BufferMonitor;
+use bip_utils::wakeup::set_wait_strategy;
+use envelope::{Envelope, Protocol};
 use error_chain::*;
+use handler_schema::describe_if_requested;
 use logging::*;
 use ph_udp::errors::*;
 use ph_udp::*;
-use socket_utils::buffered_socket_writer::BufferedSocketWriter;
-use spsc_bip_buffer::bip_buffer_with_len;
+use socket_utils::frame::{frame_prefix_len, write_frame_prefix, Metadata};
+use socket_utils::link::LinkWriter;
+use statistics_handler::latency::now_micros;
 use statistics_handler::*;
 use std::net::UdpSocket;
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use structopt::StructOpt;
 
 fn main() {
+    describe_if_requested(&arguments::INGRESS_SCHEMA);
     std::panic::set_hook(Box::new(|panic_info| {
         if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
             log::error!("{}", s);
@@ -54,6 +63,7 @@ fn outer_udp_ingress() -> Result<()> {
     )
     .chain_err(|| "Error initializing syslog")?;
     log::info!("start {}", &opt.handler_name);
+    set_wait_strategy(opt.wait_strategy);
     loop {
         match inner_udp_ingress() {
             Ok(_) => (),
@@ -71,12 +81,16 @@ fn outer_udp_ingress() -> Result<()> {
 ///in the egress network it receives the data from a unix domain socket and send it to a specific port.
 fn inner_udp_ingress() -> Result<()> {
     let opt = arguments::OptIngress::from_args();
-
-    let mut socket_writer = BufferedSocketWriter::start_listening(&opt.socket_path)
+    //every packet is sent in an envelope as the payload of a frame, stamped with the time it was received.
+    let mut metadata = Metadata::for_handler(&opt.handler_name, envelope::CONTENT_TYPE);
+    metadata.ingress_timestamp = Some(0);
+    let prefix_len = frame_prefix_len(&metadata);
+
+    let bip_buffer_len =
+        bip_buffer_len_for(opt.bip_buffer_element_count, MAX_BIP_BUFFER_MESSAGE_SIZE);
+    let mut socket_writer = LinkWriter::start(opt.link, &opt.socket_path, bip_buffer_len)
         .chain_err(|| "Error creating socket writer")?;
-
-    let (mut bip_writer, mut bip_reader) =
-        bip_buffer_with_len(MAX_BIP_BUFFER_MESSAGE_SIZE * opt.bip_buffer_element_count as usize);
+    let (mut bip_writer, mut bip_reader) = bip_buffer_with_len(bip_buffer_len);
 
     //Start stats thread
     let stats_addr = format!("{}:{}", opt.host_stats_server, opt.port_stats_server);
@@ -86,6 +100,17 @@ fn inner_udp_ingress() -> Result<()> {
         .run(stats_addr, opt.handler_name)
         .chain_err(|| "Error while running statitics")?;
 
+    //The monitor applies the overflow policy when the udp_receiver writes to the bip_buffer.
+    let monitor = Arc::new(BufferMonitor::new(
+        opt.overflow_policy,
+        Duration::from_millis(opt.overflow_wait_ms),
+        bip_buffer_len,
+        stats.data.register_buffer("udp_receiver_to_socket"),
+    ));
+    socket_writer.set_buffer_monitor(monitor.clone());
+    socket_writer.set_in_flight_policy(opt.in_flight_policy);
+    socket_writer.set_stats_data(stats.get_data_clone());
+
     //2 threads:
     //- udp_receiver,
     //- bipreader_socketwriter
@@ -99,15 +124,44 @@ fn inner_udp_ingress() -> Result<()> {
     let udp_receiver = thread::Builder::new()
         .name("udp_receiver".into())
         .spawn(move || {
-            let mut buf = [0; MAX_UDP_SIZE];
+            let mut packet = vec![0; MAX_UDP_SIZE];
+            let mut buf = vec![0; MAX_BIP_BUFFER_MESSAGE_SIZE];
             loop {
-                match socket.recv_from(&mut buf) {
-                    Ok((length, _)) => {
+                match socket.recv_from(&mut packet) {
+                    Ok((length, source)) => {
                         stats_data.in_packets.add(1);
                         stats_data.in_bytes.add(length as u64);
                         log::trace!("Received packet with size {}", length);
-                        if length != 0 {
-                            write_to_bip_buffer(&mut bip_writer, &buf[..length]);
+                        if length == 0 {
+                            continue;
+                        }
+                        let now = now_micros();
+                        let mut envelope = Envelope::new(Protocol::Udp, &packet[..length]);
+                        envelope.headers.push((
+                            SOURCE_HEADER_NAME.to_string(),
+                            source.to_string().into_bytes(),
+                        ));
+                        envelope.received_timestamp = now;
+                        metadata.ingress_timestamp = Some(now);
+                        let envelope_length =
+                            match envelope.serialize_packet(&mut buf[prefix_len..]) {
+                                Ok(envelope_length) => envelope_length,
+                                Err(e) => {
+                                    log::warn!("Dropped packet of {} bytes: {}", length, e);
+                                    stats_data.dropped_packets.add(1);
+                                    stats_data.dropped_bytes.add(length as u64);
+                                    continue;
+                                }
+                            };
+                        if let Err(e) = write_frame_prefix(&mut buf, &metadata, envelope_length) {
+                            log::warn!("Dropped packet of {} bytes: {}", length, e);
+                            stats_data.dropped_packets.add(1);
+                            stats_data.dropped_bytes.add(length as u64);
+                            continue;
+                        }
+                        if !monitor.write(&mut bip_writer, &buf[..prefix_len + envelope_length]) {
+                            stats_data.dropped_packets.add(1);
+                            stats_data.dropped_bytes.add(length as u64);
                         }
                     }
                     Err(e) => {
@@ -23,3 +23,6 @@ pub const MAX_BIP_BUFFER_MESSAGE_SIZE: usize = 1_050_000;
 ///The field size sets a theoretical limit of 65,535 bytes (8 byte header + 65,527 bytes of data) for a UDP datagram.\
 ///However the actual limit for the data length, which is imposed by the underlying IPv4 protocol, is 65,507 bytes (65,535 − 8 byte UDP header − 20 byte IP header).
 pub const MAX_UDP_SIZE: usize = 65507;
+
+///The envelope header that holds the address the packet was received from.
+pub const SOURCE_HEADER_NAME: &str = "udp.source";
@@ -0,0 +1,119 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{Counter, Gauge, Timer};
+use statsd::client::Pipeline;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+///Returns the current time in microseconds since the unix epoch.
+///This is the format of the ingress timestamps carried across the diode.
+pub fn now_micros() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_micros() as u64,
+        Err(_) => 0,
+    }
+}
+
+#[derive(Default)]
+struct JitterState {
+    last_transit_us: Option<i64>,
+    jitter_us: f64,
+}
+
+///Latency and inter-arrival jitter of elements that carry an ingress timestamp.
+///Both sides of the diode are expected to be synchronized by NTP or PTP.
+///A negative latency means the clocks are skewed, this is counted in `clock_skew` instead of the latency timer.
+#[derive(Default)]
+pub struct LatencyStats {
+    ///Latency of every element in milliseconds.
+    pub latency: Timer,
+    ///Inter-arrival jitter in microseconds, as defined in RFC 3550.
+    pub jitter: Gauge,
+    ///Amount of elements that arrived before they were sent according to the clocks.
+    pub clock_skew: Counter,
+    state: Mutex<JitterState>,
+}
+
+impl LatencyStats {
+    ///Records the arrival of an element that was stamped at `ingress_timestamp_us`.
+    /// # Arguments
+    /// * `ingress_timestamp_us` - The ingress timestamp of the element in microseconds since the unix epoch.
+    pub fn record(&self, ingress_timestamp_us: u64) {
+        self.record_at(ingress_timestamp_us, now_micros());
+    }
+
+    fn record_at(&self, ingress_timestamp_us: u64, arrival_us: u64) {
+        let transit_us = arrival_us as i64 - ingress_timestamp_us as i64;
+        if transit_us < 0 {
+            self.clock_skew.add(1);
+            log::warn!(
+                "Element arrived {}us before it was sent, clocks are not synchronized!",
+                -transit_us
+            );
+        } else {
+            self.latency.record(transit_us as f64 / 1000.0);
+        }
+        //the jitter only depends on the difference between transit times, so a constant skew cancels out.
+        if let Ok(mut state) = self.state.lock() {
+            if let Some(last_transit_us) = state.last_transit_us {
+                let difference = (transit_us - last_transit_us).abs() as f64;
+                state.jitter_us += (difference - state.jitter_us) / 16.0;
+                self.jitter.set(state.jitter_us as u64);
+            }
+            state.last_transit_us = Some(transit_us);
+        }
+    }
+
+    pub(crate) fn fill_pipeline(&self, pipeline: &mut Pipeline) {
+        let has_measured = match self.state.lock() {
+            Ok(state) => state.last_transit_us.is_some(),
+            Err(_) => false,
+        };
+        if !has_measured {
+            return;
+        }
+        for sample in self.latency.take_samples() {
+            pipeline.timer(&"latency", sample);
+        }
+        pipeline.gauge(&"latency.jitter_us", self.jitter.get());
+        pipeline.count(&"latency.clock_skew", self.clock_skew.get_and_reset());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_and_jitter_test() {
+        let stats = LatencyStats::default();
+        stats.record_at(1_000, 3_000);
+        stats.record_at(2_000, 4_000);
+        assert_eq!(stats.latency.take_samples(), vec![2.0, 2.0]);
+        assert_eq!(stats.jitter.get() as u64, 0);
+        stats.record_at(3_000, 6_600);
+        //transit went from 2000us to 3600us, the jitter moves 1/16th towards that difference.
+        assert_eq!(stats.jitter.get() as u64, 100);
+        assert_eq!(stats.clock_skew.load(), 0);
+    }
+
+    #[test]
+    fn clock_skew_test() {
+        let stats = LatencyStats::default();
+        stats.record_at(5_000, 4_000);
+        assert_eq!(stats.clock_skew.load(), 1);
+        assert!(stats.latency.take_samples().is_empty());
+    }
+}
@@ -17,12 +17,21 @@ use crate::errors::*;
 use statsd::client::Pipeline;
 use statsd::Client;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
 
 pub mod errors;
 
+///This module contains the LatencyStats used to measure the latency and jitter of elements.
+pub mod latency;
+
+///This module contains the LossStats used to publish the loss reconciled with the sender.
+pub mod loss;
+
+use crate::latency::LatencyStats;
+use crate::loss::LossStats;
+
 ///Delay used in the run loop of the statistics handler thread.
 const STATS_DELAY_SEC: u64 = 1;
 
@@ -53,6 +62,57 @@ impl Gauge {
     }
 }
 
+///The maximum amount of samples a Timer keeps between two publications.
+const MAX_TIMER_SAMPLES: usize = 1000;
+
+///Samples of a duration, every sample is published as a statsd timer.
+#[derive(Default)]
+pub struct Timer(Mutex<Vec<f64>>);
+
+impl Timer {
+    ///Records a sample in milliseconds, samples above MAX_TIMER_SAMPLES are discarded until the next publication.
+    pub fn record(&self, value_ms: f64) {
+        if let Ok(mut samples) = self.0.lock() {
+            if samples.len() < MAX_TIMER_SAMPLES {
+                samples.push(value_ms);
+            }
+        }
+    }
+    fn take_samples(&self) -> Vec<f64> {
+        match self.0.lock() {
+            Ok(mut samples) => std::mem::take(&mut *samples),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+///The statistics of a single bip_buffer, published under the name of the buffer.
+#[derive(Default)]
+pub struct BufferStats {
+    pub name: String,
+    ///Percentage of the bip_buffer that is in use.
+    pub fill_level: Gauge,
+    pub dropped_bytes: Counter,
+    pub dropped_packets: Counter,
+}
+
+impl BufferStats {
+    fn fill_pipeline(&self, pipeline: &mut Pipeline) {
+        pipeline.gauge(
+            &format!("bip_buffer.{}.fill_level", self.name),
+            self.fill_level.get(),
+        );
+        pipeline.count(
+            &format!("bip_buffer.{}.dropped.bytes", self.name),
+            self.dropped_bytes.get_and_reset(),
+        );
+        pipeline.count(
+            &format!("bip_buffer.{}.dropped.packets", self.name),
+            self.dropped_packets.get_and_reset(),
+        );
+    }
+}
+
 #[derive(Default)]
 pub struct StatsAllHandlers {
     pub in_bytes: Counter,
@@ -62,8 +122,30 @@ pub struct StatsAllHandlers {
     pub dropped_bytes: Counter,
     pub dropped_packets: Counter,
     pub packetloss: Counter,
+    pub reconnections: Counter,
+    ///Elements dropped because they do not fit in a bip_buffer or in the buffer they are read into.
+    pub oversized_packets: Counter,
     pub custom_counter: Option<(Counter, String)>,
     pub custom_gauge: Option<(Gauge, String)>,
+    pub buffers: Mutex<Vec<Arc<BufferStats>>>,
+    pub latency: LatencyStats,
+    pub loss: LossStats,
+}
+
+impl StatsAllHandlers {
+    ///Registers a bip_buffer with the given name.
+    ///The returned BufferStats are published together with the other statistics.
+    pub fn register_buffer(&self, name: &str) -> Arc<BufferStats> {
+        let buffer_stats = Arc::new(BufferStats {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        self.buffers
+            .lock()
+            .expect("Error locking buffer statistics")
+            .push(buffer_stats.clone());
+        buffer_stats
+    }
 }
 
 impl StatisticData for StatsAllHandlers {
@@ -75,12 +157,21 @@ impl StatisticData for StatsAllHandlers {
         pipeline.count(&"dropped.bytes", self.dropped_bytes.get_and_reset());
         pipeline.count(&"dropped.packets", self.dropped_packets.get_and_reset());
         pipeline.count(&"packetloss", self.packetloss.get_and_reset());
+        pipeline.count(&"reconnections", self.reconnections.get_and_reset());
+        pipeline.count(&"oversized.packets", self.oversized_packets.get_and_reset());
         if let Some(x) = &self.custom_counter {
             pipeline.count(&x.1, x.0.get_and_reset());
         }
         if let Some(x) = &self.custom_gauge {
             pipeline.gauge(&x.1, x.0.get());
         }
+        if let Ok(buffers) = self.buffers.lock() {
+            for buffer_stats in buffers.iter() {
+                buffer_stats.fill_pipeline(pipeline);
+            }
+        }
+        self.latency.fill_pipeline(pipeline);
+        self.loss.fill_pipeline(pipeline);
     }
     fn set_custom_gauge(&self, number: u64) -> Result<()> {
         match self.custom_gauge.as_ref() {
@@ -152,6 +243,11 @@ where
                 custom_counter: counter_option,
                 custom_gauge: gauge_option,
                 packetloss: Counter::default(),
+                reconnections: Counter::default(),
+                oversized_packets: Counter::default(),
+                buffers: Mutex::new(Vec::new()),
+                latency: LatencyStats::default(),
+                loss: LossStats::default(),
             }),
             is_running: Arc::new(AtomicBool::default()),
         }
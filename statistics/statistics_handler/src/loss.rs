@@ -0,0 +1,49 @@
+// Copyright 2020 Ministerie van Defensie
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Counter;
+use statsd::client::Pipeline;
+use std::sync::Mutex;
+
+///Loss across the diode, reconciled from the totals the sender reports through the diode.
+///Only the receiving transport handler fills these statistics, they are published once the first report is reconciled.
+#[derive(Default)]
+pub struct LossStats {
+    ///Elements the sender sent that never arrived completely.
+    pub elements_lost: Counter,
+    ///Bytes of the elements that never arrived completely.
+    pub bytes_lost: Counter,
+    transfer_efficiency: Mutex<Option<f64>>,
+}
+
+impl LossStats {
+    ///Sets the percentage of the sent bytes that arrived during the last reconciled interval.
+    pub fn set_transfer_efficiency(&self, percentage: f64) {
+        if let Ok(mut transfer_efficiency) = self.transfer_efficiency.lock() {
+            *transfer_efficiency = Some(percentage);
+        }
+    }
+
+    pub(crate) fn fill_pipeline(&self, pipeline: &mut Pipeline) {
+        let transfer_efficiency = match self.transfer_efficiency.lock() {
+            Ok(transfer_efficiency) => *transfer_efficiency,
+            Err(_) => None,
+        };
+        if let Some(percentage) = transfer_efficiency {
+            pipeline.count(&"diode.elements_lost", self.elements_lost.get_and_reset());
+            pipeline.count(&"diode.bytes_lost", self.bytes_lost.get_and_reset());
+            pipeline.gauge(&"diode.transfer_efficiency", percentage);
+        }
+    }
+}